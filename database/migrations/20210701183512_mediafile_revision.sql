@@ -0,0 +1,3 @@
+-- Release revision of a file, ie PROPER/REPACK or `v2` style releases.
+-- 0 means the original release, higher values replace lower ones.
+ALTER TABLE mediafile ADD COLUMN revision INTEGER;
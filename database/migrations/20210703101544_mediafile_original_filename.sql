@@ -0,0 +1,2 @@
+-- Exact filename (including the extension) the mediafile was mounted from.
+ALTER TABLE mediafile ADD COLUMN original_filename TEXT;
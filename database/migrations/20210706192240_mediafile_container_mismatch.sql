@@ -0,0 +1,2 @@
+-- Set when the extension of a file disagrees with the container ffprobe detected.
+ALTER TABLE mediafile ADD COLUMN container_mismatch BOOLEAN;
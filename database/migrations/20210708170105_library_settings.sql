@@ -0,0 +1,2 @@
+-- Per library settings stored as a json blob, defaults to `{}`.
+ALTER TABLE library ADD COLUMN settings BLOB NOT NULL DEFAULT X'7B7D';
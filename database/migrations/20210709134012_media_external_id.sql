@@ -0,0 +1,2 @@
+-- Id of the media on the metadata provider it was matched with, ie the TMDB id of a tv show.
+ALTER TABLE _tblmedia ADD COLUMN external_id INTEGER;
@@ -0,0 +1,2 @@
+-- Number of channels of the primary audio stream, used to rank versions of a media by audio.
+ALTER TABLE mediafile ADD COLUMN audio_channels INTEGER;
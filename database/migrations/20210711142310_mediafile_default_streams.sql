@@ -0,0 +1,4 @@
+-- Streams picked as the default audio and subtitle tracks based on the language preferences of
+-- the library.
+ALTER TABLE mediafile ADD COLUMN default_audio_stream INTEGER;
+ALTER TABLE mediafile ADD COLUMN default_subtitle_stream INTEGER;
@@ -0,0 +1,13 @@
+-- Local copy of the daily id exports of TMDB, used to match media without network access.
+CREATE TABLE tmdb_dump (
+    tmdb_id INTEGER NOT NULL,
+    media_type TEXT NOT NULL,
+    title TEXT NOT NULL,
+    -- Lowercase title stripped of punctuation, which is what we look media up by.
+    search_title TEXT NOT NULL,
+    year INTEGER,
+    popularity REAL NOT NULL DEFAULT 0,
+    PRIMARY KEY (tmdb_id, media_type)
+);
+
+CREATE INDEX tmdb_dump_search_idx ON tmdb_dump (media_type, search_title);
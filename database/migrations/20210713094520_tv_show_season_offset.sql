@@ -0,0 +1,3 @@
+-- Offset added to the season numbers of the files of a tv show to get the season on TMDB, for
+-- shows whose folders are numbered differently.
+ALTER TABLE tv_show ADD COLUMN season_offset INTEGER;
@@ -0,0 +1,2 @@
+-- What mounted a file, ie the daemon or a manual scan.
+ALTER TABLE mediafile ADD COLUMN mount_source TEXT;
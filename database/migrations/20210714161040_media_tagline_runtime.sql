@@ -0,0 +1,3 @@
+-- Tagline and runtime in minutes of a movie, as reported by the metadata provider.
+ALTER TABLE _tblmedia ADD COLUMN tagline TEXT;
+ALTER TABLE _tblmedia ADD COLUMN runtime INTEGER;
@@ -0,0 +1,2 @@
+-- Why a file that could be probed cant be played as a video, ie because it is audio only.
+ALTER TABLE mediafile ADD COLUMN problem TEXT;
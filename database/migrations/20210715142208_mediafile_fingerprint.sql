@@ -0,0 +1,2 @@
+-- Perceptual fingerprint of a video, used to find re-encodes of the same content.
+ALTER TABLE mediafile ADD COLUMN fingerprint TEXT;
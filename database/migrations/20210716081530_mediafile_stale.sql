@@ -0,0 +1,2 @@
+-- Set when the content of a file changed on disk since it was matched.
+ALTER TABLE mediafile ADD COLUMN stale BOOLEAN;
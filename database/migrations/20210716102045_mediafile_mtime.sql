@@ -0,0 +1,2 @@
+-- Modification time of a file as of when it was last probed, used to skip unchanged files.
+ALTER TABLE mediafile ADD COLUMN mtime INTEGER;
@@ -0,0 +1,3 @@
+-- Ids of the media a file holds as found in a nfo file next to it.
+ALTER TABLE mediafile ADD COLUMN tmdb_id INTEGER;
+ALTER TABLE mediafile ADD COLUMN imdb_id TEXT;
@@ -0,0 +1,2 @@
+-- Which part of a movie split over several files a file is.
+ALTER TABLE mediafile ADD COLUMN part_number INTEGER;
@@ -0,0 +1,11 @@
+-- Files can hold several episodes, ie `Show.S01E01-E03.mkv`. The file is matched with the first
+-- episode through `media_id`, the episodes that follow are linked to it here.
+ALTER TABLE mediafile ADD COLUMN episode_end INTEGER;
+
+CREATE TABLE mediafile_episode (
+    mediafile_id INTEGER NOT NULL,
+    episode_id INTEGER NOT NULL,
+    PRIMARY KEY (mediafile_id, episode_id),
+    FOREIGN KEY(mediafile_id) REFERENCES mediafile (id) ON DELETE CASCADE,
+    FOREIGN KEY(episode_id) REFERENCES episode (id) ON DELETE CASCADE
+);
@@ -0,0 +1,2 @@
+-- How confident the matcher was in the match of a file, between 0 and 1.
+ALTER TABLE mediafile ADD COLUMN match_score REAL;
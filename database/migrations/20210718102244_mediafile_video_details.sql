@@ -0,0 +1,4 @@
+-- Technical details of the primary video stream of a file as reported by ffprobe.
+ALTER TABLE mediafile ADD COLUMN frame_rate REAL;
+ALTER TABLE mediafile ADD COLUMN bit_depth INTEGER;
+ALTER TABLE mediafile ADD COLUMN hdr TEXT;
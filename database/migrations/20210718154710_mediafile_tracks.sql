@@ -0,0 +1,20 @@
+-- Audio and subtitle streams of a mediafile as reported by ffprobe.
+CREATE TABLE mediafile_track (
+    id INTEGER NOT NULL,
+    mediafile_id INTEGER NOT NULL,
+    -- Index of the stream within the file.
+    stream_index INTEGER NOT NULL,
+    -- Either `audio` or `subtitle`.
+    kind TEXT NOT NULL,
+    codec TEXT NOT NULL,
+    language TEXT,
+    title TEXT,
+    channels INTEGER,
+    is_default BOOLEAN NOT NULL DEFAULT 0,
+    forced BOOLEAN NOT NULL DEFAULT 0,
+
+    PRIMARY KEY (id),
+    FOREIGN KEY(mediafile_id) REFERENCES mediafile(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX mediafile_track_idx ON mediafile_track(mediafile_id, stream_index);
@@ -0,0 +1,13 @@
+-- Subtitle files found next to a mediafile when it was mounted.
+CREATE TABLE external_subtitle (
+    id INTEGER NOT NULL,
+    mediafile_id INTEGER NOT NULL,
+    path TEXT NOT NULL,
+    language TEXT,
+    forced BOOLEAN NOT NULL DEFAULT 0,
+
+    PRIMARY KEY (id),
+    FOREIGN KEY(mediafile_id) REFERENCES mediafile(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX external_subtitle_idx ON external_subtitle(mediafile_id, path);
@@ -0,0 +1,11 @@
+-- Metadata of every successful match, looked up again when TMDB cant be reached.
+CREATE TABLE match_cache (
+    -- media type, title and year we searched for, ie `movie:inception:2010`.
+    key TEXT NOT NULL,
+    -- the metadata we found, serialized as json.
+    media TEXT NOT NULL,
+    -- unix timestamp of when we found it.
+    fetched INTEGER NOT NULL,
+
+    PRIMARY KEY (key)
+);
@@ -0,0 +1,3 @@
+-- Id of the media on TheTVDB, for tv shows and episodes matched with it. Their `external_id` is
+-- left empty, as it only ever holds TMDB ids.
+ALTER TABLE _tblmedia ADD COLUMN tvdb_id INTEGER;
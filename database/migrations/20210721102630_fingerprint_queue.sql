@@ -0,0 +1,10 @@
+-- Files waiting to be fingerprinted, kept so that the queue survives restarts.
+CREATE TABLE fingerprint_queue (
+    id INTEGER NOT NULL,
+    mediafile_id INTEGER NOT NULL,
+
+    PRIMARY KEY (id),
+    FOREIGN KEY(mediafile_id) REFERENCES mediafile(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX fingerprint_queue_idx ON fingerprint_queue(mediafile_id);
@@ -0,0 +1,12 @@
+-- Attempts at matching mediafiles that were left unmatched once more, used to back off from
+-- files that keep failing to match.
+CREATE TABLE match_attempt (
+    mediafile_id INTEGER NOT NULL,
+    -- number of times we tried to match the file again.
+    attempts INTEGER NOT NULL,
+    -- unix timestamp of the last attempt.
+    last_attempt INTEGER NOT NULL,
+
+    PRIMARY KEY (mediafile_id),
+    FOREIGN KEY(mediafile_id) REFERENCES mediafile(id) ON DELETE CASCADE
+);
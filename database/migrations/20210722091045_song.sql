@@ -0,0 +1,15 @@
+-- Tags of the audio files of music libraries, as read by ffprobe.
+CREATE TABLE song (
+    mediafile_id INTEGER NOT NULL,
+    artist TEXT,
+    -- artist the album is credited to, which differs from the artist of the track on compilations.
+    album_artist TEXT,
+    album TEXT,
+    title TEXT,
+    -- number of the track on its disc.
+    track_number INTEGER,
+    disc_number INTEGER,
+
+    PRIMARY KEY (mediafile_id),
+    FOREIGN KEY(mediafile_id) REFERENCES mediafile(id) ON DELETE CASCADE
+);
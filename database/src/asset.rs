@@ -65,6 +65,14 @@ impl Asset {
         .id)
     }
 
+    pub async fn get_all_remote(conn: &crate::DbConnection) -> Result<Vec<Self>, DatabaseError> {
+        Ok(
+            sqlx::query_as!(Asset, "SELECT * FROM assets WHERE remote_url IS NOT NULL")
+                .fetch_all(conn)
+                .await?,
+        )
+    }
+
     pub async fn get_url_by_file(
         conn: &crate::DbConnection,
         path: &PathBuf,
@@ -107,6 +107,30 @@ impl Episode {
         Ok(episodes)
     }
 
+    /// Method returns the season and episode numbers of all the episodes we have of a tv show,
+    /// ordered by season and episode number.
+    ///
+    /// # Arguments
+    /// * `conn` - diesel connection reference to postgres
+    /// * `tv_show_id` - id of the tv show.
+    pub async fn get_numbers_of_tv(
+        conn: &crate::DbConnection,
+        tv_show_id: i64,
+    ) -> Result<Vec<(i64, i64)>, DatabaseError> {
+        Ok(sqlx::query!(
+            r#"SELECT season.season_number, episode.episode_ FROM episode
+                INNER JOIN season ON season.id = episode.seasonid
+                WHERE season.tvshowid = ?
+                ORDER BY season.season_number, episode.episode_"#,
+            tv_show_id
+        )
+        .fetch_all(conn)
+        .await?
+        .into_iter()
+        .map(|x| (x.season_number, x.episode_))
+        .collect())
+    }
+
     // FIXME: This function might be especially heavy on the DB.
     /// Method returns all of the episodes belonging to a season.
     ///
@@ -296,6 +320,47 @@ impl Episode {
         // cascade delete.
         Ok(Media::delete(conn, episode_id).await?)
     }
+
+    /// Method deletes the episodes of a tv show that no mediafile holds anymore, along with the
+    /// seasons that are left without episodes, in a single transaction. Returns the number of
+    /// episodes deleted.
+    ///
+    /// # Arguments
+    /// * `conn` - diesel connection reference to postgres
+    /// * `tv_show_id` - id of the tv show
+    pub async fn delete_unused_of_tv(
+        conn: &crate::DbConnection,
+        tv_show_id: i64,
+    ) -> Result<usize, DatabaseError> {
+        let mut tx = conn.begin().await?;
+
+        let deleted = sqlx::query!(
+            "DELETE FROM _tblmedia WHERE id IN (
+                SELECT episode.id FROM episode
+                INNER JOIN _tblseason ON _tblseason.id = episode.seasonid
+                WHERE _tblseason.tvshowid = $1
+                AND NOT EXISTS (SELECT 1 FROM mediafile WHERE mediafile.media_id = episode.id)
+                AND NOT EXISTS
+                    (SELECT 1 FROM mediafile_episode WHERE mediafile_episode.episode_id = episode.id)
+            )",
+            tv_show_id
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected() as usize;
+
+        sqlx::query!(
+            "DELETE FROM _tblseason WHERE tvshowid = $1
+            AND NOT EXISTS (SELECT 1 FROM episode WHERE episode.seasonid = _tblseason.id)",
+            tv_show_id
+        )
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(deleted)
+    }
 }
 
 #[derive(Debug)]
@@ -6,6 +6,21 @@ pub enum DatabaseError {
     DatabaseError(sqlx::error::Error),
 }
 
+impl DatabaseError {
+    /// Method returns whether this error was caused by a row violating a unique constraint, ie
+    /// inserting a mediafile for a file that is already in the database.
+    pub fn is_unique_violation(&self) -> bool {
+        // NOTE: 2067 and 1555 are the extended sqlite result codes for `SQLITE_CONSTRAINT_UNIQUE`
+        // and `SQLITE_CONSTRAINT_PRIMARYKEY`.
+        match self {
+            Self::DatabaseError(sqlx::error::Error::Database(e)) => {
+                matches!(e.code().as_deref(), Some("2067") | Some("1555"))
+            }
+            _ => false,
+        }
+    }
+}
+
 impl From<sqlx::error::Error> for DatabaseError {
     fn from(e: sqlx::error::Error) -> DatabaseError {
         Self::DatabaseError(e)
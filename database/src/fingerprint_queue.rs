@@ -0,0 +1,63 @@
+use crate::mediafile::MediaFile;
+use crate::DatabaseError;
+
+/// Struct represents the queue of mediafiles waiting to be fingerprinted. Files are fingerprinted
+/// in the order they were queued in.
+pub struct FingerprintQueue;
+
+impl FingerprintQueue {
+    /// Method queues a mediafile, unless it is queued already. Returns the number of rows
+    /// inserted.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `mediafile_id` - id of the mediafile to queue
+    pub async fn push(
+        conn: &crate::DbConnection,
+        mediafile_id: i64,
+    ) -> Result<usize, DatabaseError> {
+        Ok(sqlx::query!(
+            "INSERT OR IGNORE INTO fingerprint_queue (mediafile_id) VALUES (?)",
+            mediafile_id
+        )
+        .execute(conn)
+        .await?
+        .rows_affected() as usize)
+    }
+
+    /// Method returns the mediafile that was queued first, if any. Files stay queued until they
+    /// are removed with [`FingerprintQueue::remove`], so that a file isnt lost if we are stopped
+    /// while fingerprinting it.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    pub async fn peek(conn: &crate::DbConnection) -> Result<Option<MediaFile>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            MediaFile,
+            r#"SELECT mediafile.* FROM mediafile
+                INNER JOIN fingerprint_queue ON fingerprint_queue.mediafile_id = mediafile.id
+                ORDER BY fingerprint_queue.id ASC
+                LIMIT 1"#
+        )
+        .fetch_optional(conn)
+        .await?)
+    }
+
+    /// Method removes a mediafile from the queue. Returns the number of rows deleted.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `mediafile_id` - id of the mediafile to remove
+    pub async fn remove(
+        conn: &crate::DbConnection,
+        mediafile_id: i64,
+    ) -> Result<usize, DatabaseError> {
+        Ok(sqlx::query!(
+            "DELETE FROM fingerprint_queue WHERE mediafile_id = ?",
+            mediafile_id
+        )
+        .execute(conn)
+        .await?
+        .rows_affected() as usize)
+    }
+}
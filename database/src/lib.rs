@@ -11,15 +11,22 @@ use std::sync::atomic::Ordering;
 pub mod asset;
 pub mod episode;
 pub mod error;
+pub mod fingerprint_queue;
 pub mod genre;
 pub mod library;
+pub mod match_attempt;
+pub mod match_cache;
 pub mod media;
 pub mod mediafile;
 pub mod movie;
 pub mod progress;
 pub mod season;
+pub mod song;
+pub mod subtitle;
 #[cfg(test)]
 pub mod tests;
+pub mod tmdb_dump;
+pub mod track;
 pub mod tv;
 pub mod user;
 pub mod utils;
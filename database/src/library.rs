@@ -12,6 +12,8 @@ pub enum MediaType {
     Movie,
     Tv,
     Episode,
+    /// Albums of a music library, whose songs are the mediafiles matched with them.
+    Music,
 }
 
 impl fmt::Display for MediaType {
@@ -23,6 +25,7 @@ impl fmt::Display for MediaType {
                 Self::Movie => "movie",
                 Self::Tv => "tv",
                 Self::Episode => "episode",
+                Self::Music => "music",
             }
         )
     }
@@ -34,6 +37,120 @@ impl Default for MediaType {
     }
 }
 
+/// Kind of disk a library is stored on. Spinning disks are slowed down to a crawl by parallel
+/// reads as the head keeps seeking between files, so we scan them serially.
+#[derive(Copy, Serialize, Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiskType {
+    Hdd,
+    Ssd,
+}
+
+impl Default for DiskType {
+    fn default() -> Self {
+        Self::Ssd
+    }
+}
+
+/// Metadata provider tv shows of a library are matched against.
+#[derive(Copy, Serialize, Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TvAgent {
+    Tmdb,
+    Tvdb,
+}
+
+impl Default for TvAgent {
+    fn default() -> Self {
+        Self::Tmdb
+    }
+}
+
+/// Settings that can be configured per library. These are stored as a json blob alongside the
+/// library, thus every field must have a default so that libraries created before a setting
+/// existed keep working.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct LibrarySettings {
+    /// Whether files and folders whose name starts with a `.` should be scanned, for libraries that
+    /// keep media in folders like `.media`. Only folders within the library locations count.
+    pub include_hidden: bool,
+    /// Kind of disk the library is stored on, used to decide how many files we read at once.
+    pub disk_type: DiskType,
+    /// Whether files without an extension should be probed with ffprobe to find out if they are
+    /// media. This is slow on large libraries, so its off by default.
+    pub probe_extensionless: bool,
+    /// Languages of the audio tracks we should play by default, most preferred first. Languages
+    /// are ISO 639-2 codes as found in the files, ie `jpn`.
+    pub audio_languages: Vec<String>,
+    /// Languages of the subtitle tracks we should show by default, most preferred first.
+    pub subtitle_languages: Vec<String>,
+    /// Minimum number of seconds since a file was last modified before we scan it, so that files
+    /// which are still being downloaded or copied are left alone.
+    pub min_age: Option<u64>,
+    /// Maximum number of seconds since a file was last modified for us to scan it. Older files are
+    /// skipped.
+    pub max_age: Option<u64>,
+    /// Whether files tagged with a release group in brackets, ie `[Group] Show - 05.mkv`, should
+    /// be matched with the anime agent first, for libraries that mix anime and other shows. Other
+    /// files keep going through TMDB first.
+    pub prefer_anime_agent: bool,
+    /// Extensions of the files we scan, ie `m2ts`, matched ignoring case. When empty the default
+    /// set of extensions for the media type of the library is used.
+    pub extensions: Vec<String>,
+    /// Metadata provider tv shows are matched against. Shows fall back to TMDB if TheTVDB doesnt
+    /// know them or no TheTVDB api key is configured.
+    pub tv_agent: TvAgent,
+    /// Whether episodes without a season, ie `Show - 137.mkv`, are numbered by their absolute
+    /// episode number as is common for anime, and should be mapped onto the season they aired in.
+    pub absolute_numbering: bool,
+    /// Glob patterns of files and folders that shouldnt be scanned, ie `**/sample/**` or
+    /// `*trailer*`, matched against paths relative to the library location ignoring case.
+    pub ignore_patterns: Vec<String>,
+    /// Size in bytes below which files are skipped, so that samples shipped alongside a rip arent
+    /// mounted as media. `0` scans files of any size. Music libraries scan songs of any size.
+    pub min_size: u64,
+    /// Minimum score, from `0.0` to `1.0`, the best match of a file must reach for the file to be
+    /// matched with it. Files whose best match scores lower are left unmatched. Raising this trades
+    /// files left unmatched for fewer wrong matches.
+    pub match_threshold: f64,
+    /// Regex filenames are parsed with before falling back to our filename parser, for libraries
+    /// whose files dont follow the usual release naming, ie `2020 - My Home Video - Birthday.mkv`.
+    /// The named captures `title`, `year`, `season` and `episode` are picked up, of which `title`
+    /// is required.
+    pub filename_pattern: Option<String>,
+    /// Url we post the outcome of every scan of the library to as json, for automation that
+    /// should run once a scan finished.
+    pub webhook_url: Option<String>,
+    /// Language metadata is fetched from TMDB in, ie `de-DE`. Titles and overviews TMDB has no
+    /// translation for are kept in english. When unset metadata is fetched in english.
+    pub metadata_language: Option<String>,
+}
+
+impl Default for LibrarySettings {
+    fn default() -> Self {
+        Self {
+            include_hidden: false,
+            disk_type: Default::default(),
+            probe_extensionless: false,
+            audio_languages: Vec::new(),
+            subtitle_languages: Vec::new(),
+            min_age: None,
+            max_age: None,
+            prefer_anime_agent: false,
+            extensions: Vec::new(),
+            tv_agent: Default::default(),
+            absolute_numbering: false,
+            ignore_patterns: Vec::new(),
+            min_size: 100 * 1024 * 1024,
+            match_threshold: 0.4,
+            filename_pattern: None,
+            webhook_url: None,
+            metadata_language: None,
+        }
+    }
+}
+
 /// Library struct which we can use to deserialize database queries into.
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Library {
@@ -47,9 +164,13 @@ pub struct Library {
     pub locations: Vec<String>,
 
     /// Enum used to identify the media type that this library contains. At the
-    /// moment only `movie` and `tv` are supported
-    // TODO: support mixed content, music
+    /// moment `movie`, `tv` and `music` are supported
+    // TODO: support mixed content
     pub media_type: MediaType,
+
+    /// Settings specific to this library.
+    #[serde(default)]
+    pub settings: LibrarySettings,
 }
 
 impl Library {
@@ -59,18 +180,21 @@ impl Library {
     /// This method will not return the locations indexed for this library, if you need those you
     /// must query for them separately.
     pub async fn get_all(conn: &crate::DbConnection) -> Vec<Self> {
-        sqlx::query!(r#"SELECT id, name, media_type as "media_type: MediaType" FROM library"#)
-            .fetch_all(conn)
-            .await
-            .unwrap_or_default()
-            .into_iter()
-            .map(|x| Self {
-                id: x.id,
-                name: x.name,
-                media_type: x.media_type,
-                locations: vec![],
-            })
-            .collect()
+        sqlx::query!(
+            r#"SELECT id, name, media_type as "media_type: MediaType", settings FROM library"#
+        )
+        .fetch_all(conn)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|x| Self {
+            id: x.id,
+            name: x.name,
+            media_type: x.media_type,
+            locations: vec![],
+            settings: serde_json::from_slice(&x.settings).unwrap_or_default(),
+        })
+        .collect()
     }
 
     pub async fn get_locations(
@@ -97,7 +221,7 @@ impl Library {
         let _tx = conn.begin().await?;
 
         let library = sqlx::query!(
-            r#"SELECT id, name, media_type as "media_type: MediaType" FROM library
+            r#"SELECT id, name, media_type as "media_type: MediaType", settings FROM library
             WHERE id = ?"#,
             lib_id
         )
@@ -117,9 +241,84 @@ impl Library {
             name: library.name,
             media_type: library.media_type,
             locations,
+            settings: serde_json::from_slice(&library.settings).unwrap_or_default(),
         })
     }
 
+    /// Method replaces the settings of the library with the id supplied.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    /// * `lib_id` - id of the library we are updating
+    /// * `settings` - the new settings of the library
+    pub async fn set_settings(
+        conn: &crate::DbConnection,
+        lib_id: i64,
+        settings: &LibrarySettings,
+    ) -> Result<usize, DatabaseError> {
+        let settings = serde_json::to_vec(settings).unwrap_or_default();
+
+        Ok(sqlx::query!(
+            "UPDATE library SET settings = ? WHERE id = ?",
+            settings,
+            lib_id
+        )
+        .execute(conn)
+        .await?
+        .rows_affected() as usize)
+    }
+
+    /// Method moves everything a library holds under `old_root` to `new_root`, ie after the media has
+    /// been moved to a new drive. The locations of the library and the paths of its files are
+    /// rewritten in a single transaction, so files stay matched. Returns the number of files
+    /// moved.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    /// * `lib_id` - id of the library we are relocating
+    /// * `old_root` - the directory the media used to be located in
+    /// * `new_root` - the directory the media is located in now
+    pub async fn relocate(
+        conn: &crate::DbConnection,
+        lib_id: i64,
+        old_root: &str,
+        new_root: &str,
+    ) -> Result<usize, DatabaseError> {
+        let old_root = old_root.trim_end_matches('/');
+        let new_root = new_root.trim_end_matches('/');
+
+        let mut tx = conn.begin().await?;
+
+        // NOTE: Paths are matched on whole components, so relocating `/mnt/a` leaves `/mnt/ab`
+        // alone.
+        sqlx::query!(
+            r#"UPDATE indexed_paths SET location = $1 || substr(location, length($2) + 1)
+            WHERE library_id = $3
+            AND (location = $2 OR substr(location, 1, length($2) + 1) = $2 || '/')"#,
+            new_root,
+            old_root,
+            lib_id
+        )
+        .execute(&mut tx)
+        .await?;
+
+        let rows = sqlx::query!(
+            r#"UPDATE mediafile SET target_file = $1 || substr(target_file, length($2) + 1)
+            WHERE library_id = $3
+            AND (target_file = $2 OR substr(target_file, 1, length($2) + 1) = $2 || '/')"#,
+            new_root,
+            old_root,
+            lib_id
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected() as usize;
+
+        tx.commit().await?;
+
+        Ok(rows)
+    }
+
     /// Method filters the database for a library with the id supplied and deletes it.
     ///
     /// # Arguments
@@ -142,6 +341,8 @@ pub struct InsertableLibrary {
     pub name: String,
     pub locations: Vec<String>,
     pub media_type: MediaType,
+    #[serde(default)]
+    pub settings: LibrarySettings,
 }
 
 impl InsertableLibrary {
@@ -150,11 +351,14 @@ impl InsertableLibrary {
     /// # Arguments
     /// * `conn` - [diesel connection](crate::DbConnection)
     pub async fn insert(&self, conn: &crate::DbConnection) -> Result<i64, DatabaseError> {
+        let settings = serde_json::to_vec(&self.settings).unwrap_or_default();
+
         let tx = conn.begin().await?;
         let lib_id = sqlx::query!(
-            r#"INSERT INTO library (name, media_type) VALUES ($1, $2)"#,
+            r#"INSERT INTO library (name, media_type, settings) VALUES ($1, $2, $3)"#,
             self.name,
-            self.media_type
+            self.media_type,
+            settings
         )
         .execute(conn)
         .await?
@@ -0,0 +1,63 @@
+use crate::DatabaseError;
+
+use std::time::SystemTime;
+
+/// Struct represents our attempts at matching a mediafile that was left unmatched once more.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchAttempt {
+    pub mediafile_id: i64,
+    /// Number of times we tried to match the file again.
+    pub attempts: i64,
+    /// Unix timestamp of the last attempt.
+    pub last_attempt: i64,
+}
+
+impl MatchAttempt {
+    /// Method returns the attempts recorded for the mediafiles of a library.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `library_id` - id of the library whose files we want
+    pub async fn get_by_lib(
+        conn: &crate::DbConnection,
+        library_id: i64,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            MatchAttempt,
+            r#"SELECT match_attempt.mediafile_id as "mediafile_id!", match_attempt.attempts,
+                match_attempt.last_attempt
+                FROM match_attempt
+                INNER JOIN mediafile ON mediafile.id = match_attempt.mediafile_id
+                WHERE mediafile.library_id = ?"#,
+            library_id
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
+    /// Method records another attempt at matching a mediafile, made right now.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `mediafile_id` - id of the mediafile we tried to match
+    pub async fn record(
+        conn: &crate::DbConnection,
+        mediafile_id: i64,
+    ) -> Result<(), DatabaseError> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        sqlx::query!(
+            "INSERT INTO match_attempt (mediafile_id, attempts, last_attempt) VALUES ($1, 1, $2)
+            ON CONFLICT (mediafile_id) DO UPDATE SET attempts = attempts + 1, last_attempt = $2",
+            mediafile_id,
+            now
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use crate::library::MediaType;
+use crate::DatabaseError;
+
+use std::time::SystemTime;
+
+/// Struct represents the metadata we found when matching a title, kept around so that the title
+/// can still be matched when our metadata providers cant be reached.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CachedMatch {
+    /// Key of the search, as returned by [`CachedMatch::key`].
+    pub key: String,
+    /// The metadata we found, serialized as json.
+    pub media: String,
+    /// Unix timestamp of when we found it.
+    pub fetched: i64,
+}
+
+impl CachedMatch {
+    /// Function returns the key a search for `title` released in `year` is cached under.
+    pub fn key(media_type: MediaType, title: &str, year: Option<i32>) -> String {
+        format!(
+            "{}:{}:{}",
+            media_type,
+            title.to_lowercase(),
+            year.map(|x| x.to_string()).unwrap_or_default()
+        )
+    }
+
+    /// Function returns the key the seasons of the tv show `id` are cached under.
+    pub fn seasons_key(id: u64) -> String {
+        format!("seasons:{}", id)
+    }
+
+    /// Method returns the cached match stored under `key`.
+    ///
+    /// # Arguments
+    /// * `conn` - db connection
+    /// * `key` - key of the search
+    pub async fn get(conn: &crate::DbConnection, key: &str) -> Result<Self, DatabaseError> {
+        Ok(sqlx::query_as!(
+            CachedMatch,
+            r#"SELECT key as "key!", media, fetched FROM match_cache WHERE key = ?"#,
+            key
+        )
+        .fetch_one(conn)
+        .await?)
+    }
+
+    /// Method stores `media` under `key`, replacing whatever was cached for it before.
+    ///
+    /// # Arguments
+    /// * `conn` - db connection
+    /// * `key` - key of the search
+    /// * `media` - metadata we found, serialized as json
+    pub async fn set(
+        conn: &crate::DbConnection,
+        key: &str,
+        media: &str,
+    ) -> Result<(), DatabaseError> {
+        let fetched = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        sqlx::query!(
+            "INSERT OR REPLACE INTO match_cache (key, media, fetched) VALUES ($1, $2, $3)",
+            key,
+            media,
+            fetched
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -115,6 +115,62 @@ impl Media {
             ).fetch_one(conn).await?)
     }
 
+    /// Method returns the TMDB id of a media, if we know it. Media matched with TheTVDB have none.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `id` - id of the media
+    pub async fn get_external_id(
+        conn: &crate::DbConnection,
+        id: i64,
+    ) -> Result<Option<i64>, DatabaseError> {
+        Ok(
+            sqlx::query_scalar!("SELECT external_id FROM _tblmedia WHERE id = ?", id)
+                .fetch_one(conn)
+                .await?,
+        )
+    }
+
+    /// Method returns the tagline and the runtime in minutes of a media, if we know them.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `id` - id of the media
+    pub async fn get_details(
+        conn: &crate::DbConnection,
+        id: i64,
+    ) -> Result<(Option<String>, Option<i64>), DatabaseError> {
+        let record = sqlx::query!("SELECT tagline, runtime FROM _tblmedia WHERE id = ?", id)
+            .fetch_one(conn)
+            .await?;
+
+        Ok((record.tagline, record.runtime))
+    }
+
+    /// Method sets the tagline and the runtime in minutes of a media.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `id` - id of the media
+    /// * `tagline` - tagline of the media
+    /// * `runtime` - runtime of the media in minutes
+    pub async fn set_details(
+        conn: &crate::DbConnection,
+        id: i64,
+        tagline: Option<String>,
+        runtime: Option<i64>,
+    ) -> Result<usize, DatabaseError> {
+        Ok(sqlx::query!(
+            "UPDATE _tblmedia SET tagline = ?, runtime = ? WHERE id = ?",
+            tagline,
+            runtime,
+            id
+        )
+        .execute(conn)
+        .await?
+        .rows_affected() as usize)
+    }
+
     /// Method returns the top rated medias
     pub async fn get_top_rated(
         conn: &crate::DbConnection,
@@ -263,6 +319,10 @@ pub struct InsertableMedia {
     pub poster: Option<i64>,
     pub backdrop: Option<i64>,
     pub media_type: MediaType,
+    /// Id of this media on TMDB, which TMDB dumps are keyed by as well.
+    pub external_id: Option<i64>,
+    /// Id of this media on TheTVDB, for tv shows and episodes matched with it.
+    pub tvdb_id: Option<i64>,
 }
 
 impl InsertableMedia {
@@ -281,8 +341,8 @@ impl InsertableMedia {
         }
 
         let id = sqlx::query!(
-            r#"INSERT INTO _tblmedia (library_id, name, description, rating, year, added, poster, backdrop, media_type)
-            VALUES ($1, $2, $3, $4, $5, $6,$7, $8, $9)
+            r#"INSERT INTO _tblmedia (library_id, name, description, rating, year, added, poster, backdrop, media_type, external_id, tvdb_id)
+            VALUES ($1, $2, $3, $4, $5, $6,$7, $8, $9, $10, $11)
             ON CONFLICT DO UPDATE
             SET name = $2
             RETURNING _tblmedia.id as "id!: i64"
@@ -295,7 +355,9 @@ impl InsertableMedia {
             self.added,
             self.poster,
             self.backdrop,
-            self.media_type
+            self.media_type,
+            self.external_id,
+            self.tvdb_id
         ).fetch_one(conn).await?.id;
 
         tx.commit().await?;
@@ -307,8 +369,8 @@ impl InsertableMedia {
     /// which are not indexed in the database.
     pub async fn insert_blind(&self, conn: &crate::DbConnection) -> Result<i64, DatabaseError> {
         Ok(sqlx::query!(
-            r#"INSERT INTO _tblmedia (library_id, name, description, rating, year, added, poster, backdrop, media_type)
-            VALUES ($1, $2, $3, $4, $5, $6,$7, $8, $9)"#,
+            r#"INSERT INTO _tblmedia (library_id, name, description, rating, year, added, poster, backdrop, media_type, external_id, tvdb_id)
+            VALUES ($1, $2, $3, $4, $5, $6,$7, $8, $9, $10, $11)"#,
             self.library_id,
             self.name,
             self.description,
@@ -317,7 +379,9 @@ impl InsertableMedia {
             self.added,
             self.poster,
             self.backdrop,
-            self.media_type
+            self.media_type,
+            self.external_id,
+            self.tvdb_id
         ).execute(conn).await?.last_insert_rowid())
     }
 }
@@ -1,12 +1,40 @@
 use crate::media::Media;
+use crate::song::InsertableSong;
+use crate::track::InsertableTrack;
 use crate::DatabaseError;
 
 use serde::Deserialize;
 use serde::Serialize;
 
+/// Enum holds what mounted a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MountSource {
+    /// Scans started by a user, ie when a library is created or a rescan is requested.
+    Manual,
+    /// The filesystem watcher picked up a new file.
+    Daemon,
+    /// Scans dim starts on its own, ie on startup.
+    Scheduled,
+    /// Files relinked while importing a library export.
+    Import,
+}
+
+impl MountSource {
+    /// Method returns the name of the source as stored in the db.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Manual => "manual",
+            Self::Daemon => "daemon",
+            Self::Scheduled => "scheduled",
+            Self::Import => "import",
+        }
+    }
+}
+
 /// MediaFile struct which represents a media file on the filesystem. This struct holds some basic
 /// information which the video player on the front end might require.
-#[derive(Serialize, PartialEq, Debug, Clone)]
+#[derive(Serialize, PartialEq, Debug, Clone, Default)]
 pub struct MediaFile {
     /// Unique identifier provided by postgres
     pub id: i64,
@@ -47,6 +75,60 @@ pub struct MediaFile {
     /// Flag which tells us if the file is corrupted or not. ie if ffprobe cant open the file and
     /// reports no metadata this flag will be set.
     pub corrupt: Option<bool>,
+
+    /// Release revision extracted from the filename. Originals are `0`, while PROPER/REPACK
+    /// releases and `v2` style version tags bump this so that they are preferred over the release
+    /// they replace.
+    pub revision: Option<i64>,
+    /// The exact filename, including its extension, that this mediafile was mounted from. Unlike
+    /// `raw_name` this is never touched by the filename parser.
+    pub original_filename: Option<String>,
+    /// Flag set when the extension of the file lies about its container, ie a `.avi` file that is
+    /// really a matroska file. `container` always holds the container detected by ffprobe.
+    pub container_mismatch: Option<bool>,
+    /// Number of channels of the primary audio stream, ie `8` for 7.1 and `2` for stereo.
+    pub audio_channels: Option<i64>,
+    /// Index of the audio stream that should be played by default, picked based on the preferred
+    /// audio languages of the library.
+    pub default_audio_stream: Option<i64>,
+    /// Index of the subtitle stream that should be shown by default, picked based on the preferred
+    /// subtitle languages of the library.
+    pub default_subtitle_stream: Option<i64>,
+    /// What mounted this file, one of `manual`, `daemon`, `scheduled` or `import`. Files mounted
+    /// before we kept track of this have no source.
+    pub mount_source: Option<String>,
+    /// Why this file cant be played as a video even though ffprobe could read it, either
+    /// `audio_only` or `zero_duration`. Unlike `corrupt` files, these are never matched.
+    pub problem: Option<String>,
+    /// Perceptual fingerprint of the video, made up of a 64 bit hash of each sampled frame
+    /// encoded as hex. Only computed when video fingerprints are enabled.
+    pub fingerprint: Option<String>,
+    /// Set when the file at this path was probed again and no longer looks like the media it is
+    /// matched with, ie because it was replaced by another film. Cleared once the file is matched again.
+    pub stale: Option<bool>,
+    /// Last time the file was modified, in seconds since the unix epoch, as of when we last probed it.
+    /// Unchanged files are skipped on rescans.
+    pub mtime: Option<i64>,
+    /// TMDB id of the media this file holds as found in a nfo file next to it, used to match the file
+    /// directly instead of searching by its title.
+    pub tmdb_id: Option<i64>,
+    /// IMDB id of the media this file holds as found in a nfo file next to it, ie `tt1375666`.
+    pub imdb_id: Option<String>,
+    /// Which part of a movie split over several files this file is, ie `2` for `Movie.cd2.mkv`.
+    pub part_number: Option<i64>,
+    /// Last episode held by a file holding several episodes, ie `3` for `Show.S01E01-E03.mkv`, in
+    /// which case `episode` is the first one.
+    pub episode_end: Option<i64>,
+    /// How confident the matcher was in the match of this file, on a scale from `0.0` to `1.0`.
+    /// Files matched before we kept track of this have no score.
+    pub match_score: Option<f64>,
+    /// Average frame rate of the primary video stream, ie `23.976`.
+    pub frame_rate: Option<f64>,
+    /// Bit depth of the primary video stream, ie `10` for most HDR releases.
+    pub bit_depth: Option<i64>,
+    /// HDR format of the primary video stream as told by its color transfer, either `hdr10` or
+    /// `hlg`. Files in SDR or whose color transfer is unknown have none.
+    pub hdr: Option<String>,
 }
 
 impl MediaFile {
@@ -87,7 +169,26 @@ impl MediaFile {
         .await?)
     }
 
-    /// Method returns all mediafiles associated with a Media object.
+    /// Method returns all mediafiles of a library that ffprobe marked as corrupt.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `library_id` - id of the library whose files we want
+    pub async fn get_corrupt_by_lib(
+        conn: &crate::DbConnection,
+        library_id: i64,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            MediaFile,
+            "SELECT * FROM mediafile WHERE library_id = ? AND corrupt = 1",
+            library_id
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
+    /// Method returns all mediafiles associated with a Media object. The files are ranked by their
+    /// release revision, thus the first file returned is the preferred version.
     ///
     /// # Arguments
     /// * `conn` - postgres connection
@@ -99,8 +200,10 @@ impl MediaFile {
         Ok(sqlx::query_as!(
             MediaFile,
             "SELECT mediafile.* FROM mediafile
-                INNER JOIN media ON media.id = mediafile.media_id
-                WHERE media.id = ?",
+                INNER JOIN media ON media.id = $1
+                WHERE mediafile.media_id = $1
+                OR mediafile.id IN (SELECT mediafile_id FROM mediafile_episode WHERE episode_id = $1)
+                ORDER BY COALESCE(mediafile.revision, 0) DESC, COALESCE(mediafile.part_number, 0) ASC, mediafile.id ASC",
             media_id
         )
         .fetch_all(conn)
@@ -146,6 +249,72 @@ impl MediaFile {
         .await?)
     }
 
+    /// Method returns every mediafile located in the directory `dir` or any of its
+    /// subdirectories. Paths are matched on whole components, so `/mnt/a` doesnt include
+    /// `/mnt/ab`.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `dir` - the directory we want the files of
+    pub async fn get_by_dir(
+        conn: &crate::DbConnection,
+        dir: &str,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        let dir = dir.trim_end_matches('/');
+
+        Ok(sqlx::query_as!(
+            MediaFile,
+            r#"SELECT * FROM mediafile WHERE substr(target_file, 1, length($1) + 1) = $1 || '/'"#,
+            dir
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
+    /// Method matches the part `self` of a multi-part movie with the media of another part of
+    /// the same movie that is matched already, if any. Parts are the same movie if they share
+    /// their library, title and year. The lookup and the update are done within a single
+    /// transaction so that parts matched concurrently cant end up with different medias. Returns
+    /// the id of the media the part was matched with.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    pub async fn join_matched_part(
+        &self,
+        conn: &crate::DbConnection,
+    ) -> Result<Option<i64>, DatabaseError> {
+        let mut tx = conn.begin().await?;
+
+        let media_id = sqlx::query!(
+            r#"SELECT media_id as "media_id!: i64" FROM mediafile
+                WHERE library_id = ? AND raw_name = ? AND raw_year IS ? AND id != ?
+                AND part_number IS NOT NULL AND media_id IS NOT NULL
+                ORDER BY part_number ASC
+                LIMIT 1"#,
+            self.library_id,
+            self.raw_name,
+            self.raw_year,
+            self.id
+        )
+        .fetch_optional(&mut tx)
+        .await?
+        .map(|x| x.media_id);
+
+        if let Some(media_id) = media_id {
+            sqlx::query!(
+                "UPDATE mediafile SET media_id = ?, stale = 0, match_score = 1.0 WHERE id = ?",
+                media_id,
+                self.id
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(media_id)
+    }
+
     /// Function will return the largest duration for a media.
     pub async fn get_largest_duration(
         conn: &crate::DbConnection,
@@ -175,6 +344,68 @@ impl MediaFile {
             .rows_affected() as usize)
     }
 
+    /// Method links the mediafile with the id supplied to the episode `episode_id`, for files
+    /// holding several episodes. The file is matched with its first episode through `media_id`.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `id` - id of the mediafile holding the episode
+    /// * `episode_id` - id of the episode
+    pub async fn link_episode(
+        conn: &crate::DbConnection,
+        id: i64,
+        episode_id: i64,
+    ) -> Result<usize, DatabaseError> {
+        Ok(sqlx::query!(
+            "INSERT OR IGNORE INTO mediafile_episode (mediafile_id, episode_id) VALUES (?, ?)",
+            id,
+            episode_id
+        )
+        .execute(conn)
+        .await?
+        .rows_affected() as usize)
+    }
+
+    /// Method unlinks the mediafile with the id supplied from every episode it was linked to with
+    /// [`link_episode`](Self::link_episode).
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `id` - id of the mediafile
+    pub async fn unlink_episodes(
+        conn: &crate::DbConnection,
+        id: i64,
+    ) -> Result<usize, DatabaseError> {
+        Ok(
+            sqlx::query!("DELETE FROM mediafile_episode WHERE mediafile_id = ?", id)
+                .execute(conn)
+                .await?
+                .rows_affected() as usize,
+        )
+    }
+
+    /// Method moves the mediafile with the id supplied into the library `library_id`. The media
+    /// the file was matched with belongs to its old library, so the file is unmatched.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `id` - id of the mediafile we are moving
+    /// * `library_id` - id of the library we are moving the file into
+    pub async fn move_to_library(
+        conn: &crate::DbConnection,
+        id: i64,
+        library_id: i64,
+    ) -> Result<usize, DatabaseError> {
+        Ok(sqlx::query!(
+            "UPDATE mediafile SET library_id = ?, media_id = NULL WHERE id = ?",
+            library_id,
+            id
+        )
+        .execute(conn)
+        .await?
+        .rows_affected() as usize)
+    }
+
     /// Function deletes all mediafiles with `library_id` of lib_id. This function is used when
     /// deleting a library with a sqlite backend.
     pub async fn delete_by_lib_id(
@@ -188,6 +419,59 @@ impl MediaFile {
                 .rows_affected() as usize,
         )
     }
+    /// Method sorts several versions of a media so that the preferred version comes first.
+    /// Versions are compared by each preference in `priority` in turn, versions that compare equal
+    /// keep their relative order.
+    ///
+    /// # Arguments
+    /// * `files` - versions of a single media
+    /// * `priority` - what we care about most when picking a version, most important first
+    pub fn sort_versions(files: &mut [Self], priority: &[VersionPreference]) {
+        files.sort_by(|a, b| {
+            priority
+                .iter()
+                .map(|x| x.score(b).cmp(&x.score(a)))
+                .find(|x| *x != std::cmp::Ordering::Equal)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+    }
+}
+
+/// Properties of a mediafile we can rank versions of a media by. Used by
+/// [`MediaFile::sort_versions`](MediaFile::sort_versions) to pick the default version of a media.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionPreference {
+    /// Prefer PROPER/REPACK releases over the releases they replace.
+    Revision,
+    /// Prefer higher video resolutions.
+    Video,
+    /// Prefer more audio channels, ie 7.1 over stereo.
+    AudioChannels,
+    /// Prefer lossless and object based audio codecs over lossy ones.
+    AudioCodec,
+}
+
+impl VersionPreference {
+    fn score(&self, file: &MediaFile) -> i64 {
+        match self {
+            Self::Revision => file.revision.unwrap_or(0),
+            Self::Video => file
+                .quality
+                .as_ref()
+                .and_then(|x| x.parse().ok())
+                .unwrap_or(0),
+            Self::AudioChannels => file.audio_channels.unwrap_or(0),
+            Self::AudioCodec => match file.audio.as_deref() {
+                Some("truehd") => 5,
+                Some("dts") | Some("flac") | Some("pcm_s16le") | Some("pcm_s24le") => 4,
+                Some("eac3") => 3,
+                Some("ac3") => 2,
+                Some(_) => 1,
+                None => 0,
+            },
+        }
+    }
 }
 
 /// Same as [`MediaFile`](MediaFile) except its missing the id field.
@@ -214,6 +498,29 @@ pub struct InsertableMediaFile {
     pub season: Option<i64>,
     /*** ***/
     pub corrupt: Option<bool>,
+    pub revision: Option<i64>,
+    pub original_filename: Option<String>,
+    pub container_mismatch: Option<bool>,
+    pub audio_channels: Option<i64>,
+    pub default_audio_stream: Option<i64>,
+    pub default_subtitle_stream: Option<i64>,
+    pub mount_source: Option<String>,
+    pub problem: Option<String>,
+    pub fingerprint: Option<String>,
+    pub stale: Option<bool>,
+    pub mtime: Option<i64>,
+    pub tmdb_id: Option<i64>,
+    pub imdb_id: Option<String>,
+    pub part_number: Option<i64>,
+    pub episode_end: Option<i64>,
+    pub match_score: Option<f64>,
+    pub frame_rate: Option<f64>,
+    pub bit_depth: Option<i64>,
+    pub hdr: Option<String>,
+    /// Audio and subtitle streams of the file, inserted along with it.
+    pub tracks: Vec<InsertableTrack>,
+    /// Tags of the file if it is a song, inserted along with it.
+    pub song: Option<InsertableSong>,
 }
 
 impl InsertableMediaFile {
@@ -222,13 +529,54 @@ impl InsertableMediaFile {
     /// # Arguments
     /// * `conn` - postgres connection
     pub async fn insert(&self, conn: &crate::DbConnection) -> Result<i64, DatabaseError> {
-        let tx = conn.begin().await?;
+        let mut tx = conn.begin().await?;
+        let id = self.insert_with(&mut tx).await?;
+        tx.commit().await?;
+
+        Ok(id)
+    }
+
+    /// Method inserts several mediafiles within a single transaction, which is a lot faster than
+    /// inserting them one at a time as every transaction has to hit the disk. Returns the new
+    /// mediafiles in the order they were supplied. If any of them cant be inserted, none of them
+    /// are.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `files` - mediafiles to insert
+    pub async fn insert_many(
+        conn: &crate::DbConnection,
+        files: &[Self],
+    ) -> Result<Vec<MediaFile>, DatabaseError> {
+        let mut tx = conn.begin().await?;
+        let mut inserted = Vec::with_capacity(files.len());
+
+        for file in files {
+            let id = file.insert_with(&mut tx).await?;
+            let row = sqlx::query_as!(MediaFile, "SELECT * FROM mediafile WHERE id = ?", id)
+                .fetch_one(&mut tx)
+                .await?;
+
+            inserted.push(row);
+        }
+
+        tx.commit().await?;
+
+        Ok(inserted)
+    }
 
+    async fn insert_with(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    ) -> Result<i64, DatabaseError> {
         let id = sqlx::query!(
             r#"
             INSERT INTO mediafile (media_id, library_id, target_file, raw_name, raw_year, quality,
-            codec, container, audio, original_resolution, duration, episode, season, corrupt)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+            codec, container, audio, original_resolution, duration, episode, season, corrupt,
+            revision, original_filename, container_mismatch, audio_channels, default_audio_stream,
+            default_subtitle_stream, mount_source, problem, fingerprint, stale, mtime, tmdb_id, imdb_id, part_number, episode_end, match_score, frame_rate, bit_depth, hdr)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18,
+            $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30, $31, $32, $33)
         "#,
             self.media_id,
             self.library_id,
@@ -243,13 +591,38 @@ impl InsertableMediaFile {
             self.duration,
             self.episode,
             self.season,
-            self.corrupt
+            self.corrupt,
+            self.revision,
+            self.original_filename,
+            self.container_mismatch,
+            self.audio_channels,
+            self.default_audio_stream,
+            self.default_subtitle_stream,
+            self.mount_source,
+            self.problem,
+            self.fingerprint,
+            self.stale,
+            self.mtime,
+            self.tmdb_id,
+            self.imdb_id,
+            self.part_number,
+            self.episode_end,
+            self.match_score,
+            self.frame_rate,
+            self.bit_depth,
+            self.hdr
         )
-        .execute(conn)
+        .execute(&mut *tx)
         .await?
         .last_insert_rowid();
 
-        tx.commit().await?;
+        for track in self.tracks.iter() {
+            track.insert(&mut *tx, id).await?;
+        }
+
+        if let Some(song) = self.song.as_ref() {
+            song.insert(&mut *tx, id).await?;
+        }
 
         Ok(id)
     }
@@ -277,6 +650,25 @@ pub struct UpdateMediaFile {
     pub season: Option<i64>,
     /*** ***/
     pub corrupt: Option<bool>,
+    pub revision: Option<i64>,
+    pub original_filename: Option<String>,
+    pub container_mismatch: Option<bool>,
+    pub audio_channels: Option<i64>,
+    pub default_audio_stream: Option<i64>,
+    pub default_subtitle_stream: Option<i64>,
+    pub mount_source: Option<String>,
+    pub problem: Option<String>,
+    pub fingerprint: Option<String>,
+    pub stale: Option<bool>,
+    pub mtime: Option<i64>,
+    pub tmdb_id: Option<i64>,
+    pub imdb_id: Option<String>,
+    pub part_number: Option<i64>,
+    pub episode_end: Option<i64>,
+    pub match_score: Option<f64>,
+    pub frame_rate: Option<f64>,
+    pub bit_depth: Option<i64>,
+    pub hdr: Option<String>,
 }
 
 impl UpdateMediaFile {
@@ -306,7 +698,26 @@ impl UpdateMediaFile {
             "UPDATE mediafile SET duration = ? WHERE id = ?" => (self.duration, id),
             "UPDATE mediafile SET episode = ? WHERE id = ?" => (self.episode, id),
             "UPDATE mediafile SET season = ? WHERE id = ?" => (self.season, id),
-            "UPDATE mediafile SET corrupt = ? WHERE id = ?" => (self.corrupt, id)
+            "UPDATE mediafile SET corrupt = ? WHERE id = ?" => (self.corrupt, id),
+            "UPDATE mediafile SET revision = ? WHERE id = ?" => (self.revision, id),
+            "UPDATE mediafile SET original_filename = ? WHERE id = ?" => (self.original_filename, id),
+            "UPDATE mediafile SET container_mismatch = ? WHERE id = ?" => (self.container_mismatch, id),
+            "UPDATE mediafile SET audio_channels = ? WHERE id = ?" => (self.audio_channels, id),
+            "UPDATE mediafile SET default_audio_stream = ? WHERE id = ?" => (self.default_audio_stream, id),
+            "UPDATE mediafile SET default_subtitle_stream = ? WHERE id = ?" => (self.default_subtitle_stream, id),
+            "UPDATE mediafile SET mount_source = ? WHERE id = ?" => (self.mount_source, id),
+            "UPDATE mediafile SET problem = ? WHERE id = ?" => (self.problem, id),
+            "UPDATE mediafile SET fingerprint = ? WHERE id = ?" => (self.fingerprint, id),
+            "UPDATE mediafile SET stale = ? WHERE id = ?" => (self.stale, id),
+            "UPDATE mediafile SET mtime = ? WHERE id = ?" => (self.mtime, id),
+            "UPDATE mediafile SET tmdb_id = ? WHERE id = ?" => (self.tmdb_id, id),
+            "UPDATE mediafile SET imdb_id = ? WHERE id = ?" => (self.imdb_id, id),
+            "UPDATE mediafile SET part_number = ? WHERE id = ?" => (self.part_number, id),
+            "UPDATE mediafile SET episode_end = ? WHERE id = ?" => (self.episode_end, id),
+            "UPDATE mediafile SET match_score = ? WHERE id = ?" => (self.match_score, id),
+            "UPDATE mediafile SET frame_rate = ? WHERE id = ?" => (self.frame_rate, id),
+            "UPDATE mediafile SET bit_depth = ? WHERE id = ?" => (self.bit_depth, id),
+            "UPDATE mediafile SET hdr = ? WHERE id = ?" => (self.hdr, id)
         );
 
         tx.commit().await?;
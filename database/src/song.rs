@@ -0,0 +1,85 @@
+use crate::DatabaseError;
+
+use serde::Serialize;
+
+/// Struct represents the tags of a audio file in a music library.
+#[derive(Clone, Serialize, Debug, PartialEq, Default)]
+pub struct Song {
+    /// Id of the mediafile the tags were read from.
+    pub mediafile_id: i64,
+    pub artist: Option<String>,
+    /// Artist the album is credited to, which differs from `artist` on compilations.
+    pub album_artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    /// Number of the track on its disc.
+    pub track_number: Option<i64>,
+    pub disc_number: Option<i64>,
+}
+
+impl Song {
+    /// Method returns the tags of a mediafile, if it is a song.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `mediafile_id` - id of the mediafile
+    pub async fn get_by_mediafile(
+        conn: &crate::DbConnection,
+        mediafile_id: i64,
+    ) -> Result<Option<Self>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            Song,
+            "SELECT * FROM song WHERE mediafile_id = ?",
+            mediafile_id
+        )
+        .fetch_optional(conn)
+        .await?)
+    }
+
+    /// Method returns the artist the album of the song is credited to, falling back to the artist
+    /// of the song itself.
+    pub fn credited_artist(&self) -> Option<&str> {
+        self.album_artist
+            .as_deref()
+            .or_else(|| self.artist.as_deref())
+    }
+}
+
+/// Struct represents the tags of a song that has yet to be inserted.
+#[derive(Clone, Serialize, Debug, PartialEq, Default)]
+pub struct InsertableSong {
+    pub artist: Option<String>,
+    pub album_artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub track_number: Option<i64>,
+    pub disc_number: Option<i64>,
+}
+
+impl InsertableSong {
+    /// Method inserts the tags as belonging to the mediafile `mediafile_id`.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `mediafile_id` - id of the mediafile the tags were read from
+    pub async fn insert<'e, E>(&self, conn: E, mediafile_id: i64) -> Result<usize, DatabaseError>
+    where
+        E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+    {
+        Ok(sqlx::query!(
+            r#"INSERT INTO song (mediafile_id, artist, album_artist, album, title, track_number,
+            disc_number)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)"#,
+            mediafile_id,
+            self.artist,
+            self.album_artist,
+            self.album,
+            self.title,
+            self.track_number,
+            self.disc_number
+        )
+        .execute(conn)
+        .await?
+        .rows_affected() as usize)
+    }
+}
@@ -0,0 +1,67 @@
+use crate::DatabaseError;
+
+use serde::Serialize;
+
+/// Struct represents a subtitle file kept next to a mediafile, ie `Movie.en.srt`.
+#[derive(Clone, Serialize, Debug, PartialEq, Default)]
+pub struct ExternalSubtitle {
+    pub id: i64,
+    /// Id of the mediafile these subtitles belong to.
+    pub mediafile_id: i64,
+    /// Absolute path to the subtitle file.
+    pub path: String,
+    /// Language of the subtitles as found in the name of the file, ie `en` or `English`.
+    pub language: Option<String>,
+    /// Whether the subtitles are forced, ie only cover foreign dialogue.
+    pub forced: bool,
+}
+
+impl ExternalSubtitle {
+    /// Method returns the external subtitles of a mediafile.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `mediafile_id` - id of the mediafile
+    pub async fn get_by_mediafile(
+        conn: &crate::DbConnection,
+        mediafile_id: i64,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            ExternalSubtitle,
+            "SELECT * FROM external_subtitle WHERE mediafile_id = ? ORDER BY path",
+            mediafile_id
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+}
+
+/// Struct represents a external subtitle file that has yet to be inserted.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct InsertableExternalSubtitle {
+    pub mediafile_id: i64,
+    pub path: String,
+    pub language: Option<String>,
+    pub forced: bool,
+}
+
+impl InsertableExternalSubtitle {
+    /// Method inserts the subtitle file, unless it is already known for its mediafile. Returns the
+    /// number of rows inserted.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    pub async fn insert(&self, conn: &crate::DbConnection) -> Result<usize, DatabaseError> {
+        Ok(sqlx::query!(
+            "INSERT OR IGNORE INTO external_subtitle (mediafile_id, path, language, forced)
+            VALUES ($1, $2, $3, $4)",
+            self.mediafile_id,
+            self.path,
+            self.language,
+            self.forced
+        )
+        .execute(conn)
+        .await?
+        .rows_affected() as usize)
+    }
+}
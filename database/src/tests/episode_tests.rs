@@ -231,3 +231,174 @@ async fn test_update() {
     let result = episode::Episode::get(conn, tv, season, 3).await.unwrap();
     assert_eq!(result.id, _episode);
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_numbers_of_tv() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let _lib = create_test_library(conn).await;
+    let tv = insert_media(conn).await;
+    tv::TVShow::insert(conn, tv).await.unwrap();
+
+    for season_number in 1..=2 {
+        let season = season::InsertableSeason {
+            season_number,
+            ..Default::default()
+        }
+        .insert(conn, tv)
+        .await
+        .unwrap();
+
+        for &i in &[3, 1] {
+            let _episode = episode::InsertableEpisode {
+                media: media::InsertableMedia {
+                    library_id: _lib,
+                    name: format!("TestEpisode{}{}", season_number, i),
+                    ..Default::default()
+                },
+                seasonid: season,
+                episode: i,
+            }
+            .insert(conn)
+            .await
+            .unwrap();
+        }
+    }
+
+    let result = episode::Episode::get_numbers_of_tv(conn, tv).await.unwrap();
+    assert_eq!(result, vec![(1, 1), (1, 3), (2, 1), (2, 3)]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_link_episode() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let _lib = create_test_library(conn).await;
+    let tv = insert_media(conn).await;
+    tv::TVShow::insert(conn, tv).await.unwrap();
+
+    let season = season::InsertableSeason {
+        season_number: 1,
+        ..Default::default()
+    }
+    .insert(conn, tv)
+    .await
+    .unwrap();
+
+    let mut episodes = Vec::new();
+
+    for number in 1..=2 {
+        let episode = episode::InsertableEpisode {
+            media: media::InsertableMedia {
+                library_id: _lib,
+                name: format!("TestEpisode{}", number),
+                ..Default::default()
+            },
+            seasonid: season,
+            episode: number,
+        }
+        .insert(conn)
+        .await
+        .unwrap();
+
+        episodes.push(episode);
+    }
+
+    let file = crate::mediafile::InsertableMediaFile {
+        library_id: _lib,
+        media_id: Some(episodes[0]),
+        target_file: "/dev/null/S01E01-E02.mkv".into(),
+        raw_name: "Test".into(),
+        episode: Some(1),
+        episode_end: Some(2),
+        ..Default::default()
+    }
+    .insert(conn)
+    .await
+    .unwrap();
+
+    crate::mediafile::MediaFile::link_episode(conn, file, episodes[1])
+        .await
+        .unwrap();
+
+    for episode in episodes {
+        let result = crate::mediafile::MediaFile::get_of_media(conn, episode)
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].id, file);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_delete_unused_of_tv() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let _lib = create_test_library(conn).await;
+    let tv = insert_media(conn).await;
+    tv::TVShow::insert(conn, tv).await.unwrap();
+
+    let mut episodes = Vec::new();
+
+    for (season_number, number) in vec![(1, 1), (1, 2), (1, 3), (2, 1)] {
+        let season = season::InsertableSeason {
+            season_number,
+            ..Default::default()
+        }
+        .insert(conn, tv)
+        .await
+        .unwrap();
+
+        let episode = episode::InsertableEpisode {
+            media: media::InsertableMedia {
+                library_id: _lib,
+                name: format!("TestEpisode{}x{}", season_number, number),
+                ..Default::default()
+            },
+            seasonid: season,
+            episode: number,
+        }
+        .insert(conn)
+        .await
+        .unwrap();
+
+        episodes.push(episode);
+    }
+
+    let file = crate::mediafile::InsertableMediaFile {
+        library_id: _lib,
+        media_id: Some(episodes[0]),
+        target_file: "/dev/null/S01E01-E02.mkv".into(),
+        raw_name: "Test".into(),
+        ..Default::default()
+    }
+    .insert(conn)
+    .await
+    .unwrap();
+
+    crate::mediafile::MediaFile::link_episode(conn, file, episodes[1])
+        .await
+        .unwrap();
+
+    let deleted = episode::Episode::delete_unused_of_tv(conn, tv)
+        .await
+        .unwrap();
+    assert_eq!(deleted, 2);
+
+    let result = episode::Episode::get_numbers_of_tv(conn, tv).await.unwrap();
+    assert_eq!(result, vec![(1, 1), (1, 2)]);
+
+    let result = season::Season::get_all(conn, tv).await.unwrap();
+    assert_eq!(result.len(), 1);
+
+    crate::mediafile::MediaFile::unlink_episodes(conn, file)
+        .await
+        .unwrap();
+
+    let deleted = episode::Episode::delete_unused_of_tv(conn, tv)
+        .await
+        .unwrap();
+    assert_eq!(deleted, 1);
+
+    let result = crate::mediafile::MediaFile::get_of_media(conn, episodes[0])
+        .await
+        .unwrap();
+    assert_eq!(result.len(), 1);
+}
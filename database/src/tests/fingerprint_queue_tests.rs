@@ -0,0 +1,52 @@
+use crate::fingerprint_queue::FingerprintQueue;
+use crate::get_conn_memory;
+use crate::mediafile;
+
+use super::library_tests::create_test_library;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_push_peek_and_remove() {
+    let conn = get_conn_memory().await.unwrap();
+    let _library = create_test_library(&conn).await;
+
+    assert!(FingerprintQueue::peek(&conn).await.unwrap().is_none());
+
+    let mut ids = Vec::new();
+
+    for target_file in &["/dev/null/b", "/dev/null/a"] {
+        let id = mediafile::InsertableMediaFile {
+            library_id: 1,
+            target_file: target_file.to_string(),
+            raw_name: "Test".into(),
+            ..Default::default()
+        }
+        .insert(&conn)
+        .await
+        .unwrap();
+
+        ids.push(id);
+    }
+
+    assert_eq!(FingerprintQueue::push(&conn, ids[0]).await.unwrap(), 1);
+    assert_eq!(FingerprintQueue::push(&conn, ids[1]).await.unwrap(), 1);
+    // NOTE: Rescans queue the same files again.
+    assert_eq!(FingerprintQueue::push(&conn, ids[0]).await.unwrap(), 0);
+
+    // NOTE: Files are fingerprinted in the order they were queued in, and stay queued until they
+    // are removed.
+    let next = FingerprintQueue::peek(&conn).await.unwrap().unwrap();
+    assert_eq!(next.id, ids[0]);
+    assert_eq!(
+        FingerprintQueue::peek(&conn).await.unwrap().unwrap().id,
+        ids[0]
+    );
+
+    assert_eq!(FingerprintQueue::remove(&conn, ids[0]).await.unwrap(), 1);
+
+    let next = FingerprintQueue::peek(&conn).await.unwrap().unwrap();
+    assert_eq!(next.id, ids[1]);
+    assert_eq!(next.target_file, "/dev/null/a");
+
+    mediafile::MediaFile::delete(&conn, ids[1]).await.unwrap();
+    assert!(FingerprintQueue::peek(&conn).await.unwrap().is_none());
+}
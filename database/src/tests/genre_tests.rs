@@ -38,6 +38,8 @@ async fn test_get_by_media() {
         poster: None,
         backdrop: None,
         media_type: library::MediaType::Movie,
+        external_id: None,
+        tvdb_id: None,
     };
 
     let media_id = media.insert(conn).await.unwrap();
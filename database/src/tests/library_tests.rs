@@ -1,5 +1,6 @@
 use crate::get_conn_memory;
 use crate::library;
+use crate::mediafile;
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
 
@@ -7,8 +8,9 @@ pub async fn create_test_library(conn: &crate::DbConnection) -> i64 {
     static _LIB: AtomicU64 = AtomicU64::new(0);
     let lib = library::InsertableLibrary {
         name: format!("test{}", _LIB.load(Ordering::Relaxed)),
-        location: format!("/dev/null{}", _LIB.load(Ordering::Relaxed)),
+        locations: vec![format!("/dev/null{}", _LIB.load(Ordering::Relaxed))],
         media_type: library::MediaType::Movie,
+        settings: Default::default(),
     };
 
     _LIB.fetch_add(1, Ordering::SeqCst);
@@ -54,3 +56,98 @@ async fn test_delete() {
     let rows = library::Library::delete(&conn, id).await.unwrap();
     assert_eq!(rows, 1);
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_set_settings() {
+    let conn = get_conn_memory().await.unwrap();
+    let id = create_test_library(&conn).await;
+
+    let result = library::Library::get_one(&conn, id).await.unwrap();
+    assert_eq!(result.settings, library::LibrarySettings::default());
+
+    let settings = library::LibrarySettings {
+        include_hidden: true,
+        disk_type: library::DiskType::Hdd,
+        probe_extensionless: true,
+        audio_languages: vec!["jpn".into()],
+        subtitle_languages: vec!["eng".into()],
+        min_age: Some(300),
+        max_age: None,
+        prefer_anime_agent: true,
+        extensions: vec!["mkv".into(), "m2ts".into()],
+        tv_agent: library::TvAgent::Tvdb,
+        absolute_numbering: true,
+        ignore_patterns: vec!["**/sample/**".into()],
+        min_size: 0,
+        match_threshold: 0.75,
+        filename_pattern: Some(r"^(?P<year>\d{4}) - (?P<title>.+?) - .*$".into()),
+        webhook_url: Some("http://localhost:8080/scanned".into()),
+        metadata_language: Some("de-DE".into()),
+    };
+
+    library::Library::set_settings(&conn, id, &settings)
+        .await
+        .unwrap();
+
+    let result = library::Library::get_one(&conn, id).await.unwrap();
+    assert_eq!(result.settings, settings);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_relocate() {
+    let conn = get_conn_memory().await.unwrap();
+    let id = create_test_library(&conn).await;
+    let root = library::Library::get_one(&conn, id)
+        .await
+        .unwrap()
+        .locations[0]
+        .clone();
+
+    for file in &["movie.mkv", "show/episode.mkv"] {
+        mediafile::InsertableMediaFile {
+            library_id: id,
+            target_file: format!("{}/{}", root, file),
+            raw_name: "Test".into(),
+            ..Default::default()
+        }
+        .insert(&conn)
+        .await
+        .unwrap();
+    }
+
+    // NOTE: Shares a prefix with the root but isnt located in it.
+    mediafile::InsertableMediaFile {
+        library_id: id,
+        target_file: format!("{}1/movie.mkv", root),
+        raw_name: "Test".into(),
+        ..Default::default()
+    }
+    .insert(&conn)
+    .await
+    .unwrap();
+
+    let rows = library::Library::relocate(&conn, id, &root, "/mnt/media/")
+        .await
+        .unwrap();
+    assert_eq!(rows, 2);
+
+    let result = library::Library::get_one(&conn, id).await.unwrap();
+    assert_eq!(result.locations, vec!["/mnt/media".to_string()]);
+
+    let mut files = mediafile::MediaFile::get_by_lib(&conn, id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|x| x.target_file)
+        .collect::<Vec<_>>();
+    files.sort();
+
+    assert_eq!(
+        files,
+        vec![
+            format!("{}1/movie.mkv", root),
+            "/mnt/media/movie.mkv".to_string(),
+            "/mnt/media/show/episode.mkv".to_string(),
+        ]
+    );
+}
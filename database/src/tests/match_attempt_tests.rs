@@ -0,0 +1,26 @@
+use crate::get_conn_memory;
+use crate::match_attempt::MatchAttempt;
+
+use super::library_tests::create_test_library;
+use super::mediafile_tests::insert_mediafile;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_record_and_get_by_lib() {
+    let conn = get_conn_memory().await.unwrap();
+    let _library = create_test_library(&conn).await;
+    let mediafile = insert_mediafile(&conn).await;
+
+    assert!(MatchAttempt::get_by_lib(&conn, 1).await.unwrap().is_empty());
+
+    MatchAttempt::record(&conn, mediafile).await.unwrap();
+    MatchAttempt::record(&conn, mediafile).await.unwrap();
+
+    let result = MatchAttempt::get_by_lib(&conn, 1).await.unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].mediafile_id, mediafile);
+    assert_eq!(result[0].attempts, 2);
+    assert!(result[0].last_attempt > 0);
+
+    // NOTE: Attempts are only listed for the library the file is in.
+    assert!(MatchAttempt::get_by_lib(&conn, 2).await.unwrap().is_empty());
+}
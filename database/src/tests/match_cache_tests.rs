@@ -0,0 +1,22 @@
+use crate::get_conn_memory;
+use crate::library::MediaType;
+use crate::match_cache::CachedMatch;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_and_set() {
+    let conn = get_conn_memory().await.unwrap();
+    let key = CachedMatch::key(MediaType::Movie, "Inception", Some(2010));
+    assert_eq!(key, "movie:inception:2010");
+    assert_eq!(CachedMatch::key(MediaType::Tv, "Dark", None), "tv:dark:");
+    assert_eq!(CachedMatch::seasons_key(70523), "seasons:70523");
+
+    assert!(CachedMatch::get(&conn, &key).await.is_err());
+
+    CachedMatch::set(&conn, &key, "{\"id\":1}").await.unwrap();
+    let result = CachedMatch::get(&conn, &key).await.unwrap();
+    assert_eq!(result.media, "{\"id\":1}");
+
+    CachedMatch::set(&conn, &key, "{\"id\":2}").await.unwrap();
+    let result = CachedMatch::get(&conn, &key).await.unwrap();
+    assert_eq!(result.media, "{\"id\":2}");
+}
@@ -17,6 +17,8 @@ pub async fn insert_media(conn: &crate::DbConnection) -> i64 {
         poster: None,
         backdrop: None,
         media_type: library::MediaType::Movie,
+        external_id: None,
+        tvdb_id: None,
     };
 
     media.insert(conn).await.unwrap()
@@ -34,6 +36,8 @@ pub async fn insert_many(conn: &crate::DbConnection, n: usize) {
             poster: None,
             backdrop: None,
             media_type: library::MediaType::Movie,
+            external_id: None,
+            tvdb_id: None,
         };
 
         media.insert(conn).await.unwrap();
@@ -142,6 +146,8 @@ async fn test_blind_insert() {
         poster: None,
         backdrop: None,
         media_type: library::MediaType::Episode,
+        external_id: None,
+        tvdb_id: None,
     };
 
     let result = media.clone().insert_blind(conn).await.unwrap();
@@ -166,6 +172,8 @@ async fn test_update() {
         poster: None,
         backdrop: None,
         media_type: library::MediaType::Movie,
+        external_id: None,
+        tvdb_id: None,
     };
 
     let media_id = media.insert(conn).await.unwrap();
@@ -182,3 +190,21 @@ async fn test_update() {
     assert_eq!(result.name, "TestMedia2".to_string());
     assert_eq!(result.rating, Some(5));
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_details() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let _library_id = create_test_library(conn).await;
+    let media_id = insert_media(conn).await;
+
+    let result = media::Media::get_details(conn, media_id).await.unwrap();
+    assert_eq!(result, (None, None));
+
+    let rows = media::Media::set_details(conn, media_id, Some("Tagline".into()), Some(148))
+        .await
+        .unwrap();
+    assert_eq!(rows, 1);
+
+    let result = media::Media::get_details(conn, media_id).await.unwrap();
+    assert_eq!(result, (Some("Tagline".into()), Some(148)));
+}
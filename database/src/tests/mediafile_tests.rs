@@ -59,6 +59,74 @@ async fn test_get_by_lib() {
     assert_eq!(result.len(), 10);
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_insert_many() {
+    let conn = get_conn_memory().await.unwrap();
+    let id = create_test_library(&conn).await;
+
+    let files = (0..3)
+        .map(|i| mediafile::InsertableMediaFile {
+            library_id: 1,
+            target_file: format!("/dev/null/{}", i),
+            raw_name: "Test".into(),
+            ..Default::default()
+        })
+        .collect::<Vec<_>>();
+
+    let inserted = mediafile::InsertableMediaFile::insert_many(&conn, &files)
+        .await
+        .unwrap();
+    assert_eq!(inserted.len(), 3);
+
+    for (i, mfile) in inserted.into_iter().enumerate() {
+        assert_eq!(mfile.target_file, format!("/dev/null/{}", i));
+
+        let result = mediafile::MediaFile::get_one(&conn, mfile.id)
+            .await
+            .unwrap();
+        assert_eq!(result.target_file, mfile.target_file);
+    }
+
+    // NOTE: A single duplicate rolls back the whole batch.
+    let files = vec![
+        mediafile::InsertableMediaFile {
+            library_id: 1,
+            target_file: "/dev/null/3".into(),
+            raw_name: "Test".into(),
+            ..Default::default()
+        },
+        files[0].clone(),
+    ];
+
+    let result = mediafile::InsertableMediaFile::insert_many(&conn, &files).await;
+    assert!(result.unwrap_err().is_unique_violation());
+
+    let result = mediafile::MediaFile::get_by_lib(&conn, id).await.unwrap();
+    assert_eq!(result.len(), 3);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_insert_duplicate_target_file() {
+    let conn = get_conn_memory().await.unwrap();
+    let id = create_test_library(&conn).await;
+
+    let _mfile_id = insert_mediafile(&conn).await;
+
+    let result = mediafile::InsertableMediaFile {
+        library_id: 1,
+        target_file: "/dev/null".into(),
+        raw_name: "Test".into(),
+        ..Default::default()
+    }
+    .insert(&conn)
+    .await;
+
+    assert!(result.unwrap_err().is_unique_violation());
+
+    let result = mediafile::MediaFile::get_by_lib(&conn, id).await.unwrap();
+    assert_eq!(result.len(), 1);
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_get_by_lib_null_media() {
     let conn = get_conn_memory().await.unwrap();
@@ -74,6 +142,29 @@ async fn test_get_by_lib_null_media() {
     // TODO: check that mfiles with media_id dont get returned
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_corrupt_by_lib() {
+    let conn = get_conn_memory().await.unwrap();
+    let id = create_test_library(&conn).await;
+
+    let _mfile_id = insert_mediafile(&conn).await;
+    let corrupt = mediafile::InsertableMediaFile {
+        library_id: id,
+        target_file: "/dev/corrupt".into(),
+        raw_name: "Test".into(),
+        corrupt: Some(true),
+        ..Default::default()
+    };
+    let corrupt_id = corrupt.insert(&conn).await.unwrap();
+
+    let result = mediafile::MediaFile::get_corrupt_by_lib(&conn, id)
+        .await
+        .unwrap();
+
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].id, corrupt_id);
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_get_one() {
     let conn = get_conn_memory().await.unwrap();
@@ -160,3 +251,173 @@ async fn test_get_of_media() {
     assert_eq!(result[0].media_id, Some(media_id));
     assert_eq!(result[0].id, mfile);
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_of_media_prefers_revision() {
+    let conn = get_conn_memory().await.unwrap();
+    let _ = create_test_library(&conn).await;
+    let media_id = super::media_tests::insert_media(&conn).await;
+    let original = insert_mediafile_with_mediaid(&conn, media_id).await;
+
+    let proper = mediafile::InsertableMediaFile {
+        library_id: 1,
+        target_file: "/dev/null/proper".into(),
+        raw_name: "Test".into(),
+        media_id: Some(media_id),
+        revision: Some(1),
+        ..Default::default()
+    }
+    .insert(&conn)
+    .await
+    .unwrap();
+
+    let result = mediafile::MediaFile::get_of_media(&conn, media_id)
+        .await
+        .unwrap();
+    assert_eq!(result.len(), 2);
+    assert_eq!(result[0].id, proper);
+    assert_eq!(result[1].id, original);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_sort_versions() {
+    let conn = get_conn_memory().await.unwrap();
+    let id = create_test_library(&conn).await;
+
+    let versions = vec![
+        ("/dev/null/4k", "2160", "aac", 2),
+        ("/dev/null/1080p", "1080", "truehd", 8),
+    ];
+
+    for (target_file, quality, audio, audio_channels) in versions {
+        mediafile::InsertableMediaFile {
+            library_id: id,
+            target_file: target_file.into(),
+            raw_name: "Test".into(),
+            quality: Some(quality.into()),
+            audio: Some(audio.into()),
+            audio_channels: Some(audio_channels),
+            ..Default::default()
+        }
+        .insert(&conn)
+        .await
+        .unwrap();
+    }
+
+    let mut result = mediafile::MediaFile::get_by_lib(&conn, id).await.unwrap();
+
+    mediafile::MediaFile::sort_versions(&mut result, &[mediafile::VersionPreference::Video]);
+    assert_eq!(result[0].target_file, "/dev/null/4k");
+
+    mediafile::MediaFile::sort_versions(
+        &mut result,
+        &[
+            mediafile::VersionPreference::AudioChannels,
+            mediafile::VersionPreference::Video,
+        ],
+    );
+    assert_eq!(result[0].target_file, "/dev/null/1080p");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_join_matched_part() {
+    let conn = get_conn_memory().await.unwrap();
+    let _ = create_test_library(&conn).await;
+    let media_id = super::media_tests::insert_media(&conn).await;
+
+    let part =
+        |target_file: &str, raw_year, part_number, media_id| mediafile::InsertableMediaFile {
+            library_id: 1,
+            target_file: target_file.into(),
+            raw_name: "Test".into(),
+            raw_year,
+            media_id,
+            part_number: Some(part_number),
+            ..Default::default()
+        };
+
+    let unmatched = part("/dev/null/cd1", Some(2020), 1, None)
+        .insert(&conn)
+        .await
+        .unwrap();
+    let unmatched = mediafile::MediaFile::get_one(&conn, unmatched)
+        .await
+        .unwrap();
+
+    assert_eq!(unmatched.join_matched_part(&conn).await.unwrap(), None);
+
+    // NOTE: Parts of a remake share the title, but not the year.
+    part("/dev/null/remake/cd2", Some(1990), 2, Some(media_id))
+        .insert(&conn)
+        .await
+        .unwrap();
+
+    assert_eq!(unmatched.join_matched_part(&conn).await.unwrap(), None);
+
+    part("/dev/null/cd2", Some(2020), 2, Some(media_id))
+        .insert(&conn)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        unmatched.join_matched_part(&conn).await.unwrap(),
+        Some(media_id)
+    );
+
+    let joined = mediafile::MediaFile::get_one(&conn, unmatched.id)
+        .await
+        .unwrap();
+    assert_eq!(joined.media_id, Some(media_id));
+    assert_eq!(joined.match_score, Some(1.0));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_by_dir() {
+    let conn = get_conn_memory().await.unwrap();
+    let _ = create_test_library(&conn).await;
+
+    for file in &[
+        "/mnt/a/movie.mkv",
+        "/mnt/a/show/episode.mkv",
+        "/mnt/ab/movie.mkv",
+    ] {
+        mediafile::InsertableMediaFile {
+            library_id: 1,
+            target_file: file.to_string(),
+            raw_name: "Test".into(),
+            ..Default::default()
+        }
+        .insert(&conn)
+        .await
+        .unwrap();
+    }
+
+    let mut files = mediafile::MediaFile::get_by_dir(&conn, "/mnt/a/")
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|x| x.target_file)
+        .collect::<Vec<_>>();
+    files.sort();
+
+    assert_eq!(files, vec!["/mnt/a/movie.mkv", "/mnt/a/show/episode.mkv"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_move_to_library() {
+    let conn = get_conn_memory().await.unwrap();
+    let _ = create_test_library(&conn).await;
+    let other = create_test_library(&conn).await;
+
+    let media_id = super::media_tests::insert_media(&conn).await;
+    let id = insert_mediafile_with_mediaid(&conn, media_id).await;
+
+    let rows = mediafile::MediaFile::move_to_library(&conn, id, other)
+        .await
+        .unwrap();
+    assert_eq!(rows, 1);
+
+    let result = mediafile::MediaFile::get_one(&conn, id).await.unwrap();
+    assert_eq!(result.library_id, other);
+    assert_eq!(result.media_id, None);
+}
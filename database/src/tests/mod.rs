@@ -1,10 +1,17 @@
 pub mod episode_tests;
+pub mod fingerprint_queue_tests;
 pub mod genre_tests;
 pub mod library_tests;
+pub mod match_attempt_tests;
+pub mod match_cache_tests;
 pub mod media_tests;
 pub mod mediafile_tests;
 pub mod movie_tests;
 pub mod progress_tests;
 pub mod season_tests;
+pub mod song_tests;
+pub mod subtitle_tests;
+pub mod tmdb_dump_tests;
+pub mod track_tests;
 pub mod tv_tests;
 pub mod user_tests;
@@ -0,0 +1,42 @@
+use crate::get_conn_memory;
+use crate::mediafile;
+use crate::song;
+
+use super::library_tests::create_test_library;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_insert_with_mediafile() {
+    let conn = get_conn_memory().await.unwrap();
+    let _library = create_test_library(&conn).await;
+
+    let mfile = mediafile::InsertableMediaFile {
+        library_id: 1,
+        target_file: "/music/Album/01 - Song.flac".into(),
+        raw_name: "Album".into(),
+        song: Some(song::InsertableSong {
+            artist: Some("Artist".into()),
+            album: Some("Album".into()),
+            title: Some("Song".into()),
+            track_number: Some(1),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let id = mfile.insert(&conn).await.unwrap();
+
+    let result = song::Song::get_by_mediafile(&conn, id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(result.mediafile_id, id);
+    assert_eq!(result.title.as_deref(), Some("Song"));
+    assert_eq!(result.track_number, Some(1));
+    assert_eq!(result.disc_number, None);
+
+    // NOTE: Songs without a album artist are credited to the artist of the song.
+    assert_eq!(result.credited_artist(), Some("Artist"));
+
+    let result = song::Song::get_by_mediafile(&conn, id + 1).await.unwrap();
+    assert!(result.is_none());
+}
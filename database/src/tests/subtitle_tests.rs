@@ -0,0 +1,36 @@
+use crate::get_conn_memory;
+use crate::subtitle;
+
+use super::library_tests::create_test_library;
+use super::mediafile_tests::insert_mediafile;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_insert_and_get_by_mediafile() {
+    let conn = get_conn_memory().await.unwrap();
+    let _library = create_test_library(&conn).await;
+    let mediafile = insert_mediafile(&conn).await;
+
+    let result = subtitle::ExternalSubtitle::get_by_mediafile(&conn, mediafile)
+        .await
+        .unwrap();
+    assert!(result.is_empty());
+
+    let sub = subtitle::InsertableExternalSubtitle {
+        mediafile_id: mediafile,
+        path: "/dev/null.es.forced.srt".into(),
+        language: Some("es".into()),
+        forced: true,
+    };
+
+    assert_eq!(sub.insert(&conn).await.unwrap(), 1);
+    // NOTE: Rescans come across the same subtitles again.
+    assert_eq!(sub.insert(&conn).await.unwrap(), 0);
+
+    let result = subtitle::ExternalSubtitle::get_by_mediafile(&conn, mediafile)
+        .await
+        .unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].path, "/dev/null.es.forced.srt");
+    assert_eq!(result[0].language.as_deref(), Some("es"));
+    assert!(result[0].forced);
+}
@@ -0,0 +1,55 @@
+use crate::get_conn_memory;
+use crate::library::MediaType;
+use crate::tmdb_dump;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_search() {
+    let conn = get_conn_memory().await.unwrap();
+
+    let entries = vec![
+        tmdb_dump::InsertableDumpEntry {
+            tmdb_id: 315635,
+            media_type: MediaType::Movie,
+            title: "Spider-Man: Homecoming".into(),
+            year: Some(2017),
+            popularity: 80.0,
+        },
+        tmdb_dump::InsertableDumpEntry {
+            tmdb_id: 1,
+            media_type: MediaType::Movie,
+            title: "Spider Man Homecoming".into(),
+            year: None,
+            popularity: 0.5,
+        },
+        tmdb_dump::InsertableDumpEntry {
+            tmdb_id: 2,
+            media_type: MediaType::Tv,
+            title: "Spider-Man: Homecoming".into(),
+            year: None,
+            popularity: 100.0,
+        },
+    ];
+
+    tmdb_dump::InsertableDumpEntry::insert_batch(&conn, &entries)
+        .await
+        .unwrap();
+
+    let result = tmdb_dump::DumpEntry::search(&conn, MediaType::Movie, "spider man homecoming")
+        .await
+        .unwrap();
+
+    assert_eq!(
+        result.iter().map(|x| x.tmdb_id).collect::<Vec<_>>(),
+        vec![315635, 1]
+    );
+
+    // NOTE: Importing the same dump again replaces the entries.
+    tmdb_dump::InsertableDumpEntry::insert_batch(&conn, &entries)
+        .await
+        .unwrap();
+
+    let count = tmdb_dump::DumpEntry::count(&conn, MediaType::Movie)
+        .await
+        .unwrap();
+    assert_eq!(count, 2);
+}
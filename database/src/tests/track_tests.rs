@@ -0,0 +1,55 @@
+use crate::get_conn_memory;
+use crate::mediafile;
+use crate::track;
+
+use super::library_tests::create_test_library;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_insert_with_mediafile() {
+    let conn = get_conn_memory().await.unwrap();
+    let _library = create_test_library(&conn).await;
+
+    let mfile = mediafile::InsertableMediaFile {
+        library_id: 1,
+        target_file: "/dev/null".into(),
+        raw_name: "Test".into(),
+        tracks: vec![
+            track::InsertableTrack {
+                stream_index: 2,
+                kind: "subtitle".into(),
+                codec: "subrip".into(),
+                language: Some("eng".into()),
+                forced: true,
+                ..Default::default()
+            },
+            track::InsertableTrack {
+                stream_index: 1,
+                kind: "audio".into(),
+                codec: "aac".into(),
+                language: Some("jpn".into()),
+                channels: Some(2),
+                is_default: true,
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    };
+
+    let id = mfile.insert(&conn).await.unwrap();
+
+    let result = track::Track::get_by_mediafile(&conn, id).await.unwrap();
+    assert_eq!(result.len(), 2);
+
+    assert!(result[0].is_audio());
+    assert_eq!(result[0].mediafile_id, id);
+    assert_eq!(result[0].language.as_deref(), Some("jpn"));
+    assert_eq!(result[0].channels, Some(2));
+    assert!(result[0].is_default);
+
+    assert!(result[1].is_subtitle());
+    assert_eq!(result[1].codec, "subrip");
+    assert!(result[1].forced);
+
+    let result = track::Track::get_by_mediafile(&conn, id + 1).await.unwrap();
+    assert!(result.is_empty());
+}
@@ -17,6 +17,8 @@ pub async fn insert_tv(conn: &crate::DbConnection) -> i64 {
         poster: None,
         backdrop: None,
         media_type: library::MediaType::Movie,
+        external_id: None,
+        tvdb_id: None,
     };
 
     let id = media.insert(conn).await.unwrap();
@@ -53,3 +55,19 @@ async fn test_upgrade() {
 
     assert!(result.is_ok());
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_season_offset() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let _lib = create_test_library(conn).await;
+    let id = insert_tv(conn).await;
+
+    let result = tv::TVShow::get_season_offset(conn, id).await.unwrap();
+    assert_eq!(result, 0);
+
+    let rows = tv::TVShow::set_season_offset(conn, id, 1).await.unwrap();
+    assert_eq!(rows, 1);
+
+    let result = tv::TVShow::get_season_offset(conn, id).await.unwrap();
+    assert_eq!(result, 1);
+}
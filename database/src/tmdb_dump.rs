@@ -0,0 +1,115 @@
+use crate::library::MediaType;
+use crate::DatabaseError;
+
+/// Struct represents a single media of a imported TMDB dump.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DumpEntry {
+    /// Id of the media on TMDB.
+    pub tmdb_id: i64,
+    pub media_type: MediaType,
+    pub title: String,
+    /// Lowercase title stripped of punctuation, as returned by [`search_title`].
+    pub search_title: String,
+    pub year: Option<i64>,
+    pub popularity: f64,
+}
+
+impl DumpEntry {
+    /// Method returns all entries of type `media_type` whose title matches `title`, ignoring case
+    /// and punctuation. The most popular entries come first.
+    ///
+    /// # Arguments
+    /// * `conn` - db connection
+    /// * `media_type` - type of media we are looking for
+    /// * `title` - title of the media
+    pub async fn search(
+        conn: &crate::DbConnection,
+        media_type: MediaType,
+        title: &str,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        let search_title = search_title(title);
+
+        Ok(sqlx::query_as!(
+            DumpEntry,
+            r#"SELECT tmdb_id, media_type as "media_type: MediaType", title, search_title, year,
+                popularity FROM tmdb_dump
+                WHERE media_type = ? AND search_title = ?
+                ORDER BY popularity DESC"#,
+            media_type,
+            search_title
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
+    /// Method returns the number of entries of type `media_type` we have imported.
+    pub async fn count(
+        conn: &crate::DbConnection,
+        media_type: MediaType,
+    ) -> Result<i64, DatabaseError> {
+        Ok(sqlx::query!(
+            r#"SELECT COUNT(*) as "count!: i64" FROM tmdb_dump WHERE media_type = ?"#,
+            media_type
+        )
+        .fetch_one(conn)
+        .await?
+        .count)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct InsertableDumpEntry {
+    pub tmdb_id: i64,
+    pub media_type: MediaType,
+    pub title: String,
+    pub year: Option<i64>,
+    pub popularity: f64,
+}
+
+impl InsertableDumpEntry {
+    /// Method inserts a batch of entries within a single transaction, replacing entries that were
+    /// imported before, as dumps are usually imported over and over again.
+    ///
+    /// # Arguments
+    /// * `conn` - db connection
+    /// * `entries` - entries to insert
+    pub async fn insert_batch(
+        conn: &crate::DbConnection,
+        entries: &[Self],
+    ) -> Result<(), DatabaseError> {
+        let mut tx = conn.begin().await?;
+
+        for entry in entries {
+            let search_title = search_title(&entry.title);
+
+            sqlx::query!(
+                r#"INSERT OR REPLACE INTO tmdb_dump
+                    (tmdb_id, media_type, title, search_title, year, popularity)
+                    VALUES ($1, $2, $3, $4, $5, $6)"#,
+                entry.tmdb_id,
+                entry.media_type,
+                entry.title,
+                search_title,
+                entry.year,
+                entry.popularity
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+/// Function normalizes a title for lookups, ie `Spider-Man: Homecoming` yields
+/// `spider man homecoming`.
+pub fn search_title(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|x| !x.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,100 @@
+use crate::DatabaseError;
+
+use serde::Serialize;
+
+/// Struct represents a single audio or subtitle stream of a mediafile.
+#[derive(Clone, Serialize, Debug, PartialEq, Default)]
+pub struct Track {
+    pub id: i64,
+    /// Id of the mediafile this stream belongs to.
+    pub mediafile_id: i64,
+    /// Index of the stream within the file, as used by ffmpeg.
+    pub stream_index: i64,
+    /// Either `audio` or `subtitle`.
+    pub kind: String,
+    /// Codec of the stream, for subtitles this is their format, ie `subrip` or `hdmv_pgs_subtitle`.
+    pub codec: String,
+    /// Language tag of the stream, ie `eng`.
+    pub language: Option<String>,
+    /// Title of the stream, ie `Commentary`.
+    pub title: Option<String>,
+    /// Number of channels of a audio stream.
+    pub channels: Option<i64>,
+    /// Whether the stream is the one played by default.
+    pub is_default: bool,
+    /// Whether the subtitles are forced, ie only cover foreign dialogue.
+    pub forced: bool,
+}
+
+impl Track {
+    /// Method returns all the audio and subtitle streams of a mediafile, ordered by their index.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `mediafile_id` - id of the mediafile
+    pub async fn get_by_mediafile(
+        conn: &crate::DbConnection,
+        mediafile_id: i64,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            Track,
+            "SELECT * FROM mediafile_track WHERE mediafile_id = ? ORDER BY stream_index",
+            mediafile_id
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
+    /// Method returns whether this is a audio stream.
+    pub fn is_audio(&self) -> bool {
+        self.kind == "audio"
+    }
+
+    /// Method returns whether this is a subtitle stream.
+    pub fn is_subtitle(&self) -> bool {
+        self.kind == "subtitle"
+    }
+}
+
+/// Struct represents a audio or subtitle stream that has yet to be inserted.
+#[derive(Clone, Serialize, Debug, PartialEq, Default)]
+pub struct InsertableTrack {
+    pub stream_index: i64,
+    pub kind: String,
+    pub codec: String,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub channels: Option<i64>,
+    pub is_default: bool,
+    pub forced: bool,
+}
+
+impl InsertableTrack {
+    /// Method inserts the stream as belonging to the mediafile `mediafile_id`.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `mediafile_id` - id of the mediafile the stream belongs to
+    pub async fn insert<'e, E>(&self, conn: E, mediafile_id: i64) -> Result<i64, DatabaseError>
+    where
+        E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+    {
+        Ok(sqlx::query!(
+            r#"INSERT INTO mediafile_track (mediafile_id, stream_index, kind, codec, language,
+            title, channels, is_default, forced)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"#,
+            mediafile_id,
+            self.stream_index,
+            self.kind,
+            self.codec,
+            self.language,
+            self.title,
+            self.channels,
+            self.is_default,
+            self.forced
+        )
+        .execute(conn)
+        .await?
+        .last_insert_rowid())
+    }
+}
@@ -102,6 +102,47 @@ impl TVShow {
         .total)
     }
 
+    /// Method returns the offset added to the season numbers of the files of a tv show to get the
+    /// season they belong to, `0` if none is set.
+    ///
+    /// # Arguments
+    /// * `conn` - db connection
+    /// * `id` - id of the tv show
+    pub async fn get_season_offset(
+        conn: &crate::DbConnection,
+        id: i64,
+    ) -> Result<i64, DatabaseError> {
+        Ok(sqlx::query!(
+            r#"SELECT COALESCE(season_offset, 0) as "season_offset!: i64" FROM tv_show
+                WHERE id = ?"#,
+            id
+        )
+        .fetch_one(conn)
+        .await?
+        .season_offset)
+    }
+
+    /// Method sets the season offset of a tv show.
+    ///
+    /// # Arguments
+    /// * `conn` - db connection
+    /// * `id` - id of the tv show
+    /// * `offset` - offset added to the season numbers of its files
+    pub async fn set_season_offset(
+        conn: &crate::DbConnection,
+        id: i64,
+        offset: i64,
+    ) -> Result<usize, DatabaseError> {
+        Ok(sqlx::query!(
+            "UPDATE tv_show SET season_offset = ? WHERE id = ?",
+            offset,
+            id
+        )
+        .execute(conn)
+        .await?
+        .rows_affected() as usize)
+    }
+
     /// Method inserts a new tv show in the database.
     ///
     /// # Arguments
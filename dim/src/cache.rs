@@ -0,0 +1,111 @@
+//! Reporting on and purging of the caches we keep around. Metadata are the search results we got
+//! from TMDB, kept in memory, while artwork are the posters, backdrops and stills we fetched and
+//! saved to the metadata directory. Purged artwork is fetched again the next time it is
+//! requested.
+use crate::core::METADATA_PATH;
+use crate::fetcher::clear_poster_cache;
+use crate::scanners::tmdb;
+
+use database::asset::Asset;
+use database::DbConnection;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The caches that can be purged.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheKind {
+    Metadata,
+    Artwork,
+}
+
+impl FromStr for CacheKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "metadata" => Ok(Self::Metadata),
+            "artwork" => Ok(Self::Artwork),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How much a single cache holds.
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct CacheUsage {
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct CacheStats {
+    pub metadata: CacheUsage,
+    pub artwork: CacheUsage,
+}
+
+/// Function returns how many entries and bytes every cache holds.
+pub async fn cache_stats(conn: &DbConnection) -> CacheStats {
+    let (entries, bytes) = tmdb::search_cache_usage().await;
+
+    CacheStats {
+        metadata: CacheUsage { entries, bytes },
+        artwork: artwork_usage(&artwork_files(conn).await).await,
+    }
+}
+
+/// Function purges the cache `which` and returns how much it held before it was purged.
+pub async fn clear_cache(conn: &DbConnection, which: CacheKind) -> CacheUsage {
+    match which {
+        CacheKind::Metadata => {
+            let (entries, bytes) = tmdb::search_cache_usage().await;
+            tmdb::clear_search_cache().await;
+
+            CacheUsage { entries, bytes }
+        }
+        CacheKind::Artwork => {
+            let files = artwork_files(conn).await;
+            let usage = artwork_usage(&files).await;
+
+            for file in files {
+                let _ = tokio::fs::remove_file(file).await;
+            }
+
+            clear_poster_cache().await;
+
+            usage
+        }
+    }
+}
+
+/// Function returns the artwork files we fetched from a remote. Assets without a remote, like
+/// user avatars, live in the same directory but cant be fetched again, so we leave them alone.
+async fn artwork_files(conn: &DbConnection) -> HashSet<PathBuf> {
+    let metadata_path = PathBuf::from(METADATA_PATH.get().unwrap());
+
+    Asset::get_all_remote(conn)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|x| !x.local_path.is_empty())
+        .map(|x| metadata_path.join(x.local_path.trim_start_matches("images/")))
+        .collect()
+}
+
+async fn artwork_usage(files: &HashSet<PathBuf>) -> CacheUsage {
+    let mut usage = CacheUsage::default();
+
+    for file in files {
+        if let Ok(metadata) = tokio::fs::metadata(file).await {
+            usage.entries += 1;
+            usage.bytes += metadata.len();
+        }
+    }
+
+    usage
+}
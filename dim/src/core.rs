@@ -1,15 +1,21 @@
 use crate::balanced_or_tree;
+use crate::get_global_settings;
 use crate::logger::RequestLogger;
 use crate::routes;
 use crate::scanners;
+use crate::shutdown;
 use crate::stream_tracking::StreamTracking;
 use crate::websocket;
 
+use database::mediafile::MountSource;
+
 use once_cell::sync::OnceCell;
 
 use slog::info;
 use slog::Logger;
 
+use std::time::Duration;
+
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::mpsc::UnboundedSender;
 
@@ -40,7 +46,18 @@ pub async fn run_scanners(log: Logger, tx: EventTx) {
             let library_id = lib.id;
             let tx_clone = tx.clone();
 
-            tokio::spawn(scanners::start(library_id, log_clone.clone(), tx_clone));
+            tokio::spawn(scanners::start(
+                library_id,
+                log_clone.clone(),
+                tx_clone,
+                MountSource::Scheduled,
+            ));
+
+            tokio::spawn(scanners::retry_orphans_daemon(
+                library_id,
+                log.clone(),
+                tx.clone(),
+            ));
 
             let log_clone = log.clone();
             let library_id = lib.id;
@@ -98,9 +115,21 @@ pub async fn warp_core(
         routes::library::filters::library_get(conn.clone()),
         routes::library::filters::library_post(conn.clone(), logger.clone(), event_tx.clone()),
         routes::library::filters::library_delete(conn.clone(), event_tx.clone()),
+        routes::library::filters::library_cancel_scan(),
         routes::library::filters::library_get_self(conn.clone()),
         routes::library::filters::get_all_of_library(conn.clone()),
         routes::library::filters::get_all_unmatched_media(conn.clone()),
+        routes::library::filters::library_set_settings(conn.clone(), logger.clone()),
+        routes::library::filters::library_export(conn.clone()),
+        routes::library::filters::library_parse_audit(conn.clone()),
+        routes::library::filters::library_duplicates(conn.clone()),
+        routes::library::filters::library_fingerprint_matches(conn.clone()),
+        routes::library::filters::library_relocate(conn.clone()),
+        routes::library::filters::library_import(conn.clone(), logger.clone(), event_tx.clone()),
+        routes::library::filters::scan_library_files(logger.clone(), event_tx.clone()),
+        routes::library::filters::rescan_library_file(logger.clone(), event_tx.clone()),
+        routes::library::filters::library_reprobe(logger.clone(), event_tx.clone()),
+        routes::library::filters::library_scan_preview(logger.clone()),
         /* dashboard routes */
         routes::dashboard::filters::dashboard(conn.clone(), rt.clone()),
         routes::dashboard::filters::banners(conn.clone()),
@@ -113,6 +142,8 @@ pub async fn warp_core(
         routes::media::filters::map_progress(conn.clone()),
         /* tv routes */
         routes::tv::filters::get_tv_seasons(conn.clone()),
+        routes::tv::filters::get_tv_structure(conn.clone()),
+        routes::tv::filters::set_season_offset(conn.clone()),
         routes::tv::filters::patch_episode_by_id(conn.clone()),
         routes::tv::filters::delete_season_by_id(conn.clone()),
         routes::tv::filters::get_season_episodes(conn.clone()),
@@ -120,12 +151,19 @@ pub async fn warp_core(
         routes::tv::filters::delete_episode_by_id(conn.clone()),
         /* mediafile routes */
         routes::mediafile::filters::get_mediafile_info(conn.clone()),
-        routes::mediafile::filters::rematch_mediafile(conn.clone(), logger.clone()),
+        routes::mediafile::filters::get_external_subtitles(conn.clone()),
+        routes::mediafile::filters::rematch_mediafile(
+            conn.clone(),
+            logger.clone(),
+            event_tx.clone(),
+        ),
         /* settings routes */
         routes::settings::filters::get_user_settings(conn.clone()),
         routes::settings::filters::post_user_settings(conn.clone()),
         routes::settings::filters::get_global_settings(),
         routes::settings::filters::set_global_settings(),
+        routes::settings::filters::get_cache_stats(conn.clone()),
+        routes::settings::filters::clear_cache(conn.clone()),
         /* stream routes */
         routes::stream::filters::return_virtual_manifest(
             conn.clone(),
@@ -186,7 +224,9 @@ pub async fn warp_core(
 
     tokio::select! {
         _ = warp::serve(routes).run(([0, 0, 0, 0], port)) => {},
-        _ = tokio::signal::ctrl_c() => {
+        _ = shutdown::signal() => {
+            let timeout = Duration::from_secs(get_global_settings().shutdown_timeout);
+            shutdown::drain(&logger, timeout).await;
             std::process::exit(0);
         }
     }
@@ -39,6 +39,8 @@ pub enum DimError {
     MissingFieldInBody { description: String },
     #[error(display = "Unsupported file type.")]
     UnsupportedFile,
+    #[error(display = "Invalid settings ({})", description)]
+    InvalidSettings { description: String },
 }
 
 impl warp::reject::Reject for DimError {}
@@ -47,6 +49,7 @@ impl warp::Reply for DimError {
     fn into_response(self) -> warp::reply::Response {
         let status = match self {
             Self::NoneError | Self::NotFoundError => StatusCode::NOT_FOUND,
+            Self::ScannerError(ScannerError::InvalidLocation(_)) => StatusCode::NOT_ACCEPTABLE,
             Self::StreamingError(_)
             | Self::DatabaseError
             | Self::UnknownError
@@ -55,9 +58,10 @@ impl warp::Reply for DimError {
             | Self::ScannerError(_)
             | Self::UploadFailed => StatusCode::INTERNAL_SERVER_ERROR,
             Self::AuthRequired | Self::Unauthorized => StatusCode::UNAUTHORIZED,
-            Self::UnsupportedFile | Self::InvalidMediaType | Self::MissingFieldInBody { .. } => {
-                StatusCode::NOT_ACCEPTABLE
-            }
+            Self::UnsupportedFile
+            | Self::InvalidMediaType
+            | Self::MissingFieldInBody { .. }
+            | Self::InvalidSettings { .. } => StatusCode::NOT_ACCEPTABLE,
         };
 
         let resp = json!({
@@ -1,26 +1,87 @@
 use crate::core::*;
+use crate::get_global_settings;
+use crate::streaming::FFMPEG_BIN;
+
+use serde::Deserialize;
+use serde::Serialize;
 
 use slog::debug;
 use slog::error;
+use slog::warn;
 use slog::Logger;
 
 use priority_queue::PriorityQueue;
 use tokio::sync::Mutex;
+use tokio::task::spawn_blocking;
 
+use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fs::File;
 use std::io::copy;
 use std::io::Cursor;
+use std::io::Write;
+use std::path::Path;
 use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
 use std::time::Duration;
 
 use once_cell::sync::Lazy;
 
+/// Format artwork is cached in.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtworkFormat {
+    /// Cache artwork as it was downloaded.
+    Original,
+    Webp,
+    Avif,
+}
+
+impl ArtworkFormat {
+    /// Method returns the arguments ffmpeg needs to encode a image in this format at `quality`,
+    /// from `1` to `100`. Returns `None` if the image should be left alone.
+    fn ffmpeg_args(&self, quality: u8) -> Option<Vec<String>> {
+        let quality = quality.clamp(1, 100) as u32;
+
+        let args = match self {
+            Self::Original => return None,
+            Self::Webp => vec![
+                "-c:v".into(),
+                "libwebp".into(),
+                "-quality".into(),
+                quality.to_string(),
+                "-f".into(),
+                "webp".into(),
+            ],
+            // NOTE: AV1 is tuned with a constant rate factor from `0`, lossless, to `63`.
+            Self::Avif => vec![
+                "-c:v".into(),
+                "libaom-av1".into(),
+                "-still-picture".into(),
+                "1".into(),
+                "-crf".into(),
+                (63 - quality * 63 / 100).to_string(),
+                "-f".into(),
+                "avif".into(),
+            ],
+        };
+
+        Some(args)
+    }
+}
+
 static PROCESSING_QUEUE: Lazy<Mutex<PriorityQueue<String, usize>>> =
     Lazy::new(|| Mutex::new(Default::default()));
 static POSTER_CACHE: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(Default::default()));
 
 pub async fn insert_into_queue(log: &Logger, poster: String, priority: usize) {
+    // NOTE: Machines matching against earlier matches only cant reach TMDB, the asset keeps its
+    // remote path so it can still be fetched later on.
+    if get_global_settings().match_cache_only {
+        return;
+    }
+
     let mut cache_lock = POSTER_CACHE.lock().await;
 
     if !cache_lock.contains(&poster) {
@@ -33,13 +94,34 @@ pub async fn insert_into_queue(log: &Logger, poster: String, priority: usize) {
     }
 }
 
+/// Function forgets which posters were already queued, so that they are fetched again the next
+/// time they are requested.
+pub async fn clear_poster_cache() {
+    POSTER_CACHE.lock().await.clear();
+}
+
 pub async fn bump_priority(log: &Logger, poster: String, priority: usize) {
     debug!(log, "Bumping priority of {:?} to {}", &poster, priority);
     let mut lock = PROCESSING_QUEUE.lock().await;
     lock.push_increase(poster, priority);
 }
 
+/// Number of times we try to download a image before giving up on it. The asset keeps its remote
+/// url, so clients can still fetch the image from there.
+const MAX_FETCH_ATTEMPTS: usize = 5;
+
+/// Function returns the path a image downloaded from `url` is cached at.
+fn cache_path(url: &str) -> Option<PathBuf> {
+    let fname = url.rsplit('/').next().filter(|x| !x.is_empty())?;
+    let mut out_path = PathBuf::from(METADATA_PATH.get()?);
+    out_path.push(fname);
+
+    Some(out_path)
+}
+
 async fn process_queue(log: Logger) {
+    let mut attempts: HashMap<String, usize> = HashMap::new();
+
     loop {
         let mut lock = PROCESSING_QUEUE.lock().await;
         if lock.is_empty() {
@@ -48,23 +130,45 @@ async fn process_queue(log: Logger) {
         }
 
         if let Some((url, priority)) = lock.pop() {
+            // NOTE: Artwork is shared between media and survives restarts, so there is no point
+            // in downloading it again.
+            if cache_path(&url).map_or(false, |x| x.exists()) {
+                debug!(log, "{} is already cached", url);
+                continue;
+            }
+
+            let attempt = attempts.entry(url.clone()).or_default();
+            *attempt += 1;
+
+            if *attempt > MAX_FETCH_ATTEMPTS {
+                warn!(
+                    log,
+                    "Giving up on caching {} locally, it will be served from its remote url", &url
+                );
+                attempts.remove(&url);
+                continue;
+            }
+
             debug!(log, "Trying to cache {}", url);
             match reqwest::get(url.as_str()).await {
                 Ok(resp) => {
-                    if let Some(fname) = resp.url().path_segments().and_then(|segs| segs.last()) {
-                        let meta_path = METADATA_PATH.get().unwrap();
-                        let mut out_path = PathBuf::from(meta_path);
-                        out_path.push(fname);
-
+                    // NOTE: Images are cached under the url we requested rather than the one we
+                    // were redirected to, as that is where assets expect them.
+                    if let Some(out_path) = cache_path(&url) {
                         debug!(log, "Caching {} -> {:?}", url, out_path);
 
-                        if let Ok(mut file) = File::create(out_path) {
+                        if let Ok(mut file) = File::create(&out_path) {
                             if let Ok(bytes) = resp.bytes().await {
+                                let bytes = reencode(&log, bytes.to_vec(), &out_path).await;
                                 let mut content = Cursor::new(bytes);
                                 if copy(&mut content, &mut file).is_ok() {
+                                    attempts.remove(&url);
                                     continue;
                                 }
                             }
+
+                            // NOTE: Partial downloads would otherwise pass as cached.
+                            let _ = std::fs::remove_file(&out_path);
                         }
                     }
                     error!(
@@ -84,6 +188,66 @@ async fn process_queue(log: Logger) {
     }
 }
 
+/// Function re-encodes the downloaded image `image` into the configured artwork format. The file
+/// name is kept as is, so that the assets pointing to it stay valid. Returns the original image if
+/// re-encoding is disabled or fails.
+async fn reencode(log: &Logger, image: Vec<u8>, out_path: &Path) -> Vec<u8> {
+    let settings = get_global_settings();
+    let args = match settings
+        .artwork_format
+        .ffmpeg_args(settings.artwork_quality)
+    {
+        Some(x) => x,
+        None => return image,
+    };
+
+    // NOTE: Some muxers need to seek, so ffmpeg writes into a scratch file next to the cache.
+    let tmp_path = out_path.with_extension("tmp");
+    let encode = {
+        let tmp_path = tmp_path.clone();
+        let image = image.clone();
+
+        spawn_blocking(move || {
+            let mut child = Command::new(*FFMPEG_BIN)
+                .args(&["-y", "-v", "quiet", "-i", "-"])
+                .args(args)
+                .arg(&tmp_path)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()?;
+
+            // NOTE: ffmpeg closes its input once it has read the image.
+            if let Some(mut stdin) = child.stdin.take() {
+                let _ = stdin.write_all(&image);
+            }
+
+            let status = child.wait()?;
+            let encoded = std::fs::read(&tmp_path);
+            let _ = std::fs::remove_file(&tmp_path);
+
+            match encoded {
+                Ok(x) if status.success() && !x.is_empty() => Ok(x),
+                Ok(_) => Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    format!("ffmpeg exited with {}", status),
+                )),
+                Err(e) => Err(e),
+            }
+        })
+        .await
+    };
+
+    match encode {
+        Ok(Ok(x)) => x,
+        Ok(Err(e)) => {
+            warn!(log, "Failed to re-encode artwork, caching it as is"; "reason" => e.to_string());
+            image
+        }
+        Err(_) => image,
+    }
+}
+
 /// Function creates a task that fetches and caches posters from various sources.
 pub async fn tmdb_poster_fetcher(log: Logger) {
     tokio::spawn(process_queue(log.clone()));
@@ -36,6 +36,8 @@ use slog_term::TermDecorator;
 use std::fs::create_dir_all;
 use std::fs::File;
 
+/// Reporting on and purging of our metadata and artwork caches.
+pub mod cache;
 /// Module contains our core initialization logic.
 pub mod core;
 /// Module contains all the error definitions used in dim, and returned by the web-service.
@@ -48,6 +50,8 @@ pub mod logger;
 pub mod routes;
 /// Contains our media scanners and so on.
 pub mod scanners;
+/// Coordinates draining in-flight scans on shutdown.
+pub mod shutdown;
 /// Contains the fairing which tracks streams across rest api
 pub mod stream_tracking;
 /// Contains all the logic needed for streaming and on-the-fly transcoding.
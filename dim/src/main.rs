@@ -12,6 +12,8 @@ use dim::core;
 use dim::routes::settings::GlobalSettings;
 use dim::streaming;
 
+use database::library::MediaType;
+
 use structopt::StructOpt;
 
 #[derive(Debug, structopt::StructOpt)]
@@ -21,6 +23,16 @@ use structopt::StructOpt;
 struct Args {
     #[structopt(short, long, parse(from_os_str))]
     config: Option<PathBuf>,
+    /// Run every stage of the scanner pipeline against a generated file, report which ones
+    /// failed, then exit.
+    #[structopt(long)]
+    self_test: bool,
+    /// Import a decompressed TMDB movie id export for offline matching, then exit.
+    #[structopt(long, parse(from_os_str))]
+    import_movie_dump: Option<PathBuf>,
+    /// Import a decompressed TMDB tv series id export for offline matching, then exit.
+    #[structopt(long, parse(from_os_str))]
+    import_tv_dump: Option<PathBuf>,
 }
 
 fn main() {
@@ -60,6 +72,52 @@ fn main() {
 
     let logger = build_logger(global_settings.verbose);
 
+    if args.self_test {
+        let report = tokio::runtime::Runtime::new()
+            .expect("Failed to create a tokio runtime.")
+            .block_on(dim::scanners::self_test::self_test(&logger));
+
+        for stage in report.iter() {
+            let status = if stage.passed { "PASS" } else { "FAIL" };
+            println!("[{}] {}: {}", status, stage.stage, stage.detail);
+        }
+
+        std::process::exit(if report.iter().all(|x| x.passed) {
+            0
+        } else {
+            1
+        });
+    }
+
+    if args.import_movie_dump.is_some() || args.import_tv_dump.is_some() {
+        let dumps = args
+            .import_movie_dump
+            .map(|x| (x, MediaType::Movie))
+            .into_iter()
+            .chain(args.import_tv_dump.map(|x| (x, MediaType::Tv)));
+
+        let import = async {
+            let conn = dim::scanners::get_conn_with_retry(&logger).await?;
+
+            for (path, media_type) in dumps {
+                dim::scanners::dump::import_dump(&conn, &path, media_type, &logger).await?;
+            }
+
+            Ok::<_, dim::scanners::base::ScannerError>(())
+        };
+
+        let result = tokio::runtime::Runtime::new()
+            .expect("Failed to create a tokio runtime.")
+            .block_on(import);
+
+        if let Err(e) = result {
+            error!(logger, "Failed to import TMDB dump"; "reason" => e.to_string());
+            std::process::exit(1);
+        }
+
+        std::process::exit(0);
+    }
+
     {
         let failed = streaming::ffcheck()
             .into_iter()
@@ -84,6 +142,7 @@ fn main() {
 
     let async_main = async move {
         dim::fetcher::tmdb_poster_fetcher(logger.clone()).await;
+        dim::scanners::fingerprint::fingerprint_worker(logger.clone()).await;
 
         let (event_tx, event_rx) = tokio::sync::mpsc::unbounded_channel();
 
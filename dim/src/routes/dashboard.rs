@@ -1,5 +1,6 @@
 use crate::core::DbConnection;
 use crate::errors;
+use crate::get_global_settings;
 use crate::json;
 
 use auth::Wrapper as Auth;
@@ -147,7 +148,8 @@ pub async fn banners(conn: DbConnection, user: Auth) -> Result<impl warp::Reply,
         if let Ok(x) = match media.media_type {
             MediaType::Tv => banner_for_show(&conn, &user, &media).await,
             MediaType::Movie => banner_for_movie(&conn, &user, &media).await,
-            _ => unreachable!(),
+            // NOTE: Albums have no backdrops to show in a banner.
+            _ => continue,
         } {
             banners.push(x);
         }
@@ -166,7 +168,8 @@ async fn banner_for_movie(
         .map(|x| x.delta)
         .unwrap_or(0);
 
-    let mediafiles = MediaFile::get_of_media(conn, media.id).await?;
+    let mut mediafiles = MediaFile::get_of_media(conn, media.id).await?;
+    MediaFile::sort_versions(&mut mediafiles, &get_global_settings().version_priority);
     let media_duration = MediaFile::get_largest_duration(conn, media.id).await?;
 
     let genres = Genre::get_by_media(conn, media.id)
@@ -240,7 +243,8 @@ async fn banner_for_show(
         .await
         .unwrap_or(0);
 
-    let mediafiles = MediaFile::get_of_media(conn, episode.id).await?;
+    let mut mediafiles = MediaFile::get_of_media(conn, episode.id).await?;
+    MediaFile::sort_versions(&mut mediafiles, &get_global_settings().version_priority);
 
     let caption = if progress > 0 {
         "CONTINUE WATCHING"
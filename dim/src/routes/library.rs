@@ -1,14 +1,25 @@
 use crate::core::DbConnection;
 use crate::core::EventTx;
 use crate::errors;
+use crate::json;
 use crate::scanners;
+use crate::scanners::audit::parse_audit;
+use crate::scanners::duplicates::find_duplicate_media;
+use crate::scanners::export::export_library;
+use crate::scanners::export::import_library;
+use crate::scanners::export::ExportDoc;
+use crate::scanners::filename::sort_key;
+use crate::scanners::fingerprint::find_similar;
+use crate::scanners::preview::scan_preview;
 
 use auth::Wrapper as Auth;
 
 use database::library::InsertableLibrary;
 use database::library::Library;
+use database::library::LibrarySettings;
 use database::media::Media;
 use database::mediafile::MediaFile;
+use database::mediafile::MountSource;
 
 use events::Message;
 use events::PushEventType;
@@ -16,12 +27,15 @@ use events::PushEventType;
 use std::collections::HashMap;
 use std::convert::Infallible;
 use std::path::Path;
+use std::path::PathBuf;
 
+use slog::error;
 use slog::Logger;
 
 use warp::http::StatusCode;
 use warp::reply;
 
+use serde::Deserialize;
 use serde::Serialize;
 
 pub mod filters {
@@ -92,6 +106,18 @@ pub mod filters {
             )
     }
 
+    pub fn library_cancel_scan(
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / i64 / "scan")
+            .and(warp::delete())
+            .and(auth::with_auth())
+            .and_then(|id: i64, user: Auth| async move {
+                super::library_cancel_scan(id, user)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
     pub fn library_get_self(
         conn: DbConnection,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
@@ -120,6 +146,205 @@ pub mod filters {
             })
     }
 
+    pub fn library_set_settings(
+        conn: DbConnection,
+        logger: slog::Logger,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / i64 / "settings")
+            .and(warp::post())
+            .and(warp::body::json::<LibrarySettings>())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and(with_state::<slog::Logger>(logger))
+            .and_then(
+                |id: i64,
+                 settings: LibrarySettings,
+                 user: Auth,
+                 conn: DbConnection,
+                 logger: slog::Logger| async move {
+                    super::library_set_settings(conn, id, settings, logger, user)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
+    pub fn scan_library_files(
+        logger: slog::Logger,
+        event_tx: EventTx,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / i64 / "scan")
+            .and(warp::post())
+            .and(warp::body::json::<ScanFiles>())
+            .and(auth::with_auth())
+            .and(with_state::<slog::Logger>(logger))
+            .and(with_state::<EventTx>(event_tx))
+            .and_then(
+                |id: i64,
+                 body: ScanFiles,
+                 user: Auth,
+                 logger: slog::Logger,
+                 event_tx: EventTx| async move {
+                    super::scan_library_files(id, body, logger, event_tx, user)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
+    pub fn rescan_library_file(
+        logger: slog::Logger,
+        event_tx: EventTx,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / i64 / "rescan")
+            .and(warp::post())
+            .and(warp::body::json::<RescanFile>())
+            .and(auth::with_auth())
+            .and(with_state::<slog::Logger>(logger))
+            .and(with_state::<EventTx>(event_tx))
+            .and_then(
+                |id: i64,
+                 body: RescanFile,
+                 user: Auth,
+                 logger: slog::Logger,
+                 event_tx: EventTx| async move {
+                    super::rescan_library_file(id, body, logger, event_tx, user)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
+    pub fn library_reprobe(
+        logger: slog::Logger,
+        event_tx: EventTx,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / i64 / "reprobe")
+            .and(warp::post())
+            .and(auth::with_auth())
+            .and(with_state::<slog::Logger>(logger))
+            .and(with_state::<EventTx>(event_tx))
+            .and_then(
+                |id: i64, user: Auth, logger: slog::Logger, event_tx: EventTx| async move {
+                    super::library_reprobe(id, logger, event_tx, user)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
+    pub fn library_scan_preview(
+        logger: slog::Logger,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / i64 / "scan" / "preview")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<slog::Logger>(logger))
+            .and_then(|id: i64, user: Auth, logger: slog::Logger| async move {
+                super::library_scan_preview(id, logger, user)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
+    pub fn library_export(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / i64 / "export")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|id: i64, user: Auth, conn: DbConnection| async move {
+                super::library_export(conn, id, user)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
+    pub fn library_parse_audit(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / i64 / "parse_audit")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|id: i64, user: Auth, conn: DbConnection| async move {
+                super::library_parse_audit(conn, id, user)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
+    pub fn library_relocate(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / i64 / "relocate")
+            .and(warp::post())
+            .and(warp::body::json::<super::RelocateLibrary>())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(
+                |id: i64, body: super::RelocateLibrary, user: Auth, conn: DbConnection| async move {
+                    super::library_relocate(conn, id, body, user)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
+    pub fn library_duplicates(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / i64 / "duplicates")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|id: i64, user: Auth, conn: DbConnection| async move {
+                super::library_duplicates(conn, id, user)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
+    pub fn library_fingerprint_matches(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / i64 / "fingerprint_matches")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|id: i64, user: Auth, conn: DbConnection| async move {
+                super::library_fingerprint_matches(conn, id, user)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
+    pub fn library_import(
+        conn: DbConnection,
+        logger: slog::Logger,
+        event_tx: EventTx,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / "import")
+            .and(warp::post())
+            .and(warp::body::json::<ImportLibrary>())
+            .and(auth::with_auth())
+            .and(with_state::<EventTx>(event_tx))
+            .and(with_state::<slog::Logger>(logger))
+            .and(with_state::<DbConnection>(conn))
+            .and_then(
+                |body: ImportLibrary,
+                 user: Auth,
+                 event_tx: EventTx,
+                 logger: slog::Logger,
+                 conn: DbConnection| async move {
+                    super::library_import(conn, body, logger, event_tx, user)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
     pub fn get_all_unmatched_media(
         conn: DbConnection,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
@@ -145,7 +370,7 @@ pub mod filters {
 pub async fn library_get(conn: DbConnection, _user: Auth) -> Result<impl warp::Reply, Infallible> {
     Ok(reply::json(&{
         let mut x = Library::get_all(&conn).await;
-        x.sort_by(|a, b| a.name.cmp(&b.name));
+        x.sort_by_cached_key(|lib| sort_key(&lib.name));
         x
     }))
 }
@@ -166,12 +391,19 @@ pub async fn library_post(
     event_tx: EventTx,
     _user: Auth,
 ) -> Result<impl warp::Reply, errors::DimError> {
+    let mut new_library = new_library;
+    new_library.locations = new_library
+        .locations
+        .iter()
+        .map(|x| scanners::normalize_library_location(x).map(|x| x.to_string_lossy().to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+
     let id = new_library.insert(&conn).await?;
     let tx_clone = event_tx.clone();
     let log_clone = log.clone();
 
     tokio::spawn(async move {
-        let _ = scanners::start(id, log_clone, tx_clone).await;
+        let _ = scanners::start(id, log_clone, tx_clone, MountSource::Manual).await;
     });
 
     let media_type = new_library.media_type;
@@ -188,6 +420,12 @@ pub async fn library_post(
             .expect("Something went wrong with the fs-watcher");
     });
 
+    tokio::spawn(scanners::retry_orphans_daemon(
+        id,
+        log.clone(),
+        event_tx.clone(),
+    ));
+
     let event = Message {
         id,
         event_type: PushEventType::EventNewLibrary,
@@ -215,6 +453,9 @@ pub async fn library_delete(
     conn: DbConnection,
     event_tx: EventTx,
 ) -> Result<impl warp::Reply, errors::DimError> {
+    scanners::cancel_scans(id);
+    scanners::stop_watcher(id);
+
     Media::delete_by_lib_id(&conn, id).await?;
     MediaFile::delete_by_lib_id(&conn, id).await?;
     Library::delete(&conn, id).await?;
@@ -229,6 +470,26 @@ pub async fn library_delete(
     Ok(StatusCode::NO_CONTENT)
 }
 
+/// Method mapped to `DELETE /api/v1/library/<id>/scan` aborts the running scans of a library.
+/// Files that are already being scanned are finished, no new files are picked up. The fs watcher
+/// of the library keeps running. Only owners can cancel scans.
+///
+/// # Arguments
+/// * `id` - id of the library whose scans we want to cancel
+/// * `user` - Auth middleware
+pub async fn library_cancel_scan(
+    id: i64,
+    user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    if !user.0.claims.has_role("owner") {
+        return Err(errors::DimError::Unauthorized);
+    }
+
+    scanners::cancel_scans(id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
 /// Method mapped to `GET /api/v1/library/<id>` returns info about the library with the supplied
 /// id. Method can only be accessed by authenticated users.
 ///
@@ -277,13 +538,309 @@ pub async fn get_all_library(
     .await
     .map_err(|_| errors::DimError::NotFoundError)?;
 
-    data.sort_by(|a, b| a.name.cmp(&b.name));
+    data.sort_by_cached_key(|media| sort_key(&media.name));
 
     result.insert(lib.name, data);
 
     Ok(reply::json(&result))
 }
 
+/// Method mapped to `POST /api/v1/library/<id>/settings` replaces the settings of a library. The
+/// new settings are picked up the next time the library gets scanned. The locations of the
+/// library are normalized as well, the request is refused with 406 if any of them, the new
+/// `metadata_language` or the `webhook_url` is invalid. Only owners can change the settings of a
+/// library as the webhook is posted to by dim itself.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the library
+/// * `settings` - the new settings of the library
+/// * `log` - logger
+/// * `user` - Auth middleware
+pub async fn library_set_settings(
+    conn: DbConnection,
+    id: i64,
+    settings: LibrarySettings,
+    log: Logger,
+    user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    if !user.0.claims.has_role("owner") {
+        return Err(errors::DimError::Unauthorized);
+    }
+
+    if let Some(url) = settings.webhook_url.as_deref() {
+        if !url.trim().is_empty() && !scanners::hook::is_webhook_url(url) {
+            return Err(errors::DimError::InvalidSettings {
+                description: format!("{} is not a http or https url", url),
+            });
+        }
+    }
+
+    if let Some(language) = settings.metadata_language.as_deref() {
+        if !scanners::tmdb::is_language(language) {
+            return Err(errors::DimError::InvalidSettings {
+                description: format!("{} is not a language tag like de-DE", language),
+            });
+        }
+    }
+
+    let _ = Library::get_one(&conn, id).await?;
+    scanners::normalize_locations(&conn, id, &log).await?;
+    Library::set_settings(&conn, id, &settings).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Method mapped to `GET /api/v1/library/<id>/export` returns a portable export of the matched
+/// media of a library, which can be imported on another instance without matching it again.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the library
+/// * `_user` - Auth middleware
+pub async fn library_export(
+    conn: DbConnection,
+    id: i64,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    Ok(reply::json(&export_library(&conn, id).await?))
+}
+
+/// Method mapped to `GET /api/v1/library/<id>/parse_audit` returns the files of a library whose
+/// titles were probably mis-parsed, ie because they still contain tags like `1080p`. Nothing is
+/// changed, the files are only reported so they can be renamed.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the library
+/// * `_user` - Auth middleware
+pub async fn library_parse_audit(
+    conn: DbConnection,
+    id: i64,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    Ok(reply::json(&parse_audit(&conn, id).await?))
+}
+
+/// Method mapped to `GET /api/v1/library/<id>/duplicates` returns groups of movies and tv shows
+/// in a library that are most likely the same, either because they were matched to the same TMDB
+/// id or because their titles and years are near identical. Nothing is merged, the groups are only
+/// reported.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the library
+/// * `_user` - Auth middleware
+pub async fn library_duplicates(
+    conn: DbConnection,
+    id: i64,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    Ok(reply::json(&find_duplicate_media(&conn, id).await?))
+}
+
+/// Method mapped to `GET /api/v1/library/<id>/fingerprint_matches` returns pairs of files in a
+/// library whose video fingerprints are near identical, ie the same film encoded twice. Only files
+/// fingerprinted while video fingerprints were enabled are compared.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the library
+/// * `_user` - Auth middleware
+pub async fn library_fingerprint_matches(
+    conn: DbConnection,
+    id: i64,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    Ok(reply::json(&find_similar(&conn, id).await?))
+}
+
+#[derive(Deserialize)]
+pub struct RelocateLibrary {
+    /// Directory the media of the library used to be located in.
+    pub old_root: String,
+    /// Directory the media has been moved to.
+    pub new_root: String,
+}
+
+/// Method mapped to `POST /api/v1/library/<id>/relocate` moves the files of a library located
+/// under `old_root` to `new_root`, ie after the media has been moved to a new drive. Files stay
+/// matched. The request is refused if any of the files is missing from its new location. Method
+/// returns the number of files moved.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the library
+/// * `body` - the old and the new location of the media
+/// * `user` - Auth middleware
+pub async fn library_relocate(
+    conn: DbConnection,
+    id: i64,
+    body: RelocateLibrary,
+    user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    if !user.0.claims.has_role("owner") {
+        return Err(errors::DimError::Unauthorized);
+    }
+
+    let moved = scanners::relocate_library(&conn, id, &body.old_root, &body.new_root).await?;
+
+    Ok(reply::json(&json!({ "moved": moved })))
+}
+
+#[derive(Deserialize)]
+pub struct ImportLibrary {
+    /// Location of the library on this machine.
+    pub root: PathBuf,
+    pub doc: ExportDoc,
+}
+
+/// Method mapped to `POST /api/v1/library/import` creates a new library out of an export and
+/// relinks the exported files located under `root` in the background. Method returns 202 ACCEPTED
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `body` - the export along with the location of the library on this machine
+/// * `log` - logger
+/// * `event_tx` - channel over which to dispatch events
+/// * `_user` - Auth middleware
+pub async fn library_import(
+    conn: DbConnection,
+    body: ImportLibrary,
+    log: Logger,
+    event_tx: EventTx,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    // NOTE: Validate the location upfront so that clients get a error right away.
+    let _ = scanners::normalize_library_location(&body.root)?;
+    let media_type = body.doc.media_type;
+
+    tokio::spawn(async move {
+        let id = match import_library(&conn, body.doc, &body.root, &log, &event_tx).await {
+            Ok(x) => x,
+            Err(e) => {
+                error!(log, "Failed to import library"; "reason" => e.to_string());
+                return;
+            }
+        };
+
+        let watcher =
+            scanners::scanner_daemon::FsWatcher::new(log.clone(), id, media_type, event_tx).await;
+
+        watcher
+            .start_daemon()
+            .await
+            .expect("Something went wrong with the fs-watcher");
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[derive(Deserialize)]
+pub struct ScanFiles {
+    files: Vec<PathBuf>,
+}
+
+/// Method mapped to `POST /api/v1/library/<id>/scan` scans only the files supplied instead of
+/// walking the whole library. This is meant to be called by external tools that know which files
+/// changed, like the completion hook of a download client. The scan runs in the background so the
+/// method returns 202 Accepted right away.
+///
+/// # Arguments
+/// * `id` - id of the library the files belong to
+/// * `body` - list of files to scan
+/// * `log` - logger
+/// * `event_tx` - channel over which to dispatch events
+/// * `_user` - Auth middleware
+pub async fn scan_library_files(
+    id: i64,
+    body: ScanFiles,
+    log: Logger,
+    event_tx: EventTx,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    tokio::spawn(async move {
+        let _ = scanners::scan_files(id, body.files, log, event_tx).await;
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[derive(Deserialize)]
+pub struct RescanFile {
+    path: PathBuf,
+    old_path: Option<PathBuf>,
+}
+
+/// Method mapped to `POST /api/v1/library/<id>/rescan` scans a single file again from scratch,
+/// ie after it was renamed because it was matched wrongly. If the file was renamed, `old_path`
+/// should hold its previous path so that the mediafile left behind for it is removed. The scan
+/// runs in the background so the method returns 202 Accepted right away.
+///
+/// # Arguments
+/// * `id` - id of the library the file belongs to
+/// * `body` - path of the file and optionally the path it had before it was renamed
+/// * `log` - logger
+/// * `event_tx` - channel over which to dispatch events
+/// * `_user` - Auth middleware
+pub async fn rescan_library_file(
+    id: i64,
+    body: RescanFile,
+    log: Logger,
+    event_tx: EventTx,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    tokio::spawn(async move {
+        let _ = scanners::rescan_file(id, body.path, body.old_path, log, event_tx).await;
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Method mapped to `POST /api/v1/library/<id>/reprobe` probes the files of a library that were
+/// mounted as corrupt once more, ie after they were repaired or downloaded again, and matches the
+/// ones that probe clean now. The probes run in the background so the method returns 202 Accepted
+/// right away. Only owners can reprobe libraries.
+///
+/// # Arguments
+/// * `id` - id of the library
+/// * `log` - logger
+/// * `event_tx` - channel over which to dispatch events
+/// * `user` - Auth middleware
+pub async fn library_reprobe(
+    id: i64,
+    log: Logger,
+    event_tx: EventTx,
+    user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    if !user.0.claims.has_role("owner") {
+        return Err(errors::DimError::Unauthorized);
+    }
+
+    tokio::spawn(async move {
+        let _ = scanners::reprobe_corrupt(id, log, event_tx).await;
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Method mapped to `GET /api/v1/library/<id>/scan/preview` runs a dry run of a scan of the
+/// library and returns how each file would be parsed and what it would be matched with. Nothing
+/// is written to the db. The response is only sent once every file was looked up, which can take
+/// a while on large libraries.
+///
+/// # Arguments
+/// * `id` - id of the library
+/// * `log` - logger
+/// * `_user` - Auth middleware
+pub async fn library_scan_preview(
+    id: i64,
+    log: Logger,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    Ok(reply::json(&scan_preview(id, &log).await?))
+}
+
 /// Method mapped to `GET` /api/v1/library/<id>/unmatched` returns a list of all unmatched medias
 /// to be displayed in the library pages.
 ///
@@ -305,11 +862,12 @@ pub async fn get_all_unmatched_media(
         name: String,
         duration: Option<i64>,
         target_file: String,
+        original_filename: Option<String>,
     }
 
     sqlx::query_as!(
         Record,
-        r#"SELECT id, raw_name as name, duration, target_file FROM mediafile
+        r#"SELECT id, raw_name as name, duration, target_file, original_filename FROM mediafile
         WHERE library_id = ? AND media_id IS NULL"#,
         id
     )
@@ -1,5 +1,6 @@
 use crate::core::DbConnection;
 use crate::errors;
+use crate::get_global_settings;
 use crate::json;
 
 use auth::Wrapper as Auth;
@@ -165,7 +166,7 @@ pub async fn get_media_by_id(
     let media = Media::get(&conn, id).await?;
 
     let media_id = match media.media_type {
-        MediaType::Movie | MediaType::Episode => id,
+        MediaType::Movie | MediaType::Episode | MediaType::Music => id,
         MediaType::Tv => Episode::get_first_for_show(&conn, id).await?.id,
     };
 
@@ -188,7 +189,7 @@ pub async fn get_media_by_id(
         .collect::<Vec<String>>();
 
     let progress = match media.media_type {
-        MediaType::Episode | MediaType::Movie => {
+        MediaType::Episode | MediaType::Movie | MediaType::Music => {
             Progress::get_for_media_user(&conn, user.0.claims.get_user(), id)
                 .await
                 .map(|x| json!({"progress": x.delta}))
@@ -253,6 +254,8 @@ pub async fn get_media_by_id(
         _ => None,
     };
 
+    let (tagline, runtime) = Media::get_details(&conn, id).await?;
+
     // FIXME: Remove the duration tag once the UI transitioned to using duration_pretty
     Ok(reply::json(&json!({
         "id": media.id,
@@ -267,6 +270,8 @@ pub async fn get_media_by_id(
         "media_type": media.media_type,
         "genres": genres,
         "duration": duration,
+        "tagline": tagline,
+        "runtime": runtime,
         ..?season_episode_tag,
         ..?progress
     })))
@@ -276,7 +281,9 @@ pub async fn get_media_files(
     conn: DbConnection,
     id: i64,
 ) -> Result<impl warp::Reply, errors::DimError> {
-    let mediafiles = MediaFile::get_of_media(&conn, id).await?;
+    let mut mediafiles = MediaFile::get_of_media(&conn, id).await?;
+    MediaFile::sort_versions(&mut mediafiles, &get_global_settings().version_priority);
+
     Ok(reply::json(&mediafiles))
 }
 
@@ -1,10 +1,24 @@
 use crate::core::DbConnection;
+use crate::core::EventTx;
 use crate::errors;
+use crate::scanners::subtitles::find_external_subtitles;
 
 use auth::Wrapper as Auth;
+use database::media::Media;
 use database::mediafile::MediaFile;
+use database::subtitle::ExternalSubtitle;
+use database::track::Track;
+
+use events::Message;
+use events::PushEventType;
+use slog::error;
 
 use serde_json::json;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tokio::task::spawn_blocking;
+
 use warp::http::status::StatusCode;
 use warp::reply;
 
@@ -13,6 +27,7 @@ pub mod filters {
     use warp::Filter;
 
     use super::super::global_filters::with_state;
+    use crate::core::EventTx;
     use auth::Wrapper as Auth;
     use database::DbConnection;
 
@@ -32,9 +47,24 @@ pub mod filters {
             })
     }
 
+    pub fn get_external_subtitles(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "mediafile" / i64 / "subtitles")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|id: i64, auth: Auth, conn: DbConnection| async move {
+                super::get_external_subtitles(conn, id, auth)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
     pub fn rematch_mediafile(
         conn: DbConnection,
         log: slog::Logger,
+        event_tx: EventTx,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
         #[derive(Deserialize)]
         struct RouteArgs {
@@ -47,17 +77,19 @@ pub mod filters {
             .and(auth::with_auth())
             .and(with_state::<DbConnection>(conn))
             .and(with_state::<slog::Logger>(log))
+            .and(with_state::<EventTx>(event_tx))
             .and(warp::query::query::<RouteArgs>())
             .and_then(
                 |id: i64,
                  _auth: Auth,
                  conn: DbConnection,
                  log: slog::Logger,
+                 event_tx: EventTx,
                  RouteArgs {
                      tmdb_id,
                      media_type,
                  }: RouteArgs| async move {
-                    super::rematch_mediafile(conn, log, id, tmdb_id, media_type)
+                    super::rematch_mediafile(conn, log, event_tx, id, tmdb_id, media_type)
                         .await
                         .map_err(|e| reject::custom(e))
                 },
@@ -66,6 +98,7 @@ pub mod filters {
 }
 
 /// Method mapped to `GET /api/v1/mediafile/<id>` is used to get information about a mediafile by its id.
+/// The audio and subtitle streams of the file are listed under `audio_tracks` and `subtitle_tracks`.
 ///
 /// # Arguments
 /// * `id` - id of the mediafile we want info about
@@ -78,16 +111,57 @@ pub async fn get_mediafile_info(
         .await
         .map_err(|_| errors::DimError::NotFoundError)?;
 
+    let (audio_tracks, subtitle_tracks): (Vec<_>, Vec<_>) = Track::get_by_mediafile(&conn, id)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .partition(Track::is_audio);
+
     Ok(reply::json(&json!({
         "id": mediafile.id,
         "media_id": mediafile.media_id,
         "library_id": mediafile.library_id,
         "raw_name": mediafile.raw_name,
+        "audio_tracks": audio_tracks,
+        "subtitle_tracks": subtitle_tracks,
     })))
 }
 
-/// Method mapped to `PATCH /api/v1/mediafile/<id>/match` used to match a unmatched(orphan)
-/// mediafile to a tmdb id.
+/// Method mapped to `GET /api/v1/mediafile/<id>/subtitles` returns the external subtitles of a
+/// mediafile, kept either beside it or in a `Subs` folder next to it, along with their language
+/// and whether they are forced.
+///
+/// # Arguments
+/// * `id` - id of the mediafile we want the subtitles of
+pub async fn get_external_subtitles(
+    conn: DbConnection,
+    id: i64,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    let mediafile = MediaFile::get_one(&conn, id)
+        .await
+        .map_err(|_| errors::DimError::NotFoundError)?;
+
+    let recorded = ExternalSubtitle::get_by_mediafile(&conn, id)
+        .await
+        .unwrap_or_default();
+
+    // NOTE: Files mounted before we started recording subtitles are looked up on the fly.
+    if !recorded.is_empty() {
+        return Ok(reply::json(&recorded));
+    }
+
+    let video = PathBuf::from(mediafile.target_file);
+    let subtitles = spawn_blocking(move || find_external_subtitles(&video))
+        .await
+        .unwrap_or_default();
+
+    Ok(reply::json(&subtitles))
+}
+
+/// Method mapped to `PATCH /api/v1/mediafile/<id>/match` used to match a mediafile to a tmdb id,
+/// bypassing the matcher. This works for unmatched(orphan) mediafiles as well as mediafiles that
+/// were matched wrongly, whose old media is removed if no other mediafiles are left for it.
 ///
 /// # Arguments
 /// * `conn` - database connection
@@ -95,41 +169,81 @@ pub async fn get_mediafile_info(
 /// * `event_tx` - websocket channel over which we dispatch a event notifying other clients of the
 /// new metadata
 ///
-/// * `id` - id of the orphan mediafile we want to rematch
+/// * `id` - id of the mediafile we want to rematch
 /// * `tmdb_id` - the tmdb id of the proper metadata we want to fetch for the media
 pub async fn rematch_mediafile(
     conn: DbConnection,
-    _log: slog::Logger,
+    log: slog::Logger,
+    event_tx: EventTx,
     id: i64,
     tmdb_id: i32,
     media_type: String,
 ) -> Result<impl warp::Reply, errors::DimError> {
     use crate::scanners::tmdb::Tmdb;
+    use database::library::Library;
     use database::library::MediaType;
 
     let mediafile = MediaFile::get_one(&conn, id).await?;
+    let old_media_id = mediafile.media_id;
     let matcher = crate::scanners::get_matcher_unchecked();
+    let settings = Library::get_one(&conn, mediafile.library_id)
+        .await
+        .map(|x| x.settings)
+        .unwrap_or_default();
 
+    // NOTE: Users rematch files when the metadata we have is wrong or outdated, so we ask TMDB for
+    // fresh metadata.
     let mut tmdb = match media_type.to_lowercase().as_ref() {
         "movie" => Tmdb::new("38c372f5bc572c8aadde7a802638534e".into(), MediaType::Movie),
         "tv" => Tmdb::new("38c372f5bc572c8aadde7a802638534e".into(), MediaType::Tv),
         _ => return Err(errors::DimError::InvalidMediaType),
-    };
+    }
+    .language(settings.metadata_language.clone())
+    .bypass_cache();
 
     let result = tmdb
         .search_by_id(tmdb_id)
         .await
         .map_err(|_| errors::DimError::NotFoundError)?;
 
+    // NOTE: Matches picked by hand are as certain as they get.
+    let mediafile = MediaFile {
+        match_score: Some(1.0),
+        ..mediafile
+    };
+
     match media_type.to_lowercase().as_ref() {
         "movie" => {
             matcher
                 .match_movie_to_result(mediafile, result.into())
                 .await?
         }
-        "tv" => matcher.match_tv_to_result(mediafile, result.into()).await?,
+        "tv" => {
+            matcher
+                .match_tv_to_result(mediafile, result.into(), Arc::new(settings))
+                .await?
+        }
         _ => unreachable!(),
     }
 
+    // NOTE: Files that were matched wrongly leave their old media behind, which would show up as
+    // a card without any files.
+    let new_media_id = MediaFile::get_one(&conn, id).await?.media_id;
+
+    if let Some(old) = old_media_id.filter(|x| Some(*x) != new_media_id) {
+        if MediaFile::get_of_media(&conn, old).await?.is_empty() {
+            if let Err(e) = Media::delete(&conn, old).await {
+                error!(log, "Failed to delete ghost media {:?}", e);
+            } else {
+                let event = Message {
+                    id: old,
+                    event_type: PushEventType::EventRemoveCard,
+                };
+
+                let _ = event_tx.send(serde_json::to_string(&event).unwrap());
+            }
+        }
+    }
+
     Ok(StatusCode::OK)
 }
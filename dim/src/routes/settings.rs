@@ -1,7 +1,14 @@
+use crate::cache;
+use crate::cache::CacheKind;
 use crate::core::DbConnection;
 use crate::errors;
+use crate::fetcher::ArtworkFormat;
+use crate::scanners::MediaTypeMismatch;
+use crate::scanners::OverlapOwner;
+use crate::scanners::ScanPipeline;
 use crate::utils::ffpath;
 
+use database::mediafile::VersionPreference;
 use database::user::UpdateableUser;
 use database::user::User;
 use database::user::UserSettings;
@@ -23,6 +30,7 @@ use once_cell::sync::OnceCell;
 use warp::reply;
 
 #[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct GlobalSettings {
     pub enable_ssl: bool,
     pub port: u16,
@@ -37,6 +45,105 @@ pub struct GlobalSettings {
 
     pub verbose: bool,
     pub secret_key: Option<[u8; 16]>,
+
+    /// Number of metadata matchers that query external APIs in parallel. Requests going out to
+    /// TMDB are capped at 40 every 10 seconds across all matchers, and still back off when we get
+    /// rate limited.
+    pub match_concurrency: usize,
+    /// Number of files mounted in parallel, each of which runs ffprobe. Libraries on spinning
+    /// disks are always mounted one file at a time.
+    pub mount_concurrency: usize,
+    /// Walk the top level directories of a library in parallel when enumerating files. Mostly
+    /// useful for very large libraries on multi-core systems.
+    pub parallel_walk: bool,
+    /// How many times the scanners retry acquiring a database connection before giving up.
+    pub db_connection_retries: u32,
+    /// Extra tokens stripped from titles parsed out of filenames before we search for metadata,
+    /// ie release group or uploader tags. Matching ignores case and punctuation.
+    pub strip_tokens: Vec<String>,
+    /// What we care about most when picking the default version of a media that we have several
+    /// files of, most important first.
+    pub version_priority: Vec<VersionPreference>,
+    /// Accept tv shows whose run covers the year found in a filename, instead of requiring the
+    /// year the show first aired in.
+    pub tv_air_year_range: bool,
+    /// Prefer artwork kept next to the media, ie `poster.jpg` or `fanart.jpg`, over artwork from
+    /// TMDB. When disabled local artwork is only used if TMDB has none.
+    pub prefer_local_artwork: bool,
+    /// Map every part of a season released in parts, ie a `Season 4 Part 2` folder, to the season
+    /// itself.
+    pub group_season_parts: bool,
+    /// Match media against a locally imported TMDB dump instead of querying TMDB, for machines
+    /// without network access. Dumps are imported with `--import-movie-dump` and
+    /// `--import-tv-dump`.
+    pub offline_matching: bool,
+    /// Number of seconds a single file may take to be probed, parsed and matched before it is
+    /// skipped, so that one pathological file cant stall a scan. `0` disables the limit.
+    pub file_timeout: u64,
+    /// Directory every scan writes a JSONL log into, listing what happened to each file. Scan logs
+    /// are disabled when unset.
+    pub scan_log_dir: Option<String>,
+    /// Drop every non ascii character from filenames before parsing them, like older versions
+    /// did. Only useful as a workaround for filenames our parser chokes on, as titles in other
+    /// scripts, ie Arabic or Hebrew, are lost entirely.
+    pub ascii_filenames: bool,
+    /// Number of seconds we wait on shutdown for the files that are being scanned to be committed
+    /// before exiting anyway.
+    pub shutdown_timeout: u64,
+    /// Which library owns a file that can be reached from several libraries, ie because their
+    /// locations overlap. Other libraries skip the file.
+    pub overlap_owner: OverlapOwner,
+    /// How many times a search is retried while matching when it fails because of a transient
+    /// error, ie a timeout or a server error, before the file is left unmatched.
+    pub match_retries: u32,
+    /// Treat episodes numbered `E00` within a numbered season, ie `S03E00`, as specials and place
+    /// them in season 0. By default they are episode 0 of the season they are tagged with.
+    pub episode_zero_specials: bool,
+    /// Compute a perceptual fingerprint of every newly scanned video in the background, used to
+    /// find files that are the same content but were encoded differently. Fingerprinting decodes
+    /// several frames of each file, so it is off by default.
+    pub video_fingerprints: bool,
+    /// What to do when the name of a file gives away that it is of another type of media than the
+    /// library it belongs to, ie an episode numbered `S01E01` in a movie library.
+    pub media_type_mismatch: MediaTypeMismatch,
+    /// Strip the group and quality tags wrapped in brackets off anime style filenames, ie
+    /// `[Group] Show - 05 [1080p].mkv`, before parsing them, and read the number after the dash
+    /// as the episode.
+    pub anime_brackets: bool,
+    /// Command run after every library scan, ie to update an external index. It is split on
+    /// whitespace and run without a shell, with the id of the library and the state of the scan
+    /// appended as arguments. Can only be set in the config file.
+    pub post_scan_command: Option<String>,
+    /// Probe files we already know about again on every scan and flag those whose duration
+    /// changed substantially or that now parse as another title or year, ie because the file was
+    /// replaced by another film, so that their match can be reviewed. Probing every file makes rescans slower, so it is off by default.
+    pub reprobe_mounted: bool,
+    /// Size TMDB posters, season posters and episode stills are downloaded in, ie `w500`.
+    pub poster_size: String,
+    /// Size TMDB backdrops are downloaded in, ie `w1280`.
+    pub backdrop_size: String,
+    /// Format downloaded artwork is re-encoded to before it is cached. Re-encoding is done by
+    /// ffmpeg, artwork is cached as it was downloaded if that fails.
+    pub artwork_format: ArtworkFormat,
+    /// Quality from `1` to `100` artwork is re-encoded at.
+    pub artwork_quality: u8,
+    /// Whether scans match every file as soon as it has been mounted, or only once every file has
+    /// been mounted.
+    pub scan_pipeline: ScanPipeline,
+    /// Api key used to query TheTVDB, which libraries can match tv shows against instead of TMDB.
+    pub tvdb_api_key: Option<String>,
+    /// Number of seconds ffprobe may take on a single file before we give up on it and mark the
+    /// file as corrupt.
+    pub ffprobe_timeout: u64,
+    /// Only match media against the metadata of earlier matches, never querying TMDB or TheTVDB
+    /// and not downloading any artwork, for machines that lose their network access. Metadata of
+    /// every match is always kept, and used whenever TMDB cant be reached, regardless of this
+    /// setting.
+    pub match_cache_only: bool,
+    /// Number of seconds between attempts at matching the files that were left unmatched once
+    /// more, for releases our metadata providers didnt know of yet. The wait doubles for every
+    /// attempt a file fails to match. `0` disables the retries.
+    pub orphan_retry_interval: u64,
 }
 
 impl Default for GlobalSettings {
@@ -60,6 +167,37 @@ impl Default for GlobalSettings {
             disable_auth: false,
             verbose: false,
             secret_key: None,
+            match_concurrency: 6,
+            mount_concurrency: 4,
+            parallel_walk: false,
+            db_connection_retries: 5,
+            strip_tokens: Vec::new(),
+            version_priority: vec![VersionPreference::Revision],
+            tv_air_year_range: true,
+            prefer_local_artwork: true,
+            group_season_parts: true,
+            offline_matching: false,
+            file_timeout: 300,
+            scan_log_dir: None,
+            ascii_filenames: false,
+            shutdown_timeout: 30,
+            overlap_owner: OverlapOwner::Innermost,
+            match_retries: 3,
+            episode_zero_specials: false,
+            video_fingerprints: false,
+            media_type_mismatch: MediaTypeMismatch::Reject,
+            anime_brackets: true,
+            post_scan_command: None,
+            reprobe_mounted: false,
+            poster_size: "w600_and_h900_bestv2".into(),
+            backdrop_size: "original".into(),
+            artwork_format: ArtworkFormat::Original,
+            artwork_quality: 80,
+            scan_pipeline: ScanPipeline::Inline,
+            tvdb_api_key: None,
+            ffprobe_timeout: 30,
+            match_cache_only: false,
+            orphan_retry_interval: 0,
         }
     }
 }
@@ -124,6 +262,7 @@ pub mod filters {
     use warp::Rejection;
 
     use super::super::global_filters::with_state;
+    use crate::cache::CacheKind;
 
     pub fn get_user_settings(
         conn: DbConnection,
@@ -185,6 +324,36 @@ pub mod filters {
                     .map_err(|e| reject::custom(e))
             })
     }
+
+    pub fn get_cache_stats(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
+        warp::path!("api" / "v1" / "host" / "cache")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|auth: Auth, conn: DbConnection| async move {
+                super::get_cache_stats(conn, auth)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
+    pub fn clear_cache(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
+        warp::path!("api" / "v1" / "host" / "cache" / CacheKind)
+            .and(warp::delete())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(
+                |which: CacheKind, auth: Auth, conn: DbConnection| async move {
+                    super::clear_cache(conn, which, auth)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
 }
 
 pub async fn get_user_settings(
@@ -218,8 +387,12 @@ pub async fn http_get_global_settings(_user: Auth) -> Result<impl warp::Reply, e
 // TODO: Disallow setting secret key over http.
 pub async fn http_set_global_settings(
     user: Auth,
-    new_settings: GlobalSettings,
+    mut new_settings: GlobalSettings,
 ) -> Result<impl warp::Reply, errors::DimError> {
+    // NOTE: Anyone who can set the post scan command can run anything as dim, so it can only be
+    // changed in the config file.
+    new_settings.post_scan_command = get_global_settings().post_scan_command;
+
     if user.0.claims.has_role("owner") {
         set_global_settings(new_settings).unwrap();
         return Ok(reply::json(&get_global_settings()));
@@ -227,3 +400,25 @@ pub async fn http_set_global_settings(
 
     Err(errors::DimError::Unauthorized)
 }
+
+/// Method mapped to `GET /api/v1/host/cache` returns how many entries and bytes every cache holds.
+pub async fn get_cache_stats(
+    conn: DbConnection,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    Ok(reply::json(&cache::cache_stats(&conn).await))
+}
+
+/// Method mapped to `DELETE /api/v1/host/cache/<which>` purges a cache, either `metadata` or
+/// `artwork`, and returns how much it held. Only owners can purge caches.
+pub async fn clear_cache(
+    conn: DbConnection,
+    which: CacheKind,
+    user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    if !user.0.claims.has_role("owner") {
+        return Err(errors::DimError::Unauthorized);
+    }
+
+    Ok(reply::json(&cache::clear_cache(&conn, which).await))
+}
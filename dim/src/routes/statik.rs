@@ -130,9 +130,15 @@ pub async fn get_image(
     };
     */
 
+    // NOTE: Images that arent cached yet, or that we failed to cache, are served from their remote
+    // url in the meantime.
     if !Path::new(&file_path).exists() {
-        if let Ok(x) = dbg!(asset::Asset::get_url_by_file(&conn, &url_path).await) {
-            bump_priority(&log, x, 5).await;
+        if let Ok(x) = asset::Asset::get_url_by_file(&conn, &url_path).await {
+            bump_priority(&log, x.clone(), 5).await;
+
+            if let Ok(uri) = x.parse::<warp::http::Uri>() {
+                return Ok(warp::redirect::temporary(uri).into_response());
+            }
         }
     }
 
@@ -143,6 +149,7 @@ pub async fn get_image(
             .status(StatusCode::OK)
             .header("ContentType", "image/jpeg")
             .body(data)
+            .map(Reply::into_response)
             .map_err(|_| warp::reject::not_found());
     }
 
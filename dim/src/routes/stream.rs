@@ -305,7 +305,10 @@ pub async fn return_virtual_manifest(
     })
     .await
     .unwrap()
-    .map_err(|_| errors::StreamingErrors::FFProbeCtxFailed)?;
+    .map_err(|e| match e.kind() {
+        std::io::ErrorKind::InvalidData => errors::StreamingErrors::FileIsCorrupt,
+        _ => errors::StreamingErrors::FFProbeCtxFailed,
+    })?;
 
     let mut ms = info
         .get_ms()
@@ -539,7 +542,10 @@ pub async fn create_audio(
     let audio_streams = info.find_by_type("audio");
 
     for stream in audio_streams {
-        let is_default = info.get_primary("audio") == Some(stream);
+        let is_default = match media.default_audio_stream {
+            Some(index) => stream.index == index,
+            None => info.get_primary("audio") == Some(stream),
+        };
         let bitrate = stream
             .bit_rate
             .as_ref()
@@ -595,7 +601,10 @@ pub async fn create_subtitles(
     let subtitles = info.find_by_type("subtitle");
 
     for stream in subtitles {
-        let is_default = info.get_primary("subtitle") == Some(stream);
+        let is_default = match media.default_subtitle_stream {
+            Some(index) => stream.index == index,
+            None => info.get_primary("subtitle") == Some(stream),
+        };
 
         if !["subrip", "ass", "ssa", "srt", "webvtt", "vtt"].contains(&stream.codec_name.as_str()) {
             // FIXME: hdmv_pgs_subtitle are not supported yet.
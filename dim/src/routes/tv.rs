@@ -1,14 +1,29 @@
 use crate::core::DbConnection;
 use crate::errors;
 
+use crate::scanners::tv_show::get_show_structure;
+use crate::scanners::tv_show::rematch_show;
+
 use auth::Wrapper as Auth;
 
 use database::episode::{Episode, UpdateEpisode};
+use database::library::MediaType;
+use database::media::Media;
 use database::season::{Season, UpdateSeason};
+use database::tv::TVShow;
+
+use serde::Deserialize;
 
 use warp::http::status::StatusCode;
 use warp::reply;
 
+/// Struct holds the body of a request changing the season offset of a tv show.
+#[derive(Deserialize)]
+pub struct SeasonOffset {
+    /// Offset added to the season number of each file before it is matched.
+    pub offset: i64,
+}
+
 pub mod filters {
     use warp::reject;
     use warp::Filter;
@@ -34,6 +49,37 @@ pub mod filters {
             })
     }
 
+    pub fn get_tv_structure(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
+        warp::path!("api" / "v1" / "tv" / i64 / "structure")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|id: i64, auth: Auth, conn: DbConnection| async move {
+                super::get_tv_structure(conn, id, auth)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
+    pub fn set_season_offset(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
+        warp::path!("api" / "v1" / "tv" / i64 / "season_offset")
+            .and(warp::post())
+            .and(warp::body::json::<super::SeasonOffset>())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(
+                |id: i64, data: super::SeasonOffset, auth: Auth, conn: DbConnection| async move {
+                    super::set_season_offset(conn, id, data, auth)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
     pub fn get_season_by_id(
         conn: DbConnection,
     ) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
@@ -138,6 +184,54 @@ pub async fn get_tv_seasons(
     Ok(reply::json(&Season::get_all(&conn, id).await?))
 }
 
+/// Method mapped to `GET /api/v1/tv/<id>/structure` returns every season and episode of the TV
+/// Show mapped to the id passed in, including the episodes we dont have. Episodes we have a file
+/// for are marked as owned.
+///
+/// # Arguments
+/// * `id` - id of the tv show we want info about
+pub async fn get_tv_structure(
+    conn: DbConnection,
+    id: i64,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    if Media::get(&conn, id).await?.media_type != MediaType::Tv {
+        return Err(errors::DimError::NotFoundError);
+    }
+
+    Ok(reply::json(&get_show_structure(&conn, id).await?))
+}
+
+/// Method mapped to `POST /api/v1/tv/<id>/season_offset` sets the season offset of the TV Show
+/// mapped to the id passed in and matches its episodes again. The offset is added to the season
+/// number of each file, ie a offset of `-1` matches `Season 2` on disk with season 1.
+///
+/// # Arguments
+/// * `id` - id of the tv show
+///
+/// # Data
+/// This route additionally requires you to pass in a json object by the format of
+/// `SeasonOffset`.
+pub async fn set_season_offset(
+    conn: DbConnection,
+    id: i64,
+    data: SeasonOffset,
+    user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    if !user.0.claims.has_role("owner") {
+        return Err(errors::DimError::Unauthorized);
+    }
+
+    if Media::get(&conn, id).await?.media_type != MediaType::Tv {
+        return Err(errors::DimError::NotFoundError);
+    }
+
+    TVShow::set_season_offset(&conn, id, data.offset).await?;
+    rematch_show(&conn, id).await?;
+
+    Ok(StatusCode::OK)
+}
+
 /// Method mapped to `GET /api/v1/tv/<id>/season/<season_num>` returns info about the season
 /// <season_num> for tv show by <id>
 ///
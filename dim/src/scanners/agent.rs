@@ -0,0 +1,85 @@
+//! Sources of metadata that media can be matched against. TMDB is queried over the network, while
+//! a [`DumpAgent`](super::dump::DumpAgent) looks media up in a locally imported dump of TMDB.
+use super::tmdb::Tmdb;
+use super::tmdb::TmdbError;
+use super::ApiMedia;
+use super::ApiSeason;
+
+use async_trait::async_trait;
+
+/// Enum holds the databases whose ids media can be looked up by, besides the ids of the agent
+/// itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExternalIdSource {
+    /// IMDB ids, ie `tt1375666`.
+    Imdb,
+    /// TheTVDB ids, ie `81189`.
+    Tvdb,
+}
+
+impl ExternalIdSource {
+    /// Method returns the name TMDB knows the source as.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Imdb => "imdb_id",
+            Self::Tvdb => "tvdb_id",
+        }
+    }
+}
+
+#[async_trait]
+pub trait MetadataAgent: Send {
+    /// Method returns the best match for `title`, preferably one released in `year`.
+    async fn search(&mut self, title: String, year: Option<i32>) -> Result<ApiMedia, TmdbError>;
+
+    /// Method returns the media whose id in the database `source` is `external_id`, ie a IMDB id
+    /// found in a nfo file. Agents that cant look media up by id find nothing.
+    async fn search_by_external_id(
+        &mut self,
+        _external_id: &str,
+        _source: ExternalIdSource,
+    ) -> Result<ApiMedia, TmdbError> {
+        Err(TmdbError::NoResults)
+    }
+
+    /// Method returns the seasons of the tv show with id `id`, along with their episodes.
+    async fn seasons(&mut self, id: u64) -> Result<Vec<ApiSeason>, TmdbError>;
+}
+
+#[async_trait]
+impl MetadataAgent for Tmdb {
+    async fn search(&mut self, title: String, year: Option<i32>) -> Result<ApiMedia, TmdbError> {
+        Tmdb::search(self, title, year).await
+    }
+
+    async fn search_by_external_id(
+        &mut self,
+        external_id: &str,
+        source: ExternalIdSource,
+    ) -> Result<ApiMedia, TmdbError> {
+        self.find_by_external_id(external_id, source)
+            .await
+            .map(Into::into)
+    }
+
+    async fn seasons(&mut self, id: u64) -> Result<Vec<ApiSeason>, TmdbError> {
+        let mut seasons: Vec<ApiSeason> = self
+            .get_seasons_for(id)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+
+        for season in seasons.iter_mut() {
+            season.episodes = self
+                .get_episodes_for(id, season.season_number)
+                .await
+                .unwrap_or_default()
+                .into_iter()
+                .map(Into::into)
+                .collect();
+        }
+
+        Ok(seasons)
+    }
+}
@@ -0,0 +1,146 @@
+//! Helpers used to pick up artwork that users keep next to their media, the way Kodi and Jellyfin
+//! expect it, ie `Movie (2010)/poster.jpg` or `Show/fanart.jpg`.
+use crate::core::METADATA_PATH;
+
+use database::asset::InsertableAsset;
+use database::DbConnection;
+
+use slog::warn;
+use slog::Logger;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Extensions of images we are willing to use as artwork.
+const IMAGE_EXTS: &[&str] = &["jpg", "jpeg", "png"];
+
+/// Enum holds the kinds of artwork a media can have.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArtworkKind {
+    Poster,
+    Backdrop,
+}
+
+impl ArtworkKind {
+    /// Method returns the file stems we look for, in order of preference.
+    fn names(&self) -> &'static [&'static str] {
+        match self {
+            Self::Poster => &["poster", "folder", "cover"],
+            Self::Backdrop => &["fanart", "backdrop", "background"],
+        }
+    }
+}
+
+/// Function returns the directories we look for artwork of the media `target_file` belongs to.
+/// Episodes usually live in a season folder, so for tv shows we also look one level up.
+pub fn artwork_dirs(target_file: &str, tv: bool) -> Vec<PathBuf> {
+    let dir = match Path::new(target_file).parent() {
+        Some(x) => x,
+        None => return Vec::new(),
+    };
+
+    let mut dirs = vec![dir.to_path_buf()];
+
+    if tv {
+        if let Some(parent) = dir.parent() {
+            dirs.push(parent.to_path_buf());
+        }
+    }
+
+    dirs
+}
+
+/// Function returns the first sidecar image of type `kind` found in `dirs`. Names are matched
+/// ignoring case, so both `Poster.JPG` and `poster.jpg` are picked up.
+pub fn find_sidecar(dirs: &[PathBuf], kind: ArtworkKind) -> Option<PathBuf> {
+    for dir in dirs {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(x) => x
+                .filter_map(Result::ok)
+                .map(|x| x.path())
+                .filter(|x| x.is_file())
+                .collect::<Vec<_>>(),
+            Err(_) => continue,
+        };
+
+        for name in kind.names() {
+            let found = entries.iter().find(|path| {
+                let stem = path.file_stem().and_then(|x| x.to_str());
+                let ext = path.extension().and_then(|x| x.to_str());
+
+                match (stem, ext) {
+                    (Some(stem), Some(ext)) => {
+                        stem.eq_ignore_ascii_case(name)
+                            && IMAGE_EXTS.iter().any(|x| ext.eq_ignore_ascii_case(x))
+                    }
+                    _ => false,
+                }
+            });
+
+            if let Some(found) = found {
+                return Some(found.clone());
+            }
+        }
+    }
+
+    None
+}
+
+/// Function copies a sidecar image into our metadata directory, so that it is served like any
+/// other poster, and inserts it as a asset. The copy is refreshed every time the media is matched
+/// so edits to the original image are picked up on rescans. Returns the id of the asset.
+pub async fn insert_sidecar(conn: &DbConnection, log: &Logger, image: &Path) -> Option<i64> {
+    let ext = image
+        .extension()
+        .and_then(|x| x.to_str())
+        .unwrap_or("jpg")
+        .to_lowercase();
+
+    // NOTE: Sidecar images tend to all be called the same, so we name our copy after the full
+    // path of the original.
+    let mut hasher = DefaultHasher::new();
+    image.hash(&mut hasher);
+    let file_name = format!("local/{:016x}.{}", hasher.finish(), ext);
+
+    let mut out_path = PathBuf::from(METADATA_PATH.get()?);
+    out_path.push(&file_name);
+
+    let copied = out_path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::copy(image, &out_path));
+
+    if let Err(e) = copied {
+        warn!(
+            log,
+            "Failed to copy local artwork";
+            "file" => image.to_string_lossy().to_string(),
+            "reason" => e.to_string(),
+        );
+        return None;
+    }
+
+    let asset = InsertableAsset {
+        remote_url: None,
+        local_path: format!("images/{}", file_name),
+        file_ext: ext,
+    }
+    .insert(conn)
+    .await;
+
+    match asset {
+        Ok(x) => Some(x.id),
+        Err(e) => {
+            warn!(
+                log,
+                "Failed to insert local artwork into db";
+                "file" => image.to_string_lossy().to_string(),
+                "reason" => e.to_string(),
+            );
+            None
+        }
+    }
+}
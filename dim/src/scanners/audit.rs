@@ -0,0 +1,77 @@
+//! Read-only quality checks of the titles we parsed out of filenames, used to find files that
+//! should be renamed so they match properly.
+use super::filename;
+
+use database::mediafile::MediaFile;
+use database::DatabaseError;
+use database::DbConnection;
+
+use serde::Serialize;
+
+/// Titles with fewer alphanumeric characters than this are considered suspiciously short.
+const MIN_TITLE_CHARS: usize = 2;
+
+/// Struct describes a file whose parsed title probably went wrong.
+#[derive(Serialize, Clone, Debug)]
+pub struct ParseWarning {
+    pub mediafile_id: i64,
+    pub target_file: String,
+    pub raw_name: String,
+    pub issue: ParseIssue,
+}
+
+/// Enum holds what is wrong with a parsed title.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ParseIssue {
+    /// Nothing was left of the title after parsing.
+    Empty,
+    /// The title is too short to be anything meaningful.
+    TooShort,
+    /// The title still contains release tags, ie `1080p` or `x264`.
+    Noise { tokens: Vec<String> },
+}
+
+/// Function returns what is wrong with a parsed title, if anything.
+pub fn check_title(raw_name: &str) -> Option<ParseIssue> {
+    let chars = raw_name.chars().filter(|c| c.is_alphanumeric()).count();
+
+    if chars == 0 {
+        return Some(ParseIssue::Empty);
+    }
+
+    if chars < MIN_TITLE_CHARS {
+        return Some(ParseIssue::TooShort);
+    }
+
+    let tokens = filename::noise_tokens(raw_name);
+
+    if !tokens.is_empty() {
+        return Some(ParseIssue::Noise { tokens });
+    }
+
+    None
+}
+
+/// Function audits the titles of all files in a library and returns a warning for each file whose
+/// title was probably mis-parsed.
+pub async fn parse_audit(
+    conn: &DbConnection,
+    library_id: i64,
+) -> Result<Vec<ParseWarning>, DatabaseError> {
+    let files = MediaFile::get_by_lib(conn, library_id).await?;
+
+    Ok(files
+        .into_iter()
+        .filter_map(|file| {
+            let issue = check_title(&file.raw_name)?;
+
+            Some(ParseWarning {
+                mediafile_id: file.id,
+                target_file: file.target_file,
+                raw_name: file.raw_name,
+                issue,
+            })
+        })
+        .collect())
+}
@@ -1,23 +1,49 @@
 use err_derive::Error;
+use std::cmp::Ordering;
+use std::future::Future;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
+use database::library::LibrarySettings;
 use database::library::MediaType;
+use database::library::TvAgent;
+use database::match_cache::CachedMatch;
 use database::mediafile::InsertableMediaFile;
 use database::mediafile::MediaFile;
+use database::mediafile::MountSource;
 use database::mediafile::UpdateMediaFile;
+use database::song::Song;
+use database::subtitle::InsertableExternalSubtitle;
+use database::track::InsertableTrack;
 use database::DbConnection;
 
 use crate::core::EventTx;
+use crate::get_global_settings;
+use crate::scanners::agent::ExternalIdSource;
+use crate::scanners::agent::MetadataAgent;
+use crate::scanners::disc;
+use crate::scanners::dump::DumpAgent;
+use crate::scanners::filename;
+use crate::scanners::fingerprint;
 use crate::scanners::movie::MovieMatcher;
+use crate::scanners::music;
+use crate::scanners::music::MusicMatcher;
+use crate::scanners::nfo;
+use crate::scanners::subtitles;
+use crate::scanners::tmdb::Media as TmdbMedia;
 use crate::scanners::tmdb::Tmdb;
+use crate::scanners::tmdb::TmdbError;
 use crate::scanners::tv_show::TvShowMatcher;
+use crate::scanners::tvdb::TvdbAgent;
+use crate::streaming::ffprobe::FFPWrapper;
 use crate::streaming::ffprobe::FFProbeCtx;
 use crate::streaming::FFPROBE_BIN;
 
 use super::ApiMedia;
-
-use torrent_name_parser::Metadata;
+use super::ApiSeason;
+use super::MediaTypeMismatch;
 
 use slog::debug;
 use slog::error;
@@ -25,6 +51,7 @@ use slog::info;
 use slog::o;
 use slog::warn;
 
+use serde::de::DeserializeOwned;
 use serde::Serialize;
 
 use tokio::task::spawn_blocking;
@@ -37,18 +64,52 @@ use anitomy::Anitomy;
 use anitomy::ElementCategory;
 use anitomy::Elements;
 
+use once_cell::sync::Lazy;
+
 #[derive(Debug, Error, Serialize, Clone)]
 pub enum ScannerError {
     #[error(display = "Could not get a connection to the db")]
     DatabaseConnectionError,
     #[error(display = "The filename parser returned no useful results")]
     FilenameParserError,
-    #[error(display = "Something happened to ffprobe")]
-    FFProbeError,
+    #[error(display = "Could not run ffprobe on {} ({})", _0, _1)]
+    FFProbeError(String, String),
     #[error(display = "An unknown error has occured")]
     UnknownError,
+    #[error(display = "The file has already been mounted")]
+    AlreadyMounted,
+    #[error(display = "Scanning the file took too long")]
+    FileTimeout,
     #[error(display = "Database error why={}", _0)]
     DatabaseError(String),
+    #[error(display = "Invalid library location ({})", _0)]
+    InvalidLocation(String),
+    #[error(display = "Could not read the TMDB dump ({})", _0)]
+    InvalidDump(String),
+    #[error(display = "The file looks like a {} but the library holds {}s", _0, _1)]
+    MediaTypeMismatch(MediaType, MediaType),
+    #[error(display = "Gave up matching the file as TMDB kept rate limiting us")]
+    RateLimited,
+    #[error(display = "Could not run {}, is ffprobe installed?", _0)]
+    FFProbeMissing(String),
+    #[error(
+        display = "Best match scored {:.2}, below the match threshold of {:.2}",
+        _0,
+        _1
+    )]
+    LowConfidence(f64, f64),
+    #[error(display = "No match was found for the file")]
+    NoMatch,
+    #[error(display = "The disc at {} holds no title we could mount", _0)]
+    NoDiscTitle(String),
+}
+
+impl ScannerError {
+    /// Method returns whether the file simply didnt match anything, ie because TMDB doesnt know of
+    /// it yet, rather than matching failing.
+    pub fn is_no_match(&self) -> bool {
+        matches!(self, Self::NoMatch | Self::LowConfidence(..))
+    }
 }
 
 impl From<database::DatabaseError> for ScannerError {
@@ -80,121 +141,841 @@ impl MetadataExtractor {
         }
     }
 
+    /// Handler mounts the file at `file` into the library `library_id`. The title, year, season
+    /// and episode parsed from the name of a file that is already mounted are kept as they are,
+    /// thus the filename parser only ever runs on paths we dont know yet. The same goes for
+    /// ffprobe, except when `reprobe_mounted` is set, in which case mounted files are probed
+    /// again to flag the ones that were replaced. `settings` are the settings of the library,
+    /// loaded once by the caller rather than for every file.
     #[handler]
     pub async fn mount_file(
         &mut self,
         file: PathBuf,
         library_id: i64,
-        _media_type: MediaType,
+        media_type: MediaType,
+        source: MountSource,
+        settings: Arc<LibrarySettings>,
     ) -> Result<MediaFile, ScannerError> {
-        let target_file = file.to_str().unwrap().to_owned();
+        let media_file = parse_unmounted(
+            &self.conn,
+            &self.logger,
+            &file,
+            library_id,
+            media_type,
+            source,
+            &settings,
+        )
+        .await?;
 
-        let _file_name = if let Some(file_name) = file.file_name().and_then(|x| x.to_str()) {
-            file_name
-        } else {
-            warn!(
-                self.logger,
-                "Received non-unicode filename";
-                "file" => target_file,
+        insert_file(&self.conn, &self.logger, media_file).await
+    }
+
+    /// Handler parses the file at `file` like [`mount_file`](Self::mount_file) would, but leaves
+    /// inserting it to the caller so that files can be inserted in batches with [`insert_batch`].
+    #[handler]
+    pub async fn parse_new_file(
+        &mut self,
+        file: PathBuf,
+        library_id: i64,
+        media_type: MediaType,
+        source: MountSource,
+        settings: Arc<LibrarySettings>,
+    ) -> Result<InsertableMediaFile, ScannerError> {
+        parse_unmounted(
+            &self.conn,
+            &self.logger,
+            &file,
+            library_id,
+            media_type,
+            source,
+            &settings,
+        )
+        .await
+    }
+}
+
+/// Function returns the file we mount for `file`, which is `file` itself unless it is a disc
+/// folder, in which case it is the main title of the disc.
+pub(super) async fn resolve_disc(file: &Path) -> Result<PathBuf, ScannerError> {
+    if !disc::is_disc_folder(file) {
+        return Ok(file.to_path_buf());
+    }
+
+    disc::cached_main_title(file)
+        .await
+        .ok_or_else(|| ScannerError::NoDiscTitle(file.to_string_lossy().to_string()))
+}
+
+/// Function parses `file` unless it is already mounted, in which case we only check whether it
+/// has changed since and return [`ScannerError::AlreadyMounted`].
+async fn parse_unmounted(
+    conn: &DbConnection,
+    log: &slog::Logger,
+    file: &Path,
+    library_id: i64,
+    media_type: MediaType,
+    source: MountSource,
+    settings: &LibrarySettings,
+) -> Result<InsertableMediaFile, ScannerError> {
+    let file = resolve_disc(file).await?;
+    let file = file.as_path();
+    let target_file = file.to_str().unwrap().to_owned();
+
+    if let Ok(media_file) = MediaFile::get_by_file(conn, &target_file).await {
+        debug!(
+            log,
+            "File already exists in the db";
+            "file" => &target_file,
+            "library_id" => library_id,
+        );
+
+        if get_global_settings().reprobe_mounted && media_file.stale != Some(true) {
+            if let Ok(parsed) =
+                parse_file(log, file, library_id, media_type, source, settings).await
+            {
+                flag_if_stale(conn, log, &media_file, &parsed).await;
+            }
+        } else if media_file.mtime.is_none() {
+            // NOTE: Files mounted before we kept track of modification times are assumed to
+            // be unchanged since.
+            let update = UpdateMediaFile {
+                mtime: super::modified_secs(file),
+                ..Default::default()
+            };
+
+            let _ = update.update(conn, media_file.id).await;
+        }
+
+        return Err(ScannerError::AlreadyMounted);
+    }
+
+    parse_file(log, file, library_id, media_type, source, settings).await
+}
+
+/// Function inserts a single parsed file and returns the row it ended up as. Files that were
+/// mounted concurrently are reported as [`AlreadyMounted`](ScannerError::AlreadyMounted).
+async fn insert_file(
+    conn: &DbConnection,
+    log: &slog::Logger,
+    media_file: InsertableMediaFile,
+) -> Result<MediaFile, ScannerError> {
+    // NOTE: Another extractor might have mounted the same file while we were probing it, in
+    // which case the unique constraint on `target_file` kicks in. The row that made it into the db
+    // first is matched by whoever mounted it.
+    let file_id = match media_file.insert(conn).await {
+        Ok(x) => x,
+        Err(e) if e.is_unique_violation() => {
+            debug!(
+                log,
+                "File was mounted concurrently";
+                "file" => &media_file.target_file,
+                "library_id" => media_file.library_id,
             );
-            return Err(ScannerError::UnknownError);
-        };
+            return Err(ScannerError::AlreadyMounted);
+        }
+        Err(e) => return Err(e.into()),
+    };
 
-        let target_file_clone = target_file.clone();
-        let res = MediaFile::get_by_file(&self.conn, &target_file_clone).await;
+    finish_mount(conn, log, MediaFile::get_one(conn, file_id).await?).await
+}
 
-        if let Ok(_media_file) = res {
+/// Function inserts several parsed files at once, which saves a transaction per file. The results
+/// are returned in the order the files were supplied. Should the batch fail as a whole, for
+/// example because one of the files got mounted concurrently, the files are inserted one by one
+/// instead.
+pub(super) async fn insert_batch(
+    conn: &DbConnection,
+    log: &slog::Logger,
+    files: Vec<InsertableMediaFile>,
+) -> Vec<Result<MediaFile, ScannerError>> {
+    let inserted = match InsertableMediaFile::insert_many(conn, &files).await {
+        Ok(x) => x,
+        Err(e) => {
             debug!(
-                self.logger,
-                "File already exists in the db";
-                "file" => file.to_string_lossy().to_string(),
-                "library_id" => library_id,
+                log,
+                "Batch insert failed, inserting files one by one";
+                "files" => files.len(),
+                "reason" => e.to_string(),
             );
-            return Err(ScannerError::UnknownError);
+
+            let mut results = Vec::with_capacity(files.len());
+            for file in files {
+                results.push(insert_file(conn, log, file).await);
+            }
+
+            return results;
         }
+    };
 
-        let ctx = FFProbeCtx::new(&FFPROBE_BIN);
+    let mut results = Vec::with_capacity(inserted.len());
+    for media_file in inserted {
+        results.push(finish_mount(conn, log, media_file).await);
+    }
 
+    results
+}
+
+async fn finish_mount(
+    conn: &DbConnection,
+    log: &slog::Logger,
+    media_file: MediaFile,
+) -> Result<MediaFile, ScannerError> {
+    info!(
+        log,
+        "Scanned file";
+        "file" => &media_file.target_file,
+        "library_id" => media_file.library_id,
+        "id" => media_file.id,
+        "season" => media_file.season.unwrap_or(0),
+        "episode" => media_file.episode.unwrap_or(0),
+    );
+
+    // NOTE: Only files holding a video can be fingerprinted, which songs dont.
+    let has_video = media_file.codec.is_some();
+    if get_global_settings().video_fingerprints && media_file.problem.is_none() && has_video {
+        fingerprint::insert_into_queue(conn, log, &media_file).await;
+    }
+
+    record_subtitles(conn, log, &media_file).await;
+
+    Ok(media_file)
+}
+
+/// Function records the external subtitles kept next to a freshly mounted file.
+async fn record_subtitles(conn: &DbConnection, log: &slog::Logger, media_file: &MediaFile) {
+    let video = PathBuf::from(&media_file.target_file);
+    let subtitles = spawn_blocking(move || subtitles::find_external_subtitles(&video))
+        .await
+        .unwrap_or_default();
+
+    for subtitle in subtitles {
+        let insertable = InsertableExternalSubtitle {
+            mediafile_id: media_file.id,
+            path: subtitle.path,
+            language: subtitle.language,
+            forced: subtitle.forced,
+        };
+
+        if let Err(e) = insertable.insert(conn).await {
+            warn!(
+                log,
+                "Failed to record external subtitles";
+                "file" => &insertable.path,
+                "mediafile_id" => media_file.id,
+                "reason" => e.to_string(),
+            );
+        }
+    }
+}
+
+/// Function parses the filename of `file` and probes it with ffprobe, returning what the file
+/// would be mounted as with the library `settings`. Songs are parsed by their tags instead, see
+/// [`parse_song`]. Nothing is written to the db.
+pub(super) async fn parse_file(
+    log: &slog::Logger,
+    file: &Path,
+    library_id: i64,
+    media_type: MediaType,
+    source: MountSource,
+    settings: &LibrarySettings,
+) -> Result<InsertableMediaFile, ScannerError> {
+    let file = file.to_path_buf();
+    let target_file = file.to_str().unwrap().to_owned();
+
+    let original_filename = if let Some(file_name) = file.file_name().and_then(|x| x.to_str()) {
+        file_name.to_owned()
+    } else {
+        warn!(
+            log,
+            "Received non-unicode filename";
+            "file" => target_file,
+        );
+        return Err(ScannerError::UnknownError);
+    };
+
+    if media_type == MediaType::Music {
+        return parse_song(log, &file, library_id, source, settings).await;
+    }
+
+    // NOTE: The streams of a disc carry meaningless names, thus we parse the name of the
+    // folder that encloses the disc instead.
+    let file_name_clone = if let Some(title) = disc::title_folder(&file) {
+        title
+    } else {
         // we clone so that we can strip the extension.
         let mut file_name_clone = file.to_owned();
         file_name_clone.set_extension("");
         // unwrap will never panic because we validate the path earlier on.
-        let file_name_clone = file_name_clone
+        file_name_clone
             .file_name()
             .unwrap()
             .to_str()
             .unwrap()
-            .to_owned();
+            .to_owned()
+    };
 
-        let clone = file_name_clone.clone().replace(|c: char| !c.is_ascii(), "");
+    // NOTE: Users can force a match by tagging the id of a movie in its filename or folder, ie
+    // `Movie (2020) {tmdb-12345}.mkv`. The tags would only confuse the filename parser.
+    let (file_name_clone, mut id_tags) = filename::id_tags(&file_name_clone);
+    if id_tags == Default::default() {
+        if let Some(folder) = file
+            .parent()
+            .and_then(|x| x.file_name())
+            .and_then(|x| x.to_str())
+        {
+            id_tags = filename::id_tags(folder).1;
+        }
+    }
 
-        // closure needs to be bound because of a lifetime bug where the closure passed to
-        // `spawn_blocking` lives more than the data moved into it thus we cant pass a reference to
-        // `Metadata::from` directly.
-        let meta_from_string =
-            move || Metadata::from(&clone).map_err(|_| ScannerError::FilenameParserError);
+    // NOTE: Libraries of hand named files can bring a pattern of their own, names it matches
+    // skip our filename parser entirely.
+    let custom =
+        settings
+            .filename_pattern
+            .as_deref()
+            .and_then(|pattern| match regex::Regex::new(pattern) {
+                Ok(x) => filename::parse_custom(&x, &file_name_clone),
+                Err(e) => {
+                    warn!(
+                        log,
+                        "Invalid filename pattern";
+                        "pattern" => pattern,
+                        "reason" => e.to_string(),
+                    );
+                    None
+                }
+            });
+    let is_custom = custom.is_some();
 
-        let metadata = match spawn_blocking(meta_from_string).await {
-            Ok(x) => x?,
-            Err(e) => {
-                error!(self.logger, "Metadata::from possibly panic'd"; "e" => format!("{:?}", e));
-                return Err(ScannerError::UnknownError);
+    let (parsed, anime_episode) = match custom {
+        Some(x) => (x, None),
+        None => parse_name(log, &file_name_clone).await?,
+    };
+
+    let ffprobe_data = probe_file(log, &file).await?;
+
+    let container_mismatch = file
+        .extension()
+        .and_then(|x| x.to_str())
+        .and_then(|x| ffprobe_data.container_matches_ext(x))
+        .map(|x| !x);
+
+    if container_mismatch == Some(true) {
+        warn!(
+            log,
+            "File extension doesnt match its container";
+            "file" => &target_file,
+            "container" => ffprobe_data.get_container(),
+        );
+    }
+
+    // NOTE: The filename parser misses years that arent wrapped in parentheses, in which case
+    // they usually end up glued to the end of the title.
+    let mut raw_name = anime_episode
+        .as_ref()
+        .map_or_else(|| parsed.title.clone(), |(title, _)| title.clone());
+    let raw_year = match parsed.year {
+        Some(x) => Some(x),
+        None if is_custom => None,
+        None => filename::year_from_name(&file_name_clone).map(|year| {
+            if let Some(title) = raw_name.strip_suffix(&year.to_string()) {
+                raw_name = title.trim_end().to_owned();
             }
-        };
 
-        let ffprobe_data = if let Ok(data) = ctx.get_meta(&file) {
-            data
-        } else {
+            year
+        }),
+    };
+
+    // NOTE: Movies split over several files carry a `cd1` or `part1` tag which has to go for
+    // all parts to end up with the same title.
+    let part_number = filename::split_part(&file_name_clone).map(|(_, x)| x);
+
+    if part_number.is_some() {
+        if let Some((title, _)) = filename::split_part(&raw_name) {
+            raw_name = title;
+        }
+    }
+
+    // NOTE: Shows are often kept as `Show/Season 01/e01.mkv`, whose episodes dont repeat the
+    // title of the show, in which case the folders tell us the show and season.
+    let show_folder = match media_type {
+        MediaType::Tv if !is_custom && filename::is_weak_title(&raw_name) => {
+            filename::show_from_folders(&file)
+        }
+        _ => None,
+    };
+
+    let weak_episode = show_folder
+        .as_ref()
+        .and_then(|_| filename::episode_from_weak_title(&file_name_clone));
+    let (raw_name, raw_year) = match &show_folder {
+        Some(x) => (x.title.clone(), x.year.or(raw_year)),
+        None => (raw_name, raw_year),
+    };
+
+    // NOTE: Seasons released in parts are usually kept in folders like `Season 4 Part 2`, whose
+    // files are sometimes tagged as a season of their own, thus the folder decides the season.
+    // Episode numbers are kept as they are.
+    let season = match filename::season_from_folder(&file) {
+        Some((season, Some(_))) if get_global_settings().group_season_parts => Some(season),
+        _ => parsed.season.or_else(|| show_folder.map(|x| x.season)),
+    };
+
+    let episode = anime_episode
+        .map(|(_, x)| x)
+        .or(parsed.episode)
+        .or(weak_episode);
+    let season =
+        filename::episode_zero_season(season, episode, get_global_settings().episode_zero_specials);
+
+    // NOTE: The filename parser only picks up the first episode of files holding several, ie
+    // `Show.S01E01-E03.mkv`.
+    let episode_end = filename::episode_range(&file_name_clone)
+        .filter(|(start, _)| episode == Some(*start))
+        .map(|(_, end)| end);
+
+    // NOTE: Misnamed audio files and broken remuxes probe just fine, but would end up as
+    // movies that cant be played.
+    let problem = match ffprobe_data.is_corrupt() {
+        Some(true) => None,
+        _ => ffprobe_data.get_problem(),
+    };
+
+    if let Some(problem) = problem {
+        warn!(
+            log,
+            "File cant be played as a video";
+            "file" => &target_file,
+            "problem" => problem,
+        );
+    }
+
+    // NOTE: Users that curate their movies with other tools often keep a nfo file next to them
+    // whose ids and title are far more reliable than whatever we can make of the filename.
+    let nfo = if media_type == MediaType::Movie {
+        let file = file.clone();
+        spawn_blocking(move || nfo::read_nfo(&file))
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+    } else {
+        // NOTE: Only movies are ever matched by their id.
+        id_tags = Default::default();
+        Default::default()
+    };
+
+    let raw_name = nfo
+        .title
+        .unwrap_or_else(|| filename::strip_tokens(&raw_name, &get_global_settings().strip_tokens));
+    let raw_year = nfo.year.or(raw_year);
+
+    let default_audio_stream = ffprobe_data
+        .find_by_language("audio", &settings.audio_languages)
+        .map(|x| x.index);
+    let default_subtitle_stream = ffprobe_data
+        .find_by_language("subtitle", &settings.subtitle_languages)
+        .map(|x| x.index);
+
+    Ok(InsertableMediaFile {
+        library_id,
+        media_id: None,
+        target_file: target_file.to_string(),
+
+        raw_name,
+        raw_year,
+        season,
+        episode,
+
+        quality: ffprobe_data.get_height().map(|x| x.to_string()),
+        codec: ffprobe_data.get_video_codec(),
+        container: ffprobe_data.get_container(),
+        audio: ffprobe_data
+            .get_primary_codec("audio")
+            .map(ToOwned::to_owned),
+        original_resolution: Default::default(),
+        duration: ffprobe_data.get_duration().map(|x| x as i64),
+        corrupt: ffprobe_data.is_corrupt(),
+        revision: Some(filename::release_revision(&file_name_clone)),
+        original_filename: Some(original_filename),
+        container_mismatch,
+        audio_channels: ffprobe_data.get_primary("audio").and_then(|x| x.channels),
+        default_audio_stream,
+        default_subtitle_stream,
+        mount_source: Some(source.as_str().to_owned()),
+        problem: problem.map(ToOwned::to_owned),
+        fingerprint: None,
+        stale: None,
+        mtime: super::modified_secs(&file),
+        tmdb_id: id_tags.tmdb_id.or(nfo.tmdb_id),
+        imdb_id: id_tags.imdb_id.or(nfo.imdb_id),
+        part_number,
+        episode_end,
+        match_score: None,
+        frame_rate: ffprobe_data.get_frame_rate(),
+        bit_depth: ffprobe_data.get_bit_depth(),
+        hdr: ffprobe_data.get_hdr(),
+        tracks: tracks_of(&ffprobe_data),
+        song: None,
+    })
+}
+
+/// Function probes `file` with ffprobe off the runtime. Files ffprobe hangs on are returned as
+/// corrupt.
+async fn probe_file(log: &slog::Logger, file: &Path) -> Result<FFPWrapper, ScannerError> {
+    let target_file = file.to_string_lossy().to_string();
+    let ctx = FFProbeCtx::new(&FFPROBE_BIN)
+        .timeout(Duration::from_secs(get_global_settings().ffprobe_timeout));
+
+    // NOTE: ffprobe takes a while on large files, so we run it off the runtime to let the
+    // other extractors probe their files in the meantime.
+    let probe = {
+        let file = file.to_path_buf();
+        spawn_blocking(move || ctx.get_meta(&file))
+            .await
+            .map_err(|e| e.to_string())
+    };
+
+    // NOTE: Files that make ffprobe hang are usually broken, so we mount them as corrupt
+    // instead of stalling on them every scan.
+    let probe = probe.and_then(|x| match x {
+        Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
+            warn!(
+                log,
+                "ffprobe timed out, marking file as corrupt";
+                "file" => &target_file,
+            );
+            Ok(FFPWrapper::corrupt())
+        }
+        x => x.map_err(|e| e.to_string()),
+    });
+
+    match probe {
+        Ok(x) => Ok(x),
+        Err(e) => {
             error!(
-                self.logger,
+                log,
                 "Couldnt extract media information with ffprobe";
-                "file" => file.to_string_lossy().to_string(),
+                "file" => &target_file,
+                "reason" => &e,
             );
-            return Err(ScannerError::FFProbeError);
-        };
+            Err(ScannerError::FFProbeError(target_file, e))
+        }
+    }
+}
 
-        let media_file = InsertableMediaFile {
-            library_id,
-            media_id: None,
-            target_file: target_file.to_string(),
-
-            raw_name: metadata.title().to_owned(),
-            raw_year: metadata.year().map(|x| x as i64),
-            season: metadata.season().map(|x| x as i64),
-            episode: metadata.episode().map(|x| x as i64),
-
-            quality: ffprobe_data.get_height().map(|x| x.to_string()),
-            codec: ffprobe_data.get_video_codec(),
-            container: ffprobe_data.get_container(),
-            audio: ffprobe_data
-                .get_primary_codec("audio")
-                .map(ToOwned::to_owned),
-            original_resolution: Default::default(),
-            duration: ffprobe_data.get_duration().map(|x| x as i64),
-            corrupt: ffprobe_data.is_corrupt(),
+/// Function probes the song at `file` and returns what it would be mounted as in the music
+/// library `library_id`. Songs are mounted by their tags, their album is kept as their title
+/// and the year their album was released in as their year. Nothing is written to the db.
+async fn parse_song(
+    log: &slog::Logger,
+    file: &Path,
+    library_id: i64,
+    source: MountSource,
+    settings: &LibrarySettings,
+) -> Result<InsertableMediaFile, ScannerError> {
+    let target_file = file.to_string_lossy().to_string();
+    let ffprobe_data = probe_file(log, file).await?;
+
+    let song = music::song_tags(file, &ffprobe_data);
+    let (raw_name, raw_year) = music::album_of(file, &song, &ffprobe_data);
+    let problem = song_problem(&ffprobe_data);
+
+    if let Some(problem) = problem {
+        warn!(
+            log,
+            "File cant be played as a song";
+            "file" => &target_file,
+            "problem" => problem,
+        );
+    }
+
+    let container_mismatch = file
+        .extension()
+        .and_then(|x| x.to_str())
+        .and_then(|x| ffprobe_data.container_matches_ext(x))
+        .map(|x| !x);
+
+    Ok(InsertableMediaFile {
+        library_id,
+        target_file,
+        raw_name,
+        raw_year,
+        container: ffprobe_data.get_container(),
+        audio: ffprobe_data
+            .get_primary_codec("audio")
+            .map(ToOwned::to_owned),
+        duration: ffprobe_data.get_duration().map(|x| x as i64),
+        corrupt: ffprobe_data.is_corrupt(),
+        original_filename: file
+            .file_name()
+            .and_then(|x| x.to_str())
+            .map(ToOwned::to_owned),
+        container_mismatch,
+        audio_channels: ffprobe_data.get_primary("audio").and_then(|x| x.channels),
+        default_audio_stream: ffprobe_data
+            .find_by_language("audio", &settings.audio_languages)
+            .map(|x| x.index),
+        mount_source: Some(source.as_str().to_owned()),
+        problem: problem.map(ToOwned::to_owned),
+        mtime: super::modified_secs(file),
+        tracks: tracks_of(&ffprobe_data),
+        song: Some(song),
+        ..Default::default()
+    })
+}
+
+/// Function returns why a song that ffprobe could read cant be played, if it cant. Songs are
+/// expected to hold no video, thus only a missing audio stream or a zero duration count.
+fn song_problem(ffprobe_data: &FFPWrapper) -> Option<&'static str> {
+    if ffprobe_data.is_corrupt() == Some(true) {
+        return None;
+    }
+
+    if ffprobe_data.get_primary("audio").is_none() {
+        return Some("no_audio");
+    }
+
+    match ffprobe_data.get_duration() {
+        Some(x) if x < 1 => Some("zero_duration"),
+        _ => None,
+    }
+}
+
+/// Function probes the corrupt mediafile `media_file` again, ie after it was repaired or downloaded
+/// once more, and updates its row with what ffprobe finds if the file now probes clean. Returns
+/// whether it does.
+pub(super) async fn reprobe_file(
+    conn: &DbConnection,
+    log: &slog::Logger,
+    media_file: &MediaFile,
+    settings: &LibrarySettings,
+) -> Result<bool, ScannerError> {
+    let file = PathBuf::from(&media_file.target_file);
+    let ctx = FFProbeCtx::new(&FFPROBE_BIN)
+        .timeout(Duration::from_secs(get_global_settings().ffprobe_timeout));
+
+    let ffprobe_data = {
+        let file = file.clone();
+        spawn_blocking(move || ctx.get_meta(&file))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|x| x.map_err(|e| e.to_string()))
+            .map_err(|e| ScannerError::FFProbeError(media_file.target_file.clone(), e))?
+    };
+
+    if ffprobe_data.is_corrupt() == Some(true) {
+        return Ok(false);
+    }
+
+    // NOTE: Songs hold no video, which would otherwise make them `audio_only`.
+    let problem = match Song::get_by_mediafile(conn, media_file.id).await? {
+        Some(_) => song_problem(&ffprobe_data),
+        None => ffprobe_data.get_problem(),
+    };
+
+    let container_mismatch = file
+        .extension()
+        .and_then(|x| x.to_str())
+        .and_then(|x| ffprobe_data.container_matches_ext(x))
+        .map(|x| !x);
+
+    let update = UpdateMediaFile {
+        quality: ffprobe_data.get_height().map(|x| x.to_string()),
+        codec: ffprobe_data.get_video_codec(),
+        container: ffprobe_data.get_container(),
+        audio: ffprobe_data
+            .get_primary_codec("audio")
+            .map(ToOwned::to_owned),
+        duration: ffprobe_data.get_duration().map(|x| x as i64),
+        corrupt: Some(false),
+        container_mismatch,
+        audio_channels: ffprobe_data.get_primary("audio").and_then(|x| x.channels),
+        default_audio_stream: ffprobe_data
+            .find_by_language("audio", &settings.audio_languages)
+            .map(|x| x.index),
+        default_subtitle_stream: ffprobe_data
+            .find_by_language("subtitle", &settings.subtitle_languages)
+            .map(|x| x.index),
+        problem: problem.map(ToOwned::to_owned),
+        mtime: super::modified_secs(&file),
+        frame_rate: ffprobe_data.get_frame_rate(),
+        bit_depth: ffprobe_data.get_bit_depth(),
+        hdr: ffprobe_data.get_hdr(),
+        ..Default::default()
+    };
+
+    update.update(conn, media_file.id).await?;
+
+    // NOTE: Corrupt files have no streams ffprobe could make sense of, thus no tracks either.
+    for track in tracks_of(&ffprobe_data) {
+        if let Err(e) = track.insert(conn, media_file.id).await {
+            warn!(
+                log,
+                "Failed to insert track of reprobed file";
+                "file" => &media_file.target_file,
+                "reason" => e.to_string(),
+            );
+        }
+    }
+
+    Ok(true)
+}
+
+/// Function parses `file_name` with our filename parser. Anime releases are parsed without their
+/// bracketed tags, in which case their episode number is returned too.
+async fn parse_name(
+    log: &slog::Logger,
+    file_name: &str,
+) -> Result<(filename::ParsedName, Option<(String, i64)>), ScannerError> {
+    // NOTE: Titles can be in any script, so only characters that dont affect the title are
+    // dropped, unless the user asked for ascii only filenames.
+    let clone = if get_global_settings().ascii_filenames {
+        file_name.replace(|c: char| !c.is_ascii(), "")
+    } else {
+        filename::strip_bidi_controls(file_name)
+    };
+
+    // NOTE: The group tag in front of anime releases is often mistaken for the title, so we
+    // parse the name without its bracketed tags.
+    let anime_name = if get_global_settings().anime_brackets {
+        filename::strip_anime_brackets(&clone)
+    } else {
+        None
+    };
+    let anime_episode = anime_name
+        .as_deref()
+        .and_then(filename::split_anime_episode);
+    let clone = anime_name.unwrap_or(clone);
+
+    // closure needs to be bound because of a lifetime bug where the closure passed to
+    // `spawn_blocking` lives more than the data moved into it thus we cant pass a reference to
+    // `filename::parse_release` directly.
+    let meta_from_string =
+        move || filename::parse_release(&clone).ok_or(ScannerError::FilenameParserError);
+
+    let parsed = match spawn_blocking(meta_from_string).await {
+        Ok(x) => x?,
+        Err(e) => {
+            error!(log, "Metadata::from possibly panic'd"; "e" => format!("{:?}", e));
+            return Err(ScannerError::UnknownError);
+        }
+    };
+
+    Ok((parsed, anime_episode))
+}
+
+/// Function returns the audio and subtitle streams ffprobe found in a file as we store them.
+fn tracks_of(ffprobe_data: &FFPWrapper) -> Vec<InsertableTrack> {
+    let audio = ffprobe_data
+        .get_audio_tracks()
+        .into_iter()
+        .map(|x| InsertableTrack {
+            stream_index: x.index,
+            kind: "audio".into(),
+            codec: x.codec,
+            language: x.language,
+            title: x.title,
+            channels: x.channels,
+            is_default: x.default,
+            forced: false,
+        });
+
+    let subtitles = ffprobe_data
+        .get_subtitle_tracks()
+        .into_iter()
+        .map(|x| InsertableTrack {
+            stream_index: x.index,
+            kind: "subtitle".into(),
+            codec: x.format,
+            language: x.language,
+            title: x.title,
+            channels: None,
+            is_default: x.default,
+            forced: x.forced,
+        });
+
+    audio.chain(subtitles).collect()
+}
+
+/// Number of seconds the duration of a file may change by without it being considered a different
+/// media. Longer media get a tenth of their duration.
+const STALE_DURATION_TOLERANCE: i64 = 60;
+
+/// How similar the title a file is parsed as has to stay to the title it was mounted with, on the
+/// scale of [`filename::title_similarity`]. Titles rewritten while matching, ie specials that only
+/// keep the name of their show, still share most of their words.
+const STALE_TITLE_SIMILARITY: f64 = 0.5;
+
+/// Function compares a file we already know about with what it parses as now and flags it as stale
+/// if its duration changed substantially, or if it is now parsed as another title or year, ie
+/// because its nfo file was edited. Either means that the file at this path was replaced with
+/// something else and its match most likely no longer applies.
+async fn flag_if_stale(
+    conn: &DbConnection,
+    log: &slog::Logger,
+    media_file: &MediaFile,
+    parsed: &InsertableMediaFile,
+) {
+    // NOTE: Remuxes and re-encodes of the same film differ by a couple of seconds at most.
+    let duration_changed = match (media_file.duration, parsed.duration) {
+        (Some(old), Some(new)) => (old - new).abs() > STALE_DURATION_TOLERANCE.max(old / 10),
+        _ => false,
+    };
+    let title_changed =
+        filename::title_similarity(&media_file.raw_name, &parsed.raw_name) < STALE_TITLE_SIMILARITY;
+    let year_changed = matches!(
+        (media_file.raw_year, parsed.raw_year),
+        (Some(old), Some(new)) if old != new
+    );
+
+    if !duration_changed && !title_changed && !year_changed {
+        let update = UpdateMediaFile {
+            mtime: parsed.mtime,
+            ..Default::default()
         };
 
-        let file_id = media_file.insert(&self.conn).await?;
+        let _ = update.update(conn, media_file.id).await;
+        return;
+    }
 
-        let id = MediaFile::get_one(&self.conn, file_id).await?;
+    warn!(
+        log,
+        "File changed since it was matched, flagging it for review";
+        "file" => &media_file.target_file,
+        "old_duration" => media_file.duration,
+        "new_duration" => parsed.duration,
+        "old_name" => &media_file.raw_name,
+        "new_name" => &parsed.raw_name,
+        "old_year" => media_file.raw_year,
+        "new_year" => parsed.raw_year,
+    );
 
-        assert!(file_id == id.id);
+    // NOTE: The file is matched again with what it parses as now once it has been reviewed.
+    let update = UpdateMediaFile {
+        raw_name: Some(parsed.raw_name.clone()),
+        raw_year: parsed.raw_year,
+        duration: parsed.duration,
+        stale: Some(true),
+        mtime: parsed.mtime,
+        ..Default::default()
+    };
 
-        info!(
-            self.logger,
-            "Scanned file";
-            "file" => &target_file,
-            "library_id" => library_id,
-            "id" => file_id,
-            "2nd_pass_id" => id.id,
-            "season" => metadata.season().unwrap_or(0),
-            "episode" => metadata.episode().unwrap_or(0),
+    if let Err(e) = update.update(conn, media_file.id).await {
+        warn!(
+            log,
+            "Failed to flag stale file";
+            "file" => &media_file.target_file,
+            "reason" => e.to_string(),
         );
-
-        Ok(id)
     }
 }
 
+/// Lock matches are written to the db under. Our matchers look files up in parallel, but writing
+/// their matches one at a time saves them from fighting over the db. Only the writes are done under
+/// the lock, artwork is looked up and copied before it is taken.
+pub(super) static MATCH_WRITES: Lazy<tokio::sync::Mutex<()>> = Lazy::new(Default::default);
+
 #[actor]
 pub struct MetadataMatcher {
     pub movie_tmdb: Tmdb,
@@ -216,13 +997,35 @@ impl MetadataMatcher {
         }
     }
 
+    /// Handler matches a movie against our metadata providers. Returns how similar the title we
+    /// matched is to the title parsed from the filename, on a scale from `0.0` to `1.0`.
+    /// `settings` are the settings of the library the movie is in.
     #[handler]
-    pub async fn match_movie(&mut self, media: MediaFile) -> Result<(), ScannerError> {
-        let result = match self
-            .movie_tmdb
-            .search(media.raw_name.clone(), media.raw_year.map(|x| x as i32))
-            .await
-        {
+    pub async fn match_movie(
+        &mut self,
+        media: MediaFile,
+        settings: Arc<LibrarySettings>,
+    ) -> Result<f64, ScannerError> {
+        // NOTE: Once one part of a multi-part movie is matched, the other parts join its media
+        // instead of being matched on their own.
+        if media.part_number.is_some() && media.join_matched_part(&self.conn).await?.is_some() {
+            return Ok(1.0);
+        }
+
+        check_media_type(&self.log, &media, MediaType::Movie)?;
+
+        let mut movie_tmdb = library_tmdb(&self.movie_tmdb, &settings);
+        let result = search_movie(
+            &movie_tmdb,
+            &self.conn,
+            &self.log,
+            &media,
+            &settings,
+            CacheWrites::Keep,
+        )
+        .await;
+
+        let (mut result, matched_by_id) = match result {
             Ok(v) => v,
             Err(e) => {
                 error!(
@@ -233,11 +1036,43 @@ impl MetadataMatcher {
                     "raw_year" => media.raw_year,
                     "target_file" => media.target_file.clone(),
                 );
-                return Err(ScannerError::UnknownError);
+                return Err(match e {
+                    TmdbError::RateLimited(_) | TmdbError::ReachedMaxTries => {
+                        ScannerError::RateLimited
+                    }
+                    TmdbError::NoResults => ScannerError::NoMatch,
+                    _ => ScannerError::UnknownError,
+                });
+            }
+        };
+
+        // NOTE: Ids are taken as is, as they cant be mistaken for another movie.
+        let score = if matched_by_id {
+            1.0
+        } else {
+            check_score(&self.log, &media, &result, media.raw_year, &settings)?
+        };
+
+        // NOTE: Search results dont carry the tagline and runtime of a movie, those only come with
+        // its details. Failing to fetch them isnt worth failing the match over. Neither do they
+        // carry the english version of fields TMDB has no translation of.
+        if online_matching() && !matched_by_id {
+            if let Ok(details) = movie_tmdb.search_by_id(result.id as i32).await {
+                result.tagline = details.tagline;
+                result.runtime = details.runtime;
             }
+
+            movie_tmdb.fill_untranslated(&mut result).await;
+        }
+
+        let media = MediaFile {
+            match_score: Some(score),
+            ..media
         };
 
-        self.match_movie_to_result(media, result).await
+        self.match_movie_to_result(media, result)
+            .await
+            .map(|_| score)
     }
 
     #[handler]
@@ -256,9 +1091,72 @@ impl MetadataMatcher {
         Ok(())
     }
 
+    /// Handler matches a song with its album, which is looked up on MusicBrainz unless another
+    /// song of the album was matched already. Returns how similar the album we matched is to the
+    /// one the song is tagged with, same as [`match_movie`](Self::match_movie).
     #[handler]
-    pub async fn match_tv(&mut self, media: MediaFile) -> Result<(), ScannerError> {
+    pub async fn match_music(
+        &mut self,
+        media: MediaFile,
+        settings: Arc<LibrarySettings>,
+    ) -> Result<f64, ScannerError> {
+        let matcher = MusicMatcher {
+            conn: &self.conn,
+            log: &self.log,
+            event_tx: &self.event_tx,
+        };
+
+        matcher.match_song(&media, &settings).await
+    }
+
+    /// Handler matches a episode against our metadata providers. Returns how similar the title of
+    /// the show we matched is to the title parsed from the filename, same as
+    /// [`match_movie`](Self::match_movie).
+    #[handler]
+    pub async fn match_tv(
+        &mut self,
+        media: MediaFile,
+        settings: Arc<LibrarySettings>,
+    ) -> Result<f64, ScannerError> {
         let mut media = media;
+        let offline = get_global_settings().offline_matching;
+        let mut tv_tmdb = library_tmdb(&self.tv_tmdb, &settings);
+
+        // NOTE: Specials usually lack season and episode numbers, ie `Doctor Who - The Christmas
+        // Invasion.mkv`, so we try to place them in season 0 by their name instead. Dumps and the
+        // match cache carry no episode names, so this only works online.
+        let special = Path::new(&media.target_file)
+            .file_stem()
+            .and_then(|x| x.to_str())
+            .filter(|_| online_matching() && media.season.is_none() && media.episode.is_none())
+            .and_then(filename::split_special);
+
+        if let Some((show, special)) = special {
+            if let Some((result, episode)) =
+                find_special(&mut tv_tmdb, show.clone(), &special).await
+            {
+                let update_mediafile = UpdateMediaFile {
+                    season: Some(0),
+                    episode: Some(episode as i64),
+                    raw_name: Some(show.clone()),
+                    ..Default::default()
+                };
+
+                let _ = update_mediafile.update(&self.conn, media.id).await;
+
+                media.season = Some(0);
+                media.episode = Some(episode as i64);
+                media.raw_name = show;
+
+                let score = check_score(&self.log, &media, &result, None, &settings)?;
+                media.match_score = Some(score);
+
+                return self
+                    .match_tv_to_result(media, result, settings)
+                    .await
+                    .map(|_| score);
+            }
+        }
 
         let path = Path::new(&media.target_file);
         let filename = path
@@ -278,18 +1176,58 @@ impl MetadataMatcher {
             Ok(v) | Err(v) => v,
         };
 
-        let mut result = self
-            .tv_tmdb
-            .search(media.raw_name.clone(), media.raw_year.map(|x| x as i32))
-            .await;
+        let raw_year = media.raw_year.map(|x| x as i32);
+        let raw_name = media.raw_name.clone();
+        let dump_agent = DumpAgent::new(self.conn.clone(), MediaType::Tv);
+
+        let search_default = || {
+            search_show(
+                &self.conn,
+                &self.log,
+                &tv_tmdb,
+                &raw_name,
+                raw_year,
+                &settings,
+                CacheWrites::Keep,
+            )
+        };
+
+        // NOTE: Libraries mixing anime and other shows can route files tagged with a release
+        // group to the anime agent first, everything else goes through TMDB first.
+        let anime_title = els.get(ElementCategory::AnimeTitle);
+        let anime_first = anime_title.is_some()
+            && Path::new(&media.target_file)
+                .file_name()
+                .and_then(|x| x.to_str())
+                .and_then(filename::strip_anime_brackets)
+                .is_some()
+            && settings.prefer_anime_agent;
 
-        if let Some(x) = els.get(ElementCategory::AnimeTitle) {
+        let mut result = if anime_first {
+            Err(TmdbError::NoResults)
+        } else {
+            search_default().await
+        };
+
+        if let Some(x) = anime_title {
             if result.is_err() {
                 // NOTE: If we got here then we assume that the file uses common anime release naming schemes.
                 // Thus we prioritise metadata extracted by anitomy.
-                result = self.tv_tmdb.search(x.to_string(), None).await;
+                result = if offline {
+                    dump_agent.clone().search(x.to_string(), None).await
+                } else {
+                    with_match_cache(
+                        &self.conn,
+                        &self.log,
+                        CachedMatch::key(MediaType::Tv, x, None),
+                        CacheWrites::Keep,
+                        tv_tmdb.clone().search(x.to_string(), None),
+                    )
+                    .await
+                };
 
-                // NOTE: Some releases dont include season number, so we just assume its the first one.
+                // NOTE: Releases without a season number are put into the first season once they
+                // are matched, unless their episode turns out to be numbered absolutely.
                 let anitomy_episode = els
                     .get(ElementCategory::EpisodeNumber)
                     .and_then(|x| x.parse::<i64>().ok())
@@ -298,7 +1236,7 @@ impl MetadataMatcher {
                 let anitomy_season = els
                     .get(ElementCategory::AnimeSeason)
                     .and_then(|x| x.parse::<i64>().ok())
-                    .or(Some(1));
+                    .or(media.season);
 
                 let update_mediafile = UpdateMediaFile {
                     episode: anitomy_episode.map(|x| x as i64),
@@ -314,7 +1252,11 @@ impl MetadataMatcher {
             }
         }
 
-        let result = match result {
+        if anime_first && result.is_err() {
+            result = search_default().await;
+        }
+
+        let mut result = match result {
             Ok(v) => v,
             Err(e) => {
                 error!(
@@ -324,11 +1266,30 @@ impl MetadataMatcher {
                     "raw_name" => media.raw_name.clone(),
                     "target_file" => media.target_file.clone(),
                 );
-                return Err(ScannerError::UnknownError);
+                return Err(match e {
+                    TmdbError::RateLimited(_) | TmdbError::ReachedMaxTries => {
+                        ScannerError::RateLimited
+                    }
+                    TmdbError::NoResults => ScannerError::NoMatch,
+                    _ => ScannerError::UnknownError,
+                });
             }
         };
 
-        self.match_tv_to_result(media, result).await
+        check_media_type(&self.log, &media, MediaType::Tv)?;
+
+        if online_matching() && result.agent == TvAgent::Tmdb {
+            tv_tmdb.fill_untranslated(&mut result).await;
+        }
+
+        // NOTE: The year of a episode is often the year of its season rather than the year the
+        // show first aired, so shows are scored by their title alone.
+        let score = check_score(&self.log, &media, &result, None, &settings)?;
+        media.match_score = Some(score);
+
+        self.match_tv_to_result(media, result, settings)
+            .await
+            .map(|_| score)
     }
 
     #[handler]
@@ -336,6 +1297,7 @@ impl MetadataMatcher {
         &mut self,
         media: MediaFile,
         result: ApiMedia,
+        settings: Arc<LibrarySettings>,
     ) -> Result<(), ScannerError> {
         // FIXME: Our handler macro cant handle `mut` keyword yet.
         let mut media = media;
@@ -375,6 +1337,11 @@ impl MetadataMatcher {
             media.episode = anitomy_episode.map(|x| x as i64);
         }
 
+        // NOTE: Only files that dont name a season might be numbered absolutely, a explicit `S01`
+        // is taken as is.
+        let season_missing =
+            media.season.is_none() && els.get(ElementCategory::AnimeSeason).is_none();
+
         if media.season.is_none() {
             // NOTE: Some releases dont include season number, so we just assume its the first one.
             let anitomy_season = els
@@ -391,27 +1358,46 @@ impl MetadataMatcher {
             media.season = anitomy_season.map(|x| x as i64);
         }
 
-        let mut seasons: Vec<super::ApiSeason> = self
-            .tv_tmdb
-            .get_seasons_for(result.id)
-            .await
-            .unwrap_or_default()
-            .into_iter()
-            .map(Into::into)
-            .collect();
-
-        for season in seasons.iter_mut() {
-            season.episodes = self
-                .tv_tmdb
-                .get_episodes_for(result.id, season.season_number)
+        // NOTE: Shows matched with TheTVDB come with their seasons, whose ids TMDB doesnt know.
+        if result.seasons.is_empty() && result.agent == TvAgent::Tmdb {
+            let seasons = if get_global_settings().offline_matching {
+                DumpAgent::new(self.conn.clone(), MediaType::Tv)
+                    .seasons(result.id)
+                    .await
+            } else {
+                let mut tmdb = library_tmdb(&self.tv_tmdb, &settings);
+
+                with_match_cache(
+                    &self.conn,
+                    &self.log,
+                    CachedMatch::seasons_key(result.id),
+                    CacheWrites::Keep,
+                    tmdb.seasons(result.id),
+                )
                 .await
-                .unwrap_or_default()
-                .into_iter()
-                .map(Into::into)
-                .collect();
+            };
+
+            result.seasons = seasons.unwrap_or_default();
         }
 
-        result.seasons = seasons;
+        // NOTE: Anime is often numbered by its absolute episode number, ie `Show - 137.mkv`, which
+        // we have to map onto the season the episode aired in.
+        if let (true, true, Some(episode)) =
+            (settings.absolute_numbering, season_missing, media.episode)
+        {
+            if let Some((season, episode)) = resolve_absolute(&result.seasons, episode as u64) {
+                let updated_mediafile = UpdateMediaFile {
+                    season: Some(season as i64),
+                    episode: Some(episode as i64),
+                    ..Default::default()
+                };
+
+                let _ = updated_mediafile.update(&self.conn, media.id).await;
+
+                media.season = Some(season as i64);
+                media.episode = Some(episode as i64);
+            }
+        }
 
         let matcher = TvShowMatcher {
             conn: &self.conn,
@@ -423,3 +1409,591 @@ impl MetadataMatcher {
         Ok(())
     }
 }
+
+/// Function searches for the movie `media` holds, by the ids stored with it if there are any and
+/// otherwise by its title, in a library with `settings`. Returns the match and whether it was found
+/// by id. Matches found by title are kept in the match cache as `cache` says, nothing else is
+/// written to the db.
+pub(super) async fn search_movie(
+    tmdb: &Tmdb,
+    conn: &DbConnection,
+    log: &slog::Logger,
+    media: &MediaFile,
+    settings: &LibrarySettings,
+    cache: CacheWrites,
+) -> Result<(ApiMedia, bool), TmdbError> {
+    let raw_year = media.raw_year.map(|x| x as i32);
+    // NOTE: Without a year in the filename we cant tell apart movies with identical
+    // titles, so we weigh in the year of the folder the file is in.
+    let folder_year = filename::year_from_folder(Path::new(&media.target_file));
+
+    let threshold = settings.match_threshold;
+
+    // NOTE: Ids found in a nfo file next to the movie beat any title search.
+    let by_id = if online_matching() {
+        search_by_external_id(&mut tmdb.clone(), log, media).await
+    } else {
+        None
+    };
+
+    if let Some(x) = by_id {
+        Ok((x.into(), true))
+    } else if get_global_settings().offline_matching {
+        let agent = DumpAgent::new(conn.clone(), MediaType::Movie);
+        let year = raw_year.or_else(|| folder_year.map(|x| x as i32));
+
+        search_candidates(&media.raw_name, media.raw_year, threshold, |title| {
+            let mut agent = agent.clone();
+            async move { agent.search(title, year).await }
+        })
+        .await
+        .map(|x| (x, false))
+    } else {
+        let search = search_candidates(&media.raw_name, media.raw_year, threshold, |title| {
+            let mut tmdb = tmdb.clone();
+
+            async move {
+                if let Some(year) = raw_year {
+                    // NOTE: Festival and wide release dates often lie a year apart, so when
+                    // nothing came out in the year of the filename we settle for a movie
+                    // released a year before or after it, but nothing further off.
+                    return match tmdb.search(title.clone(), raw_year).await {
+                        Err(TmdbError::NoResults) => {
+                            let candidates = tmdb.search_by_name(title, None, None).await?;
+
+                            closest_by_year(&candidates, year as i64)
+                                .map(Into::into)
+                                .ok_or(TmdbError::NoResults)
+                        }
+                        x => x,
+                    };
+                }
+
+                let candidates = tmdb.search_by_name(title.clone(), None, None).await?;
+
+                select_by_year(&title, candidates, folder_year)
+                    .map(Into::into)
+                    .ok_or(TmdbError::NoResults)
+            }
+        });
+
+        let key = CachedMatch::key(MediaType::Movie, &media.raw_name, raw_year);
+
+        with_match_cache(conn, log, key, cache, search)
+            .await
+            .map(|x| (x, false))
+    }
+}
+
+/// Function returns `tmdb` set up to fetch metadata in the language of a library with `settings`.
+fn library_tmdb(tmdb: &Tmdb, settings: &LibrarySettings) -> Tmdb {
+    tmdb.clone().language(settings.metadata_language.clone())
+}
+
+/// Function returns whether we may query our metadata providers, which we dont when matching
+/// against a TMDB dump or against earlier matches only.
+pub(super) fn online_matching() -> bool {
+    let global = get_global_settings();
+    !global.offline_matching && !global.match_cache_only
+}
+
+/// Function returns the TheTVDB agent a library matches its tv shows with, if it is configured to
+/// and a api key is set.
+fn tvdb_agent(settings: &LibrarySettings) -> Option<TvdbAgent> {
+    match (settings.tv_agent, get_global_settings().tvdb_api_key) {
+        (TvAgent::Tvdb, Some(key)) if online_matching() => Some(TvdbAgent::new(key)),
+        _ => None,
+    }
+}
+
+/// Function searches for the show `raw_name` with TheTVDB if the library `settings` say so,
+/// falling back to TMDB, or with the dump when matching offline. Matches are kept in the match
+/// cache as `cache` says, nothing else is written to the db.
+pub(super) async fn search_show(
+    conn: &DbConnection,
+    log: &slog::Logger,
+    tmdb: &Tmdb,
+    raw_name: &str,
+    raw_year: Option<i32>,
+    settings: &LibrarySettings,
+    cache: CacheWrites,
+) -> Result<ApiMedia, TmdbError> {
+    let threshold = settings.match_threshold;
+
+    if get_global_settings().offline_matching {
+        let dump_agent = DumpAgent::new(conn.clone(), MediaType::Tv);
+
+        return search_candidates(raw_name, None, threshold, |title| {
+            let mut agent = dump_agent.clone();
+            async move { agent.search(title, raw_year).await }
+        })
+        .await;
+    }
+
+    let tvdb = tvdb_agent(settings);
+    let search = search_online_show(tmdb, tvdb.as_ref(), raw_name, raw_year, threshold);
+    let key = CachedMatch::key(MediaType::Tv, raw_name, raw_year);
+
+    with_match_cache(conn, log, key, cache, search).await
+}
+
+async fn search_online_show(
+    tmdb: &Tmdb,
+    tvdb: Option<&TvdbAgent>,
+    raw_name: &str,
+    raw_year: Option<i32>,
+    threshold: f64,
+) -> Result<ApiMedia, TmdbError> {
+    // NOTE: The seasons are fetched from TheTVDB right away, as its episode numbering can differ
+    // from TMDB. Shows it doesnt know of are matched with TMDB instead.
+    if let Some(agent) = tvdb {
+        let result = search_candidates(raw_name, None, threshold, |title| {
+            let mut agent = agent.clone();
+            async move { agent.search(title, raw_year).await }
+        })
+        .await;
+
+        if let Ok(mut result) = result {
+            result.seasons = agent.clone().seasons(result.id).await.unwrap_or_default();
+
+            if !result.seasons.is_empty() {
+                return Ok(result);
+            }
+        }
+    }
+
+    search_candidates(raw_name, None, threshold, |title| {
+        search_tv(tmdb.clone(), title, raw_year)
+    })
+    .await
+}
+
+/// Enum holds whether a search keeps what it found in the match cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum CacheWrites {
+    /// Matches are kept, which is what scans do.
+    Keep,
+    /// Nothing is written, ie for dry runs of a scan. Earlier matches are still looked up.
+    Discard,
+}
+
+/// Function runs the metadata lookup `search` cached under `key` and keeps what it found unless
+/// `cache` is [`CacheWrites::Discard`], so that the same lookup can be answered from the db once
+/// our metadata providers cant be reached, ie on machines that lose their network access every
+/// now and then. With `match_cache_only` set the lookup never runs at all.
+async fn with_match_cache<T: Serialize + DeserializeOwned>(
+    conn: &DbConnection,
+    log: &slog::Logger,
+    key: String,
+    cache: CacheWrites,
+    search: impl Future<Output = Result<T, TmdbError>>,
+) -> Result<T, TmdbError> {
+    let cached = || async {
+        CachedMatch::get(conn, &key)
+            .await
+            .ok()
+            .and_then(|x| serde_json::from_str::<T>(&x.media).ok())
+    };
+
+    if get_global_settings().match_cache_only {
+        return cached().await.ok_or(TmdbError::NoResults);
+    }
+
+    match search.await {
+        Ok(x) if cache == CacheWrites::Discard => Ok(x),
+        Ok(x) => {
+            if let Ok(media) = serde_json::to_string(&x) {
+                if let Err(e) = CachedMatch::set(conn, &key, &media).await {
+                    warn!(log, "Failed to cache match"; "key" => &key, "reason" => e.to_string());
+                }
+            }
+
+            Ok(x)
+        }
+        Err(e) if e.is_transient() => match cached().await {
+            Some(x) => {
+                info!(
+                    log,
+                    "Metadata provider unreachable, using cached match";
+                    "key" => &key,
+                    "reason" => e.to_string(),
+                );
+                Ok(x)
+            }
+            None => Err(e),
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// Function maps the absolute episode number `absolute` of a show onto the season it aired in and
+/// its number within that season, ie with seasons of 12 and 13 episodes `20` yields `(2, 8)`.
+/// Specials dont count towards the absolute number. Returns `None` if a single season holds that
+/// many episodes, in which case the number is most likely not absolute, or if the show doesnt
+/// have that many episodes.
+fn resolve_absolute(seasons: &[ApiSeason], absolute: u64) -> Option<(u64, u64)> {
+    let mut seasons = seasons
+        .iter()
+        .filter(|x| x.season_number > 0)
+        .map(|x| {
+            let mut episodes = x
+                .episodes
+                .iter()
+                .filter_map(|x| x.episode)
+                .collect::<Vec<_>>();
+            episodes.sort_unstable();
+            (x.season_number, episodes)
+        })
+        .collect::<Vec<_>>();
+    seasons.sort_by_key(|(x, _)| *x);
+
+    if seasons.iter().any(|(_, x)| x.len() as u64 >= absolute) {
+        return None;
+    }
+
+    let mut remaining = absolute;
+
+    for (season, episodes) in seasons {
+        let count = episodes.len() as u64;
+
+        if remaining <= count {
+            return Some((season, episodes[remaining as usize - 1]));
+        }
+
+        remaining -= count;
+    }
+
+    None
+}
+
+/// Function scores how confident we are that `result` is the media `media` holds, parsed as
+/// released in `year`. Matches scoring below the match threshold in the library `settings` are
+/// rejected, leaving the file unmatched. The score is recorded along with the match.
+fn check_score(
+    log: &slog::Logger,
+    media: &MediaFile,
+    result: &ApiMedia,
+    year: Option<i64>,
+    settings: &LibrarySettings,
+) -> Result<f64, ScannerError> {
+    let found_year = result
+        .release_date
+        .as_deref()
+        .and_then(filename::year_from_date);
+    let score = filename::match_score(&media.raw_name, year, &result.title, found_year);
+
+    let threshold = settings.match_threshold;
+
+    if score < threshold {
+        warn!(
+            log,
+            "Best match is below the match threshold";
+            "target_file" => media.target_file.clone(),
+            "raw_name" => media.raw_name.clone(),
+            "title" => result.title.clone(),
+            "score" => score,
+            "threshold" => threshold,
+        );
+        return Err(ScannerError::LowConfidence(score, threshold));
+    }
+
+    Ok(score)
+}
+
+/// Function returns the type of media the parsed name of `media` gives away, if any. Only tv
+/// shows have numbered episodes, while files that carry neither a season nor an episode number are
+/// movies.
+fn parsed_media_type(media: &MediaFile) -> Option<MediaType> {
+    match (media.season, media.episode) {
+        (_, Some(_)) => Some(MediaType::Tv),
+        (None, None) => Some(MediaType::Movie),
+        (Some(_), None) => None,
+    }
+}
+
+/// Function checks that `media` looks like the media type `expected` of its library, going by its
+/// parsed name. Mismatches are rejected unless configured otherwise.
+fn check_media_type(
+    log: &slog::Logger,
+    media: &MediaFile,
+    expected: MediaType,
+) -> Result<(), ScannerError> {
+    let found = match parsed_media_type(media) {
+        Some(x) if x != expected => x,
+        _ => return Ok(()),
+    };
+
+    warn!(
+        log,
+        "File looks like another media type than the library";
+        "target_file" => media.target_file.clone(),
+        "season" => media.season,
+        "episode" => media.episode,
+        "found" => found.to_string(),
+        "expected" => expected.to_string(),
+    );
+
+    match get_global_settings().media_type_mismatch {
+        MediaTypeMismatch::Reject => Err(ScannerError::MediaTypeMismatch(found, expected)),
+        MediaTypeMismatch::Keep => Ok(()),
+    }
+}
+
+/// Function looks up a movie by the TMDB or IMDB id stored with it, if any. Failed lookups are
+/// logged and leave it to the title search to match the movie.
+async fn search_by_external_id(
+    tmdb: &mut Tmdb,
+    log: &slog::Logger,
+    media: &MediaFile,
+) -> Option<TmdbMedia> {
+    let result = match (media.tmdb_id, media.imdb_id.as_deref()) {
+        (Some(id), _) => tmdb.search_by_id(id as i32).await,
+        (None, Some(id)) => tmdb.find_by_external_id(id, ExternalIdSource::Imdb).await,
+        (None, None) => return None,
+    };
+
+    match result {
+        Ok(x) => Some(x),
+        Err(e) => {
+            warn!(
+                log,
+                "Could not match movie by its external id";
+                "reason" => e.to_string(),
+                "tmdb_id" => media.tmdb_id,
+                "imdb_id" => media.imdb_id.clone(),
+                "target_file" => media.target_file.clone(),
+            );
+            None
+        }
+    }
+}
+
+/// Function looks up the show `show` and returns it along with the number of the special in
+/// season 0 whose name is the closest to `special`, if any of them is close enough.
+async fn find_special(tmdb: &mut Tmdb, show: String, special: &str) -> Option<(ApiMedia, u64)> {
+    let result = tmdb.search(show, None).await.ok()?;
+
+    let (_, episode) = tmdb
+        .get_episodes_for(result.id, 0)
+        .await
+        .ok()?
+        .into_iter()
+        .filter_map(|x| {
+            let score = filename::title_similarity(special, x.name.as_deref()?);
+            Some((score, x.episode_number?))
+        })
+        .filter(|(score, _)| *score >= 0.5)
+        .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal))?;
+
+    Some((result, episode))
+}
+
+/// Maximum number of search results we look up the air years of when matching a tv show.
+const MAX_AIR_YEAR_LOOKUPS: usize = 5;
+
+/// Function searches for a tv show. Releases of long running shows are often tagged with the year
+/// of the season rather than the year the show first aired in, so unless disabled we accept the
+/// first search result whose run covers `year`.
+async fn search_tv(
+    mut tmdb: Tmdb,
+    title: String,
+    year: Option<i32>,
+) -> Result<ApiMedia, TmdbError> {
+    let year = match year {
+        Some(x) if get_global_settings().tv_air_year_range => x as i64,
+        _ => return tmdb.search(title, year).await,
+    };
+
+    let candidates = tmdb.search_by_name(title.clone(), None, None).await?;
+
+    for candidate in candidates.into_iter().take(MAX_AIR_YEAR_LOOKUPS) {
+        if let Ok(Some(years)) = tmdb.get_air_years(candidate.id).await {
+            if years.contains(&year) {
+                return Ok(candidate.into());
+            }
+        }
+    }
+
+    tmdb.search(title, Some(year as i32)).await
+}
+
+/// Function searches for the candidate titles of `title` with `search` and returns the result that
+/// scores the best against `title` itself and `year`, as the candidates are only ever parts of
+/// it. Candidates are searched for until a result scores at least `threshold`.
+async fn search_candidates<F, Fut>(
+    title: &str,
+    year: Option<i64>,
+    threshold: f64,
+    search: F,
+) -> Result<ApiMedia, TmdbError>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<ApiMedia, TmdbError>>,
+{
+    let mut best: Option<(f64, ApiMedia)> = None;
+    let mut error = TmdbError::NoResults;
+
+    for candidate in filename::candidate_titles(title) {
+        match with_retries(|| search(candidate.clone())).await {
+            Ok(result) => {
+                let found_year = result
+                    .release_date
+                    .as_deref()
+                    .and_then(filename::year_from_date);
+                let score = filename::match_score(title, year, &result.title, found_year);
+
+                if best.as_ref().map_or(true, |(x, _)| score > *x) {
+                    best = Some((score, result));
+                }
+
+                // NOTE: Every further candidate costs another search, so we settle for the first
+                // result that would be accepted anyway.
+                if score >= threshold {
+                    break;
+                }
+            }
+            // NOTE: There is no point in searching for other titles while we are rate limited.
+            Err(e @ TmdbError::RateLimited(_)) => {
+                error = e;
+                break;
+            }
+            Err(e) => error = e,
+        }
+    }
+
+    best.map(|(_, x)| x).ok_or(error)
+}
+
+/// Delay before the first retry of a search that failed because of a transient error, doubled
+/// with every further retry.
+const MATCH_RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Function calls `search` until it succeeds or fails with an error that isnt
+/// [transient](TmdbError::is_transient), backing off between attempts. Gives up after
+/// `match_retries` retries, so that a network blip doesnt leave a file unmatched until the next
+/// scan. When TMDB rate limits us we wait for as long as it asks us to, if that is longer.
+async fn with_retries<T, F, Fut>(search: F) -> Result<T, TmdbError>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, TmdbError>>,
+{
+    let retries = get_global_settings().match_retries;
+    let mut backoff = MATCH_RETRY_BACKOFF;
+    let mut attempt = 0;
+
+    loop {
+        match search().await {
+            Err(e) if e.is_transient() && attempt < retries => {
+                attempt += 1;
+                tokio::time::sleep(e.retry_after().map_or(backoff, |x| x.max(backoff))).await;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Function picks the search result whose release year is closest to `year`, see
+/// [`filename::pick_by_year`]. When the candidates are tied, or more than one candidate carries
+/// the exact title we searched for while no year tells them apart, the match is ambiguous and we
+/// leave the file unmatched so that the user can pick the right one manually.
+fn select_by_year(title: &str, candidates: Vec<TmdbMedia>, year: Option<i64>) -> Option<TmdbMedia> {
+    let found = candidates
+        .iter()
+        .map(|x| {
+            let release_year = x.release_date.as_deref().and_then(filename::year_from_date);
+            (x.title.as_str(), release_year)
+        })
+        .collect::<Vec<_>>();
+
+    let idx = filename::pick_by_year(title, &found, year)?;
+    candidates.into_iter().nth(idx)
+}
+
+/// Function returns the candidate released the closest to `year`, preferring the one that comes
+/// first in the search results, if any of them was released within a year of it.
+fn closest_by_year(candidates: &[TmdbMedia], year: i64) -> Option<TmdbMedia> {
+    let release_year = |x: &TmdbMedia| x.release_date.as_deref().and_then(filename::year_from_date);
+
+    candidates
+        .iter()
+        .filter_map(|x| release_year(x).map(|y| ((y - year).abs(), x)))
+        .filter(|(distance, _)| *distance <= 1)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, x)| x.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn season(season_number: u64, episodes: u64) -> ApiSeason {
+        ApiSeason {
+            id: season_number,
+            name: None,
+            poster_path: None,
+            poster_file: None,
+            season_number,
+            episodes: (1..=episodes)
+                .map(|x| crate::scanners::ApiEpisode {
+                    id: x,
+                    name: None,
+                    overview: None,
+                    episode: Some(x),
+                    still: None,
+                    still_file: None,
+                    owned: false,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn resolve_absolute_across_seasons() {
+        let seasons = [season(1, 12), season(2, 13), season(3, 10)];
+
+        assert_eq!(resolve_absolute(&seasons, 14), Some((2, 2)));
+        assert_eq!(resolve_absolute(&seasons, 20), Some((2, 8)));
+        assert_eq!(resolve_absolute(&seasons, 25), Some((2, 13)));
+        assert_eq!(resolve_absolute(&seasons, 26), Some((3, 1)));
+        assert_eq!(resolve_absolute(&seasons, 35), Some((3, 10)));
+        assert_eq!(resolve_absolute(&seasons, 36), None);
+    }
+
+    #[test]
+    fn resolve_absolute_skips_specials() {
+        let seasons = [season(0, 5), season(2, 13), season(1, 12)];
+
+        assert_eq!(resolve_absolute(&seasons, 14), Some((2, 2)));
+    }
+
+    #[test]
+    fn resolve_absolute_rejects_relative_numbers() {
+        let seasons = [season(1, 12), season(2, 13)];
+
+        // NOTE: A single season holds that many episodes, so the number is most likely relative.
+        assert_eq!(resolve_absolute(&seasons, 5), None);
+        assert_eq!(resolve_absolute(&seasons, 13), None);
+        // NOTE: The show doesnt have that many episodes.
+        assert_eq!(resolve_absolute(&seasons, 26), None);
+        assert_eq!(resolve_absolute(&[], 3), None);
+    }
+
+    #[test]
+    fn parsed_media_type_of_files() {
+        let file = |season, episode| MediaFile {
+            season,
+            episode,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            parsed_media_type(&file(Some(1), Some(2))),
+            Some(MediaType::Tv)
+        );
+        assert_eq!(parsed_media_type(&file(None, Some(2))), Some(MediaType::Tv));
+        assert_eq!(parsed_media_type(&file(None, None)), Some(MediaType::Movie));
+        // NOTE: Seasons on their own tell us nothing, ie `Movie Season 2` is a title as well.
+        assert_eq!(parsed_media_type(&file(Some(2), None)), None);
+    }
+}
@@ -0,0 +1,188 @@
+//! Helpers used to detect and handle full disc rips, ie `Movie (2019)/BDMV/` or
+//! `Movie (2019)/VIDEO_TS/`. These dont have a top level media file, so instead we mount the main
+//! title of the disc and match it by the name of the folder enclosing the disc structure. Our
+//! directory walkers yield the disc folder itself, its main title is looked up once it is mounted.
+use crate::get_global_settings;
+use crate::streaming::ffprobe::FFProbeCtx;
+use crate::streaming::FFPROBE_BIN;
+
+use once_cell::sync::Lazy;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use tokio::task::spawn_blocking;
+
+/// Names of the folders that mark the root of a disc structure.
+const DISC_FOLDERS: &[&str] = &["BDMV", "VIDEO_TS"];
+
+/// Function checks whether `path` is the root of a disc structure.
+pub fn is_disc_folder(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|x| x.to_str())
+        .map_or(false, |x| {
+            DISC_FOLDERS.iter().any(|y| y.eq_ignore_ascii_case(x))
+        })
+}
+
+/// Number of the largest streams of a blu-ray we probe when looking for its main title.
+const MAX_PROBED_STREAMS: usize = 4;
+
+/// Function checks whether `path` lies within a disc structure. The files of a disc are never
+/// mounted on their own, the disc is mounted through its main title instead.
+pub fn in_disc_folder(path: &Path) -> bool {
+    path.ancestors().skip(1).any(is_disc_folder)
+}
+
+/// Main titles we already looked up, keyed by their disc folder. Every entry holds when the folder
+/// with the titles of the disc was last modified, so that discs that were replaced are looked up
+/// again.
+static MAIN_TITLES: Lazy<Mutex<HashMap<PathBuf, (Option<SystemTime>, Option<PathBuf>)>>> =
+    Lazy::new(Default::default);
+
+/// Function returns the main title of the disc structure located at `path` like [`main_title`],
+/// but only looks it up the first time it is asked for a disc. Streams are probed with the
+/// configured `ffprobe_timeout` on a blocking thread.
+pub async fn cached_main_title(path: &Path) -> Option<PathBuf> {
+    let modified = fs::metadata(titles_dir(path))
+        .and_then(|x| x.modified())
+        .ok();
+
+    if let Some((cached_at, title)) = MAIN_TITLES.lock().unwrap().get(path) {
+        if *cached_at == modified {
+            return title.clone();
+        }
+    }
+
+    let title = {
+        let path = path.to_path_buf();
+        let ffprobe = FFProbeCtx::new(FFPROBE_BIN)
+            .timeout(Duration::from_secs(get_global_settings().ffprobe_timeout));
+
+        spawn_blocking(move || main_title(&path, &ffprobe))
+            .await
+            .ok()
+            .flatten()
+    };
+
+    MAIN_TITLES
+        .lock()
+        .unwrap()
+        .insert(path.to_path_buf(), (modified, title.clone()));
+
+    title
+}
+
+/// Function returns the folder holding the titles of the disc structure located at `path`.
+fn titles_dir(path: &Path) -> PathBuf {
+    match path.file_name().and_then(|x| x.to_str()) {
+        Some(x) if x.eq_ignore_ascii_case("BDMV") => path.join("STREAM"),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Function returns the main title of the disc structure located at `path`. For blu-rays this is
+/// the longest of the largest `.m2ts` streams according to `ffprobe`, while for dvds this is the
+/// first `.VOB` of the largest title set.
+///
+/// NOTE: Dvds split titles into `VTS_XX_1.VOB`, `VTS_XX_2.VOB` etc, only the first part is mounted.
+pub fn main_title(path: &Path, ffprobe: &FFProbeCtx) -> Option<PathBuf> {
+    let folder = path.file_name()?.to_str()?;
+
+    if folder.eq_ignore_ascii_case("BDMV") {
+        bluray_main_title(path, ffprobe)
+    } else if folder.eq_ignore_ascii_case("VIDEO_TS") {
+        dvd_main_title(path)
+    } else {
+        None
+    }
+}
+
+fn bluray_main_title(path: &Path, ffprobe: &FFProbeCtx) -> Option<PathBuf> {
+    let mut streams = files_with_ext(&path.join("STREAM"), "m2ts");
+    streams.sort_by_key(|(len, _)| std::cmp::Reverse(*len));
+    streams.truncate(MAX_PROBED_STREAMS);
+
+    // NOTE: The largest stream usually is the main title, but extras at a higher bitrate can
+    // outgrow it, so we go by duration. Streams ffprobe cant make sense of count as empty.
+    streams
+        .into_iter()
+        .enumerate()
+        .map(|(rank, (_, x))| {
+            let duration = ffprobe
+                .get_meta(&x)
+                .ok()
+                .and_then(|meta| meta.get_duration())
+                .unwrap_or(0);
+
+            (duration, std::cmp::Reverse(rank), x)
+        })
+        .max_by_key(|(duration, rank, _)| (*duration, *rank))
+        .map(|(.., x)| x)
+}
+
+fn dvd_main_title(path: &Path) -> Option<PathBuf> {
+    let mut title_sets: HashMap<String, (u64, Option<PathBuf>)> = HashMap::new();
+
+    for (len, file) in files_with_ext(path, "vob") {
+        let stem = match file.file_stem().and_then(|x| x.to_str()) {
+            Some(x) => x.to_ascii_uppercase(),
+            None => continue,
+        };
+
+        // NOTE: `VIDEO_TS.VOB` and `VTS_XX_0.VOB` hold the menus of a dvd.
+        let (set, part) = match stem.rsplit_once('_') {
+            Some((set, part)) if set.starts_with("VTS_") && part != "0" => (set, part),
+            _ => continue,
+        };
+
+        let entry = title_sets.entry(set.to_string()).or_default();
+        entry.0 += len;
+
+        if part == "1" {
+            entry.1 = Some(file);
+        }
+    }
+
+    title_sets
+        .into_iter()
+        .filter_map(|(_, (len, first))| Some((len, first?)))
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, x)| x)
+}
+
+/// Function returns the files in `dir` with the extension `ext` along with their size.
+fn files_with_ext(dir: &Path, ext: &str) -> Vec<(u64, PathBuf)> {
+    let entries = match fs::read_dir(dir) {
+        Ok(x) => x,
+        Err(_) => return Vec::new(),
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .map(|x| x.path())
+        .filter(|x| {
+            x.extension()
+                .and_then(|e| e.to_str())
+                .map_or(false, |e| e.eq_ignore_ascii_case(ext))
+        })
+        .filter_map(|x| fs::metadata(&x).ok().map(|meta| (meta.len(), x)))
+        .collect()
+}
+
+/// Function returns the name of the folder enclosing the disc structure `file` is a part of. This
+/// name is used in place of the filename when parsing and matching discs.
+pub fn title_folder(file: &Path) -> Option<String> {
+    let disc_root = file.ancestors().find(|x| is_disc_folder(x))?;
+
+    disc_root
+        .parent()?
+        .file_name()?
+        .to_str()
+        .map(ToString::to_string)
+}
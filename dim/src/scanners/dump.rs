@@ -0,0 +1,168 @@
+//! Offline matching against a local copy of TMDB, for deployments that have no network access at
+//! all. TMDB publishes a daily export of the ids and titles of every movie and tv show, ie
+//! `movie_ids_05_15_2021.json`, which can be imported with [`import_dump`] once decompressed.
+//! Matching is then done by a [`DumpAgent`] instead of TMDB when `offline_matching` is enabled.
+//!
+//! The exports only hold titles, so media matched offline come without artwork, descriptions or
+//! episode names. Lines carrying a `release_date` or `first_air_date` are used to tell apart media
+//! with identical titles.
+use super::agent::MetadataAgent;
+use super::base::ScannerError;
+use super::filename;
+use super::tmdb::TmdbError;
+use super::ApiMedia;
+use super::ApiSeason;
+
+use database::library::MediaType;
+use database::library::TvAgent;
+use database::tmdb_dump::DumpEntry;
+use database::tmdb_dump::InsertableDumpEntry;
+use database::DbConnection;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use slog::info;
+use slog::warn;
+use slog::Logger;
+
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Number of entries inserted in a single transaction while importing a dump.
+const IMPORT_BATCH_SIZE: usize = 1000;
+
+/// A single line of a TMDB id export.
+#[derive(Deserialize)]
+struct DumpRecord {
+    id: i64,
+    #[serde(alias = "original_title", alias = "original_name")]
+    title: String,
+    #[serde(default)]
+    popularity: f64,
+    #[serde(default, alias = "release_date", alias = "first_air_date")]
+    date: Option<String>,
+}
+
+/// Function imports the entries of a decompressed TMDB id export of type `media_type` into the
+/// database. Lines that cant be parsed are skipped. Returns the number of entries imported.
+pub async fn import_dump(
+    conn: &DbConnection,
+    path: &Path,
+    media_type: MediaType,
+    log: &Logger,
+) -> Result<usize, ScannerError> {
+    let file = File::open(path).map_err(|e| ScannerError::InvalidDump(e.to_string()))?;
+
+    let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    let mut imported = 0;
+    let mut skipped = 0;
+
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| ScannerError::InvalidDump(e.to_string()))?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record = match serde_json::from_str::<DumpRecord>(&line) {
+            Ok(x) => x,
+            Err(_) => {
+                skipped += 1;
+                continue;
+            }
+        };
+
+        batch.push(InsertableDumpEntry {
+            tmdb_id: record.id,
+            media_type,
+            title: record.title,
+            year: record.date.as_deref().and_then(filename::year_from_date),
+            popularity: record.popularity,
+        });
+
+        if batch.len() >= IMPORT_BATCH_SIZE {
+            InsertableDumpEntry::insert_batch(conn, &batch).await?;
+            imported += batch.len();
+            batch.clear();
+        }
+    }
+
+    InsertableDumpEntry::insert_batch(conn, &batch).await?;
+    imported += batch.len();
+
+    if skipped > 0 {
+        warn!(
+            log,
+            "Skipped malformed lines while importing dump";
+            "file" => path.to_string_lossy().to_string(),
+            "skipped" => skipped,
+        );
+    }
+
+    info!(
+        log,
+        "Imported TMDB dump";
+        "file" => path.to_string_lossy().to_string(),
+        "media_type" => media_type.to_string(),
+        "entries" => imported,
+    );
+
+    Ok(imported)
+}
+
+/// Metadata agent that matches media against an imported TMDB dump without any network access.
+#[derive(Clone)]
+pub struct DumpAgent {
+    conn: DbConnection,
+    media_type: MediaType,
+}
+
+impl DumpAgent {
+    pub fn new(conn: DbConnection, media_type: MediaType) -> Self {
+        Self { conn, media_type }
+    }
+}
+
+#[async_trait]
+impl MetadataAgent for DumpAgent {
+    async fn search(&mut self, title: String, year: Option<i32>) -> Result<ApiMedia, TmdbError> {
+        let entries = DumpEntry::search(&self.conn, self.media_type, &title)
+            .await
+            .map_err(|_| TmdbError::DumpError)?;
+
+        // NOTE: Most entries of an id export carry no year, in which case we go with the most
+        // popular one.
+        let entry = entries
+            .iter()
+            .find(|x| year.is_some() && x.year == year.map(i64::from))
+            .or_else(|| entries.first())
+            .ok_or(TmdbError::NoResults)?;
+
+        // NOTE: Our matchers only care about the year of a release date.
+        Ok(ApiMedia {
+            id: entry.tmdb_id as u64,
+            title: entry.title.clone(),
+            release_date: entry.year.map(|x| format!("{}-01-01", x)),
+            overview: None,
+            poster_path: None,
+            backdrop_path: None,
+            poster_file: None,
+            backdrop_file: None,
+            genres: Vec::new(),
+            rating: None,
+            seasons: Vec::new(),
+            tagline: None,
+            runtime: None,
+            agent: TvAgent::Tmdb,
+        })
+    }
+
+    async fn seasons(&mut self, _id: u64) -> Result<Vec<ApiSeason>, TmdbError> {
+        // NOTE: Id exports dont list seasons, episodes are inserted with the numbers parsed out
+        // of their filenames instead.
+        Ok(Vec::new())
+    }
+}
@@ -0,0 +1,74 @@
+//! Read-only report of the medias in a library that are probably the same film or show, which
+//! happens when copies under different names or qualities didnt get grouped while matching.
+use super::filename;
+
+use database::media::Media;
+use database::DatabaseError;
+use database::DbConnection;
+
+use serde::Serialize;
+
+/// Titles at least this similar are considered to be the same if their years also match.
+const SIMILAR_TITLE_THRESHOLD: f64 = 0.9;
+
+/// Struct holds a set of medias that most likely are the same film or show.
+#[derive(Serialize, Clone, Debug)]
+pub struct DuplicateGroup {
+    /// TMDB id the medias resolved to, if they were grouped because they share one.
+    pub external_id: Option<i64>,
+    pub medias: Vec<Media>,
+}
+
+/// Function returns whether two medias are most likely the same, either because they resolved to
+/// the same external id or because their titles are near identical and they came out the same
+/// year.
+fn is_duplicate(a: &(Media, Option<i64>), b: &(Media, Option<i64>)) -> bool {
+    let ((a, a_id), (b, b_id)) = (a, b);
+
+    if a.media_type != b.media_type {
+        return false;
+    }
+
+    if let (Some(a_id), Some(b_id)) = (a_id, b_id) {
+        return a_id == b_id;
+    }
+
+    a.year.is_some()
+        && a.year == b.year
+        && filename::title_similarity(&a.name, &b.name) >= SIMILAR_TITLE_THRESHOLD
+}
+
+/// Function groups the movies and tv shows of a library that are most likely duplicates of each
+/// other so that they can be merged. Medias without a duplicate are left out of the report.
+pub async fn find_duplicate_media(
+    conn: &DbConnection,
+    library_id: i64,
+) -> Result<Vec<DuplicateGroup>, DatabaseError> {
+    let mut medias = Vec::new();
+
+    for media in Media::get_all(conn, library_id).await? {
+        let external_id = Media::get_external_id(conn, media.id).await?;
+        medias.push((media, external_id));
+    }
+
+    let mut groups: Vec<Vec<(Media, Option<i64>)>> = Vec::new();
+
+    for media in medias {
+        match groups
+            .iter_mut()
+            .find(|group| group.iter().any(|x| is_duplicate(x, &media)))
+        {
+            Some(group) => group.push(media),
+            None => groups.push(vec![media]),
+        }
+    }
+
+    Ok(groups
+        .into_iter()
+        .filter(|group| group.len() > 1)
+        .map(|group| DuplicateGroup {
+            external_id: group.iter().find_map(|(_, id)| *id),
+            medias: group.into_iter().map(|(media, _)| media).collect(),
+        })
+        .collect())
+}
@@ -0,0 +1,375 @@
+//! Portable export and import of the scan data of a library. An export holds the metadata we
+//! matched every media with along with the paths of its files relative to the library location,
+//! so that a library can be moved to another machine without having to match it all over again.
+use super::base::ScannerError;
+use super::movie::MovieMatcher;
+use super::music::MusicMatcher;
+use super::tv_show::TvShowMatcher;
+use super::ApiEpisode;
+use super::ApiMedia;
+use super::ApiSeason;
+
+use crate::core::EventTx;
+
+use database::asset::Asset;
+use database::episode::Episode;
+use database::genre::Genre;
+use database::library::InsertableLibrary;
+use database::library::Library;
+use database::library::LibrarySettings;
+use database::library::MediaType;
+use database::library::TvAgent;
+use database::media::Media;
+use database::mediafile::MediaFile;
+use database::mediafile::MountSource;
+use database::mediafile::UpdateMediaFile;
+use database::season::Season;
+use database::DbConnection;
+
+use events::Message;
+use events::PushEventType;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use slog::info;
+use slog::warn;
+use slog::Logger;
+
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Version of the export format. Bumped whenever a field is removed or changes meaning.
+pub const EXPORT_VERSION: u32 = 1;
+
+/// A portable export of a library.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ExportDoc {
+    pub version: u32,
+    pub name: String,
+    pub media_type: MediaType,
+    pub media: Vec<ExportMedia>,
+}
+
+/// A matched media of an exported library along with all of its files.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ExportMedia {
+    /// Metadata as we have it stored, `id` holds the external id of the media.
+    pub metadata: ApiMedia,
+    pub files: Vec<ExportFile>,
+}
+
+/// A single file of an exported media.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ExportFile {
+    /// Path of the file relative to the library location it is located in.
+    pub path: PathBuf,
+    pub season: Option<i64>,
+    pub episode: Option<i64>,
+}
+
+/// Function exports the matched media of a library along with their files. Unmatched files and
+/// files located outside of the library locations are left out.
+///
+/// # Arguments
+/// * `conn` - db connection
+/// * `library_id` - id of the library to export
+pub async fn export_library(
+    conn: &DbConnection,
+    library_id: i64,
+) -> Result<ExportDoc, ScannerError> {
+    let library = Library::get_one(conn, library_id).await?;
+    let mut media = Vec::new();
+
+    for item in Media::get_all(conn, library_id).await? {
+        let mut metadata = export_metadata(conn, &item).await?;
+        let mut files = Vec::new();
+
+        if library.media_type == MediaType::Tv {
+            metadata.seasons = export_seasons(conn, item.id).await?;
+
+            for episode in Episode::get_all_of_tv(conn, item.id).await? {
+                let season = episode.get_season_number(conn).await?;
+
+                for file in MediaFile::get_of_media(conn, episode.id).await? {
+                    files.extend(relative_path(&library, &file).map(|path| ExportFile {
+                        path,
+                        season: Some(season),
+                        episode: Some(episode.episode),
+                    }));
+                }
+            }
+        } else {
+            for file in MediaFile::get_of_media(conn, item.id).await? {
+                files.extend(relative_path(&library, &file).map(|path| ExportFile {
+                    path,
+                    season: None,
+                    episode: None,
+                }));
+            }
+        }
+
+        media.push(ExportMedia { metadata, files });
+    }
+
+    Ok(ExportDoc {
+        version: EXPORT_VERSION,
+        name: library.name,
+        media_type: library.media_type,
+        media,
+    })
+}
+
+/// Function creates a new library located at `new_root` out of an export and relinks every
+/// exported file that exists under `new_root` with the metadata it was exported with. Files are
+/// still probed, but never matched against any metadata provider. Returns the id of the new
+/// library.
+///
+/// # Arguments
+/// * `conn` - db connection
+/// * `doc` - the export to import
+/// * `new_root` - the location of the library on this machine
+/// * `log` - logger
+/// * `tx` - event channel
+pub async fn import_library(
+    conn: &DbConnection,
+    doc: ExportDoc,
+    new_root: impl AsRef<Path>,
+    log: &Logger,
+    tx: &EventTx,
+) -> Result<i64, ScannerError> {
+    let root = super::normalize_library_location(new_root)?;
+
+    let library_id = InsertableLibrary {
+        name: doc.name,
+        locations: vec![root.to_string_lossy().to_string()],
+        media_type: doc.media_type,
+        settings: Default::default(),
+    }
+    .insert(conn)
+    .await?;
+
+    let event = Message {
+        id: library_id,
+        event_type: PushEventType::EventNewLibrary,
+    };
+
+    let _ = tx.send(serde_json::to_string(&event).unwrap());
+
+    let extractor = super::get_extractor(log, tx);
+    let settings = Arc::new(LibrarySettings::default());
+
+    for media in doc.media {
+        for file in media.files {
+            // NOTE: Exports might come from anywhere, so we refuse paths escaping the library.
+            if file.path.is_absolute() || file.path.components().any(|x| x == Component::ParentDir)
+            {
+                warn!(
+                    log,
+                    "Refusing to import file outside of library";
+                    "library_id" => library_id,
+                    "file" => file.path.to_string_lossy().to_string(),
+                );
+                continue;
+            }
+
+            let path = root.join(&file.path);
+
+            if !path.is_file() {
+                warn!(
+                    log,
+                    "Exported file is missing";
+                    "library_id" => library_id,
+                    "file" => path.to_string_lossy().to_string(),
+                );
+                continue;
+            }
+
+            let mut mediafile = match extractor
+                .mount_file(
+                    path,
+                    library_id,
+                    doc.media_type,
+                    MountSource::Import,
+                    settings.clone(),
+                )
+                .await
+            {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+
+            match doc.media_type {
+                MediaType::Movie => {
+                    let matcher = MovieMatcher {
+                        conn,
+                        log,
+                        event_tx: tx,
+                    };
+
+                    matcher
+                        .match_to_result(media.metadata.clone(), &mediafile)
+                        .await;
+                }
+                MediaType::Tv => {
+                    // NOTE: The filename parser might disagree with the episode the file was
+                    // exported as, the export wins.
+                    let update_mediafile = UpdateMediaFile {
+                        season: file.season,
+                        episode: file.episode,
+                        ..Default::default()
+                    };
+
+                    let _ = update_mediafile.update(conn, mediafile.id).await;
+
+                    mediafile.season = file.season;
+                    mediafile.episode = file.episode;
+
+                    let matcher = TvShowMatcher {
+                        conn,
+                        log,
+                        event_tx: tx,
+                    };
+
+                    matcher
+                        .match_to_result(media.metadata.clone(), &mediafile)
+                        .await;
+                }
+                MediaType::Music => {
+                    // NOTE: Songs are grouped into their album by their tags, which the export
+                    // doesnt carry, thus they are matched like any other song.
+                    let matcher = MusicMatcher {
+                        conn,
+                        log,
+                        event_tx: tx,
+                    };
+
+                    let _ = matcher.match_song(&mediafile, &settings).await;
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    info!(log, "Imported library"; "library_id" => library_id);
+
+    Ok(library_id)
+}
+
+fn relative_path(library: &Library, file: &MediaFile) -> Option<PathBuf> {
+    let path = Path::new(&file.target_file);
+
+    library
+        .locations
+        .iter()
+        .find_map(|root| path.strip_prefix(root).ok())
+        .map(ToOwned::to_owned)
+}
+
+/// Function returns the remote url and the local filename of an asset.
+async fn export_asset(conn: &DbConnection, id: Option<i64>) -> (Option<String>, Option<String>) {
+    match id {
+        Some(id) => match Asset::get_by_id(conn, id).await {
+            Ok(asset) => (
+                asset.remote_url,
+                Some(asset.local_path.trim_start_matches("images/").to_owned()),
+            ),
+            Err(_) => (None, None),
+        },
+        None => (None, None),
+    }
+}
+
+/// Function returns the metadata we have of `media` in the shape our metadata providers return it
+/// in.
+pub(super) async fn export_metadata(
+    conn: &DbConnection,
+    media: &Media,
+) -> Result<ApiMedia, ScannerError> {
+    let assets = sqlx::query!(
+        "SELECT poster, backdrop FROM _tblmedia WHERE id = ?",
+        media.id
+    )
+    .fetch_one(conn)
+    .await
+    .map_err(|e| ScannerError::DatabaseError(e.to_string()))?;
+
+    let (poster_path, poster_file) = export_asset(conn, assets.poster).await;
+    let (backdrop_path, backdrop_file) = export_asset(conn, assets.backdrop).await;
+
+    let genres = Genre::get_by_media(conn, media.id)
+        .await?
+        .into_iter()
+        .map(|x| x.name)
+        .collect();
+
+    let (tagline, runtime) = Media::get_details(conn, media.id).await?;
+
+    Ok(ApiMedia {
+        id: Media::get_external_id(conn, media.id).await?.unwrap_or(0) as u64,
+        title: media.name.clone(),
+        release_date: media.year.map(|x| format!("{}-01-01", x)),
+        overview: media.description.clone(),
+        poster_path,
+        backdrop_path,
+        poster_file,
+        backdrop_file,
+        genres,
+        rating: media.rating.map(|x| x as i32),
+        seasons: Vec::new(),
+        tagline,
+        runtime: runtime.map(|x| x as u32),
+        agent: TvAgent::Tmdb,
+    })
+}
+
+/// Function returns the seasons and episodes we have of the tv show `tv_id`.
+pub(super) async fn export_seasons(
+    conn: &DbConnection,
+    tv_id: i64,
+) -> Result<Vec<ApiSeason>, ScannerError> {
+    let mut seasons = Vec::new();
+
+    for season in Season::get_all(conn, tv_id).await? {
+        let poster = sqlx::query_scalar!("SELECT poster FROM _tblseason WHERE id = ?", season.id)
+            .fetch_one(conn)
+            .await
+            .map_err(|e| ScannerError::DatabaseError(e.to_string()))?;
+
+        let (poster_path, poster_file) = export_asset(conn, poster).await;
+        let mut episodes = Vec::new();
+
+        for episode in Episode::get_all_of_season(conn, season.id).await? {
+            let backdrop =
+                sqlx::query_scalar!("SELECT backdrop FROM _tblmedia WHERE id = ?", episode.id)
+                    .fetch_one(conn)
+                    .await
+                    .map_err(|e| ScannerError::DatabaseError(e.to_string()))?;
+
+            let (still, still_file) = export_asset(conn, backdrop).await;
+
+            episodes.push(ApiEpisode {
+                id: Media::get_external_id(conn, episode.id).await?.unwrap_or(0) as u64,
+                name: Some(episode.media.name),
+                overview: episode.media.description,
+                episode: Some(episode.episode as u64),
+                still,
+                still_file,
+                owned: true,
+            });
+        }
+
+        seasons.push(ApiSeason {
+            id: 0,
+            name: None,
+            poster_path,
+            poster_file,
+            season_number: season.season_number as u64,
+            episodes,
+        });
+    }
+
+    Ok(seasons)
+}
@@ -0,0 +1,993 @@
+//! Small helpers used to pull extra bits of information out of filenames that our filename parser
+//! does not handle for us.
+use std::iter;
+use std::path::Path;
+
+use chrono::prelude::Utc;
+use chrono::Datelike;
+use torrent_name_parser::Metadata;
+
+/// Function splits a filename into its alphanumeric tokens, ie `Movie.2020.PROPER-GRP` yields
+/// `["Movie", "2020", "PROPER", "GRP"]`.
+pub fn tokens(name: &str) -> impl Iterator<Item = &str> {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|x| !x.is_empty())
+}
+
+/// Function returns the release revision of a file based on its name. Originals have a revision of
+/// `0`, PROPER and REPACK tags bump the revision by one each, while version tags such as `v2` or
+/// `05v3` set the revision to at least `N - 1`. Titles can contain a word like `Proper` as well,
+/// thus tags only count once the title is over, ie after the year or the episode.
+pub fn release_revision(name: &str) -> i64 {
+    let tokens = tokens(name).collect::<Vec<_>>();
+    let title_end = (1..tokens.len())
+        .find(|&idx| ends_title(tokens[idx]))
+        .unwrap_or_else(|| tokens.len());
+
+    let mut revision = 0;
+
+    for (idx, token) in tokens.iter().enumerate() {
+        let lower = token.to_ascii_lowercase();
+        let after_title = idx >= title_end;
+
+        if after_title && (lower == "proper" || lower == "repack") {
+            revision += 1;
+            continue;
+        }
+
+        // NOTE: Anime releases usually glue the version onto the episode number, ie `05v2`,
+        // which is never part of a title.
+        if let Some(idx) = lower.rfind('v') {
+            let (head, tail) = lower.split_at(idx);
+            let version = &tail[1..];
+
+            if (after_title || !head.is_empty())
+                && head.chars().all(|c| c.is_ascii_digit())
+                && !version.is_empty()
+                && version.chars().all(|c| c.is_ascii_digit())
+            {
+                if let Ok(version) = version.parse::<i64>() {
+                    revision = revision.max(version - 1);
+                }
+            }
+        }
+    }
+
+    revision
+}
+
+/// Function returns whether `token` marks the end of a title, as a year, a episode such as
+/// `S01E02` or `1x02` and release tags such as `1080p` do.
+fn ends_title(token: &str) -> bool {
+    let lower = token.to_ascii_lowercase();
+    let all_digits = |x: &str| !x.is_empty() && x.chars().all(|c| c.is_ascii_digit());
+
+    let is_year = lower.len() == 4
+        && lower
+            .parse::<i64>()
+            .map_or(false, |x| (1880..=2100).contains(&x));
+    let is_episode = lower.strip_prefix('s').map_or(false, |x| {
+        let rest = x.trim_start_matches(|c: char| c.is_ascii_digit());
+        rest.len() < x.len() && (rest.is_empty() || rest.starts_with('e'))
+    }) || lower
+        .split_once('x')
+        .map_or(false, |(a, b)| all_digits(a) && all_digits(b));
+
+    is_year || is_episode || !noise_tokens(token).is_empty()
+}
+
+/// Function returns the release year of a media from the name of the folder it is located in, ie
+/// `Crash (2004)/crash.mkv` yields `2004`. If more than one plausible year is found, the last one
+/// wins as titles themselves can contain numbers.
+pub fn year_from_folder(path: &Path) -> Option<i64> {
+    let folder = path.parent()?.file_name()?.to_str()?;
+
+    tokens(folder)
+        .filter(|x| x.len() == 4)
+        .filter_map(|x| x.parse::<i64>().ok())
+        .filter(|x| (1880..=2100).contains(x))
+        .last()
+}
+
+/// Function extracts the year out of a `release_date` such as `2004-05-06`.
+pub fn year_from_date(date: &str) -> Option<i64> {
+    date.split('-').next()?.parse().ok()
+}
+
+/// Maximum number of candidate titles we will try to match a single file with.
+pub const MAX_TITLE_CANDIDATES: usize = 4;
+
+/// Function returns the titles we should try to match a file with, best guess first. Some releases
+/// carry both a translated and an original title, ie `Parasite Gisaengchung`, which as a whole
+/// wont match anything, so we also try the titles on both sides of each word boundary. The number
+/// of candidates returned is bounded by [`MAX_TITLE_CANDIDATES`].
+pub fn candidate_titles(title: &str) -> Vec<String> {
+    let words = title.split_whitespace().collect::<Vec<_>>();
+    let mut candidates = vec![words.join(" ")];
+
+    // NOTE: Longer prefixes are tried first as the translated title usually comes first and
+    // titles themselves often consist of several words.
+    let splits = (1..words.len()).rev().flat_map(|split| {
+        iter::once(words[..split].join(" ")).chain(iter::once(words[split..].join(" ")))
+    });
+
+    for candidate in splits {
+        if candidates.len() >= MAX_TITLE_CANDIDATES {
+            break;
+        }
+
+        if !candidates.contains(&candidate) {
+            candidates.push(candidate);
+        }
+    }
+
+    candidates
+}
+
+/// Function returns how similar two titles are on a scale from `0.0` to `1.0`, ignoring case and
+/// punctuation. The score is the share of words both titles have in common.
+pub fn title_similarity(a: &str, b: &str) -> f64 {
+    let a = tokens(a).map(str::to_lowercase).collect::<Vec<_>>();
+    let b = tokens(b).map(str::to_lowercase).collect::<Vec<_>>();
+
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    let common = a.iter().filter(|x| b.contains(x)).count();
+
+    (2 * common) as f64 / (a.len() + b.len()) as f64
+}
+
+/// Function returns how confident we are that a search result titled `found`, released in
+/// `found_year`, is the media parsed as `title` and `year`, on a scale from `0.0` to `1.0`. Titles
+/// are compared by their edit distance ignoring case and punctuation, and results released in
+/// another year than the one parsed are penalized the further off they are.
+pub fn match_score(title: &str, year: Option<i64>, found: &str, found_year: Option<i64>) -> f64 {
+    let a = tokens(title)
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let b = tokens(found)
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let title_score = match a.chars().count().max(b.chars().count()) {
+        0 => 0.0,
+        len => 1.0 - levenshtein(&a, &b) as f64 / len as f64,
+    };
+
+    let year_score = match (year, found_year) {
+        (Some(x), Some(y)) => 1.0 - (0.1 * (x - y).abs() as f64).min(0.5),
+        _ => 1.0,
+    };
+
+    title_score * year_score
+}
+
+/// Function returns the number of single character edits needed to turn `a` into `b`.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, x) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+
+        for (j, y) in b.iter().enumerate() {
+            let cost = if x == *y { prev } else { prev + 1 };
+            prev = row[j + 1];
+            row[j + 1] = cost.min(row[j] + 1).min(prev + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+/// Function removes every occurrence of the tokens in `strip` from `title`, ie stripping `DDP5.1`
+/// from `Movie DDP5 1` yields `Movie`. Tokens are matched on whole words, ignoring case and
+/// punctuation. If nothing would be left of the title, the title is returned as is.
+pub fn strip_tokens(title: &str, strip: &[String]) -> String {
+    let words = title.split_whitespace().collect::<Vec<_>>();
+    let normalized = words
+        .iter()
+        .map(|x| {
+            x.trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase()
+        })
+        .collect::<Vec<_>>();
+
+    let patterns = strip
+        .iter()
+        .map(|x| tokens(x).map(str::to_lowercase).collect::<Vec<_>>())
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>();
+
+    let mut stripped = Vec::with_capacity(words.len());
+    let mut idx = 0;
+
+    while idx < words.len() {
+        let matched = patterns
+            .iter()
+            .find(|x| normalized[idx..].starts_with(x))
+            .map(Vec::len);
+
+        match matched {
+            Some(len) => idx += len,
+            None => {
+                stripped.push(words[idx]);
+                idx += 1;
+            }
+        }
+    }
+
+    if stripped.is_empty() {
+        return title.to_owned();
+    }
+
+    stripped.join(" ")
+}
+
+/// Function picks the candidate, given as its title and release year in the order they were
+/// found in, that was released the closest to `year` and returns its index. Candidates equally
+/// close to `year` are told apart by whether their title is `title`, then by the order they were
+/// found in. If several candidates titled `title` are still tied, or if no year is known and
+/// there are several of them, we cant tell which one is meant and `None` is returned.
+pub fn pick_by_year(
+    title: &str,
+    candidates: &[(&str, Option<i64>)],
+    year: Option<i64>,
+) -> Option<usize> {
+    let is_exact = |idx: &usize| candidates[*idx].0.eq_ignore_ascii_case(title);
+    let distance = |idx: &usize| {
+        year.zip(candidates[*idx].1)
+            .map(|(year, found)| (year - found).abs())
+    };
+
+    let closest = (0..candidates.len()).filter_map(|x| distance(&x)).min();
+    let tied = (0..candidates.len())
+        .filter(|x| closest.is_none() || distance(x) == closest)
+        .collect::<Vec<_>>();
+
+    match tied.iter().filter(|x| is_exact(x)).count() {
+        0 => tied.first().copied(),
+        1 => tied.into_iter().find(is_exact),
+        _ => None,
+    }
+}
+
+/// Function splits the name of a special that lacks season and episode numbers into the title of
+/// the show and the name of the special, ie `Doctor Who - The Christmas Invasion` yields
+/// `("Doctor Who", "The Christmas Invasion")`.
+pub fn split_special(name: &str) -> Option<(String, String)> {
+    let name = name.replace(|c: char| c == '.' || c == '_', " ");
+    let (show, special) = name.split_once(" - ")?;
+    let (show, special) = (show.trim(), special.trim());
+
+    if show.is_empty() || special.is_empty() {
+        return None;
+    }
+
+    Some((show.to_owned(), special.to_owned()))
+}
+
+/// Function scans a filename for a plausible release year, for when the filename parser does not
+/// pick one up, ie `Inception 2010 BluRay` yields `2010`. Years must lie between 1900 and next
+/// year and cant be part of a resolution such as `1920 x 1080`. The first word is never a year
+/// as it has to be part of the title, ie `1917 2019` yields `2019`.
+pub fn year_from_name(name: &str) -> Option<i64> {
+    let tokens = tokens(name).collect::<Vec<_>>();
+    let latest = Utc::now().year() as i64 + 1;
+
+    (1..tokens.len())
+        .filter(|&idx| tokens[idx].len() == 4)
+        .filter(|&idx| {
+            let is_dimension = |x: Option<&&str>| x.map_or(false, |x| x.eq_ignore_ascii_case("x"));
+            !is_dimension(tokens.get(idx - 1)) && !is_dimension(tokens.get(idx + 1))
+        })
+        .filter_map(|idx| tokens[idx].parse::<i64>().ok())
+        .filter(|x| (1900..=latest).contains(x))
+        .last()
+}
+
+/// Release tags that never belong in a title, all lowercase.
+const NOISE_TOKENS: &[&str] = &[
+    "4k", "uhd", "hdr", "x264", "x265", "h264", "h265", "hevc", "avc", "xvid", "divx", "10bit",
+    "bluray", "bdrip", "brrip", "webrip", "webdl", "web", "hdtv", "dvdrip", "remux", "aac", "ac3",
+    "dts", "ddp", "atmos", "truehd",
+];
+
+/// Function returns the tokens of `title` that look like release noise rather than part of the
+/// title, ie resolutions such as `1080p`, codecs or sources.
+pub fn noise_tokens(title: &str) -> Vec<String> {
+    tokens(title)
+        .filter(|x| {
+            let lower = x.to_ascii_lowercase();
+            let is_resolution = lower
+                .strip_suffix(|c: char| c == 'p' || c == 'i')
+                .map_or(false, |x| {
+                    (3..=4).contains(&x.len()) && x.chars().all(|c| c.is_ascii_digit())
+                });
+
+            is_resolution || NOISE_TOKENS.contains(&lower.as_str())
+        })
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// Function returns the season and part of a season a file belongs to based on the name of the
+/// folder it is located in, ie `Season 4 Part 2/01.mkv` yields `(4, Some(2))` while `Season 03`
+/// and `S03` yield `(3, None)`. Parts can also be written as `Pt`, `Vol` or `Volume`.
+pub fn season_from_folder(path: &Path) -> Option<(i64, Option<i64>)> {
+    let folder = path.parent()?.file_name()?.to_str()?;
+    let tokens = tokens(folder)
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>();
+
+    let short = |x: &String| {
+        x.strip_prefix('s')
+            .filter(|x| !x.is_empty() && x.chars().all(|c| c.is_ascii_digit()))
+            .and_then(|x| x.parse::<i64>().ok())
+    };
+
+    // NOTE: `rest` is the index of the first token after the season number.
+    let (season, rest) = match tokens.iter().position(|x| x == "season") {
+        Some(idx) => (tokens.get(idx + 1)?.parse::<i64>().ok()?, idx + 2),
+        None => (short(tokens.first()?)?, 1),
+    };
+
+    let part = match tokens.get(rest).map(String::as_str) {
+        Some("part") | Some("pt") | Some("vol") | Some("volume") => {
+            tokens.get(rest + 1).and_then(|x| x.parse::<i64>().ok())
+        }
+        _ => None,
+    };
+
+    Some((season, part))
+}
+
+/// Function checks whether `title` is too weak to match a show with, as is the case for episodes
+/// named after their number alone, ie `e01`, `Ep 1` or `Episode 01`.
+pub fn is_weak_title(title: &str) -> bool {
+    tokens(title).all(|x| episode_token(x).is_some() || x.eq_ignore_ascii_case("episode"))
+}
+
+/// Function returns the episode number of a name made up of just that, ie `e01` yields `1`.
+pub fn episode_from_weak_title(title: &str) -> Option<i64> {
+    tokens(title).find_map(episode_token)
+}
+
+/// Function returns the number in a token like `e01`, `ep01` or `01`.
+fn episode_token(token: &str) -> Option<i64> {
+    let lower = token.to_ascii_lowercase();
+    let number = lower
+        .strip_prefix("ep")
+        .or_else(|| lower.strip_prefix('e'))
+        .unwrap_or(&lower);
+
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    number.parse().ok()
+}
+
+/// Struct holds what the folders of a episode tell us about its show, see [`show_from_folders`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShowFolder {
+    pub title: String,
+    pub year: Option<i64>,
+    pub season: i64,
+}
+
+/// Function returns the show and season an episode belongs to based on the folders it is located
+/// in, for shows laid out like `Show (2010)/Season 01/e01.mkv`. Returns `None` unless the episode
+/// sits in a season folder.
+pub fn show_from_folders(path: &Path) -> Option<ShowFolder> {
+    let (season, _) = season_from_folder(path)?;
+    let show = path.parent()?.parent()?.file_name()?.to_str()?.trim();
+
+    // NOTE: Only a year at the end of the folder name is taken, ie `Show (2010)`, as others are
+    // likely part of the title.
+    let trailing = |year: i64| {
+        show.trim_end_matches(|c: char| c == ')' || c == ']' || c.is_whitespace())
+            .strip_suffix(&year.to_string())
+            .map(|x| x.trim_end_matches(|c: char| c == '(' || c == '[' || c.is_whitespace()))
+    };
+
+    let (title, year) = match year_from_name(show).and_then(|x| trailing(x).map(|t| (t, x))) {
+        Some((title, year)) => (title, Some(year)),
+        None => (show, None),
+    };
+
+    if title.is_empty() {
+        return None;
+    }
+
+    Some(ShowFolder {
+        title: title.to_owned(),
+        year,
+        season,
+    })
+}
+
+/// Function removes the invisible bidirectional control characters, ie `U+200F RIGHT-TO-LEFT MARK`,
+/// that often end up in filenames with Arabic or Hebrew titles. They only affect how text is
+/// displayed but split words apart and break matching.
+pub fn strip_bidi_controls(name: &str) -> String {
+    name.chars()
+        .filter(|c| {
+            !matches!(
+                *c,
+                '\u{061c}'
+                    | '\u{200e}'
+                    | '\u{200f}'
+                    | '\u{202a}'..='\u{202e}'
+                    | '\u{2066}'..='\u{2069}'
+            )
+        })
+        .collect()
+}
+
+/// Function returns the key a title should be sorted by. The key is lowercased and made up of its
+/// words only, so case, punctuation and bidirectional control characters dont affect the order,
+/// while titles in any script keep all of their letters.
+pub fn sort_key(title: &str) -> String {
+    tokens(&strip_bidi_controls(title))
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Function strips the leading group tags and trailing quality tags wrapped in brackets off an
+/// anime style filename, ie `[Group] Show - 05 [1080p][HEVC]` yields `Show - 05`. Returns `None`
+/// if the name doesnt start with a bracketed tag.
+pub fn strip_anime_brackets(name: &str) -> Option<String> {
+    let mut rest = name.trim();
+
+    if !rest.starts_with('[') {
+        return None;
+    }
+
+    while rest.starts_with('[') {
+        rest = rest[rest.find(']')? + 1..].trim_start();
+    }
+
+    while rest.ends_with(']') {
+        rest = rest[..rest.rfind('[')?].trim_end();
+    }
+
+    if rest.is_empty() {
+        return None;
+    }
+
+    Some(rest.replace('_', " "))
+}
+
+/// Function splits an anime style name into its title and episode number, ie `Show - 05` yields
+/// `("Show", 5)`. Version tags glued onto the episode number, ie `05v2`, are ignored.
+pub fn split_anime_episode(name: &str) -> Option<(String, i64)> {
+    let (title, episode) = name.rsplit_once(" - ")?;
+    let title = title.trim();
+    let episode = episode.trim();
+
+    let digits = episode
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or(episode, |idx| &episode[..idx]);
+    let rest = &episode[digits.len()..];
+
+    let is_version = rest.is_empty()
+        || rest
+            .strip_prefix(|c: char| c == 'v' || c == 'V')
+            .map_or(false, |x| {
+                !x.is_empty() && x.chars().all(|c| c.is_ascii_digit())
+            });
+
+    if title.is_empty() || digits.is_empty() || !is_version {
+        return None;
+    }
+
+    Some((title.to_owned(), digits.parse().ok()?))
+}
+
+/// Function splits the name of a movie split over several files into its title and part number,
+/// ie `Movie cd2` yields `("Movie", 2)`. Parts can be tagged as `cdN`, `cd N`, `partN` or `ptN`.
+/// Tags such as `Part 2` arent parts as they usually belong to the title itself.
+pub fn split_part(name: &str) -> Option<(String, i64)> {
+    let words = tokens(name).collect::<Vec<_>>();
+
+    let number = |digits: &str| {
+        if (1..=2).contains(&digits.len()) && digits.chars().all(|c| c.is_ascii_digit()) {
+            digits.parse::<i64>().ok()
+        } else {
+            None
+        }
+    };
+
+    let part_of = |word: &str| {
+        let lower = word.to_ascii_lowercase();
+        ["cd", "part", "pt"]
+            .iter()
+            .find_map(|x| lower.strip_prefix(x))
+            .and_then(number)
+    };
+
+    (1..words.len()).rev().find_map(|idx| {
+        let (part, title) = match part_of(words[idx]) {
+            Some(part) => (part, &words[..idx]),
+            None if idx > 1 && words[idx - 1].eq_ignore_ascii_case("cd") => {
+                (number(words[idx])?, &words[..idx - 1])
+            }
+            None => return None,
+        };
+
+        (part > 0).then(|| (title.join(" "), part))
+    })
+}
+
+/// Most episodes we believe a single file holds. Longer ranges are most likely something else,
+/// ie `S01E01-50` for a season pack or a year following the episode.
+const MAX_EPISODE_RANGE: i64 = 10;
+
+/// Function returns the first and last episode of a file holding several episodes, ie
+/// `Show.S01E01-E03` yields `(1, 3)`. Ranges can be written as `S01E01-E03`, `S01E01-03`,
+/// `S01E01E02E03` or `Episodes 1-3`. Ranges spanning more than [`MAX_EPISODE_RANGE`] episodes
+/// are ignored.
+pub fn episode_range(name: &str) -> Option<(i64, i64)> {
+    let chars = name.to_ascii_lowercase().chars().collect::<Vec<_>>();
+    let episodes = "episodes".chars().collect::<Vec<_>>();
+
+    let digits_at = |idx: usize| {
+        let idx = idx.min(chars.len());
+        let len = chars[idx..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        let number = chars[idx..idx + len]
+            .iter()
+            .collect::<String>()
+            .parse::<i64>()
+            .ok()?;
+
+        Some((number, idx + len))
+    };
+    let is_boundary = |idx: usize| idx == 0 || !chars[idx - 1].is_alphanumeric();
+
+    for idx in (0..chars.len()).filter(|&idx| is_boundary(idx)) {
+        let (start, mut rest) = if chars[idx] == 's' {
+            match digits_at(idx + 1) {
+                Some((_, next)) if chars.get(next) == Some(&'e') => match digits_at(next + 1) {
+                    Some(x) => x,
+                    None => continue,
+                },
+                _ => continue,
+            }
+        } else if chars[idx..].starts_with(&episodes) {
+            let next = idx
+                + episodes.len()
+                + chars[idx + episodes.len()..]
+                    .iter()
+                    .take_while(|c| matches!(**c, ' ' | '.' | '_'))
+                    .count();
+
+            match digits_at(next) {
+                Some(x) if chars.get(x.1) == Some(&'-') => x,
+                _ => continue,
+            }
+        } else {
+            continue;
+        };
+
+        let mut end = start;
+
+        // NOTE: Releases either list every episode, ie `E01E02E03`, or just the last one.
+        loop {
+            let next = match chars.get(rest) {
+                Some('-') if chars.get(rest + 1) == Some(&'e') => rest + 2,
+                Some('-') | Some('e') => rest + 1,
+                _ => break,
+            };
+
+            // NOTE: Make sure we dont mistake a resolution such as `1080p` for a episode.
+            let is_end = |idx: usize| {
+                chars
+                    .get(idx)
+                    .map_or(true, |c| !c.is_alphanumeric() || *c == 'e')
+            };
+
+            match digits_at(next) {
+                Some((x, after)) if x > end && is_end(after) => {
+                    end = x;
+                    rest = after;
+                }
+                _ => break,
+            }
+        }
+
+        if end > start && end - start <= MAX_EPISODE_RANGE {
+            return Some((start, end));
+        }
+    }
+
+    None
+}
+
+/// Struct holds the ids tagged in a name, see [`id_tags`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IdTags {
+    pub tmdb_id: Option<i64>,
+    pub imdb_id: Option<String>,
+}
+
+/// Function pulls the TMDB and IMDB ids tagged in a name out of it, the way Plex and Jellyfin
+/// expect them, ie `Movie (2020) {tmdb-12345}` or `Movie (2020) [imdbid-tt1234567]`. Returns the
+/// name with the tags removed along with the ids. Bracketed text that isnt a id tag is kept.
+pub fn id_tags(name: &str) -> (String, IdTags) {
+    let mut tags = IdTags::default();
+    let mut stripped = String::with_capacity(name.len());
+    let mut rest = name;
+
+    while let Some(start) = rest.find(|c| c == '{' || c == '[') {
+        let close = if rest[start..].starts_with('{') {
+            '}'
+        } else {
+            ']'
+        };
+        let end = match rest[start..].find(close) {
+            Some(x) => start + x,
+            None => break,
+        };
+
+        let tag = rest[start + 1..end].trim().to_ascii_lowercase();
+        let (key, id) = match tag.split_once(|c| c == '-' || c == '=') {
+            Some((key, id)) => (key.trim(), id.trim()),
+            None => ("", ""),
+        };
+
+        let known = match key {
+            "tmdb" | "tmdbid" => match id.parse::<i64>() {
+                Ok(x) => {
+                    tags.tmdb_id = Some(x);
+                    true
+                }
+                Err(_) => false,
+            },
+            "imdb" | "imdbid" if is_imdb_id(id) => {
+                tags.imdb_id = Some(id.to_owned());
+                true
+            }
+            _ => false,
+        };
+
+        stripped.push_str(&rest[..start]);
+        if !known {
+            stripped.push_str(&rest[start..=end]);
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    if tags == IdTags::default() {
+        return (name.to_owned(), tags);
+    }
+
+    stripped.push_str(rest);
+
+    // NOTE: Removing a tag leaves behind the whitespace that surrounded it.
+    let stripped = stripped.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    (stripped, tags)
+}
+
+/// Function checks whether `id` looks like a IMDB id, ie `tt1375666`.
+pub fn is_imdb_id(id: &str) -> bool {
+    id.strip_prefix("tt").map_or(false, |x| {
+        !x.is_empty() && x.chars().all(|c| c.is_ascii_digit())
+    })
+}
+
+/// Struct holds the parts of a filename files are matched by.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ParsedName {
+    pub title: String,
+    pub year: Option<i64>,
+    pub season: Option<i64>,
+    pub episode: Option<i64>,
+}
+
+/// Function parses `name` with the filename parser, ie `Show.S03E00.mkv` yields the title `Show`,
+/// season `3` and episode `0`. Returns `None` if the parser couldnt make anything of it.
+pub fn parse_release(name: &str) -> Option<ParsedName> {
+    let metadata = Metadata::from(name).ok()?;
+
+    Some(ParsedName {
+        title: metadata.title().to_owned(),
+        year: metadata.year().map(|x| x as i64),
+        season: metadata.season().map(|x| x as i64),
+        episode: metadata.episode().map(|x| x as i64),
+    })
+}
+
+/// Function returns the season of a episode numbered `E00` within a numbered season, ie
+/// `S03E00`. Some shows number a pilot or recap like that, which clashes with specials living in
+/// season 0, so such episodes stay episode 0 of the season they are tagged with unless
+/// `as_specials` is set. All other episodes keep their season.
+pub fn episode_zero_season(
+    season: Option<i64>,
+    episode: Option<i64>,
+    as_specials: bool,
+) -> Option<i64> {
+    match (season, episode) {
+        (Some(x), Some(0)) if x > 0 && as_specials => Some(0),
+        _ => season,
+    }
+}
+
+/// Function matches `name` against the user supplied `pattern`, whose named captures `title`,
+/// `year`, `season` and `episode` are picked up, ie `^(?P<year>\d{4}) - (?P<title>.+?) - .*$`.
+/// Returns `None` if the pattern doesnt match or captured no title. Captures that arent numbers
+/// are ignored.
+pub fn parse_custom(pattern: &regex::Regex, name: &str) -> Option<ParsedName> {
+    let captures = pattern.captures(name)?;
+    let number = |group: &str| {
+        captures
+            .name(group)
+            .and_then(|x| x.as_str().trim().parse::<i64>().ok())
+    };
+
+    // NOTE: Hand named files tend to use dots and underscores in place of spaces too.
+    let title = captures
+        .name("title")?
+        .as_str()
+        .replace(|c| c == '.' || c == '_', " ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if title.is_empty() {
+        return None;
+    }
+
+    Some(ParsedName {
+        title,
+        year: number("year"),
+        season: number("season"),
+        episode: number("episode"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pick_by_year_of_remakes() {
+        let crash = [("Crash", Some(1996)), ("Crash", Some(2004))];
+
+        assert_eq!(pick_by_year("Crash", &crash, Some(2004)), Some(1));
+        assert_eq!(pick_by_year("Crash", &crash, Some(1996)), Some(0));
+        // NOTE: Without a release within a year we settle for the closest one.
+        assert_eq!(pick_by_year("Crash", &crash, Some(2008)), Some(1));
+        assert_eq!(pick_by_year("Crash", &crash, Some(1990)), Some(0));
+        assert_eq!(pick_by_year("Crash", &crash, Some(2000)), None);
+        assert_eq!(pick_by_year("Crash", &crash, None), None);
+    }
+
+    #[test]
+    fn pick_by_year_breaks_ties() {
+        let found = [("Crash Course", Some(2004)), ("Crash", Some(2004))];
+        assert_eq!(pick_by_year("Crash", &found, Some(2004)), Some(1));
+
+        let found = [("Crash", Some(2004)), ("Crash", Some(2004))];
+        assert_eq!(pick_by_year("Crash", &found, Some(2004)), None);
+
+        let found = [("Crash Course", Some(2004)), ("Crash Test", Some(2004))];
+        assert_eq!(pick_by_year("Crash", &found, Some(2004)), Some(0));
+
+        let found = [("Crash", None), ("Crash Course", Some(2004))];
+        assert_eq!(pick_by_year("Crash", &found, Some(2004)), Some(1));
+        assert_eq!(pick_by_year("Crash", &found, None), Some(0));
+    }
+
+    #[test]
+    fn release_revision_of_tags() {
+        assert_eq!(release_revision("Movie.2020.1080p.BluRay-GRP"), 0);
+        assert_eq!(release_revision("Movie.2020.PROPER.1080p.BluRay-GRP"), 1);
+        assert_eq!(release_revision("Movie.2020.PROPER.REPACK.1080p-GRP"), 2);
+        assert_eq!(release_revision("Show.S01E02.REPACK.720p-GRP"), 1);
+        assert_eq!(release_revision("[Group] Show - 05v3 [1080p]"), 2);
+        assert_eq!(release_revision("Show.S01E05.v2.720p"), 1);
+    }
+
+    #[test]
+    fn release_revision_ignores_titles() {
+        assert_eq!(release_revision("The.Proper.Way.2019.1080p-GRP"), 0);
+        assert_eq!(release_revision("Repack.Men.S01E01.720p"), 0);
+        assert_eq!(release_revision("V2.Rockets.2019.1080p"), 0);
+        assert_eq!(release_revision("The Proper Way"), 0);
+    }
+
+    #[test]
+    fn episode_zero_stays_in_its_season() {
+        let parsed = parse_release("Show.S03E00.mkv").unwrap();
+
+        assert_eq!(parsed.title, "Show");
+        assert_eq!(parsed.season, Some(3));
+        assert_eq!(parsed.episode, Some(0));
+        assert_eq!(
+            episode_zero_season(parsed.season, parsed.episode, false),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn episode_range_of_multi_episode_files() {
+        assert_eq!(episode_range("Show.S01E01-E03.720p"), Some((1, 3)));
+        assert_eq!(episode_range("Show.S01E01-03.720p"), Some((1, 3)));
+        assert_eq!(episode_range("Show.S01E01E02E03.720p"), Some((1, 3)));
+        assert_eq!(episode_range("Show Episodes 4-5"), Some((4, 5)));
+        assert_eq!(episode_range("Show.S02E01-E11"), Some((1, 11)));
+    }
+
+    #[test]
+    fn episode_range_rejects_bogus_ranges() {
+        assert_eq!(episode_range("Show.S01E01.720p"), None);
+        assert_eq!(episode_range("Show.S01E05-E03"), None);
+        assert_eq!(episode_range("Show.S01E03-E03"), None);
+        assert_eq!(episode_range("Show.S01E01-E12"), None);
+        assert_eq!(episode_range("Show.S01E01-2019"), None);
+        assert_eq!(episode_range("Show.S01E01-1080p"), None);
+    }
+
+    #[test]
+    fn episode_zero_as_specials() {
+        assert_eq!(episode_zero_season(Some(3), Some(0), true), Some(0));
+        assert_eq!(episode_zero_season(Some(3), Some(1), true), Some(3));
+        assert_eq!(episode_zero_season(Some(0), Some(0), true), Some(0));
+        assert_eq!(episode_zero_season(None, Some(0), true), None);
+    }
+
+    #[test]
+    fn weak_titles_of_numbered_episodes() {
+        assert!(is_weak_title("e01"));
+        assert!(is_weak_title("Ep 1"));
+        assert!(is_weak_title("Episode 01"));
+        assert!(!is_weak_title("Pilot"));
+        assert!(!is_weak_title("Episode 1 - Pilot"));
+
+        assert_eq!(episode_from_weak_title("e05"), Some(5));
+        assert_eq!(episode_from_weak_title("Ep 12"), Some(12));
+        assert_eq!(episode_from_weak_title("Episode 01"), Some(1));
+        assert_eq!(episode_from_weak_title("Pilot"), None);
+    }
+
+    #[test]
+    fn show_from_season_folders() {
+        assert_eq!(
+            show_from_folders(Path::new("/tv/Show (2010)/Season 01/e01.mkv")),
+            Some(ShowFolder {
+                title: "Show".into(),
+                year: Some(2010),
+                season: 1,
+            })
+        );
+        assert_eq!(
+            show_from_folders(Path::new("/tv/2012 (2009)/S02/e01.mkv")),
+            Some(ShowFolder {
+                title: "2012".into(),
+                year: Some(2009),
+                season: 2,
+            })
+        );
+        // NOTE: A year in the middle of the folder name belongs to the title.
+        assert_eq!(
+            show_from_folders(Path::new("/tv/Show 2019 Reboot/Season 3/e01.mkv")),
+            Some(ShowFolder {
+                title: "Show 2019 Reboot".into(),
+                year: None,
+                season: 3,
+            })
+        );
+        assert_eq!(show_from_folders(Path::new("/tv/Show/e01.mkv")), None);
+    }
+
+    #[test]
+    fn anime_brackets_are_stripped() {
+        assert_eq!(
+            strip_anime_brackets("[Group] Show - 05 [1080p][HEVC]").as_deref(),
+            Some("Show - 05")
+        );
+        assert_eq!(
+            strip_anime_brackets("[Group] Show_Name - 05").as_deref(),
+            Some("Show Name - 05")
+        );
+        assert_eq!(strip_anime_brackets("Show - 05 [1080p]"), None);
+        assert_eq!(strip_anime_brackets("[Group][1080p]"), None);
+    }
+
+    #[test]
+    fn anime_episodes_are_split_off() {
+        assert_eq!(split_anime_episode("Show - 05"), Some(("Show".into(), 5)));
+        assert_eq!(split_anime_episode("Show - 05v2"), Some(("Show".into(), 5)));
+        assert_eq!(
+            split_anime_episode("Show - Arc - 12"),
+            Some(("Show - Arc".into(), 12))
+        );
+        assert_eq!(split_anime_episode("Show - 05x"), None);
+        assert_eq!(split_anime_episode("Show - Part"), None);
+        assert_eq!(split_anime_episode("Show 05"), None);
+    }
+
+    #[test]
+    fn id_tags_are_pulled_out() {
+        assert_eq!(
+            id_tags("Movie (2020) {tmdb-12345}"),
+            (
+                "Movie (2020)".into(),
+                IdTags {
+                    tmdb_id: Some(12345),
+                    imdb_id: None,
+                }
+            )
+        );
+        assert_eq!(
+            id_tags("Movie (2020) [imdbid-tt1234567]"),
+            (
+                "Movie (2020)".into(),
+                IdTags {
+                    tmdb_id: None,
+                    imdb_id: Some("tt1234567".into()),
+                }
+            )
+        );
+        assert_eq!(
+            id_tags("Movie [Director's Cut] (2020) {tmdb=603}"),
+            (
+                "Movie [Director's Cut] (2020)".into(),
+                IdTags {
+                    tmdb_id: Some(603),
+                    imdb_id: None,
+                }
+            )
+        );
+        assert_eq!(
+            id_tags("Movie [1080p]"),
+            ("Movie [1080p]".into(), IdTags::default())
+        );
+    }
+
+    #[test]
+    fn imdb_ids() {
+        assert!(is_imdb_id("tt1375666"));
+        assert!(!is_imdb_id("tt"));
+        assert!(!is_imdb_id("1375666"));
+        assert!(!is_imdb_id("ttabc"));
+    }
+
+    #[test]
+    fn custom_patterns() {
+        let pattern = regex::Regex::new(r"^(?P<year>\d{4}) - (?P<title>.+?) - .*$").unwrap();
+
+        assert_eq!(
+            parse_custom(&pattern, "2010 - Inception.Dream_Heist - 1080p"),
+            Some(ParsedName {
+                title: "Inception Dream Heist".into(),
+                year: Some(2010),
+                season: None,
+                episode: None,
+            })
+        );
+        assert_eq!(parse_custom(&pattern, "Inception (2010)"), None);
+
+        // NOTE: Captures that arent numbers are dropped rather than failing the match.
+        let pattern = regex::Regex::new(r"^(?P<title>.+) E(?P<episode>\w+)$").unwrap();
+        assert_eq!(
+            parse_custom(&pattern, "Show EAB"),
+            Some(ParsedName {
+                title: "Show".into(),
+                ..Default::default()
+            })
+        );
+    }
+}
@@ -0,0 +1,247 @@
+//! Perceptual fingerprints of videos, used to find files that are the same content even though
+//! they were encoded differently. Computing a fingerprint decodes several frames of a file, so
+//! this is opt-in and done in the background one file at a time.
+use crate::streaming::FFMPEG_BIN;
+
+use database::fingerprint_queue::FingerprintQueue;
+use database::mediafile::MediaFile;
+use database::mediafile::UpdateMediaFile;
+use database::DatabaseError;
+use database::DbConnection;
+
+use serde::Serialize;
+
+use slog::debug;
+use slog::warn;
+use slog::Logger;
+
+use tokio::task::spawn_blocking;
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+use std::time::Duration;
+
+/// Number of frames we sample from each file.
+const SAMPLES: i32 = 8;
+/// Width and height frames are scaled down to before they are hashed. The extra column is needed
+/// because each bit of the hash compares two neighbouring pixels.
+const HASH_WIDTH: usize = 9;
+const HASH_HEIGHT: usize = 8;
+/// Fingerprints at least this similar are considered to be the same content.
+pub const SIMILARITY_THRESHOLD: f64 = 0.9;
+
+/// Function queues `file` to be fingerprinted in the background. The queue is kept in the db, so
+/// files queued before a restart are fingerprinted once we are back up.
+pub async fn insert_into_queue(conn: &DbConnection, log: &Logger, file: &MediaFile) {
+    if !matches!(file.duration, Some(x) if x > 0) {
+        return;
+    }
+
+    debug!(log, "Queueing file for fingerprinting"; "file" => &file.target_file);
+
+    if let Err(e) = FingerprintQueue::push(conn, file.id).await {
+        warn!(
+            log,
+            "Failed to queue file for fingerprinting";
+            "file" => &file.target_file,
+            "reason" => e.to_string(),
+        );
+    }
+}
+
+async fn process_queue(log: Logger) {
+    let conn = match database::get_conn_logged(&log).await {
+        Ok(x) => x,
+        Err(e) => {
+            warn!(log, "Fingerprint worker failed to connect to the db"; "reason" => e.to_string());
+            return;
+        }
+    };
+
+    loop {
+        let file = match FingerprintQueue::peek(&conn).await {
+            Ok(Some(x)) => x,
+            Ok(None) => {
+                tokio::time::sleep(Duration::from_millis(1000)).await;
+                continue;
+            }
+            Err(e) => {
+                warn!(log, "Failed to read the fingerprint queue"; "reason" => e.to_string());
+                tokio::time::sleep(Duration::from_secs(30)).await;
+                continue;
+            }
+        };
+
+        fingerprint_file(&conn, &log, &file).await;
+
+        // NOTE: Files that fail to fingerprint are dropped from the queue as well, otherwise we
+        // would be stuck on them.
+        if let Err(e) = FingerprintQueue::remove(&conn, file.id).await {
+            warn!(
+                log,
+                "Failed to remove file from the fingerprint queue";
+                "file" => &file.target_file,
+                "reason" => e.to_string(),
+            );
+            tokio::time::sleep(Duration::from_secs(30)).await;
+        }
+    }
+}
+
+/// Function computes the fingerprint of `file` and stores it.
+async fn fingerprint_file(conn: &DbConnection, log: &Logger, file: &MediaFile) {
+    let duration = match file.duration {
+        Some(x) if x > 0 => x,
+        _ => return,
+    };
+
+    let path = PathBuf::from(&file.target_file);
+    let fingerprint = spawn_blocking(move || compute(&path, duration))
+        .await
+        .ok()
+        .flatten();
+
+    let fingerprint = match fingerprint {
+        Some(x) => x,
+        None => {
+            warn!(log, "Failed to fingerprint file"; "file" => &file.target_file);
+            return;
+        }
+    };
+
+    let update = UpdateMediaFile {
+        fingerprint: Some(fingerprint),
+        ..Default::default()
+    };
+
+    if let Err(e) = update.update(conn, file.id).await {
+        warn!(
+            log,
+            "Failed to store fingerprint";
+            "file" => &file.target_file,
+            "reason" => e.to_string(),
+        );
+    }
+}
+
+/// Function creates a task that fingerprints the files queued with [`insert_into_queue`].
+pub async fn fingerprint_worker(log: Logger) {
+    tokio::spawn(process_queue(log));
+}
+
+/// Function computes the fingerprint of the video at `path`, which lasts `duration` seconds. Frames
+/// are sampled evenly across the video, skipping the very start and end which tend to be black
+/// or credits, and each is reduced to a 64 bit difference hash.
+pub fn compute(path: &Path, duration: i64) -> Option<String> {
+    let mut fingerprint = String::with_capacity(SAMPLES as usize * 16);
+
+    for sample in 1..=SAMPLES {
+        let timestamp = duration * sample as i64 / (SAMPLES as i64 + 1);
+        let frame = grab_frame(path, timestamp)?;
+
+        fingerprint.push_str(&format!("{:016x}", dhash(&frame)));
+    }
+
+    Some(fingerprint)
+}
+
+/// Function decodes the frame of `path` at `timestamp` as a tiny grayscale image.
+fn grab_frame(path: &Path, timestamp: i64) -> Option<Vec<u8>> {
+    let output = Command::new(*FFMPEG_BIN)
+        .args(&["-v", "quiet", "-ss", &timestamp.to_string(), "-i"])
+        .arg(path)
+        .args(&["-frames:v", "1", "-vf"])
+        .arg(format!("scale={}:{},format=gray", HASH_WIDTH, HASH_HEIGHT))
+        .args(&["-f", "rawvideo", "-"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    if !output.status.success() || output.stdout.len() < HASH_WIDTH * HASH_HEIGHT {
+        return None;
+    }
+
+    Some(output.stdout)
+}
+
+/// Function hashes a grayscale frame by comparing each pixel with its right neighbour.
+fn dhash(frame: &[u8]) -> u64 {
+    let mut hash = 0u64;
+
+    for row in frame.chunks(HASH_WIDTH).take(HASH_HEIGHT) {
+        for pair in row.windows(2) {
+            hash = (hash << 1) | (pair[0] > pair[1]) as u64;
+        }
+    }
+
+    hash
+}
+
+/// Function returns how similar two fingerprints are on a scale from `0.0` to `1.0`, based on how
+/// many bits the hashes of their frames have in common. Fingerprints that cant be compared have
+/// a similarity of `0.0`.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let hashes = |x: &str| {
+        (0..x.len())
+            .step_by(16)
+            .map(|idx| u64::from_str_radix(x.get(idx..idx + 16)?, 16).ok())
+            .collect::<Option<Vec<_>>>()
+    };
+
+    let (a, b) = match (hashes(a), hashes(b)) {
+        (Some(a), Some(b)) if a.len() == b.len() && !a.is_empty() => (a, b),
+        _ => return 0.0,
+    };
+
+    let differing = a
+        .iter()
+        .zip(b.iter())
+        .map(|(a, b)| (a ^ b).count_ones())
+        .sum::<u32>();
+
+    1.0 - differing as f64 / (a.len() * 64) as f64
+}
+
+/// Struct holds two files that are most likely the same content.
+#[derive(Serialize, Clone, Debug)]
+pub struct FingerprintMatch {
+    pub a: i64,
+    pub b: i64,
+    pub similarity: f64,
+}
+
+/// Function returns every pair of fingerprinted files in a library that is most likely the same
+/// content, most similar first.
+pub async fn find_similar(
+    conn: &DbConnection,
+    library_id: i64,
+) -> Result<Vec<FingerprintMatch>, DatabaseError> {
+    let files = MediaFile::get_by_lib(conn, library_id)
+        .await?
+        .into_iter()
+        .filter_map(|x| Some((x.id, x.fingerprint?)))
+        .collect::<Vec<_>>();
+
+    let mut matches = Vec::new();
+
+    for (idx, (a, a_fingerprint)) in files.iter().enumerate() {
+        for (b, b_fingerprint) in files.iter().skip(idx + 1) {
+            let similarity = similarity(a_fingerprint, b_fingerprint);
+
+            if similarity >= SIMILARITY_THRESHOLD {
+                matches.push(FingerprintMatch {
+                    a: *a,
+                    b: *b,
+                    similarity,
+                });
+            }
+        }
+    }
+
+    matches.sort_by(|x, y| y.similarity.partial_cmp(&x.similarity).unwrap());
+
+    Ok(matches)
+}
@@ -0,0 +1,208 @@
+//! Integrations notified after every library scan. The post scan command is for integrations that
+//! arent webhooks such as updating an external index, it can only be configured through the config
+//! file, never over http, as it runs with the privileges of dim. Webhooks are configured per
+//! library.
+use crate::get_global_settings;
+use crate::scanners::ScanSummary;
+
+use events::ScanState;
+
+use serde::Serialize;
+
+use slog::info;
+use slog::warn;
+use slog::Logger;
+
+use std::process::Command;
+use std::process::Stdio;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use tokio::task::spawn_blocking;
+
+/// Number of times we try to deliver a webhook before giving up on it.
+const WEBHOOK_TRIES: u32 = 3;
+
+/// How long a webhook endpoint gets to respond.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long we wait for a connection to a webhook endpoint.
+const WEBHOOK_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Function checks whether `url` is something we are willing to post a webhook to, only absolute
+/// `http` and `https` urls are.
+pub fn is_webhook_url(url: &str) -> bool {
+    reqwest::Url::parse(url.trim())
+        .map(|x| matches!(x.scheme(), "http" | "https") && x.has_host())
+        .unwrap_or(false)
+}
+
+/// Struct represents the json body we post to webhooks once a scan finished.
+#[derive(Serialize)]
+struct ScanWebhook {
+    library_id: i64,
+    state: String,
+    summary: ScanSummary,
+    /// Unix timestamp of when the scan finished.
+    timestamp: u64,
+}
+
+/// Function runs the configured post scan command, if any, in the background. The command is
+/// split on whitespace and run without a shell, with the id of the library and the state of the
+/// scan appended as arguments. The tally of the scan is passed through the environment as
+/// `DIM_LIBRARY_ID`, `DIM_SCAN_STATE`, `DIM_SCAN_FILES`, `DIM_SCAN_ORPHANED`,
+/// `DIM_SCAN_MOUNT_ERRORS` and `DIM_SCAN_MATCH_ERRORS`.
+pub fn run_post_scan_hook(log: &Logger, library_id: i64, summary: ScanSummary, state: ScanState) {
+    let command = match get_global_settings().post_scan_command {
+        Some(x) if !x.trim().is_empty() => x,
+        _ => return,
+    };
+
+    let log = log.clone();
+
+    tokio::spawn(async move {
+        let state = format!("{:?}", state);
+        let mut parts = command.split_whitespace();
+        // NOTE: We return early above if the command is blank.
+        let program = parts.next().unwrap().to_owned();
+        let args = parts.map(ToOwned::to_owned).collect::<Vec<_>>();
+
+        let output = {
+            let state = state.clone();
+            let program = program.clone();
+
+            spawn_blocking(move || {
+                Command::new(program)
+                    .args(args)
+                    .arg(library_id.to_string())
+                    .arg(&state)
+                    .env("DIM_LIBRARY_ID", library_id.to_string())
+                    .env("DIM_SCAN_STATE", &state)
+                    .env("DIM_SCAN_FILES", summary.files.to_string())
+                    .env("DIM_SCAN_ORPHANED", summary.orphaned.to_string())
+                    .env("DIM_SCAN_MOUNT_ERRORS", summary.mount_errors.to_string())
+                    .env("DIM_SCAN_MATCH_ERRORS", summary.match_errors.to_string())
+                    .stdin(Stdio::null())
+                    .output()
+            })
+            .await
+        };
+
+        match output {
+            Ok(Ok(output)) => info!(
+                log,
+                "Ran post scan command";
+                "library_id" => library_id,
+                "command" => program,
+                "status" => output.status.to_string(),
+                "stdout" => String::from_utf8_lossy(&output.stdout).trim().to_owned(),
+                "stderr" => String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+            ),
+            Ok(Err(e)) => warn!(
+                log,
+                "Failed to run post scan command";
+                "library_id" => library_id,
+                "command" => program,
+                "reason" => e.to_string(),
+            ),
+            Err(e) => warn!(
+                log,
+                "Post scan command panicked";
+                "library_id" => library_id,
+                "reason" => e.to_string(),
+            ),
+        }
+    });
+}
+
+/// Function posts the outcome of a scan of `library_id` to the webhook `url` of the library, if
+/// any, in the background. Deliveries that fail are retried a couple of times with a growing delay
+/// and then logged, they never affect the scan itself.
+pub fn send_scan_webhook(
+    log: &Logger,
+    library_id: i64,
+    url: Option<String>,
+    summary: ScanSummary,
+    state: ScanState,
+) {
+    let url = match url {
+        Some(x) if !x.trim().is_empty() => x.trim().to_owned(),
+        _ => return,
+    };
+
+    if !is_webhook_url(&url) {
+        warn!(
+            log,
+            "Refusing to deliver scan webhook to a url that isnt http or https";
+            "library_id" => library_id,
+            "url" => url,
+        );
+        return;
+    }
+
+    let log = log.clone();
+    let body = ScanWebhook {
+        library_id,
+        state: format!("{:?}", state),
+        summary,
+        timestamp: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|x| x.as_secs())
+            .unwrap_or_default(),
+    };
+
+    tokio::spawn(async move {
+        let client = match reqwest::Client::builder()
+            .connect_timeout(WEBHOOK_CONNECT_TIMEOUT)
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+        {
+            Ok(x) => x,
+            Err(e) => {
+                warn!(
+                    log,
+                    "Failed to build a client for the scan webhook";
+                    "library_id" => library_id,
+                    "reason" => e.to_string(),
+                );
+                return;
+            }
+        };
+        let mut reason = String::new();
+
+        for attempt in 1..=WEBHOOK_TRIES {
+            let result = client
+                .post(&url)
+                .json(&body)
+                .send()
+                .await
+                .and_then(|x| x.error_for_status());
+
+            match result {
+                Ok(_) => {
+                    info!(
+                        log,
+                        "Delivered scan webhook";
+                        "library_id" => library_id,
+                        "url" => &url,
+                    );
+                    return;
+                }
+                Err(e) => reason = e.to_string(),
+            }
+
+            if attempt < WEBHOOK_TRIES {
+                tokio::time::sleep(Duration::from_secs(2u64.pow(attempt))).await;
+            }
+        }
+
+        warn!(
+            log,
+            "Failed to deliver scan webhook";
+            "library_id" => library_id,
+            "url" => url,
+            "tries" => WEBHOOK_TRIES,
+            "reason" => reason,
+        );
+    });
+}
@@ -1,23 +1,86 @@
+pub mod agent;
+pub mod artwork;
+pub mod audit;
 pub mod base;
+pub mod disc;
+pub mod dump;
+pub mod duplicates;
+pub mod export;
+pub mod filename;
+pub mod fingerprint;
+pub mod hook;
 pub mod movie;
+pub mod music;
+pub mod musicbrainz;
+pub mod nfo;
+pub mod preview;
+pub mod scan_log;
 pub mod scanner_daemon;
+pub mod self_test;
+pub mod subtitles;
 pub mod tmdb;
 pub mod tv_show;
+pub mod tvdb;
 
 use database::get_conn;
+use database::library::DiskType;
 use database::library::Library;
+use database::library::LibrarySettings;
 use database::library::MediaType;
+use database::library::TvAgent;
+use database::match_attempt::MatchAttempt;
+use database::media::Media;
+use database::mediafile::InsertableMediaFile;
+use database::mediafile::MediaFile;
+use database::mediafile::MountSource;
+use database::DbConnection;
+
+use events::ScanState;
 
 use crate::core::EventTx;
+use crate::get_global_settings;
+use crate::scanners::scan_log::Outcome;
+use crate::scanners::scan_log::ScanLog;
+use crate::scanners::scan_log::ScanRecord;
+use crate::shutdown;
+use crate::shutdown::InFlight;
+use crate::streaming::ffprobe::FFProbeCtx;
+use crate::streaming::FFPROBE_BIN;
 
+use slog::debug;
+use slog::error;
 use slog::info;
+use slog::warn;
 
+use futures::future;
+use futures::stream;
+use futures::StreamExt;
+use globset::GlobBuilder;
+use globset::GlobSet;
+use globset::GlobSetBuilder;
+use once_cell::sync::Lazy;
 use once_cell::sync::OnceCell;
+use tokio::task::spawn_blocking;
+use walkdir::DirEntry;
 use walkdir::WalkDir;
 
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::future::Future;
+use std::iter;
+use std::path::Component;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
 use std::time::Instant;
+use std::time::SystemTime;
 
 use serde::Deserialize;
 use serde::Serialize;
@@ -35,6 +98,15 @@ pub struct ApiMedia {
     pub genres: Vec<String>,
     pub rating: Option<i32>,
     pub seasons: Vec<ApiSeason>,
+    /// Tagline of a movie, ie `"Your mind is the scene of the crime."`.
+    #[serde(default)]
+    pub tagline: Option<String>,
+    /// Runtime of a movie in minutes.
+    #[serde(default)]
+    pub runtime: Option<u32>,
+    /// Metadata provider `id` and the ids of the seasons and episodes belong to.
+    #[serde(default)]
+    pub agent: TvAgent,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -55,6 +127,8 @@ pub struct ApiEpisode {
     pub episode: Option<u64>,
     pub still: Option<String>,
     pub still_file: Option<String>,
+    #[serde(default)]
+    pub owned: bool,
 }
 
 pub(super) static METADATA_EXTRACTOR: OnceCell<base::MetadataExtractor> = OnceCell::new();
@@ -64,8 +138,10 @@ pub(super) static SUPPORTED_EXTS: &[&str] = &["mp4", "mkv", "avi", "webm"];
 pub fn get_extractor(log: &slog::Logger, _tx: &EventTx) -> &'static base::MetadataExtractor {
     let mut handle = xtra::spawn::Tokio::Global;
 
-    METADATA_EXTRACTOR
-        .get_or_init(|| base::MetadataExtractor::cluster(&mut handle, 4, log.clone()).1)
+    METADATA_EXTRACTOR.get_or_init(|| {
+        let workers = get_global_settings().mount_concurrency.max(1);
+        base::MetadataExtractor::cluster(&mut handle, workers, log.clone()).1
+    })
 }
 
 pub fn get_matcher(log: &slog::Logger, tx: &EventTx) -> &'static base::MetadataMatcher {
@@ -73,7 +149,10 @@ pub fn get_matcher(log: &slog::Logger, tx: &EventTx) -> &'static base::MetadataM
 
     METADATA_MATCHER.get_or_init(|| {
         let conn = database::try_get_conn().expect("Failed to grab a connection");
-        base::MetadataMatcher::cluster(&mut handle, 6, log.clone(), conn.clone(), tx.clone()).1
+        let workers = get_global_settings().match_concurrency.max(1);
+
+        base::MetadataMatcher::cluster(&mut handle, workers, log.clone(), conn.clone(), tx.clone())
+            .1
     })
 }
 
@@ -81,109 +160,1394 @@ pub fn get_matcher_unchecked() -> &'static base::MetadataMatcher {
     METADATA_MATCHER.get().unwrap()
 }
 
-pub async fn start_custom(
+/// Function grabs a connection pool to the database and makes sure a connection can actually be
+/// acquired from it. When the pool is momentarily exhausted we retry with a exponential backoff,
+/// persistent failures still error out with a `DatabaseConnectionError`.
+pub async fn get_conn_with_retry(
+    log: &slog::Logger,
+) -> Result<DbConnection, self::base::ScannerError> {
+    let retries = get_global_settings().db_connection_retries;
+    let mut backoff = Duration::from_millis(100);
+
+    for attempt in 0..=retries {
+        let result = match get_conn().await {
+            Ok(conn) => conn.acquire().await.map(|_| conn),
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(conn) => return Ok(conn),
+            Err(e) if attempt < retries => {
+                warn!(
+                    log,
+                    "Failed to acquire a database connection, retrying";
+                    "reason" => e.to_string(),
+                    "attempt" => attempt + 1,
+                    "backoff_ms" => backoff.as_millis() as u64,
+                );
+
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(e) => {
+                error!(
+                    log,
+                    "Giving up on acquiring a database connection";
+                    "reason" => e.to_string(),
+                );
+            }
+        }
+    }
+
+    Err(self::base::ScannerError::DatabaseConnectionError)
+}
+
+/// Function validates and normalizes a library location before it gets saved. The path gets
+/// canonicalized, which gets rid of trailing slashes, relative segments and symlinks, and then
+/// checked to be a existing directory.
+pub fn normalize_library_location(path: impl AsRef<Path>) -> Result<PathBuf, base::ScannerError> {
+    let path = path.as_ref();
+
+    if path.as_os_str().is_empty() {
+        return Err(base::ScannerError::InvalidLocation(
+            "the path is empty".into(),
+        ));
+    }
+
+    // NOTE: Relative paths would be resolved against the cwd of dim, which is almost never what
+    // the user wants.
+    if !path.is_absolute() {
+        return Err(base::ScannerError::InvalidLocation(format!(
+            "{} is not an absolute path",
+            path.display()
+        )));
+    }
+
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| base::ScannerError::InvalidLocation(format!("{}: {}", path.display(), e)))?;
+
+    if !canonical.is_dir() {
+        return Err(base::ScannerError::InvalidLocation(format!(
+            "{} is not a directory",
+            canonical.display()
+        )));
+    }
+
+    Ok(canonical)
+}
+
+/// Function moves the files of a library located under `old_root` to `new_root`, ie after the
+/// media has been moved to a new drive, so that they dont look missing and stay matched. The move
+/// is refused unless every file exists at its new path. Returns the number of files moved.
+pub async fn relocate_library(
+    conn: &DbConnection,
     library_id: i64,
-    log: slog::Logger,
-    tx: EventTx,
-    paths: impl Iterator<Item = impl AsRef<Path>>,
-    media_type: MediaType,
-) -> Result<(), self::base::ScannerError> {
-    info!(log, "Scanning library"; "mod" => "scanner", "library_id" => library_id);
-    tx.send(
-        events::Message {
-            id: library_id,
-            event_type: events::PushEventType::EventStartedScanning,
+    old_root: &str,
+    new_root: &str,
+) -> Result<usize, base::ScannerError> {
+    let new_root = normalize_library_location(new_root)?;
+    let old_root = Path::new(old_root);
+
+    for file in MediaFile::get_by_lib(conn, library_id).await? {
+        let relative = match Path::new(&file.target_file).strip_prefix(old_root) {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+
+        let moved = new_root.join(relative);
+
+        if !moved.exists() {
+            return Err(base::ScannerError::InvalidLocation(format!(
+                "{} is missing",
+                moved.display()
+            )));
         }
-        .to_string(),
+    }
+
+    Ok(Library::relocate(
+        conn,
+        library_id,
+        &old_root.to_string_lossy(),
+        &new_root.to_string_lossy(),
     )
-    .unwrap();
+    .await?)
+}
 
-    let _conn = get_conn().await.expect("Failed to grab the conn pool");
+/// Function normalizes the locations of a library that were saved before they were normalized
+/// when saving, see [`normalize_library_location`]. Locations that change are relocated along
+/// with their files. Locations that are invalid, ie because their drive isnt mounted, are left
+/// as they are and the first error is returned once the others are done.
+pub async fn normalize_locations(
+    conn: &DbConnection,
+    library_id: i64,
+    log: &slog::Logger,
+) -> Result<(), base::ScannerError> {
+    let mut result = Ok(());
 
-    let extractor = get_extractor(&log, &tx);
-    let matcher = get_matcher(&log, &tx);
+    for location in Library::get_locations(conn, library_id).await? {
+        let normalized = match normalize_library_location(&location) {
+            Ok(x) => x.to_string_lossy().to_string(),
+            Err(e) => {
+                warn!(
+                    log,
+                    "Library location is invalid";
+                    "library_id" => library_id,
+                    "location" => &location,
+                    "reason" => e.to_string(),
+                );
+                result = result.and(Err(e));
+                continue;
+            }
+        };
 
-    let mut files = Vec::with_capacity(2048);
-    for path in paths {
-        let mut subfiles: Vec<PathBuf> = WalkDir::new(path)
-            // we want to follow all symlinks in case of complex dir structures
-            .follow_links(true)
-            .into_iter()
-            .filter_map(Result::ok)
-            // ignore all hidden files.
-            .filter(|f| {
-                !f.path()
-                    .iter()
-                    .any(|s| s.to_str().map(|x| x.starts_with('.')).unwrap_or(false))
-            })
-            // check whether `f` has a supported extension
-            .filter(|f| {
-                f.path()
-                    .extension()
-                    .and_then(|e| e.to_str())
-                    .map_or(false, |e| SUPPORTED_EXTS.contains(&e))
-            })
-            .map(|f| f.into_path())
-            .collect();
+        if normalized == location {
+            continue;
+        }
 
-        files.append(&mut subfiles);
+        info!(
+            log,
+            "Normalizing library location";
+            "library_id" => library_id,
+            "location" => &location,
+            "normalized" => &normalized,
+        );
+
+        if let Err(e) = relocate_library(conn, library_id, &location, &normalized).await {
+            result = result.and(Err(e));
+        }
     }
 
-    let total_files = files.len();
+    result
+}
 
-    info!(
+/// Function returns the file we should mount for a entry yielded by our directory walkers, if
+/// any. Disc structures are returned as their disc folder, the extractor mounts them through their
+/// main title so that the walk doesnt have to probe them.
+fn mountable_file(
+    entry: DirEntry,
+    settings: &LibrarySettings,
+    media_type: MediaType,
+    ignored: &IgnorePatterns,
+) -> Option<PathBuf> {
+    // ignore all hidden files, unless the library wants them scanned.
+    // NOTE: Only the components below the library location count, so that libraries located
+    // within a hidden folder, ie `~/.local/share/media`, can still be scanned.
+    let hidden = ignored
+        .relative(entry.path())
+        .iter()
+        .any(|s| s.to_str().map(|x| x.starts_with('.')).unwrap_or(false));
+
+    if hidden && !settings.include_hidden {
+        return None;
+    }
+
+    if ignored.is_ignored(entry.path()) || !within_age(entry.path(), settings) {
+        return None;
+    }
+
+    // NOTE: Discs only ever hold videos.
+    if entry.file_type().is_dir() {
+        let disc = media_type != MediaType::Music && disc::is_disc_folder(entry.path());
+        return disc.then(|| entry.into_path());
+    }
+
+    // NOTE: The streams of a disc would otherwise be mounted as dozens of fragments.
+    if disc::in_disc_folder(entry.path()) {
+        return None;
+    }
+
+    is_media_path(entry.path(), settings, media_type).then(|| entry.into_path())
+}
+
+/// Patterns of the files a library doesnt want scanned, ie `**/sample/**` or `*trailer*`. Paths are
+/// matched relative to the library location they are in, ignoring case.
+#[derive(Clone)]
+pub(super) struct IgnorePatterns {
+    roots: Vec<PathBuf>,
+    set: GlobSet,
+}
+
+impl IgnorePatterns {
+    /// Method compiles the ignore `patterns` of a library located at `roots`. Invalid patterns are
+    /// logged and skipped.
+    pub(super) fn new(roots: &[String], patterns: &[String], log: &slog::Logger) -> Self {
+        let mut builder = GlobSetBuilder::new();
+
+        for pattern in patterns {
+            match GlobBuilder::new(pattern).case_insensitive(true).build() {
+                Ok(x) => {
+                    builder.add(x);
+                }
+                Err(e) => warn!(
+                    log,
+                    "Skipping invalid ignore pattern";
+                    "pattern" => pattern,
+                    "reason" => e.to_string(),
+                ),
+            }
+        }
+
+        Self {
+            roots: roots.iter().map(PathBuf::from).collect(),
+            set: builder.build().unwrap_or_else(|_| GlobSet::empty()),
+        }
+    }
+
+    /// Method returns patterns that dont ignore anything.
+    pub(super) fn none() -> Self {
+        Self {
+            roots: Vec::new(),
+            set: GlobSet::empty(),
+        }
+    }
+
+    /// Method returns whether `path` matches any of the patterns.
+    pub(super) fn is_ignored(&self, path: &Path) -> bool {
+        if self.set.is_empty() {
+            return false;
+        }
+
+        self.set.is_match(self.relative(path))
+    }
+
+    /// Method returns `path` relative to the library location it is in, or `path` itself if it
+    /// isnt in any.
+    pub(super) fn relative<'a>(&self, path: &'a Path) -> &'a Path {
+        self.roots
+            .iter()
+            .filter_map(|x| path.strip_prefix(x).ok())
+            .min_by_key(|x| x.components().count())
+            .unwrap_or(path)
+    }
+}
+
+/// Function returns whether the file at `path` should be scanned by a library holding `media_type`
+/// going by its extension, either one of the extensions configured for the library or, if there
+/// are none, one of [`SUPPORTED_EXTS`] or [`music::MUSIC_EXTS`] for music libraries.
+pub(super) fn is_media_path(
+    path: &Path,
+    settings: &LibrarySettings,
+    media_type: MediaType,
+) -> bool {
+    let extension = match path.extension() {
+        Some(x) => x.to_str(),
+        // NOTE: Some remuxes come without an extension, if the library wants them we let ffprobe
+        // decide whether they are media.
+        None => return settings.probe_extensionless && is_probable_media(path, media_type),
+    };
+
+    let default_exts = match media_type {
+        MediaType::Music => music::MUSIC_EXTS,
+        _ => SUPPORTED_EXTS,
+    };
+
+    extension.map_or(false, |e| {
+        if settings.extensions.is_empty() {
+            default_exts.iter().any(|x| e.eq_ignore_ascii_case(x))
+        } else {
+            settings
+                .extensions
+                .iter()
+                .any(|x| e.eq_ignore_ascii_case(x.trim_start_matches('.')))
+        }
+    })
+}
+
+/// Function returns whether the last modification of `path` lies within the age limits of a
+/// library. Files whose modification time cant be read are always scanned.
+fn within_age(path: &Path, settings: &LibrarySettings) -> bool {
+    if settings.min_age.is_none() && settings.max_age.is_none() {
+        return true;
+    }
+
+    let modified = match std::fs::metadata(path).and_then(|x| x.modified()) {
+        Ok(x) => x,
+        Err(_) => return true,
+    };
+
+    // NOTE: Files modified in the future, ie because of clock skew on a network share, are
+    // treated as if they were just modified.
+    let age = SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or_default()
+        .as_secs();
+
+    settings.min_age.map_or(true, |x| age >= x) && settings.max_age.map_or(true, |x| age <= x)
+}
+
+/// Function returns whether the file at `path` is at least as large as the minimum size of a
+/// library holding `media_type`. Songs are small no matter what, thus music libraries take songs
+/// of any size. Skipped files are logged, as otherwise its hard to tell why they never showed up.
+pub(super) fn large_enough(
+    path: &Path,
+    settings: &LibrarySettings,
+    media_type: MediaType,
+    log: &slog::Logger,
+) -> bool {
+    if settings.min_size == 0 || media_type == MediaType::Music {
+        return true;
+    }
+
+    let size = match std::fs::metadata(path) {
+        Ok(x) if x.is_file() => x.len(),
+        _ => return true,
+    };
+
+    if size < settings.min_size {
+        info!(
+            log,
+            "Skipping file below the minimum size";
+            "file" => path.to_string_lossy().to_string(),
+            "size" => size,
+            "min_size" => settings.min_size,
+        );
+        return false;
+    }
+
+    true
+}
+
+/// Function returns whether a file that was handed to us directly instead of walked, ie by the fs
+/// watcher or a download hook, should be mounted, going by the same settings walked files are
+/// filtered by.
+pub(super) async fn wanted_file(
+    path: &Path,
+    settings: &LibrarySettings,
+    media_type: MediaType,
+    ignored: &IgnorePatterns,
+    log: &slog::Logger,
+) -> bool {
+    if ignored.is_ignored(path) || disc::in_disc_folder(path) {
+        return false;
+    }
+
+    // NOTE: Files without an extension might have to be probed, which blocks.
+    let media = {
+        let (path, settings) = (path.to_path_buf(), settings.clone());
+        spawn_blocking(move || is_media_path(&path, &settings, media_type))
+            .await
+            .unwrap_or(false)
+    };
+
+    media && large_enough(path, settings, media_type, log)
+}
+
+/// Function returns when `path` was last modified, in seconds since the unix epoch.
+pub fn modified_secs(path: &Path) -> Option<i64> {
+    let modified = std::fs::metadata(path).and_then(|x| x.modified()).ok()?;
+
+    Some(
+        modified
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .ok()?
+            .as_secs() as i64,
+    )
+}
+
+/// Function drops the files of `files` that are already mounted and havent been modified since we
+/// last probed them, so that rescans dont have to go through every file again.
+async fn skip_unchanged(
+    conn: &DbConnection,
+    library_id: i64,
+    files: Vec<PathBuf>,
+    log: &slog::Logger,
+) -> Vec<PathBuf> {
+    let known = match MediaFile::get_by_lib(conn, library_id).await {
+        Ok(x) => x
+            .into_iter()
+            .filter_map(|x| Some((x.target_file, x.mtime?)))
+            .collect::<HashMap<_, _>>(),
+        Err(_) => return files,
+    };
+
+    let total = files.len();
+    let files = files
+        .into_iter()
+        .filter(|file| {
+            let mtime = file.to_str().and_then(|x| known.get(x));
+            mtime.is_none() || mtime.copied() != modified_secs(file)
+        })
+        .collect::<Vec<_>>();
+
+    debug!(
         log,
-        "Walked library directory";
-        "mod" => "scanner",
+        "Skipping unchanged files";
         "library_id" => library_id,
-        "files" => total_files,
+        "files" => total - files.len(),
     );
 
-    let mut futures = Vec::new();
-    let now = Instant::now();
+    files
+}
+
+/// Function probes `path` with ffprobe and returns whether it holds a playable video stream, or a
+/// audio stream for music libraries.
+fn is_probable_media(path: &Path, media_type: MediaType) -> bool {
+    if !path.is_file() {
+        return false;
+    }
+
+    FFProbeCtx::new(&FFPROBE_BIN)
+        .get_meta(path)
+        .map_or(false, |x| match media_type {
+            MediaType::Music => x.get_primary("audio").is_some(),
+            _ => x.get_primary_video().is_some(),
+        })
+}
+
+/// Directories a walk has already been through, keyed by their canonical path. As we follow
+/// symlinks, a link pointing back up its own tree, ie `current -> .`, would otherwise have us walk
+/// the same directory over and over again and mount every file in it several times.
+#[derive(Default)]
+struct VisitedDirs(Mutex<HashSet<PathBuf>>);
+
+impl VisitedDirs {
+    /// Method returns whether `entry` should be walked, which is the case for anything but
+    /// directories we already walked through.
+    fn first_visit(&self, entry: &DirEntry) -> bool {
+        if !entry.file_type().is_dir() {
+            return true;
+        }
+
+        // NOTE: Directories that cant be canonicalized are walked, walkdir itself will catch
+        // any loops they are a part of.
+        match std::fs::canonicalize(entry.path()) {
+            Ok(x) => self.0.lock().unwrap().insert(x),
+            Err(_) => true,
+        }
+    }
+}
+
+/// Function walks `path` and collects every file that a library holding `media_type` can mount.
+fn walk_directory(
+    path: &Path,
+    settings: &LibrarySettings,
+    media_type: MediaType,
+    ignored: &IgnorePatterns,
+) -> Vec<PathBuf> {
+    walk_directory_with(path, settings, media_type, ignored, &VisitedDirs::default())
+}
+
+fn walk_directory_with(
+    path: &Path,
+    settings: &LibrarySettings,
+    media_type: MediaType,
+    ignored: &IgnorePatterns,
+    visited: &VisitedDirs,
+) -> Vec<PathBuf> {
+    WalkDir::new(path)
+        // we want to follow all symlinks in case of complex dir structures
+        .follow_links(true)
+        .into_iter()
+        .filter_entry(|entry| visited.first_visit(entry))
+        .filter_map(Result::ok)
+        .filter_map(|entry| mountable_file(entry, settings, media_type, ignored))
+        .collect()
+}
+
+/// Same as [`walk_directory`] except that every top level directory is walked on its own blocking
+/// thread, which makes enumerating very large trees a lot faster on multi-core systems.
+async fn walk_directory_parallel(
+    path: PathBuf,
+    settings: &LibrarySettings,
+    media_type: MediaType,
+    ignored: &IgnorePatterns,
+) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut walkers = Vec::new();
+    let visited = Arc::new(VisitedDirs::default());
+
+    // NOTE: The top level directories are only marked as visited by the walker they are handed to.
+    let entries = WalkDir::new(&path)
+        .follow_links(true)
+        .max_depth(1)
+        .into_iter()
+        .filter_entry(|entry| entry.depth() > 0 || visited.first_visit(entry))
+        .filter_map(Result::ok);
+
+    let mut top_level = Vec::new();
+
+    for entry in entries {
+        if entry.depth() > 0 && entry.file_type().is_dir() {
+            let dir = entry.into_path();
+            let settings = settings.clone();
+            let ignored = ignored.clone();
+            let visited = visited.clone();
+            walkers.push(spawn_blocking(move || {
+                walk_directory_with(&dir, &settings, media_type, &ignored, &visited)
+            }));
+        } else {
+            top_level.push(entry);
+        }
+    }
+
+    // NOTE: Files in the top level directory might have to be probed as well, which blocks.
+    let (settings, ignored) = (settings.clone(), ignored.clone());
+    walkers.push(spawn_blocking(move || {
+        top_level
+            .into_iter()
+            .filter_map(|entry| mountable_file(entry, &settings, media_type, &ignored))
+            .collect()
+    }));
+
+    for subfiles in futures::future::join_all(walkers).await {
+        files.append(&mut subfiles.unwrap_or_default());
+    }
+
+    files
+}
+
+/// Order in which the files of a scan are mounted and matched.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanPipeline {
+    /// Every file is matched as soon as it has been mounted, so that it shows up identified right
+    /// away.
+    Inline,
+    /// Every file of the scan is mounted before any of them are matched, so that mounting isnt
+    /// held up by requests to our metadata providers.
+    MountFirst,
+}
+
+/// Rules deciding which library owns a file that can be reached from more than one library, ie
+/// because their locations overlap or because of symlinks.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlapOwner {
+    /// The library whose location is the most specific, ie the deepest, wins.
+    Innermost,
+    /// The library that was created first wins.
+    Oldest,
+}
+
+/// What to do when the name of a file gives away that it is of another type of media than the
+/// library the file belongs to, ie an episode numbered `S01E01` in a movie library.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaTypeMismatch {
+    /// Leave the file unmatched so that it can be matched manually.
+    Reject,
+    /// Store the match regardless.
+    Keep,
+}
+
+/// Function returns the id of the library that owns `file` out of the library locations in
+/// `roots`. Symlinks are resolved first, so a link into another library belongs to that library,
+/// unless the link points outside of every library. Ties are broken in favour of the oldest
+/// library so that the owner is the same no matter which library is being scanned.
+pub(super) fn owning_library(
+    roots: &[(i64, PathBuf)],
+    file: &Path,
+    rule: OverlapOwner,
+) -> Option<i64> {
+    let owner = |file: &Path| {
+        let mut candidates = roots
+            .iter()
+            .filter(|(_, root)| file.starts_with(root))
+            .map(|(id, root)| (*id, root.components().count()))
+            .collect::<Vec<_>>();
+
+        match rule {
+            OverlapOwner::Innermost => candidates.sort_by_key(|&(id, depth)| (Reverse(depth), id)),
+            OverlapOwner::Oldest => candidates.sort_by_key(|&(id, _)| id),
+        }
+
+        candidates.first().map(|&(id, _)| id)
+    };
+
+    file.canonicalize()
+        .ok()
+        .and_then(|x| owner(&x))
+        .or_else(|| owner(file))
+}
+
+/// Function returns the locations of `libraries` paired with the id of their library, as expected
+/// by [`owning_library`]. Locations are listed both as configured and with symlinks resolved.
+pub(super) fn library_roots(libraries: &[Library]) -> Vec<(i64, PathBuf)> {
+    libraries
+        .iter()
+        .flat_map(|lib| {
+            lib.locations
+                .iter()
+                .map(move |root| (lib.id, PathBuf::from(root)))
+        })
+        .flat_map(|(id, root)| {
+            let canonical = root.canonicalize().ok().filter(|x| x != &root);
+            iter::once((id, root)).chain(canonical.map(|x| (id, x)))
+        })
+        .collect()
+}
+
+/// Function drops the files of `files` that are owned by another library than `library_id`,
+/// warning about each of them.
+async fn claim_files(
+    conn: &DbConnection,
+    library_id: i64,
+    files: Vec<PathBuf>,
+    log: &slog::Logger,
+) -> Vec<PathBuf> {
+    let libraries = Library::get_all(conn).await;
+
+    // NOTE: Only overlapping libraries need owners resolved, which requires more than one.
+    if libraries.len() < 2 {
+        return files;
+    }
+
+    let roots = library_roots(&libraries);
+    let rule = get_global_settings().overlap_owner;
+
+    files
+        .into_iter()
+        .filter(|file| match owning_library(&roots, file, rule) {
+            Some(owner) if owner != library_id => {
+                warn!(
+                    log,
+                    "Skipping file owned by another library";
+                    "library_id" => library_id,
+                    "owner" => owner,
+                    "file" => file.to_string_lossy().to_string(),
+                );
+                false
+            }
+            _ => true,
+        })
+        .collect()
+}
+
+/// Struct holds the tally of a single library scan.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct ScanSummary {
+    /// Number of files found in the library, including the ones we already knew about and that
+    /// were left alone because they didnt change since.
+    pub files_seen: usize,
+    /// Number of files we attempted to scan.
+    pub files: usize,
+    /// Number of files that were mounted.
+    pub mounted: usize,
+    /// Number of files that were skipped, ie because they were mounted already or didnt change
+    /// since the last scan. Every file seen is either mounted, skipped or failed to mount.
+    pub skipped: usize,
+    /// Number of files that were matched against our metadata providers.
+    pub matched: usize,
+    /// Number of files that were mounted but nothing matched, ie because TMDB doesnt know of them
+    /// yet. These arent errors, they are matched again later on.
+    pub orphaned: usize,
+    /// Number of files that failed to mount, ie because ffprobe couldnt make sense of them.
+    pub mount_errors: usize,
+    /// Number of files that failed to match against our metadata providers.
+    pub match_errors: usize,
+    /// Whether the scan was cancelled before every file was scanned.
+    pub cancelled: bool,
+    /// How long the scan took.
+    pub duration: Duration,
+}
+
+impl fmt::Display for ScanSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "scanned {} of {} files in {}s: {} mounted, {} skipped, {} matched, {} orphaned, \
+             {} mount errors, {} match errors",
+            self.files,
+            self.files_seen,
+            self.duration.as_secs(),
+            self.mounted,
+            self.skipped,
+            self.matched,
+            self.orphaned,
+            self.mount_errors,
+            self.match_errors,
+        )?;
+
+        if self.cancelled {
+            write!(f, " (cancelled)")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ScanSummary {
+    /// Method returns the state a scan with this tally ended up in. A scan in which not a single
+    /// file could be mounted is considered failed.
+    pub fn state(&self) -> ScanState {
+        if self.files > 0 && self.mount_errors == self.files {
+            ScanState::Failed
+        } else if self.mount_errors + self.match_errors > 0 {
+            ScanState::CompletedWithErrors
+        } else {
+            ScanState::CompletedClean
+        }
+    }
+}
+
+/// Function mounts every file in `files` and then matches them against our metadata providers,
+/// either as soon as each file is mounted or once all of them are, depending on the configured
+/// [`ScanPipeline`]. Files on spinning disks are mounted one at a time, others `mount_concurrency`
+/// at a time. Matching always happens concurrently. The library `settings` are shared by every
+/// file of the scan.
+/// Every file gets `file_timeout` seconds to be mounted and matched, files that take longer are
+/// skipped with a [`FileTimeout`](base::ScannerError::FileTimeout).
+async fn mount_and_match(
+    library_id: i64,
+    files: Vec<PathBuf>,
+    media_type: MediaType,
+    source: MountSource,
+    settings: Arc<LibrarySettings>,
+    log: &slog::Logger,
+    tx: &EventTx,
+) -> ScanSummary {
+    let extractor = get_extractor(log, tx);
+    let matcher = get_matcher(log, tx);
+
+    let total_files = files.len();
+    // NOTE: No more files are taken up than there are extractors to mount them, files beyond that
+    // would only queue up in front of the extractors.
+    let concurrency = match settings.disk_type {
+        DiskType::Hdd => 1,
+        DiskType::Ssd => get_global_settings().mount_concurrency.max(1),
+    };
+
+    let budget = match get_global_settings().file_timeout {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    };
+
+    let scan_log = ScanLog::create(library_id, log);
+    let scan_log = scan_log.as_ref();
+
+    let started_scan = Instant::now();
+    let mounted_files = AtomicUsize::new(0);
+    let skipped = AtomicUsize::new(0);
+    let matched = AtomicUsize::new(0);
+    let orphaned = AtomicUsize::new(0);
+    let mount_errors = AtomicUsize::new(0);
+    let match_errors = AtomicUsize::new(0);
+    let files_done = AtomicUsize::new(0);
+    // NOTE: Files TMDB wouldnt let us match are matched again once the rest of the scan is done
+    // and TMDB had some time to cool down. Their first failure isnt counted until then.
+    let rate_limited = Mutex::new(Some(Vec::new()));
+    let handle = scan_handle(library_id);
+
+    send_scan_progress(library_id, total_files, 0, None, tx);
+
+    // NOTE: Once we are asked to shut down or the scan is cancelled no new files are picked up,
+    // while the files that are in flight hold on to a guard until they have been matched so that
+    // the shutdown waits for them to be committed.
+    let running =
+        |_: &PathBuf| future::ready(!shutdown::is_shutting_down() && !handle.is_cancelled());
+
+    let parse_file = |file: PathBuf| {
+        let settings = settings.clone();
+
+        async move {
+            let in_flight = InFlight::begin();
+
+            // NOTE: The budget starts ticking once the file is taken up for mounting, not while
+            // it waits for one of the files before it to finish.
+            let started = Instant::now();
+            let target_file = file.to_string_lossy().to_string();
+            let parse = extractor.parse_new_file(file, library_id, media_type, source, settings);
+
+            let parsed = with_budget(budget, started, parse).await;
+            log_timeout(log, &parsed, &target_file);
+
+            (parsed, started, target_file, in_flight)
+        }
+    };
+
+    // NOTE: Files are parsed in batches which are inserted into the db at once, as every
+    // transaction has to hit the disk.
+    let mounted = stream::iter(files)
+        .take_while(running)
+        .chunks(MOUNT_BATCH_SIZE)
+        .then(|chunk| {
+            stream::iter(chunk)
+                .take_while(running)
+                .map(&parse_file)
+                .buffer_unordered(concurrency)
+                .collect::<Vec<_>>()
+        })
+        .then(|chunk| insert_parsed(chunk, log))
+        .flat_map(stream::iter)
+        .filter_map(|(mfile, started, target_file, in_flight)| {
+            let done = files_done.fetch_add(1, Ordering::Relaxed) + 1;
+
+            if done % SCAN_PROGRESS_INTERVAL == 0 || done == total_files {
+                send_scan_progress(library_id, total_files, done, Some(&target_file), tx);
+            }
+
+            // NOTE: Rescans come across files we already know about, which isnt an error. Files
+            // that cant be played as a video are kept but never matched.
+            let outcome = match &mfile {
+                Ok(x) if x.problem.is_some() => {
+                    mounted_files.fetch_add(1, Ordering::Relaxed);
+                    Some((Outcome::Skipped, x.problem.clone()))
+                }
+                Ok(_) => {
+                    mounted_files.fetch_add(1, Ordering::Relaxed);
+                    None
+                }
+                Err(base::ScannerError::AlreadyMounted) => {
+                    skipped.fetch_add(1, Ordering::Relaxed);
+                    Some((Outcome::Skipped, None))
+                }
+                Err(e) => {
+                    mount_errors.fetch_add(1, Ordering::Relaxed);
+                    warn!(
+                        log,
+                        "Failed to mount file";
+                        "file" => &target_file,
+                        "reason" => e.to_string(),
+                    );
+                    Some((Outcome::Error, Some(e.to_string())))
+                }
+            };
+
+            if let (Some(scan_log), Some((outcome, reason))) = (scan_log, outcome) {
+                scan_log.record(ScanRecord {
+                    file: &target_file,
+                    outcome,
+                    reason,
+                    score: None,
+                });
+            }
+
+            let mfile = mfile.ok().filter(|x| x.problem.is_none());
+            async move { mfile.map(|x| (x, started, target_file, in_flight)) }
+        });
 
-    for file in files {
-        futures.push(async move {
-            if let Ok(mfile) = extractor.mount_file(file, library_id, media_type).await {
-                match media_type {
+    let match_file =
+        |(mfile, started, target_file, _in_flight): (MediaFile, Instant, String, InFlight)| {
+            let match_errors = &match_errors;
+            let matched = &matched;
+            let orphaned = &orphaned;
+            let rate_limited = &rate_limited;
+            let settings = settings.clone();
+
+            async move {
+                let retry = mfile.clone();
+                let result = match media_type {
                     MediaType::Movie => {
-                        let _ = matcher.match_movie(mfile).await;
+                        with_budget(budget, started, matcher.match_movie(mfile, settings)).await
                     }
                     MediaType::Tv => {
-                        let _ = matcher.match_tv(mfile).await;
+                        with_budget(budget, started, matcher.match_tv(mfile, settings)).await
+                    }
+                    MediaType::Music => {
+                        with_budget(budget, started, matcher.match_music(mfile, settings)).await
                     }
                     _ => unreachable!(),
+                };
+
+                log_timeout(log, &result, &target_file);
+
+                if let Err(base::ScannerError::RateLimited) = result {
+                    if let Some(queue) = rate_limited.lock().unwrap().as_mut() {
+                        queue.push(retry);
+                        return;
+                    }
+                }
+
+                // NOTE: Files nothing matched arent errors, TMDB might just not know of them yet.
+                match &result {
+                    Ok(_) => {
+                        matched.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(e) if e.is_no_match() => {
+                        orphaned.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(_) => {
+                        match_errors.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+
+                if let Some(scan_log) = scan_log {
+                    let (outcome, reason, score) = match result {
+                        Ok(score) => (Outcome::Matched, None, Some(score)),
+                        Err(e) => (Outcome::Mounted, Some(e.to_string()), None),
+                    };
+
+                    scan_log.record(ScanRecord {
+                        file: &target_file,
+                        outcome,
+                        reason,
+                        score,
+                    });
                 }
             }
-        })
-    }
+        };
 
-    futures::future::join_all(futures).await;
-    info!(
-        log,
-        "Finished scanning library";
-        "library_id" => library_id,
-        "files" => total_files,
-        "duration" => now.elapsed().as_secs(),
-    );
-    tx.send(
-        events::Message {
-            id: library_id,
-            event_type: events::PushEventType::EventStoppedScanning,
+    match get_global_settings().scan_pipeline {
+        ScanPipeline::Inline => mounted.for_each_concurrent(None, match_file).await,
+        ScanPipeline::MountFirst => {
+            // NOTE: Matching gets a budget of its own as files wait for the rest of the library
+            // to be mounted first.
+            let mounted = mounted.collect::<Vec<_>>().await;
+
+            stream::iter(mounted)
+                .map(|(mfile, _, target_file, in_flight)| {
+                    (mfile, Instant::now(), target_file, in_flight)
+                })
+                .for_each_concurrent(None, match_file)
+                .await
         }
-        .to_string(),
-    )
-    .unwrap();
+    }
 
-    Ok(())
-}
+    let rate_limited = rate_limited.lock().unwrap().take().unwrap_or_default();
 
-pub async fn start(
-    library_id: i64,
+    if !rate_limited.is_empty() && !shutdown::is_shutting_down() && !handle.is_cancelled() {
+        warn!(
+            log,
+            "TMDB rate limited the scan, matching the affected files again shortly";
+            "library_id" => library_id,
+            "files" => rate_limited.len(),
+        );
+
+        tokio::time::sleep(RATE_LIMIT_COOLDOWN).await;
+
+        stream::iter(rate_limited)
+            .map(|mfile| {
+                let target_file = mfile.target_file.clone();
+                (mfile, Instant::now(), target_file, InFlight::begin())
+            })
+            .for_each_concurrent(None, match_file)
+            .await;
+    } else if !rate_limited.is_empty() {
+        match_errors.fetch_add(rate_limited.len(), Ordering::Relaxed);
+    }
+
+    if handle.is_cancelled() {
+        warn!(
+            log,
+            "Aborted scan";
+            "library_id" => library_id,
+            "files_done" => files_done.load(Ordering::Relaxed),
+        );
+    }
+
+    if let Some(scan_log) = scan_log {
+        info!(
+            log,
+            "Wrote scan log";
+            "library_id" => library_id,
+            "path" => scan_log.path().to_string_lossy().to_string(),
+        );
+    }
+
+    ScanSummary {
+        files_seen: total_files,
+        files: total_files,
+        mounted: mounted_files.into_inner(),
+        skipped: skipped.into_inner(),
+        matched: matched.into_inner(),
+        orphaned: orphaned.into_inner(),
+        mount_errors: mount_errors.into_inner(),
+        match_errors: match_errors.into_inner(),
+        cancelled: handle.is_cancelled(),
+        duration: started_scan.elapsed(),
+    }
+}
+
+/// Function awaits `fut` for whatever is left of the `budget` of a file whose processing began at
+/// `started`. Without a budget `fut` is awaited for as long as it takes.
+///
+/// NOTE: Timing out only stops us from waiting on the actor handling the file, the actor itself
+/// runs until it is done.
+async fn with_budget<T>(
+    budget: Option<Duration>,
+    started: Instant,
+    fut: impl Future<Output = Result<T, base::ScannerError>>,
+) -> Result<T, base::ScannerError> {
+    let budget = match budget {
+        Some(x) => x,
+        None => return fut.await,
+    };
+
+    let remaining = budget
+        .checked_sub(started.elapsed())
+        .ok_or(base::ScannerError::FileTimeout)?;
+
+    tokio::time::timeout(remaining, fut)
+        .await
+        .unwrap_or(Err(base::ScannerError::FileTimeout))
+}
+
+/// Maximum number of parsed files we insert into the db at once.
+const MOUNT_BATCH_SIZE: usize = 100;
+
+/// Function inserts the files of `chunk` that were parsed successfully in a single batch, passing
+/// the rest through untouched.
+async fn insert_parsed<T>(
+    chunk: Vec<(
+        Result<InsertableMediaFile, base::ScannerError>,
+        Instant,
+        String,
+        T,
+    )>,
+    log: &slog::Logger,
+) -> Vec<(Result<MediaFile, base::ScannerError>, Instant, String, T)> {
+    let mut results = Vec::with_capacity(chunk.len());
+    let mut files = Vec::new();
+    let mut pending = Vec::new();
+
+    for (parsed, started, target_file, x) in chunk {
+        match parsed {
+            Ok(file) => {
+                files.push(file);
+                pending.push((started, target_file, x));
+            }
+            Err(e) => results.push((Err(e), started, target_file, x)),
+        }
+    }
+
+    if files.is_empty() {
+        return results;
+    }
+
+    let mounted = match get_conn().await {
+        Ok(conn) => base::insert_batch(&conn, log, files).await,
+        Err(_) => files
+            .iter()
+            .map(|_| Err(base::ScannerError::DatabaseConnectionError))
+            .collect(),
+    };
+
+    results.extend(
+        mounted
+            .into_iter()
+            .zip(pending)
+            .map(|(mfile, (started, target_file, x))| (mfile, started, target_file, x)),
+    );
+
+    results
+}
+
+/// Function logs that a file was skipped, if `result` says it ran out of time.
+fn log_timeout<T>(log: &slog::Logger, result: &Result<T, base::ScannerError>, target_file: &str) {
+    if let Err(base::ScannerError::FileTimeout) = result {
+        warn!(
+            log,
+            "Skipping file that took too long to scan";
+            "file" => target_file,
+        );
+    }
+}
+
+/// How long we let TMDB cool down before matching the files it rate limited during a scan again.
+const RATE_LIMIT_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Number of files mounted between two progress events of a scan.
+const SCAN_PROGRESS_INTERVAL: usize = 25;
+
+/// Function relays how many of the files of a scan have been mounted so far to our clients.
+fn send_scan_progress(
+    library_id: i64,
+    files_total: usize,
+    files_done: usize,
+    current_file: Option<&str>,
+    tx: &EventTx,
+) {
+    let _ = tx.send(
+        events::Message {
+            id: library_id,
+            event_type: events::PushEventType::EventScanProgress {
+                files_total,
+                files_done,
+                current_file: current_file.map(ToOwned::to_owned),
+            },
+        }
+        .to_string(),
+    );
+}
+
+/// Function relays the final state of a scan to our clients.
+fn send_stopped_scanning(library_id: i64, state: ScanState, tx: &EventTx) {
+    tx.send(
+        events::Message {
+            id: library_id,
+            event_type: events::PushEventType::EventStoppedScanning { state },
+        }
+        .to_string(),
+    )
+    .unwrap();
+}
+
+/// Handle shared by the scans of a library, used to cancel them. The fs watcher of a library holds
+/// a handle of its own, see [`watcher_handle`].
+#[derive(Clone, Default)]
+pub struct ScanHandle(Arc<AtomicBool>);
+
+impl ScanHandle {
+    /// Method cancels the scans holding this handle. Files that are already being mounted are
+    /// still matched, no new files are picked up.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+static SCAN_HANDLES: Lazy<Mutex<HashMap<i64, ScanHandle>>> = Lazy::new(Default::default);
+
+/// Function returns the handle of the scans of `library_id`.
+pub fn scan_handle(library_id: i64) -> ScanHandle {
+    SCAN_HANDLES
+        .lock()
+        .unwrap()
+        .entry(library_id)
+        .or_default()
+        .clone()
+}
+
+/// Function cancels the running scans of `library_id`, if any. Scans started afterwards get a new
+/// handle and run as usual.
+pub fn cancel_scans(library_id: i64) {
+    if let Some(handle) = SCAN_HANDLES.lock().unwrap().remove(&library_id) {
+        handle.cancel();
+    }
+}
+
+static ACTIVE_SCANS: Lazy<Mutex<HashMap<i64, usize>>> = Lazy::new(Default::default);
+
+/// Guard that marks a library as being scanned for as long as it is held, see [`is_scanning`].
+struct ScanGuard(i64);
+
+impl ScanGuard {
+    fn begin(library_id: i64) -> Self {
+        *ACTIVE_SCANS.lock().unwrap().entry(library_id).or_default() += 1;
+        Self(library_id)
+    }
+}
+
+impl Drop for ScanGuard {
+    fn drop(&mut self) {
+        let mut scans = ACTIVE_SCANS.lock().unwrap();
+
+        if let Some(count) = scans.get_mut(&self.0) {
+            *count -= 1;
+
+            if *count == 0 {
+                scans.remove(&self.0);
+            }
+        }
+    }
+}
+
+/// Function returns whether `library_id` is being scanned right now.
+pub fn is_scanning(library_id: i64) -> bool {
+    ACTIVE_SCANS.lock().unwrap().contains_key(&library_id)
+}
+
+static WATCHER_HANDLES: Lazy<Mutex<HashMap<i64, ScanHandle>>> = Lazy::new(Default::default);
+
+/// Function returns the handle of the fs watcher of `library_id`. Cancelling a scan leaves the
+/// watcher running, it is only stopped through [`stop_watcher`].
+pub fn watcher_handle(library_id: i64) -> ScanHandle {
+    WATCHER_HANDLES
+        .lock()
+        .unwrap()
+        .entry(library_id)
+        .or_default()
+        .clone()
+}
+
+/// Function stops the fs watcher of `library_id`, if any, ie because the library was deleted.
+pub fn stop_watcher(library_id: i64) {
+    if let Some(handle) = WATCHER_HANDLES.lock().unwrap().remove(&library_id) {
+        handle.cancel();
+    }
+}
+
+/// Function returns the final state of a scan of `library_id`. If the library has been removed
+/// while we were scanning it, the scan was cancelled, or dim is shutting down, the scan counts as
+/// cancelled.
+async fn scan_state(conn: &DbConnection, library_id: i64, summary: &ScanSummary) -> ScanState {
+    if shutdown::is_shutting_down()
+        || summary.cancelled
+        || Library::get_one(conn, library_id).await.is_err()
+    {
+        return ScanState::Cancelled;
+    }
+
+    summary.state()
+}
+
+pub async fn start_custom(
+    library_id: i64,
+    log: slog::Logger,
+    tx: EventTx,
+    paths: impl Iterator<Item = impl AsRef<Path>>,
+    media_type: MediaType,
+    source: MountSource,
+) -> Result<ScanSummary, self::base::ScannerError> {
+    let started = Instant::now();
+    let _scanning = ScanGuard::begin(library_id);
+
+    info!(log, "Scanning library"; "mod" => "scanner", "library_id" => library_id);
+    tx.send(
+        events::Message {
+            id: library_id,
+            event_type: events::PushEventType::EventStartedScanning,
+        }
+        .to_string(),
+    )
+    .unwrap();
+
+    let (conn, settings) = match scan_prologue(library_id, &log).await {
+        Ok(x) => x,
+        Err(e) => {
+            send_stopped_scanning(library_id, ScanState::Failed, &tx);
+            return Err(e);
+        }
+    };
+
+    // NOTE: Walking a spinning disk in parallel only makes the head seek back and forth.
+    let parallel_walk = get_global_settings().parallel_walk && settings.disk_type == DiskType::Ssd;
+
+    let locations = Library::get_locations(&conn, library_id)
+        .await
+        .unwrap_or_default();
+    let ignored = IgnorePatterns::new(&locations, &settings.ignore_patterns, &log);
+
+    let mut files = Vec::with_capacity(2048);
+    for path in paths {
+        let mut subfiles = if parallel_walk {
+            let path = path.as_ref().to_path_buf();
+            walk_directory_parallel(path, &settings, media_type, &ignored).await
+        } else {
+            // NOTE: Walking stats every file and might probe some of them, which blocks.
+            let (path, settings, ignored) = (
+                path.as_ref().to_path_buf(),
+                settings.clone(),
+                ignored.clone(),
+            );
+            spawn_blocking(move || walk_directory(&path, &settings, media_type, &ignored))
+                .await
+                .unwrap_or_default()
+        };
+
+        files.append(&mut subfiles);
+    }
+
+    files.retain(|x| large_enough(x, &settings, media_type, &log));
+    let files_seen = files.len();
+
+    let (files, orphans) = spawn_blocking(move || {
+        let orphans = subtitles::orphaned_subtitles(&files);
+        (files, orphans)
+    })
+    .await
+    .map_err(|_| self::base::ScannerError::UnknownError)?;
+
+    for orphan in orphans {
+        info!(
+            log,
+            "Found subtitles that dont belong to any video";
+            "file" => orphan.to_string_lossy().to_string(),
+            "library_id" => library_id,
+        );
+    }
+
+    let files = claim_files(&conn, library_id, files, &log).await;
+    let files = skip_unchanged(&conn, library_id, files, &log).await;
+    let total_files = files.len();
+
+    info!(
+        log,
+        "Walked library directory";
+        "mod" => "scanner",
+        "library_id" => library_id,
+        "files" => total_files,
+    );
+
+    let mut summary = mount_and_match(
+        library_id,
+        files,
+        media_type,
+        source,
+        Arc::new(settings.clone()),
+        &log,
+        &tx,
+    )
+    .await;
+    // NOTE: Files another library claimed or that didnt change since the last scan never got to
+    // the mounting stage.
+    summary.files_seen = files_seen;
+    summary.skipped += files_seen - total_files;
+    summary.duration = started.elapsed();
+
+    let state = scan_state(&conn, library_id, &summary).await;
+
+    info!(
+        log,
+        "Finished scanning library";
+        "library_id" => library_id,
+        "files" => total_files,
+        "mount_errors" => summary.mount_errors,
+        "match_errors" => summary.match_errors,
+        "state" => format!("{:?}", state),
+        "duration" => summary.duration.as_secs(),
+        "summary" => summary.to_string(),
+    );
+    send_stopped_scanning(library_id, state, &tx);
+    hook::run_post_scan_hook(&log, library_id, summary, state);
+    hook::send_scan_webhook(&log, library_id, settings.webhook_url, summary, state);
+
+    Ok(summary)
+}
+
+/// Function grabs everything a scan of `library_id` needs before it can start walking the library.
+async fn scan_prologue(
+    library_id: i64,
+    log: &slog::Logger,
+) -> Result<(DbConnection, LibrarySettings), self::base::ScannerError> {
+    check_ffprobe(log).await?;
+
+    let conn = get_conn_with_retry(log).await?;
+    let settings = Library::get_one(&conn, library_id).await?.settings;
+
+    Ok((conn, settings))
+}
+
+/// Function checks that ffprobe can be run at all by asking it for its version. Without ffprobe
+/// every single file would fail to mount, so we rather fail the scan once before touching any.
+async fn check_ffprobe(log: &slog::Logger) -> Result<(), self::base::ScannerError> {
+    let status = spawn_blocking(|| {
+        std::process::Command::new(*FFPROBE_BIN)
+            .arg("-version")
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+    })
+    .await;
+
+    match status {
+        Ok(Ok(x)) if x.success() => Ok(()),
+        _ => {
+            error!(
+                log,
+                "Could not run ffprobe, refusing to scan";
+                "bin" => *FFPROBE_BIN,
+            );
+            Err(self::base::ScannerError::FFProbeMissing(
+                FFPROBE_BIN.to_string(),
+            ))
+        }
+    }
+}
+
+pub async fn start(
+    library_id: i64,
     log: slog::Logger,
     tx: EventTx,
-) -> Result<(), self::base::ScannerError> {
-    let conn = get_conn().await.expect("Failed to grab the conn pool");
+    source: MountSource,
+) -> Result<ScanSummary, self::base::ScannerError> {
+    let conn = get_conn_with_retry(&log).await?;
+    // NOTE: A invalid location only means that it has nothing to scan right now.
+    let _ = normalize_locations(&conn, library_id, &log).await;
     let lib = Library::get_one(&conn, library_id).await?;
     start_custom(
         library_id,
@@ -191,6 +1555,541 @@ pub async fn start(
         tx,
         lib.locations.into_iter(),
         lib.media_type,
+        source,
     )
     .await
 }
+
+/// Function scans exactly the files supplied instead of walking the library locations, which is
+/// useful for external tools that already know what changed, like the completion hook of a
+/// download client. Files that dont exist, that are located outside of the library or that the
+/// library settings filter out, ie through its ignore patterns or minimum size, are skipped.
+pub async fn scan_files(
+    library_id: i64,
+    paths: Vec<PathBuf>,
+    log: slog::Logger,
+    tx: EventTx,
+) -> Result<ScanSummary, self::base::ScannerError> {
+    let _scanning = ScanGuard::begin(library_id);
+    check_ffprobe(&log).await?;
+
+    let conn = get_conn_with_retry(&log).await?;
+    let lib = Library::get_one(&conn, library_id).await?;
+    let ignored = IgnorePatterns::new(&lib.locations, &lib.settings.ignore_patterns, &log);
+
+    let mut files = Vec::new();
+
+    for path in paths {
+        if !inside_library(&path, &lib.locations) {
+            warn!(
+                log,
+                "Refusing to scan file outside of library";
+                "library_id" => library_id,
+                "file" => path.to_string_lossy().to_string(),
+            );
+            continue;
+        }
+
+        if path.is_file() && wanted_file(&path, &lib.settings, lib.media_type, &ignored, &log).await
+        {
+            files.push(path);
+        }
+    }
+
+    let files = claim_files(&conn, library_id, files, &log).await;
+
+    info!(
+        log,
+        "Scanning supplied files";
+        "mod" => "scanner",
+        "library_id" => library_id,
+        "files" => files.len(),
+    );
+
+    tx.send(
+        events::Message {
+            id: library_id,
+            event_type: events::PushEventType::EventStartedScanning,
+        }
+        .to_string(),
+    )
+    .unwrap();
+
+    let summary = mount_and_match(
+        library_id,
+        files,
+        lib.media_type,
+        MountSource::Manual,
+        Arc::new(lib.settings.clone()),
+        &log,
+        &tx,
+    )
+    .await;
+
+    let state = scan_state(&conn, library_id, &summary).await;
+    send_stopped_scanning(library_id, state, &tx);
+    hook::run_post_scan_hook(&log, library_id, summary, state);
+    hook::send_scan_webhook(&log, library_id, lib.settings.webhook_url, summary, state);
+
+    Ok(summary)
+}
+
+/// Function returns whether `path` lies within one of the library `locations`. We check the path
+/// lexically, so anything attempting to escape the library with `..` is rejected outright.
+fn inside_library(path: &Path, locations: &[String]) -> bool {
+    path.is_absolute()
+        && !path.components().any(|x| x == Component::ParentDir)
+        && locations.iter().any(|root| path.starts_with(root))
+}
+
+/// Function scans the single file `path` again, ie after it was renamed because it was matched
+/// wrongly. The file is parsed from its current name and matched from scratch, replacing the
+/// mediafile we had for it, if any. If `old_path` is supplied, the mediafile of the file before it
+/// was renamed is removed as well, which like `path` has to be located within the library.
+pub async fn rescan_file(
+    library_id: i64,
+    path: PathBuf,
+    old_path: Option<PathBuf>,
+    log: slog::Logger,
+    tx: EventTx,
+) -> Result<ScanSummary, self::base::ScannerError> {
+    let _scanning = ScanGuard::begin(library_id);
+    check_ffprobe(&log).await?;
+
+    let conn = get_conn_with_retry(&log).await?;
+    let lib = Library::get_one(&conn, library_id).await?;
+
+    if !inside_library(&path, &lib.locations) || !path.is_file() {
+        warn!(
+            log,
+            "Refusing to rescan file outside of library";
+            "library_id" => library_id,
+            "file" => path.to_string_lossy().to_string(),
+        );
+        return Err(self::base::ScannerError::InvalidLocation(
+            path.to_string_lossy().to_string(),
+        ));
+    }
+
+    if let Some(old_path) = old_path
+        .as_ref()
+        .filter(|x| !inside_library(x, &lib.locations))
+    {
+        warn!(
+            log,
+            "Refusing to remove file outside of library";
+            "library_id" => library_id,
+            "file" => old_path.to_string_lossy().to_string(),
+        );
+        return Err(self::base::ScannerError::InvalidLocation(
+            old_path.to_string_lossy().to_string(),
+        ));
+    }
+
+    let ignored = IgnorePatterns::new(&lib.locations, &lib.settings.ignore_patterns, &log);
+    let files = match wanted_file(&path, &lib.settings, lib.media_type, &ignored, &log).await {
+        true => vec![path],
+        false => Vec::new(),
+    };
+    let files = claim_files(&conn, library_id, files, &log).await;
+
+    // NOTE: A file claimed by an overlapping library keeps the mediafile that library has for it,
+    // we only ever replace mediafiles of this library.
+    for stale in old_path.iter().chain(files.iter()) {
+        let stale = match stale.to_str() {
+            Some(x) => x,
+            None => continue,
+        };
+
+        match MediaFile::get_by_file(&conn, stale).await {
+            Ok(media_file) if media_file.library_id == library_id => {
+                remove_file(&conn, &log, media_file).await
+            }
+            _ => {}
+        }
+    }
+
+    tx.send(
+        events::Message {
+            id: library_id,
+            event_type: events::PushEventType::EventStartedScanning,
+        }
+        .to_string(),
+    )
+    .unwrap();
+
+    let summary = mount_and_match(
+        library_id,
+        files,
+        lib.media_type,
+        MountSource::Manual,
+        Arc::new(lib.settings.clone()),
+        &log,
+        &tx,
+    )
+    .await;
+
+    let state = scan_state(&conn, library_id, &summary).await;
+    send_stopped_scanning(library_id, state, &tx);
+    hook::run_post_scan_hook(&log, library_id, summary, state);
+    hook::send_scan_webhook(&log, library_id, lib.settings.webhook_url, summary, state);
+
+    Ok(summary)
+}
+
+/// Number of times the wait between attempts at matching a file that keeps failing to match is
+/// doubled at most.
+const MAX_ORPHAN_BACKOFF: i64 = 6;
+
+/// Function returns whether a file we last tried to match as recorded in `attempt` is due for
+/// another attempt at `now`. The wait between attempts starts out at `interval` seconds and
+/// doubles with every attempt, so that files nobody will ever know of dont keep using up requests.
+fn orphan_due(attempt: Option<&MatchAttempt>, interval: u64, now: i64) -> bool {
+    let attempt = match attempt {
+        Some(x) => x,
+        None => return true,
+    };
+
+    let doublings = (attempt.attempts - 1).max(0).min(MAX_ORPHAN_BACKOFF);
+    let wait = (interval as i64).saturating_mul(1 << doublings);
+
+    // NOTE: Half an interval of slack keeps the ticks of the daemon from missing a file by a
+    // second.
+    now + interval as i64 / 2 >= attempt.last_attempt + wait
+}
+
+/// Function matches the files of `library_id` that are mounted but were left unmatched once more,
+/// ie because TMDB didnt know of a release yet when it was first scanned. Files are neither walked
+/// nor probed again, and files that cant be played as a video are left alone. Files that failed
+/// to match before are backed off from, see [`orphan_due`].
+pub async fn retry_orphans(
+    library_id: i64,
+    log: slog::Logger,
+    tx: EventTx,
+) -> Result<ScanSummary, self::base::ScannerError> {
+    let started = Instant::now();
+    let conn = get_conn_with_retry(&log).await?;
+    let library = Library::get_one(&conn, library_id).await?;
+    let (media_type, settings) = (library.media_type, Arc::new(library.settings));
+
+    let attempts = MatchAttempt::get_by_lib(&conn, library_id)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|x| (x.mediafile_id, x))
+        .collect::<HashMap<_, _>>();
+    let interval = get_global_settings().orphan_retry_interval;
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+
+    let orphans = MediaFile::get_by_lib_null_media(&conn, library_id)
+        .await?
+        .into_iter()
+        .filter(|x| x.problem.is_none())
+        .filter(|x| orphan_due(attempts.get(&x.id), interval, now))
+        .collect::<Vec<_>>();
+
+    for orphan in orphans.iter() {
+        if let Err(e) = MatchAttempt::record(&conn, orphan.id).await {
+            warn!(
+                log,
+                "Failed to record attempt at matching file";
+                "file" => &orphan.target_file,
+                "reason" => e.to_string(),
+            );
+        }
+    }
+
+    info!(
+        log,
+        "Matching unmatched files again";
+        "library_id" => library_id,
+        "files" => orphans.len(),
+    );
+
+    let files = orphans.len();
+    let (matched, orphaned) = match_orphans(orphans, media_type, settings, &log, &tx).await;
+    let summary = ScanSummary {
+        files_seen: files,
+        files,
+        matched,
+        orphaned,
+        match_errors: files - matched - orphaned,
+        duration: started.elapsed(),
+        ..Default::default()
+    };
+
+    info!(
+        log,
+        "Finished matching unmatched files";
+        "library_id" => library_id,
+        "summary" => summary.to_string(),
+    );
+
+    Ok(summary)
+}
+
+/// Function matches the mounted files `orphans` of a library holding `media_type`. Returns the
+/// number of files that were matched and the number of files nothing matched.
+async fn match_orphans(
+    orphans: Vec<MediaFile>,
+    media_type: MediaType,
+    settings: Arc<LibrarySettings>,
+    log: &slog::Logger,
+    tx: &EventTx,
+) -> (usize, usize) {
+    let matcher = get_matcher(log, tx);
+    let budget = match get_global_settings().file_timeout {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    };
+
+    let matched = AtomicUsize::new(0);
+    let orphaned = AtomicUsize::new(0);
+    // NOTE: No more files are taken up than there are matchers to match them, as files queued up
+    // in front of the matchers would use up their budget waiting.
+    let concurrency = get_global_settings().match_concurrency.max(1);
+
+    stream::iter(orphans)
+        .take_while(|_| future::ready(!shutdown::is_shutting_down()))
+        .for_each_concurrent(concurrency, |mfile| {
+            let (matched, orphaned) = (&matched, &orphaned);
+            let settings = settings.clone();
+
+            async move {
+                let _in_flight = InFlight::begin();
+                let target_file = mfile.target_file.clone();
+
+                // NOTE: Orphans are retried all at once, so the budget of a file only starts once
+                // TMDB would take a request for it.
+                if base::online_matching() {
+                    tmdb::wait_for_rate_limit().await;
+                }
+
+                let started = Instant::now();
+
+                let result = match media_type {
+                    MediaType::Movie => {
+                        with_budget(budget, started, matcher.match_movie(mfile, settings)).await
+                    }
+                    MediaType::Tv => {
+                        with_budget(budget, started, matcher.match_tv(mfile, settings)).await
+                    }
+                    MediaType::Music => {
+                        with_budget(budget, started, matcher.match_music(mfile, settings)).await
+                    }
+                    _ => return,
+                };
+
+                log_timeout(log, &result, &target_file);
+
+                match result {
+                    Ok(_) => {
+                        matched.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(e) if e.is_no_match() => {
+                        orphaned.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(_) => {}
+                }
+            }
+        })
+        .await;
+
+    (matched.into_inner(), orphaned.into_inner())
+}
+
+/// Function probes the files of `library_id` that ffprobe marked as corrupt once more, updating
+/// the ones that probe clean now, ie because they were repaired or downloaded again. Repaired
+/// files that can be played as a video are matched right after. Returns the number of files that
+/// no longer are corrupt.
+pub async fn reprobe_corrupt(
+    library_id: i64,
+    log: slog::Logger,
+    tx: EventTx,
+) -> Result<usize, self::base::ScannerError> {
+    let (conn, settings) = scan_prologue(library_id, &log).await?;
+    let media_type = Library::get_one(&conn, library_id).await?.media_type;
+    let corrupt = MediaFile::get_corrupt_by_lib(&conn, library_id).await?;
+    let mut repaired = Vec::new();
+
+    for media_file in corrupt.iter() {
+        match base::reprobe_file(&conn, &log, media_file, &settings).await {
+            Ok(true) => {
+                info!(
+                    log,
+                    "File is no longer corrupt";
+                    "library_id" => library_id,
+                    "file" => &media_file.target_file,
+                );
+                repaired.push(media_file.id);
+            }
+            Ok(false) => {}
+            Err(e) => warn!(
+                log,
+                "Failed to probe corrupt file again";
+                "library_id" => library_id,
+                "file" => &media_file.target_file,
+                "reason" => e.to_string(),
+            ),
+        }
+    }
+
+    info!(
+        log,
+        "Probed corrupt files again";
+        "library_id" => library_id,
+        "files" => corrupt.len(),
+        "repaired" => repaired.len(),
+    );
+
+    // NOTE: Reprobing updated the rows, so the files are fetched again to match what they probe as
+    // now. Files that are still no video, ie audio only, stay unmatched like on a scan.
+    let mut orphans = Vec::with_capacity(repaired.len());
+    for id in repaired.iter().copied() {
+        if let Ok(x) = MediaFile::get_one(&conn, id).await {
+            if x.media_id.is_none() && x.problem.is_none() {
+                orphans.push(x);
+            }
+        }
+    }
+
+    if !orphans.is_empty() {
+        let (matched, _) = match_orphans(orphans, media_type, Arc::new(settings), &log, &tx).await;
+
+        info!(
+            log,
+            "Matched repaired files";
+            "library_id" => library_id,
+            "matched" => matched,
+        );
+    }
+
+    Ok(repaired.len())
+}
+
+/// Function calls [`retry_orphans`] on `library_id` every `orphan_retry_interval` seconds until the
+/// library is removed or dim shuts down. Does nothing if no interval is configured.
+pub async fn retry_orphans_daemon(library_id: i64, log: slog::Logger, tx: EventTx) {
+    let interval = match get_global_settings().orphan_retry_interval {
+        0 => return,
+        secs => Duration::from_secs(secs),
+    };
+
+    let mut interval = tokio::time::interval(interval);
+    // NOTE: The first tick completes right away, while the scan on boot takes care of orphans.
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        if shutdown::is_shutting_down() {
+            return;
+        }
+
+        if let Ok(conn) = get_conn_with_retry(&log).await {
+            if Library::get_one(&conn, library_id).await.is_err() {
+                return;
+            }
+        }
+
+        // NOTE: Scans match the files they mount themselves, and retrying alongside them would
+        // only have the two fight over the matchers.
+        if is_scanning(library_id) {
+            debug!(
+                log,
+                "Library is being scanned, not matching unmatched files";
+                "library_id" => library_id,
+            );
+            continue;
+        }
+
+        if let Err(e) = retry_orphans(library_id, log.clone(), tx.clone()).await {
+            warn!(
+                log,
+                "Failed to match unmatched files again";
+                "library_id" => library_id,
+                "reason" => e.to_string(),
+            );
+        }
+    }
+}
+
+/// Function removes the mediafile `media_file`, along with its media if no other mediafiles are
+/// left for it.
+pub(super) async fn remove_file(conn: &DbConnection, log: &slog::Logger, media_file: MediaFile) {
+    if let Err(e) = MediaFile::delete(conn, media_file.id).await {
+        error!(log, "Failed to remove mediafile"; "reason" => format!("{:?}", e));
+        return;
+    }
+
+    if let Some(media_id) = media_file.media_id {
+        purge_ghost_media(conn, log, media_id).await;
+    }
+}
+
+/// Function deletes the media `media_id` if no mediafiles are left for it, as it would be a ghost
+/// media entry.
+pub(super) async fn purge_ghost_media(conn: &DbConnection, log: &slog::Logger, media_id: i64) {
+    if let Ok(media_files) = MediaFile::get_of_media(conn, media_id).await {
+        if media_files.is_empty() {
+            if let Err(e) = Media::delete(conn, media_id).await {
+                error!(log, "Failed to delete ghost media {:?}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parallel_walk_finds_the_same_files() {
+        let root = std::env::temp_dir().join(format!("dim-walk-{}", uuid::Uuid::new_v4()));
+
+        for file in &[
+            "Movie (2010).mkv",
+            "notes.txt",
+            "Show/Season 01/e01.mp4",
+            "Show/Season 01/e02.avi",
+            "Other/.hidden/Movie.mkv",
+        ] {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"").unwrap();
+        }
+
+        let log = slog::Logger::root(slog::Discard, slog::o!());
+        let settings = LibrarySettings::default();
+        let ignored = IgnorePatterns::new(&[root.to_string_lossy().into_owned()], &[], &log);
+
+        let mut serial = walk_directory(&root, &settings, MediaType::Movie, &ignored);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut parallel = runtime.block_on(walk_directory_parallel(
+            root.clone(),
+            &settings,
+            MediaType::Movie,
+            &ignored,
+        ));
+
+        std::fs::remove_dir_all(&root).unwrap();
+
+        serial.sort();
+        parallel.sort();
+
+        let expected = vec![
+            root.join("Movie (2010).mkv"),
+            root.join("Show/Season 01/e01.mp4"),
+            root.join("Show/Season 01/e02.avi"),
+        ];
+
+        assert_eq!(serial, expected);
+        assert_eq!(parallel, expected);
+    }
+}
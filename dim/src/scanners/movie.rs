@@ -6,6 +6,7 @@ use database::DbConnection;
 
 use database::library::MediaType;
 use database::media::InsertableMedia;
+use database::media::Media;
 use database::mediafile::MediaFile;
 use database::mediafile::UpdateMediaFile;
 
@@ -21,6 +22,9 @@ use events::PushEventType;
 
 use crate::core::EventTx;
 use crate::fetcher::insert_into_queue;
+use crate::get_global_settings;
+use crate::scanners::artwork;
+use crate::scanners::artwork::ArtworkKind;
 
 pub struct MovieMatcher<'a> {
     pub conn: &'a DbConnection,
@@ -42,9 +46,24 @@ impl<'a> MovieMatcher<'a> {
             .unwrap_or(None)
             .map(|s| s.year() as i64);
 
-        let poster_path = result.poster_path.clone();
+        // NOTE: Artwork kept next to the media wins over remote artwork unless the user told us
+        // otherwise, in which case it is only used when the remote has none.
+        let prefer_local = get_global_settings().prefer_local_artwork;
+        let dirs = artwork::artwork_dirs(&orphan.target_file, false);
+        let local_poster = artwork::find_sidecar(&dirs, ArtworkKind::Poster)
+            .filter(|_| prefer_local || result.poster_path.is_none());
+        let local_backdrop = artwork::find_sidecar(&dirs, ArtworkKind::Backdrop)
+            .filter(|_| prefer_local || result.backdrop_path.is_none());
+
+        let poster_path = result
+            .poster_path
+            .clone()
+            .filter(|_| local_poster.is_none());
 
-        let backdrop_path = result.backdrop_path.clone();
+        let backdrop_path = result
+            .backdrop_path
+            .clone()
+            .filter(|_| local_backdrop.is_none());
 
         if let Some(poster_path) = poster_path.as_ref() {
             let _ = insert_into_queue(self.log, poster_path.clone(), 3);
@@ -116,6 +135,16 @@ impl<'a> MovieMatcher<'a> {
             None => None,
         };
 
+        let poster = match local_poster {
+            Some(image) => artwork::insert_sidecar(self.conn, self.log, &image).await,
+            None => poster,
+        };
+
+        let backdrop = match local_backdrop {
+            Some(image) => artwork::insert_sidecar(self.conn, self.log, &image).await,
+            None => backdrop,
+        };
+
         let media = InsertableMedia {
             library_id: orphan.library_id,
             name,
@@ -127,6 +156,8 @@ impl<'a> MovieMatcher<'a> {
             poster,
             backdrop,
             media_type: MediaType::Movie,
+            external_id: Some(result.id as i64),
+            tvdb_id: None,
         };
 
         if let Err(e) = self.insert(orphan, media, result).await {
@@ -145,7 +176,22 @@ impl<'a> MovieMatcher<'a> {
         media: InsertableMedia,
         result: super::ApiMedia,
     ) -> Result<(), super::base::ScannerError> {
+        let _writing = super::base::MATCH_WRITES.lock().await;
+
+        // NOTE: Parts of a multi-part movie are matched in parallel, the ones that finish after
+        // the first one join its media.
+        if orphan.part_number.is_some() && orphan.join_matched_part(&self.conn).await?.is_some() {
+            return Ok(());
+        }
+
         let media_id = media.insert(&self.conn).await?;
+        Media::set_details(
+            &self.conn,
+            media_id,
+            result.tagline.clone(),
+            result.runtime.map(i64::from),
+        )
+        .await?;
         // the reason we ignore the result here is that in some cases this can fail. Specifically when there are multiple mediafiles for a movie.
         let _ = InsertableMovie::insert(&self.conn, media_id).await;
 
@@ -157,8 +203,11 @@ impl<'a> MovieMatcher<'a> {
             }
         }
 
+        // NOTE: The score of a match is only recorded once the match went through.
         let updated_mediafile = UpdateMediaFile {
             media_id: Some(media_id),
+            stale: Some(false),
+            match_score: orphan.match_score,
             ..Default::default()
         };
 
@@ -0,0 +1,497 @@
+//! Scanning of music libraries. Songs are mounted by their tags rather than their filename and
+//! grouped into albums by the folder they are kept in, ie `Artist/Album (1997)/01 - Song.flac`.
+//! Albums are matched against MusicBrainz, albums it doesnt know of are made up from the tags of
+//! their songs.
+use super::artwork;
+use super::artwork::ArtworkKind;
+use super::base::ScannerError;
+use super::base::MATCH_WRITES;
+use super::filename;
+use super::musicbrainz::MusicBrainzAgent;
+use super::musicbrainz::ReleaseGroup;
+
+use crate::core::EventTx;
+use crate::fetcher::insert_into_queue;
+use crate::get_global_settings;
+use crate::streaming::ffprobe::FFPWrapper;
+
+use database::asset::InsertableAsset;
+use database::genre::InsertableGenre;
+use database::genre::InsertableGenreMedia;
+use database::library::LibrarySettings;
+use database::library::MediaType;
+use database::media::InsertableMedia;
+use database::mediafile::MediaFile;
+use database::mediafile::UpdateMediaFile;
+use database::song::InsertableSong;
+use database::song::Song;
+use database::DbConnection;
+
+use chrono::prelude::Utc;
+
+use events::Message;
+use events::PushEventType;
+
+use slog::warn;
+use slog::Logger;
+
+use std::cmp::Ordering;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Extensions of the files music libraries scan by default.
+pub static MUSIC_EXTS: &[&str] = &["mp3", "flac", "m4a", "ogg", "opus"];
+
+/// Names of the folders the discs of a album are split into, ie `CD1` or `Disc 2`, all lowercase.
+const DISC_FOLDERS: &[&str] = &["cd", "disc", "disk"];
+
+/// Function returns the disc number a folder named like `CD1`, `Disc 2` or `disk02` stands for.
+pub fn disc_from_folder(name: &str) -> Option<i64> {
+    let name = name.trim().to_ascii_lowercase();
+
+    DISC_FOLDERS.iter().find_map(|prefix| {
+        name.strip_prefix(prefix)?
+            .trim_start_matches(|c: char| c == ' ' || c == '.' || c == '_' || c == '-')
+            .parse()
+            .ok()
+    })
+}
+
+/// Function returns the folder the album `file` belongs to is kept in, which is the folder above
+/// its disc folder for albums split into several discs.
+pub fn album_folder(file: &Path) -> Option<&Path> {
+    let dir = file.parent()?;
+    let is_disc = dir
+        .file_name()
+        .and_then(|x| x.to_str())
+        .and_then(disc_from_folder)
+        .is_some();
+
+    match dir.parent() {
+        Some(parent) if is_disc => Some(parent),
+        _ => Some(dir),
+    }
+}
+
+/// Function parses a track or disc number as tagged, ie `3` or `3/12`.
+pub fn parse_number(tag: &str) -> Option<i64> {
+    tag.split('/').next()?.trim().parse().ok()
+}
+
+/// Function splits the name of a song file named like `03 - Song`, `03. Song` or `03 Song` into
+/// its track number and title. Names without a leading track number are returned as they are.
+pub fn track_from_filename(name: &str) -> (Option<i64>, &str) {
+    let digits = name.len() - name.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+
+    // NOTE: Names that are a number and nothing else, ie `1979`, are titles.
+    if digits == 0 || digits > 3 || digits == name.len() {
+        return (None, name);
+    }
+
+    let (number, rest) = name.split_at(digits);
+    let title = rest.trim_start_matches(|c: char| c == ' ' || c == '.' || c == '-' || c == '_');
+
+    match (number.parse().ok(), title.is_empty()) {
+        (Some(x), false) if rest.starts_with(|c: char| !c.is_alphanumeric()) => (Some(x), title),
+        _ => (None, name),
+    }
+}
+
+/// Function returns the title and year of the album kept in the folder `name`, ie `Album (1997)`
+/// or `Artist - Album`. The artist is only dropped if it is `artist`, as album titles themselves
+/// can hold dashes.
+pub fn album_from_folder(name: &str, artist: Option<&str>) -> (String, Option<i64>) {
+    let year = filename::tokens(name)
+        .filter(|x| x.len() == 4)
+        .filter_map(|x| x.parse::<i64>().ok())
+        .filter(|x| (1880..=2100).contains(x))
+        .last();
+
+    let mut title = name.trim();
+
+    if let Some(year) = year {
+        for (open, close) in &[("(", ")"), ("[", "]")] {
+            let tag = format!("{}{}{}", open, year, close);
+            title = title.strip_suffix(tag.as_str()).unwrap_or(title).trim_end();
+        }
+    }
+
+    if let Some((prefix, rest)) = title.split_once(" - ") {
+        if artist.map_or(false, |x| x.eq_ignore_ascii_case(prefix.trim())) {
+            title = rest.trim();
+        }
+    }
+
+    (title.to_owned(), year)
+}
+
+/// Function returns the tags of the song at `file` as probed by ffprobe. Songs that arent tagged
+/// with a title or track number fall back to their filename, songs without a disc number to the
+/// disc folder they are in.
+pub fn song_tags(file: &Path, ffprobe_data: &FFPWrapper) -> InsertableSong {
+    let tag = |key: &str| ffprobe_data.get_tag(key).map(ToOwned::to_owned);
+    let number = |keys: &[&str]| {
+        keys.iter()
+            .find_map(|key| ffprobe_data.get_tag(key).and_then(parse_number))
+    };
+
+    let stem = file
+        .file_stem()
+        .and_then(|x| x.to_str())
+        .unwrap_or_default();
+    let (filename_track, filename_title) = track_from_filename(stem);
+
+    let disc_folder = file
+        .parent()
+        .and_then(|x| x.file_name())
+        .and_then(|x| x.to_str())
+        .and_then(disc_from_folder);
+
+    InsertableSong {
+        artist: tag("artist"),
+        album_artist: tag("album_artist").or_else(|| tag("albumartist")),
+        album: tag("album"),
+        title: tag("title").or_else(|| Some(filename_title.to_owned())),
+        track_number: number(&["track", "tracknumber"]).or(filename_track),
+        disc_number: number(&["disc", "discnumber"]).or(disc_folder),
+    }
+}
+
+/// Function returns the title and year of the album the song at `file` with the tags `song` is
+/// on, going by its tags first and the folder it is in otherwise.
+pub fn album_of(
+    file: &Path,
+    song: &InsertableSong,
+    ffprobe_data: &FFPWrapper,
+) -> (String, Option<i64>) {
+    let artist = song.album_artist.as_deref().or(song.artist.as_deref());
+    let (folder_title, folder_year) = album_folder(file)
+        .and_then(|x| x.file_name())
+        .and_then(|x| x.to_str())
+        .map(|x| album_from_folder(x, artist))
+        .unwrap_or_default();
+
+    let year = ["date", "year", "originaldate"]
+        .iter()
+        .find_map(|key| ffprobe_data.get_tag(key).and_then(filename::year_from_date))
+        .or(folder_year);
+
+    match song.album.clone() {
+        Some(album) => (album, year),
+        None => (folder_title, year),
+    }
+}
+
+pub struct MusicMatcher<'a> {
+    pub conn: &'a DbConnection,
+    pub log: &'a Logger,
+    pub event_tx: &'a EventTx,
+}
+
+impl<'a> MusicMatcher<'a> {
+    /// Method matches the song `orphan` with its album. Songs join the album another song from the
+    /// same album folder was matched with. Other albums are looked up on MusicBrainz, albums it
+    /// doesnt know of are made up from the tags of the song. Returns how similar the album we
+    /// matched is to the one the song is tagged with.
+    pub async fn match_song(
+        &self,
+        orphan: &MediaFile,
+        settings: &LibrarySettings,
+    ) -> Result<f64, ScannerError> {
+        if orphan.raw_name.is_empty() {
+            return Err(ScannerError::NoMatch);
+        }
+
+        if let Some(score) = self.join_album(orphan).await? {
+            return Ok(score);
+        }
+
+        let song = Song::get_by_mediafile(self.conn, orphan.id)
+            .await?
+            .unwrap_or_default();
+        let artist = song.credited_artist().map(ToOwned::to_owned);
+
+        let agent = MusicBrainzAgent::new();
+        let release = if super::base::online_matching() {
+            self.search(&agent, orphan, artist.as_deref(), settings)
+                .await
+        } else {
+            None
+        };
+
+        let poster = self.poster(&agent, orphan, release.as_ref()).await;
+        let score = release.as_ref().map(|(_, score)| *score);
+
+        let media = InsertableMedia {
+            library_id: orphan.library_id,
+            name: release
+                .as_ref()
+                .map_or_else(|| orphan.raw_name.clone(), |(x, _)| x.title.clone()),
+            description: None,
+            rating: None,
+            year: release
+                .as_ref()
+                .and_then(|(x, _)| x.year())
+                .or(orphan.raw_year),
+            added: Utc::now().to_string(),
+            poster,
+            backdrop: None,
+            media_type: MediaType::Music,
+            external_id: None,
+            tvdb_id: None,
+        };
+
+        let genres = release.map(|(x, _)| x.genres).unwrap_or_default();
+        let orphan = MediaFile {
+            match_score: score,
+            ..orphan.clone()
+        };
+
+        self.insert(&orphan, media, artist, genres).await?;
+
+        // NOTE: Albums made up from tags are as good a match as the tags are.
+        Ok(score.unwrap_or(1.0))
+    }
+
+    /// Method matches `orphan` with the album another song from its album folder, tagged with the
+    /// same album, was matched with, if any. Returns the score of that match.
+    async fn join_album(&self, orphan: &MediaFile) -> Result<Option<f64>, ScannerError> {
+        let folder = match album_folder(Path::new(&orphan.target_file)) {
+            Some(x) => x.to_string_lossy().to_string(),
+            None => return Ok(None),
+        };
+
+        let matched = MediaFile::get_by_dir(self.conn, &folder)
+            .await?
+            .into_iter()
+            .find(|x| x.id != orphan.id && x.media_id.is_some() && x.raw_name == orphan.raw_name);
+
+        let matched = match matched {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+
+        let update = UpdateMediaFile {
+            media_id: matched.media_id,
+            stale: Some(false),
+            match_score: matched.match_score,
+            ..Default::default()
+        };
+
+        update.update(self.conn, orphan.id).await?;
+
+        Ok(Some(matched.match_score.unwrap_or(1.0)))
+    }
+
+    /// Method returns the album on MusicBrainz that best matches the album `orphan` is tagged
+    /// with, along with how well it matches, unless none of them match well enough.
+    async fn search(
+        &self,
+        agent: &MusicBrainzAgent,
+        orphan: &MediaFile,
+        artist: Option<&str>,
+        settings: &LibrarySettings,
+    ) -> Option<(ReleaseGroup, f64)> {
+        let found = match agent.search_album(&orphan.raw_name, artist).await {
+            Ok(x) => x,
+            Err(e) => {
+                warn!(
+                    self.log,
+                    "Could not search for album on MusicBrainz";
+                    "album" => &orphan.raw_name,
+                    "reason" => e.to_string(),
+                );
+                return None;
+            }
+        };
+
+        found
+            .into_iter()
+            .map(|x| {
+                let score =
+                    filename::match_score(&orphan.raw_name, orphan.raw_year, &x.title, x.year());
+                (x, score)
+            })
+            .filter(|(_, score)| *score >= settings.match_threshold)
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+    }
+
+    /// Method returns the asset of the cover of the album `orphan` is on. Covers kept in the album
+    /// folder, ie `cover.jpg`, win over the Cover Art Archive unless configured otherwise.
+    async fn poster(
+        &self,
+        agent: &MusicBrainzAgent,
+        orphan: &MediaFile,
+        release: Option<&(ReleaseGroup, f64)>,
+    ) -> Option<i64> {
+        let file = Path::new(&orphan.target_file);
+        let mut dirs = file
+            .parent()
+            .map(|x| vec![x.to_path_buf()])
+            .unwrap_or_default();
+        dirs.extend(album_folder(file).map(PathBuf::from));
+        dirs.dedup();
+
+        let local = artwork::find_sidecar(&dirs, ArtworkKind::Poster);
+
+        if let Some(image) = local.as_ref() {
+            if get_global_settings().prefer_local_artwork || release.is_none() {
+                return artwork::insert_sidecar(self.conn, self.log, image).await;
+            }
+        }
+
+        let remote = match release {
+            Some((x, _)) => agent.front_cover(&x.id).await.ok().flatten(),
+            None => None,
+        };
+
+        let url = match remote {
+            Some(x) => x,
+            None => return artwork::insert_sidecar(self.conn, self.log, local.as_ref()?).await,
+        };
+
+        insert_into_queue(self.log, url.clone(), 3).await;
+
+        // NOTE: Covers are cached under the last segment of their url, which holds the id of
+        // the image on the Cover Art Archive.
+        let file_name = url.rsplit('/').next().unwrap_or_default().to_owned();
+        let asset = InsertableAsset {
+            remote_url: Some(url),
+            local_path: format!("images/{}", file_name),
+            file_ext: "jpg".into(),
+        }
+        .insert(self.conn)
+        .await;
+
+        match asset {
+            Ok(x) => Some(x.id),
+            Err(e) => {
+                warn!(
+                    self.log,
+                    "Failed to insert cover into db";
+                    "reason" => e.to_string(),
+                    "orphan_id" => orphan.id,
+                );
+                None
+            }
+        }
+    }
+
+    async fn insert(
+        &self,
+        orphan: &MediaFile,
+        media: InsertableMedia,
+        artist: Option<String>,
+        genres: Vec<String>,
+    ) -> Result<(), ScannerError> {
+        let _writing = MATCH_WRITES.lock().await;
+
+        // NOTE: The songs of a album are matched in parallel, the ones that finish after the
+        // first one join its album.
+        if self.join_album(orphan).await?.is_some() {
+            return Ok(());
+        }
+
+        // NOTE: Albums are unique by their title within a library, thus albums of different
+        // artists that share a title, ie `Greatest Hits`, are told apart by their artist.
+        let media_id = match media.insert_blind(self.conn).await {
+            Err(e) if e.is_unique_violation() && artist.is_some() => {
+                let media = InsertableMedia {
+                    name: format!("{} ({})", media.name, artist.unwrap_or_default()),
+                    ..media.clone()
+                };
+
+                media.insert_blind(self.conn).await?
+            }
+            x => x?,
+        };
+
+        for name in genres {
+            let genre = InsertableGenre { name };
+
+            if let Ok(x) = genre.insert(self.conn).await {
+                let _ = InsertableGenreMedia::insert_pair(x, media_id, self.conn).await;
+            }
+        }
+
+        let update = UpdateMediaFile {
+            media_id: Some(media_id),
+            stale: Some(false),
+            match_score: orphan.match_score,
+            ..Default::default()
+        };
+
+        update.update(self.conn, orphan.id).await?;
+
+        let event = Message {
+            id: media_id,
+            event_type: PushEventType::EventNewCard {
+                lib_id: media.library_id,
+            },
+        };
+
+        let _ = self.event_tx.send(serde_json::to_string(&event).unwrap());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disc_from_folder_names() {
+        assert_eq!(disc_from_folder("CD1"), Some(1));
+        assert_eq!(disc_from_folder("Disc 2"), Some(2));
+        assert_eq!(disc_from_folder("disk02"), Some(2));
+        assert_eq!(disc_from_folder("CD.3"), Some(3));
+        assert_eq!(disc_from_folder("Discovery"), None);
+        assert_eq!(disc_from_folder("Album"), None);
+    }
+
+    #[test]
+    fn album_folder_skips_disc_folders() {
+        let file = Path::new("/music/Artist/Album/CD2/01 - Song.flac");
+        assert_eq!(album_folder(file), Some(Path::new("/music/Artist/Album")));
+
+        let file = Path::new("/music/Artist/Album/01 - Song.flac");
+        assert_eq!(album_folder(file), Some(Path::new("/music/Artist/Album")));
+    }
+
+    #[test]
+    fn parse_number_of_tags() {
+        assert_eq!(parse_number("3"), Some(3));
+        assert_eq!(parse_number("03/12"), Some(3));
+        assert_eq!(parse_number(" 7 / 9"), Some(7));
+        assert_eq!(parse_number("A1"), None);
+    }
+
+    #[test]
+    fn track_from_filename_names() {
+        assert_eq!(track_from_filename("03 - Song"), (Some(3), "Song"));
+        assert_eq!(track_from_filename("03. Song"), (Some(3), "Song"));
+        assert_eq!(track_from_filename("12 Song"), (Some(12), "Song"));
+        assert_eq!(track_from_filename("Song"), (None, "Song"));
+        // NOTE: Titles that start with a number arent track numbers.
+        assert_eq!(track_from_filename("1979"), (None, "1979"));
+        assert_eq!(track_from_filename("7th Symphony"), (None, "7th Symphony"));
+    }
+
+    #[test]
+    fn album_from_folder_names() {
+        assert_eq!(
+            album_from_folder("OK Computer (1997)", None),
+            ("OK Computer".to_owned(), Some(1997))
+        );
+        assert_eq!(
+            album_from_folder("Radiohead - OK Computer [1997]", Some("radiohead")),
+            ("OK Computer".to_owned(), Some(1997))
+        );
+        // NOTE: Dashes are only taken for a artist prefix if they name the artist.
+        assert_eq!(
+            album_from_folder("Live - Throwing Copper", Some("Radiohead")),
+            ("Live - Throwing Copper".to_owned(), None)
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! Matching of albums against [MusicBrainz](https://musicbrainz.org), whose cover art is looked up
+//! on the [Cover Art Archive](https://coverartarchive.org). MusicBrainz asks clients to send no
+//! more than one request per second and to identify themselves with a user agent that tells them
+//! how to reach us, thus requests are sent one at a time.
+use super::tmdb::check_status;
+use super::tmdb::TmdbError;
+
+use reqwest::Client;
+use reqwest::ClientBuilder;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+
+static APP_USER_AGENT: &str = concat!(
+    env!("CARGO_PKG_NAME"),
+    "/",
+    env!("CARGO_PKG_VERSION"),
+    " ( https://github.com/Dusk-Labs/dim )"
+);
+
+/// Time we leave between two requests to MusicBrainz.
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of release groups we ask for per search.
+const SEARCH_LIMIT: &str = "5";
+
+lazy_static::lazy_static! {
+    /// Release groups found per search along with when we last sent a request. Searches are sent
+    /// under this lock, so that the songs of a album waiting on it find the album cached instead
+    /// of searching for it again.
+    static ref SEARCHES: Mutex<(HashMap<String, Vec<ReleaseGroup>>, Option<Instant>)> =
+        Mutex::new(Default::default());
+}
+
+/// Struct holds a release group, which is what MusicBrainz calls a album regardless of how many
+/// times and in how many editions it was released.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReleaseGroup {
+    /// MusicBrainz id of the release group.
+    pub id: String,
+    pub title: String,
+    /// Artist the album is credited to, ie `Simon & Garfunkel`.
+    pub artist: Option<String>,
+    /// Date the album was first released on, ie `1997-05-21` or just `1997`.
+    pub release_date: Option<String>,
+    pub genres: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct MusicBrainzAgent {
+    client: Client,
+    base: String,
+    cover_base: String,
+}
+
+impl MusicBrainzAgent {
+    pub fn new() -> Self {
+        let client = ClientBuilder::new().user_agent(APP_USER_AGENT);
+
+        Self {
+            client: client.build().unwrap(),
+            base: "https://musicbrainz.org/ws/2".into(),
+            cover_base: "https://coverartarchive.org".into(),
+        }
+    }
+
+    /// Method returns the albums titled `album` MusicBrainz knows of, best match first. If the
+    /// artist of the album is known only albums credited to them are returned.
+    pub async fn search_album(
+        &self,
+        album: &str,
+        artist: Option<&str>,
+    ) -> Result<Vec<ReleaseGroup>, TmdbError> {
+        let query = match artist {
+            Some(artist) => format!(
+                "releasegroup:{} AND artist:{}",
+                lucene_phrase(album),
+                lucene_phrase(artist)
+            ),
+            None => format!("releasegroup:{}", lucene_phrase(album)),
+        };
+
+        let mut searches = SEARCHES.lock().await;
+
+        if let Some(x) = searches.0.get(&query) {
+            return Ok(x.clone());
+        }
+
+        // NOTE: The lock is held while we wait, so requests are spaced out no matter how many
+        // songs are matched at once.
+        if let Some(last) = searches.1 {
+            tokio::time::sleep(REQUEST_INTERVAL.saturating_sub(last.elapsed())).await;
+        }
+
+        searches.1 = Some(Instant::now());
+
+        let req = self
+            .client
+            .get(format!("{}/release-group", self.base))
+            .query(&[
+                ("query", query.as_str()),
+                ("fmt", "json"),
+                ("limit", SEARCH_LIMIT),
+            ])
+            .send()
+            .await
+            .map_err(TmdbError::from_reqwest)?;
+
+        // NOTE: MusicBrainz answers requests over its rate limit with a `503`.
+        if req.status() == StatusCode::SERVICE_UNAVAILABLE {
+            return Err(TmdbError::RateLimited(None));
+        }
+
+        check_status(&req)?;
+
+        let found = req
+            .json::<SearchResponse>()
+            .await
+            .map_err(|_| TmdbError::DeserializationError)?
+            .release_groups
+            .into_iter()
+            .map(Into::into)
+            .collect::<Vec<ReleaseGroup>>();
+
+        searches.0.insert(query, found.clone());
+
+        Ok(found)
+    }
+
+    /// Method returns the url of the front cover of the release group `id`, if the Cover Art
+    /// Archive has one.
+    pub async fn front_cover(&self, id: &str) -> Result<Option<String>, TmdbError> {
+        #[derive(Deserialize)]
+        struct Covers {
+            images: Vec<Image>,
+        }
+
+        #[derive(Deserialize)]
+        struct Image {
+            front: bool,
+            image: String,
+            #[serde(default)]
+            thumbnails: HashMap<String, String>,
+        }
+
+        let req = self
+            .client
+            .get(format!("{}/release-group/{}", self.cover_base, id))
+            .send()
+            .await
+            .map_err(TmdbError::from_reqwest)?;
+
+        if req.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        check_status(&req)?;
+
+        let covers = req
+            .json::<Covers>()
+            .await
+            .map_err(|_| TmdbError::DeserializationError)?;
+
+        // NOTE: The full size scans can be several megabytes large, posters dont need that.
+        Ok(covers
+            .images
+            .into_iter()
+            .find(|x| x.front)
+            .map(|x| x.thumbnails.get("500").cloned().unwrap_or(x.image)))
+    }
+}
+
+impl Default for MusicBrainzAgent {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReleaseGroup {
+    /// Method returns the year the album was first released in.
+    pub fn year(&self) -> Option<i64> {
+        self.release_date
+            .as_deref()
+            .and_then(super::filename::year_from_date)
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    #[serde(rename = "release-groups", default)]
+    release_groups: Vec<ApiReleaseGroup>,
+}
+
+#[derive(Deserialize)]
+struct ApiReleaseGroup {
+    id: String,
+    title: String,
+    #[serde(rename = "first-release-date")]
+    first_release_date: Option<String>,
+    #[serde(rename = "artist-credit", default)]
+    artist_credit: Vec<ArtistCredit>,
+    #[serde(default)]
+    tags: Vec<ApiTag>,
+}
+
+#[derive(Deserialize)]
+struct ArtistCredit {
+    name: String,
+    #[serde(default)]
+    joinphrase: String,
+}
+
+#[derive(Deserialize)]
+struct ApiTag {
+    name: String,
+}
+
+impl From<ApiReleaseGroup> for ReleaseGroup {
+    fn from(group: ApiReleaseGroup) -> Self {
+        // NOTE: Albums credited to several artists list each of them along with what joins them
+        // to the next one, ie `Simon` and `Garfunkel` joined by ` & `.
+        let artist = group
+            .artist_credit
+            .iter()
+            .map(|x| format!("{}{}", x.name, x.joinphrase))
+            .collect::<String>();
+
+        Self {
+            id: group.id,
+            title: group.title,
+            artist: Some(artist).filter(|x| !x.is_empty()),
+            release_date: group.first_release_date.filter(|x| !x.is_empty()),
+            genres: group.tags.into_iter().map(|x| x.name).collect(),
+        }
+    }
+}
+
+/// Function quotes `term` as a phrase of a MusicBrainz search, which uses the lucene query syntax.
+fn lucene_phrase(term: &str) -> String {
+    format!("\"{}\"", term.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lucene_phrase_escapes_quotes() {
+        assert_eq!(lucene_phrase("OK Computer"), "\"OK Computer\"");
+        assert_eq!(lucene_phrase("\"Heroes\""), "\"\\\"Heroes\\\"\"");
+        assert_eq!(lucene_phrase("AC\\DC"), "\"AC\\\\DC\"");
+    }
+
+    #[test]
+    fn release_group_joins_artist_credits() {
+        let group = ApiReleaseGroup {
+            id: "id".into(),
+            title: "Bookends".into(),
+            first_release_date: Some("1968-04-03".into()),
+            artist_credit: vec![
+                ArtistCredit {
+                    name: "Simon".into(),
+                    joinphrase: " & ".into(),
+                },
+                ArtistCredit {
+                    name: "Garfunkel".into(),
+                    joinphrase: String::new(),
+                },
+            ],
+            tags: Vec::new(),
+        };
+
+        let group = ReleaseGroup::from(group);
+        assert_eq!(group.artist.as_deref(), Some("Simon & Garfunkel"));
+        assert_eq!(group.year(), Some(1968));
+    }
+}
@@ -0,0 +1,163 @@
+//! Helpers used to read the Kodi style `.nfo` files users keep next to their movies, ie
+//! `Movie (2010)/Movie (2010).nfo` or `Movie (2010)/movie.nfo`, which usually carry the TMDB or
+//! IMDB id of the movie along with its title.
+use super::filename::is_imdb_id;
+
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Struct holds what we could make out of a nfo file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Nfo {
+    pub tmdb_id: Option<i64>,
+    pub imdb_id: Option<String>,
+    pub title: Option<String>,
+    pub year: Option<i64>,
+}
+
+/// Function returns the nfo file describing `video`, either named after the video or `movie.nfo`
+/// in the same folder.
+pub fn find_nfo(video: &Path) -> Option<PathBuf> {
+    let named = video.with_extension("nfo");
+
+    if named.is_file() {
+        return Some(named);
+    }
+
+    let shared = video.parent()?.join("movie.nfo");
+    shared.is_file().then(|| shared)
+}
+
+/// Function returns the trimmed text of every `<tag ...>text</tag>` element in `content` along
+/// with the attributes of its opening tag. Nesting isnt handled, which nfo files dont need.
+fn elements<'a>(content: &'a str, tag: &str) -> Vec<(&'a str, &'a str)> {
+    let open = format!("<{}", tag);
+    let close = format!("</{}>", tag);
+    let mut found = Vec::new();
+    let mut rest = content;
+
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+
+        // NOTE: Make sure we didnt just find a tag whose name starts with `tag`.
+        if !rest.starts_with(|c: char| c == '>' || c.is_whitespace()) {
+            continue;
+        }
+
+        let (attrs, body) = match rest.split_once('>') {
+            Some(x) => x,
+            None => break,
+        };
+
+        let end = match body.find(&close) {
+            Some(x) => x,
+            None => break,
+        };
+
+        found.push((attrs.trim(), body[..end].trim()));
+        rest = &body[end + close.len()..];
+    }
+
+    found
+}
+
+/// Function returns the text of the first non-empty `<tag>` element in `content`.
+fn text(content: &str, tag: &str) -> Option<String> {
+    elements(content, tag)
+        .into_iter()
+        .map(|(_, x)| unescape(x))
+        .find(|x| !x.is_empty())
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Function parses the contents of a nfo file. Ids are read from `<tmdbid>`, `<imdbid>`, `<id>`
+/// and `<uniqueid type="...">` elements.
+pub fn parse_nfo(content: &str) -> Nfo {
+    let mut nfo = Nfo {
+        tmdb_id: text(content, "tmdbid").and_then(|x| x.parse().ok()),
+        imdb_id: text(content, "imdbid").filter(|x| is_imdb_id(x)),
+        title: text(content, "title"),
+        year: text(content, "year").and_then(|x| x.parse().ok()),
+    };
+
+    for (attrs, id) in elements(content, "uniqueid") {
+        let attrs = attrs.to_ascii_lowercase();
+
+        if attrs.contains("\"tmdb\"") && nfo.tmdb_id.is_none() {
+            nfo.tmdb_id = id.parse().ok();
+        } else if attrs.contains("\"imdb\"") && nfo.imdb_id.is_none() && is_imdb_id(id) {
+            nfo.imdb_id = Some(id.to_owned());
+        }
+    }
+
+    // NOTE: Older scrapers store the IMDB id in a plain `<id>` element.
+    if nfo.imdb_id.is_none() {
+        nfo.imdb_id = text(content, "id").filter(|x| is_imdb_id(x));
+    }
+
+    nfo
+}
+
+/// Function reads and parses the nfo file describing `video`, if there is one.
+pub fn read_nfo(video: &Path) -> Option<Nfo> {
+    let content = std::fs::read_to_string(find_nfo(video)?).ok()?;
+    Some(parse_nfo(&content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elements_skip_longer_tags() {
+        let content = r#"<titles>x</titles><title>Y</title><uniqueid type="tmdb">1</uniqueid>"#;
+
+        assert_eq!(elements(content, "title"), vec![("", "Y")]);
+        assert_eq!(elements(content, "uniqueid"), vec![(r#"type="tmdb""#, "1")]);
+        assert_eq!(elements(content, "year"), Vec::<(&str, &str)>::new());
+    }
+
+    #[test]
+    fn parse_nfo_of_kodi_scrapers() {
+        let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+<movie>
+    <title>Inception</title>
+    <year>2010</year>
+    <uniqueid type="tmdb" default="true">27205</uniqueid>
+    <uniqueid type="imdb">tt1375666</uniqueid>
+</movie>"#;
+
+        assert_eq!(
+            parse_nfo(content),
+            Nfo {
+                tmdb_id: Some(27205),
+                imdb_id: Some("tt1375666".into()),
+                title: Some("Inception".into()),
+                year: Some(2010),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_nfo_of_older_scrapers() {
+        let content = "<movie><title>Tom &amp; Jerry</title><id>tt0000001</id>\
+                       <tmdbid>abc</tmdbid></movie>";
+
+        assert_eq!(
+            parse_nfo(content),
+            Nfo {
+                tmdb_id: None,
+                imdb_id: Some("tt0000001".into()),
+                title: Some("Tom & Jerry".into()),
+                year: None,
+            }
+        );
+    }
+}
@@ -0,0 +1,249 @@
+//! Dry runs of library scans, used to check how files would be parsed and matched before letting
+//! the scanner loose on a library. Files are walked, parsed, probed and searched for exactly like
+//! a real scan would, but nothing is written to the db.
+//!
+//! NOTE: Matches are looked up by title only, the anime agent and specials that a real scan falls
+//! back to are not tried. Songs are only parsed, their albums arent looked up on MusicBrainz.
+use super::base;
+use super::base::CacheWrites;
+use super::base::ScannerError;
+use super::filename;
+use super::tmdb::Tmdb;
+use super::IgnorePatterns;
+
+use database::library::Library;
+use database::library::MediaType;
+use database::mediafile::InsertableMediaFile;
+use database::mediafile::MediaFile;
+use database::mediafile::MountSource;
+
+use futures::stream;
+use futures::StreamExt;
+use serde::Serialize;
+
+use std::path::PathBuf;
+
+/// Number of files we parse and look up at once.
+const PREVIEW_CONCURRENCY: usize = 4;
+
+/// Struct describes what a scan would do with a single file.
+#[derive(Serialize, Clone, Debug)]
+pub struct PreviewRecord {
+    pub target_file: String,
+    #[serde(flatten)]
+    pub outcome: PreviewOutcome,
+}
+
+/// Enum holds what a scan would do with a file.
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum PreviewOutcome {
+    /// The file is already mounted and would be left alone.
+    Mounted,
+    /// The file would be mounted and matched with `tmdb_id`. `confidence` is how confident we are
+    /// in the match, on a scale from `0.0` to `1.0`.
+    Matched {
+        raw_name: String,
+        raw_year: Option<i64>,
+        season: Option<i64>,
+        episode: Option<i64>,
+        tmdb_id: u64,
+        title: String,
+        release_date: Option<String>,
+        confidence: f64,
+    },
+    /// The file would be mounted, but left unmatched.
+    Unmatched {
+        raw_name: String,
+        raw_year: Option<i64>,
+        season: Option<i64>,
+        episode: Option<i64>,
+        reason: String,
+    },
+    /// The file couldnt be mounted at all.
+    Failed { reason: String },
+}
+
+/// Function runs a dry run of a scan of the library `library_id` and returns what would happen to
+/// each file found in its locations.
+pub async fn scan_preview(
+    library_id: i64,
+    log: &slog::Logger,
+) -> Result<Vec<PreviewRecord>, ScannerError> {
+    let (conn, settings) = super::scan_prologue(library_id, log).await?;
+    let lib = Library::get_one(&conn, library_id).await?;
+
+    let ignored = IgnorePatterns::new(&lib.locations, &settings.ignore_patterns, log);
+
+    let mut files = Vec::new();
+    for location in lib.locations.iter() {
+        files.append(
+            &mut super::walk_directory_parallel(
+                PathBuf::from(location),
+                &settings,
+                lib.media_type,
+                &ignored,
+            )
+            .await,
+        );
+    }
+
+    files.retain(|x| super::large_enough(x, &settings, lib.media_type, log));
+
+    let tmdb = Tmdb::new("38c372f5bc572c8aadde7a802638534e".into(), lib.media_type)
+        .language(settings.metadata_language.clone());
+    let media_type = lib.media_type;
+
+    let records = stream::iter(files)
+        .map(|file| {
+            let (conn, settings, tmdb) = (&conn, &settings, &tmdb);
+
+            async move {
+                let file = match base::resolve_disc(&file).await {
+                    Ok(x) => x,
+                    Err(e) => {
+                        return PreviewRecord {
+                            target_file: file.to_string_lossy().to_string(),
+                            outcome: PreviewOutcome::Failed {
+                                reason: e.to_string(),
+                            },
+                        }
+                    }
+                };
+                let target_file = file.to_string_lossy().to_string();
+
+                if MediaFile::get_by_file(conn, &target_file).await.is_ok() {
+                    return PreviewRecord {
+                        target_file,
+                        outcome: PreviewOutcome::Mounted,
+                    };
+                }
+
+                let parsed = match base::parse_file(
+                    log,
+                    &file,
+                    library_id,
+                    media_type,
+                    MountSource::Manual,
+                    settings,
+                )
+                .await
+                {
+                    Ok(x) => x,
+                    Err(e) => {
+                        return PreviewRecord {
+                            target_file,
+                            outcome: PreviewOutcome::Failed {
+                                reason: e.to_string(),
+                            },
+                        }
+                    }
+                };
+
+                if media_type == MediaType::Music {
+                    return PreviewRecord {
+                        target_file,
+                        outcome: PreviewOutcome::Unmatched {
+                            raw_name: parsed.raw_name,
+                            raw_year: parsed.raw_year,
+                            season: parsed.season,
+                            episode: parsed.episode,
+                            reason: "Albums are only looked up once mounted".into(),
+                        },
+                    };
+                }
+
+                let result = match media_type {
+                    MediaType::Tv => {
+                        let raw_year = parsed.raw_year.map(|x| x as i32);
+                        base::search_show(
+                            conn,
+                            log,
+                            tmdb,
+                            &parsed.raw_name,
+                            raw_year,
+                            settings,
+                            CacheWrites::Discard,
+                        )
+                        .await
+                    }
+                    _ => base::search_movie(
+                        tmdb,
+                        conn,
+                        log,
+                        &as_mediafile(&parsed),
+                        settings,
+                        CacheWrites::Discard,
+                    )
+                    .await
+                    .map(|(x, _)| x),
+                };
+
+                let InsertableMediaFile {
+                    raw_name,
+                    raw_year,
+                    season,
+                    episode,
+                    ..
+                } = parsed;
+
+                let result = result.map_err(|e| e.to_string()).and_then(|x| {
+                    let year = raw_year.filter(|_| media_type != MediaType::Tv);
+                    let found_year = x.release_date.as_deref().and_then(filename::year_from_date);
+                    let score = filename::match_score(&raw_name, year, &x.title, found_year);
+
+                    if score < settings.match_threshold {
+                        return Err(ScannerError::LowConfidence(score, settings.match_threshold)
+                            .to_string());
+                    }
+
+                    Ok((x, score))
+                });
+
+                let outcome = match result {
+                    Ok((x, confidence)) => PreviewOutcome::Matched {
+                        confidence,
+                        raw_name,
+                        raw_year,
+                        season,
+                        episode,
+                        tmdb_id: x.id,
+                        title: x.title,
+                        release_date: x.release_date,
+                    },
+                    Err(e) => PreviewOutcome::Unmatched {
+                        raw_name,
+                        raw_year,
+                        season,
+                        episode,
+                        reason: e,
+                    },
+                };
+
+                PreviewRecord {
+                    target_file,
+                    outcome,
+                }
+            }
+        })
+        .buffer_unordered(PREVIEW_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+
+    Ok(records)
+}
+
+/// Function returns the row `file` would end up as once inserted, which is what the matchers take.
+fn as_mediafile(file: &InsertableMediaFile) -> MediaFile {
+    MediaFile {
+        library_id: file.library_id,
+        target_file: file.target_file.clone(),
+        raw_name: file.raw_name.clone(),
+        raw_year: file.raw_year,
+        season: file.season,
+        episode: file.episode,
+        tmdb_id: file.tmdb_id,
+        imdb_id: file.imdb_id.clone(),
+        ..Default::default()
+    }
+}
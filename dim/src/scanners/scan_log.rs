@@ -0,0 +1,96 @@
+//! Machine readable log of a single scan. When `scan_log_dir` is set, every scan writes a JSONL
+//! file into it holding one record per file, which tells what the scanner decided to do with it.
+use crate::get_global_settings;
+
+use chrono::prelude::Utc;
+use serde::Serialize;
+
+use slog::warn;
+use slog::Logger;
+
+use std::fs::create_dir_all;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Enum holds what happened to a file during a scan.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// The file was mounted, but we couldnt match it.
+    Mounted,
+    /// The file was mounted by a earlier scan.
+    Skipped,
+    /// The file couldnt be mounted.
+    Error,
+    /// The file was mounted and matched.
+    Matched,
+}
+
+/// A single line of a scan log.
+#[derive(Serialize, Clone, Debug)]
+pub struct ScanRecord<'a> {
+    pub file: &'a str,
+    pub outcome: Outcome,
+    /// Why a file wasnt matched or mounted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// How close the match is, as returned by the matchers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+}
+
+pub struct ScanLog {
+    path: PathBuf,
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl ScanLog {
+    /// Method creates the log of a new scan of `library_id`, if scan logs are enabled. Failing to
+    /// create the log doesnt stop the scan, we just log the reason and carry on without it.
+    pub fn create(library_id: i64, log: &Logger) -> Option<Self> {
+        let mut path = PathBuf::from(get_global_settings().scan_log_dir?);
+        let file = create_dir_all(&path).and_then(|_| {
+            path.push(format!(
+                "scan-{}-{}.jsonl",
+                library_id,
+                Utc::now().format("%Y%m%d%H%M%S")
+            ));
+
+            File::create(&path)
+        });
+
+        match file {
+            Ok(file) => Some(Self {
+                path,
+                writer: Mutex::new(BufWriter::new(file)),
+            }),
+            Err(e) => {
+                warn!(
+                    log,
+                    "Failed to create scan log";
+                    "path" => path.to_string_lossy().to_string(),
+                    "reason" => e.to_string(),
+                );
+                None
+            }
+        }
+    }
+
+    /// Method appends `record` to the log.
+    pub fn record(&self, record: ScanRecord<'_>) {
+        let mut writer = self.writer.lock().unwrap();
+
+        if let Ok(line) = serde_json::to_string(&record) {
+            let _ = writeln!(writer, "{}", line);
+        }
+    }
+
+    /// Method returns where the log is being written to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
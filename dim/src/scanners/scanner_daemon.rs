@@ -1,15 +1,21 @@
 use crate::core::EventTx;
+use crate::get_global_settings;
+use crate::shutdown;
+use crate::shutdown::InFlight;
 
 use std::array::IntoIter;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::mpsc;
+use std::sync::Arc;
 use std::time::Duration;
 
 use database::get_conn;
 use database::library::Library;
+use database::library::LibrarySettings;
 use database::library::MediaType;
-use database::media::Media;
 use database::mediafile::MediaFile;
+use database::mediafile::MountSource;
 use database::mediafile::UpdateMediaFile;
 use database::DbConnection;
 
@@ -61,6 +67,7 @@ impl FsWatcher {
 
     pub async fn start_daemon(&self) -> Result<(), FsWatcherError> {
         let library = Library::get_one(&self.conn, self.library_id).await?;
+        let handle = super::watcher_handle(self.library_id);
 
         let (tx, mut rx) = mpsc::channel();
         let mut watcher = <RecommendedWatcher as Watcher>::new(tx, Duration::from_secs(1))?;
@@ -81,6 +88,11 @@ impl FsWatcher {
 
             rx = _rx;
 
+            if handle.is_cancelled() {
+                debug!(self.logger, "Stopping fs watcher of removed library"; "library_id" => self.library_id);
+                return Ok(());
+            }
+
             match result {
                 Ok(DebouncedEvent::Create(path)) => self.handle_create(path).await,
                 Ok(DebouncedEvent::Rename(from, to)) => self.handle_rename(from, to).await,
@@ -94,28 +106,51 @@ impl FsWatcher {
     async fn handle_create(&self, path: PathBuf) {
         debug!(self.logger, "Received handle_create event type: {:?}", path);
 
+        let (locations, settings) = Library::get_one(&self.conn, self.library_id)
+            .await
+            .map(|x| (x.locations, Arc::new(x.settings)))
+            .unwrap_or_default();
+
+        let ignored =
+            super::IgnorePatterns::new(&locations, &settings.ignore_patterns, &self.logger);
+
         if path.is_file()
-            && path
-                .extension()
-                .and_then(|e| e.to_str())
-                .map_or(false, |e| super::SUPPORTED_EXTS.contains(&e))
+            && super::wanted_file(&path, &settings, self.media_type, &ignored, &self.logger).await
         {
+            if shutdown::is_shutting_down() {
+                return;
+            }
+
+            let claimed = super::claim_files(
+                &self.conn,
+                self.library_id,
+                vec![path.clone()],
+                &self.logger,
+            )
+            .await;
+
+            if claimed.is_empty() {
+                return;
+            }
+
+            let _in_flight = InFlight::begin();
             let extractor = super::get_extractor(&self.logger, &self.tx);
-            let matcher = super::get_matcher(&self.logger, &self.tx);
 
             if let Ok(mfile) = extractor
-                .mount_file(path.clone(), self.library_id, self.media_type)
+                .mount_file(
+                    path.clone(),
+                    self.library_id,
+                    self.media_type,
+                    MountSource::Daemon,
+                    settings.clone(),
+                )
                 .await
             {
-                match self.media_type {
-                    MediaType::Movie => {
-                        let _ = matcher.match_movie(mfile).await;
-                    }
-                    MediaType::Tv => {
-                        let _ = matcher.match_tv(mfile).await;
-                    }
-                    _ => unreachable!(),
+                if mfile.problem.is_some() {
+                    return;
                 }
+
+                self.match_file(mfile, self.media_type, settings).await;
             }
         } else if path.is_dir() {
             if let Some(x) = path.to_str() {
@@ -125,6 +160,7 @@ impl FsWatcher {
                     self.tx.clone(),
                     IntoIter::new([x]),
                     self.media_type,
+                    MountSource::Daemon,
                 )
                 .await;
             }
@@ -142,26 +178,17 @@ impl FsWatcher {
             }
         };
 
-        if let Some(media_file) = MediaFile::get_by_file(&self.conn, path).await.ok() {
-            let media = Media::get_of_mediafile(&self.conn, media_file.id).await;
-
-            if let Err(e) = MediaFile::delete(&self.conn, media_file.id).await {
-                error!(self.logger, "Failed to remove mediafile"; "reason" => format!("{:?}", e));
-                return;
-            }
+        if let Ok(media_file) = MediaFile::get_by_file(&self.conn, path).await {
+            super::remove_file(&self.conn, &self.logger, media_file).await;
+            return;
+        }
 
-            // if we have a media with no mediafiles we want to purge it as it is a ghost media
-            // entry.
-            if let Ok(media) = media {
-                if let Ok(media_files) = MediaFile::get_of_media(&self.conn, media.id).await {
-                    if media_files.is_empty() {
-                        if let Err(e) = Media::delete(&self.conn, media.id).await {
-                            error!(self.logger, "Failed to delete ghost media {:?}", e);
-                            return;
-                        }
-                    }
-                }
-            }
+        // NOTE: Removing a directory only gets us a event for the directory itself.
+        for media_file in MediaFile::get_by_dir(&self.conn, path)
+            .await
+            .unwrap_or_default()
+        {
+            super::remove_file(&self.conn, &self.logger, media_file).await;
         }
     }
 
@@ -173,7 +200,7 @@ impl FsWatcher {
             "to" => format!("{:?}", to),
         );
 
-        let from = match from.to_str() {
+        let from_str = match from.to_str() {
             Some(x) => x,
             None => {
                 warn!(self.logger, "Received path thats not unicode"; "path" => format!("{:?}", from));
@@ -181,7 +208,7 @@ impl FsWatcher {
             }
         };
 
-        let to = match to.to_str() {
+        let to_str = match to.to_str() {
             Some(x) => x,
             None => {
                 warn!(self.logger, "Received path thats not unicode"; "path" => format!("{:?}", to));
@@ -189,21 +216,118 @@ impl FsWatcher {
             }
         };
 
-        if let Some(media_file) = MediaFile::get_by_file(&self.conn, from).await.ok() {
-            let update_query = UpdateMediaFile {
-                target_file: Some(to.into()),
-                ..Default::default()
-            };
-
-            if let Err(_e) = update_query.update(&self.conn, media_file.id).await {
-                error!(
-                    self.logger,
-                    "Failed to update target file";
-                    "from" => format!("{:?}", from),
-                    "to" => format!("{:?}", to),
-                    "mediafile_id" => media_file.id
-                );
+        if let Ok(media_file) = MediaFile::get_by_file(&self.conn, from_str).await {
+            self.move_file(media_file, to_str.to_owned()).await;
+            return;
+        }
+
+        // NOTE: Renaming a directory only gets us a event for the directory itself, so we move
+        // every file we know of located in it.
+        let media_files = MediaFile::get_by_dir(&self.conn, from_str)
+            .await
+            .unwrap_or_default();
+
+        if media_files.is_empty() {
+            // NOTE: Downloads are often renamed once they finish, ie from `movie.mkv.part`, which
+            // is the first time we get to see them as media.
+            self.handle_create(to).await;
+            return;
+        }
+
+        let from_dir = from_str.trim_end_matches('/');
+        let to_dir = to_str.trim_end_matches('/');
+
+        for media_file in media_files {
+            let target_file = format!("{}{}", to_dir, &media_file.target_file[from_dir.len()..]);
+            self.move_file(media_file, target_file).await;
+        }
+    }
+
+    /// Method moves `media_file` to `to`. Files are updated in place so they stay matched, unless
+    /// they were moved into another library, in which case they are matched again in that
+    /// library. Files moved out of every library are removed.
+    async fn move_file(&self, media_file: MediaFile, to: String) {
+        let library = match self.library_of(&to).await {
+            Some(x) => x,
+            None => {
+                debug!(self.logger, "File was moved out of every library"; "file" => &to);
+                super::remove_file(&self.conn, &self.logger, media_file).await;
+                return;
+            }
+        };
+
+        let update_query = UpdateMediaFile {
+            target_file: Some(to.clone()),
+            ..Default::default()
+        };
+
+        if let Err(_e) = update_query.update(&self.conn, media_file.id).await {
+            error!(
+                self.logger,
+                "Failed to update target file";
+                "from" => &media_file.target_file,
+                "to" => &to,
+                "mediafile_id" => media_file.id
+            );
+            return;
+        }
+
+        if library.id == media_file.library_id {
+            return;
+        }
+
+        if let Err(e) = MediaFile::move_to_library(&self.conn, media_file.id, library.id).await {
+            error!(
+                self.logger,
+                "Failed to move mediafile into another library";
+                "mediafile_id" => media_file.id,
+                "library_id" => library.id,
+                "reason" => e.to_string(),
+            );
+            return;
+        }
+
+        if let Some(media_id) = media_file.media_id {
+            super::purge_ghost_media(&self.conn, &self.logger, media_id).await;
+        }
+
+        if let Ok(mfile) = MediaFile::get_one(&self.conn, media_file.id).await {
+            let _in_flight = InFlight::begin();
+            self.match_file(mfile, library.media_type, Arc::new(library.settings))
+                .await;
+        }
+    }
+
+    /// Method returns the library that owns `path`. Overlapping libraries are resolved the same
+    /// way as during scans, following the `overlap_owner` setting.
+    async fn library_of(&self, path: &str) -> Option<Library> {
+        let libraries = Library::get_all(&self.conn).await;
+        let roots = super::library_roots(&libraries);
+        let rule = get_global_settings().overlap_owner;
+        let owner = super::owning_library(&roots, Path::new(path), rule)?;
+
+        libraries.into_iter().find(|x| x.id == owner)
+    }
+
+    async fn match_file(
+        &self,
+        mfile: MediaFile,
+        media_type: MediaType,
+        settings: Arc<LibrarySettings>,
+    ) {
+        let matcher = super::get_matcher(&self.logger, &self.tx);
+
+        match media_type {
+            MediaType::Movie => {
+                let _ = matcher.match_movie(mfile, settings).await;
+            }
+            MediaType::Tv => {
+                let _ = matcher.match_tv(mfile, settings).await;
+            }
+            MediaType::Music => {
+                let _ = matcher.match_music(mfile, settings).await;
             }
+            _ => unreachable!(),
         }
     }
 }
@@ -0,0 +1,158 @@
+//! Self test which runs a tiny generated media file through every stage of the scanner pipeline,
+//! so that broken ffmpeg installs, database or network issues show up before a real scan fails.
+use super::tmdb::Tmdb;
+use crate::streaming::ffprobe::FFProbeCtx;
+use crate::streaming::FFMPEG_BIN;
+use crate::streaming::FFPROBE_BIN;
+
+use database::library::LibrarySettings;
+use database::library::MediaType;
+
+use serde::Serialize;
+use slog::Logger;
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+use tokio::task::spawn_blocking;
+
+use torrent_name_parser::Metadata;
+
+/// Name of the fixture we generate, picked so that it is guaranteed to match on TMDB.
+const FIXTURE_NAME: &str = "The Matrix (1999).mkv";
+
+/// Outcome of a single stage of the self test.
+#[derive(Debug, Serialize)]
+pub struct StageReport {
+    pub stage: &'static str,
+    pub passed: bool,
+    pub detail: String,
+}
+
+/// Function runs the self test and returns a report for every stage of the pipeline, in the order
+/// they ran in. Stages dont depend on each other passing, thus a single run reports every issue.
+pub async fn self_test(log: &Logger) -> Vec<StageReport> {
+    let dir = std::env::temp_dir().join("dim_self_test");
+    let fixture = dir.join(FIXTURE_NAME);
+
+    let report = vec![
+        stage("fixture", create_fixture(&dir, &fixture).await),
+        stage("enumeration", enumerate(&dir, &fixture).await),
+        stage("ffprobe", probe(&fixture).await),
+        stage("parsing", parse(&fixture)),
+        stage("database", database(log).await),
+        stage("matching", matching().await),
+    ];
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    report
+}
+
+fn stage(stage: &'static str, result: Result<String, String>) -> StageReport {
+    let (passed, detail) = match result {
+        Ok(x) => (true, x),
+        Err(x) => (false, x),
+    };
+
+    StageReport {
+        stage,
+        passed,
+        detail,
+    }
+}
+
+async fn create_fixture(dir: &Path, fixture: &Path) -> Result<String, String> {
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+
+    let fixture = fixture.to_path_buf();
+    let output = spawn_blocking(move || {
+        Command::new(*FFMPEG_BIN)
+            .args(&["-y", "-v", "quiet", "-f", "lavfi", "-i"])
+            .arg("testsrc=duration=1:size=128x72:rate=1")
+            .args(&["-c:v", "mpeg4"])
+            .arg(&fixture)
+            .status()
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("could not run {} ({})", *FFMPEG_BIN, e))?;
+
+    if !output.success() {
+        return Err(format!("{} exited with {}", *FFMPEG_BIN, output));
+    }
+
+    Ok(format!("generated {}", FIXTURE_NAME))
+}
+
+async fn enumerate(dir: &Path, fixture: &Path) -> Result<String, String> {
+    let dir = dir.to_path_buf();
+    let files: Vec<PathBuf> = spawn_blocking(move || {
+        super::walk_directory(
+            &dir,
+            &LibrarySettings::default(),
+            MediaType::Movie,
+            &super::IgnorePatterns::none(),
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if !files.iter().any(|x| x == fixture) {
+        return Err("the fixture was not picked up by the directory walker".into());
+    }
+
+    Ok(format!("found {} file(s)", files.len()))
+}
+
+async fn probe(fixture: &Path) -> Result<String, String> {
+    let fixture = fixture.to_path_buf();
+    let data = spawn_blocking(move || FFProbeCtx::new(&FFPROBE_BIN).get_meta(&fixture))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("could not run {} ({})", *FFPROBE_BIN, e))?;
+
+    let codec = data
+        .get_video_codec()
+        .ok_or_else(|| "no video stream found".to_string())?;
+
+    Ok(format!("found a {} video stream", codec))
+}
+
+fn parse(fixture: &Path) -> Result<String, String> {
+    let name = fixture
+        .file_stem()
+        .and_then(|x| x.to_str())
+        .ok_or_else(|| "fixture has no filename".to_string())?;
+
+    let metadata =
+        Metadata::from(name).map_err(|_| "the filename parser returned an error".to_string())?;
+
+    if metadata.title() != "The Matrix" || metadata.year() != Some(1999) {
+        return Err(format!(
+            "parsed {:?} ({:?}) out of {}",
+            metadata.title(),
+            metadata.year(),
+            name
+        ));
+    }
+
+    Ok(format!("parsed {} out of {}", metadata.title(), name))
+}
+
+async fn database(log: &Logger) -> Result<String, String> {
+    super::get_conn_with_retry(log)
+        .await
+        .map(|_| "acquired a connection".into())
+        .map_err(|e| e.to_string())
+}
+
+async fn matching() -> Result<String, String> {
+    let mut tmdb = Tmdb::new("38c372f5bc572c8aadde7a802638534e".into(), MediaType::Movie);
+
+    tmdb.search("The Matrix".into(), Some(1999))
+        .await
+        .map(|x| format!("matched to {} (tmdb id {})", x.title, x.id))
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,189 @@
+//! Helpers used to find the external subtitles of a video. Subtitles are either kept beside the
+//! video, ie `Movie.en.srt`, or in a `Subs` folder next to it, either sorted by language, ie
+//! `Subs/English/2_English.srt`, or by episode, ie `Subs/Show.S01E01/2_English.srt`.
+use super::filename;
+use super::SUPPORTED_EXTS;
+
+use serde::Serialize;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Extensions of subtitle files we pick up.
+const SUBTITLE_EXTS: &[&str] = &["srt", "vtt", "ass", "ssa", "sub"];
+
+/// Names of the folders subtitles are kept in, matched ignoring case.
+const SUBTITLE_DIRS: &[&str] = &["subs", "subtitles"];
+
+/// Tags in the name of a subtitle file that describe the subtitles rather than their language.
+const SUBTITLE_TAGS: &[&str] = &["forced", "sdh", "cc", "hi"];
+
+/// Struct describes a subtitle file that belongs to a video.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ExternalSubtitle {
+    pub path: String,
+    /// Language of the subtitles as found in the name of the file or of its folder, ie `English`
+    /// or `en`.
+    pub language: Option<String>,
+    /// Whether the subtitles are forced, ie `Movie.es.forced.srt`, which means they only cover
+    /// foreign dialogue.
+    pub forced: bool,
+}
+
+/// Function returns the entries of `dir` split into files and folders.
+fn read_dir(dir: &Path) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(x) => x
+            .filter_map(Result::ok)
+            .map(|x| x.path())
+            .collect::<Vec<_>>(),
+        Err(_) => return Default::default(),
+    };
+
+    entries.into_iter().partition(|x| x.is_file())
+}
+
+fn has_ext(path: &Path, exts: &[&str]) -> bool {
+    path.extension()
+        .and_then(|x| x.to_str())
+        .map_or(false, |ext| {
+            exts.iter().any(|x| ext.eq_ignore_ascii_case(x))
+        })
+}
+
+fn file_stem(path: &Path) -> &str {
+    path.file_stem()
+        .and_then(|x| x.to_str())
+        .unwrap_or_default()
+}
+
+/// Function checks whether `name` is named after the video `stem`, ie `Movie.en` after `Movie`,
+/// but not `Movie 2` after `Movie`. The name of the video has to be followed by a separator.
+fn named_after(name: &str, stem: &str) -> bool {
+    match name.strip_prefix(stem) {
+        Some(rest) => rest.is_empty() || rest.starts_with(&['.', '_', '-', ' '][..]),
+        None => false,
+    }
+}
+
+/// Function returns the language tagged at the end of the name of a subtitle file, ie
+/// `2_English.srt` or `Movie.en.srt`. Names made up of a single word, which is usually the title,
+/// carry no language unless `standalone` is set.
+fn language_from_name(stem: &str, standalone: bool) -> Option<String> {
+    let tokens = filename::tokens(stem).collect::<Vec<_>>();
+
+    if tokens.len() < 2 && !standalone {
+        return None;
+    }
+
+    tokens
+        .iter()
+        .rev()
+        .find(|x| !SUBTITLE_TAGS.iter().any(|y| x.eq_ignore_ascii_case(y)))
+        .filter(|x| x.chars().all(char::is_alphabetic))
+        .map(|x| x.to_string())
+}
+
+/// Function returns the language tagged in what is left of the name of a subtitle file once the
+/// name of its video is stripped, ie `es.forced` for `Movie.es.forced.srt`.
+fn language_from_tag(tag: &str) -> Option<String> {
+    filename::tokens(tag)
+        .find(|x| {
+            x.chars().all(char::is_alphabetic)
+                && !SUBTITLE_TAGS.iter().any(|y| x.eq_ignore_ascii_case(y))
+        })
+        .map(ToOwned::to_owned)
+}
+
+fn subtitle(path: &Path, language: Option<String>) -> ExternalSubtitle {
+    ExternalSubtitle {
+        path: path.to_string_lossy().to_string(),
+        language,
+        forced: filename::tokens(file_stem(path)).any(|x| x.eq_ignore_ascii_case("forced")),
+    }
+}
+
+/// Function returns the external subtitles of `video`. Subtitles in a `Subs` folder that is shared
+/// by several videos are associated with the video they are named after, or with the only video
+/// in the folder.
+pub fn find_external_subtitles(video: &Path) -> Vec<ExternalSubtitle> {
+    let (dir, stem) = match (video.parent(), video.file_stem().and_then(|x| x.to_str())) {
+        (Some(dir), Some(stem)) => (dir, stem),
+        _ => return Vec::new(),
+    };
+
+    let (files, dirs) = read_dir(dir);
+    let belongs_to_video = |path: &Path| named_after(file_stem(path), stem);
+    let only_video = files.iter().filter(|x| has_ext(x, SUPPORTED_EXTS)).count() <= 1;
+
+    let mut subtitles = files
+        .iter()
+        .filter(|x| has_ext(x, SUBTITLE_EXTS) && belongs_to_video(x))
+        .map(|x| subtitle(x, language_from_tag(&file_stem(x)[stem.len()..])))
+        .collect::<Vec<_>>();
+
+    let subs_dirs = dirs.iter().filter(|x| {
+        x.file_name()
+            .and_then(|x| x.to_str())
+            .map_or(false, |name| {
+                SUBTITLE_DIRS.iter().any(|x| name.eq_ignore_ascii_case(x))
+            })
+    });
+
+    for subs_dir in subs_dirs {
+        let (files, dirs) = read_dir(subs_dir);
+
+        for file in files.iter().filter(|x| has_ext(x, SUBTITLE_EXTS)) {
+            if only_video || belongs_to_video(file) {
+                subtitles.push(subtitle(file, language_from_name(file_stem(file), false)));
+            }
+        }
+
+        for sub_dir in dirs {
+            let name = sub_dir
+                .file_name()
+                .and_then(|x| x.to_str())
+                .unwrap_or_default();
+
+            // NOTE: Season packs keep a folder per episode named after the video, otherwise the
+            // folder is named after the language of the subtitles it holds.
+            let is_episode_dir = name == stem;
+            let (files, _) = read_dir(&sub_dir);
+
+            for file in files.iter().filter(|x| has_ext(x, SUBTITLE_EXTS)) {
+                if is_episode_dir {
+                    subtitles.push(subtitle(file, language_from_name(file_stem(file), true)));
+                } else if only_video || belongs_to_video(file) {
+                    subtitles.push(subtitle(file, Some(name.to_owned())));
+                }
+            }
+        }
+    }
+
+    subtitles
+}
+
+/// Function returns the subtitle files kept beside the videos in `videos` that are not named
+/// after any of them, and thus cant be associated with a video. This reads every folder holding a
+/// video, so it should be run on a blocking thread.
+pub fn orphaned_subtitles(videos: &[PathBuf]) -> Vec<PathBuf> {
+    let mut dirs: HashMap<&Path, Vec<&str>> = HashMap::new();
+
+    for video in videos {
+        if let Some(dir) = video.parent() {
+            dirs.entry(dir).or_default().push(file_stem(video));
+        }
+    }
+
+    let mut orphans = Vec::new();
+    for (dir, stems) in dirs {
+        let (files, _) = read_dir(dir);
+
+        orphans.extend(files.into_iter().filter(|x| {
+            has_ext(x, SUBTITLE_EXTS) && !stems.iter().any(|y| named_after(file_stem(x), y))
+        }));
+    }
+
+    orphans
+}
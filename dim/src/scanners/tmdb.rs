@@ -1,13 +1,22 @@
 pub(crate) use database::library::MediaType;
+use database::library::TvAgent;
+
+use super::agent::ExternalIdSource;
+use crate::get_global_settings;
 use serde::Deserialize;
 use serde::Serialize;
 
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
 use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
 
+use reqwest::header::RETRY_AFTER;
 use reqwest::Client;
 use reqwest::ClientBuilder;
+use reqwest::RequestBuilder;
+use reqwest::Response;
 use reqwest::StatusCode;
 
 use err_derive::Error;
@@ -17,6 +26,9 @@ use tokio::sync::RwLock;
 
 use async_recursion::async_recursion;
 
+use chrono::prelude::Utc;
+use chrono::Datelike;
+
 static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
 
 #[derive(Debug, Error, Serialize)]
@@ -31,6 +43,165 @@ pub enum TmdbError {
     DeserializationError,
     #[error(display = "No results are found")]
     NoResults,
+    #[error(display = "The local TMDB dump could not be queried")]
+    DumpError,
+    #[error(display = "TMDB returned a server error")]
+    ServerError,
+    #[error(display = "TMDB is rate limiting us")]
+    RateLimited(Option<u64>),
+}
+
+impl TmdbError {
+    pub(super) fn from_reqwest(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            Self::Timeout
+        } else {
+            Self::ReqwestError
+        }
+    }
+
+    /// Method returns whether the request that failed with this error is worth retrying, ie
+    /// because of a network blip, as opposed to TMDB not knowing what we searched for.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Self::Timeout
+                | Self::ReachedMaxTries
+                | Self::ReqwestError
+                | Self::ServerError
+                | Self::RateLimited(_)
+        )
+    }
+
+    /// Method returns how long TMDB asked us to wait before retrying, taken from the
+    /// `Retry-After` header of a rate limited response.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::RateLimited(secs) => secs.map(Duration::from_secs),
+            _ => None,
+        }
+    }
+}
+
+/// Function turns rate limited responses and server errors into errors.
+pub(super) fn check_status(res: &Response) -> Result<(), TmdbError> {
+    if res.status() == StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = res
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.trim().parse().ok());
+
+        return Err(TmdbError::RateLimited(retry_after));
+    }
+
+    if res.status().is_server_error() {
+        return Err(TmdbError::ServerError);
+    }
+
+    Ok(())
+}
+
+type CacheKey = (String, Option<i32>, MediaType, String);
+type CacheStore = Arc<RwLock<HashMap<CacheKey, Vec<Media>>>>;
+type DetailsStore = Arc<RwLock<HashMap<(i32, MediaType, String), Media>>>;
+
+/// Number of requests TMDB lets us send within [`TMDB_RATE_WINDOW`].
+const TMDB_RATE_LIMIT: u32 = 40;
+
+/// Window TMDB rate limits requests over.
+const TMDB_RATE_WINDOW: Duration = Duration::from_secs(10);
+
+/// Token bucket every request to TMDB has to take a token from, so that matching files in parallel
+/// doesnt get us rate limited. Tokens are refilled steadily, allowing for bursts of up to the full
+/// limit.
+struct RateLimiter {
+    /// Tokens left in the bucket and when we last refilled it.
+    state: tokio::sync::Mutex<(f64, Instant)>,
+    capacity: f64,
+    per_sec: f64,
+}
+
+impl RateLimiter {
+    fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            state: tokio::sync::Mutex::new((limit as f64, Instant::now())),
+            capacity: limit as f64,
+            per_sec: limit as f64 / window.as_secs_f64(),
+        }
+    }
+
+    /// Method waits until a token is available and takes it.
+    async fn acquire(&self) {
+        self.wait(true).await
+    }
+
+    /// Method waits until a token is available without taking it.
+    async fn ready(&self) {
+        self.wait(false).await
+    }
+
+    async fn wait(&self, take: bool) {
+        loop {
+            let wait = {
+                let mut lock = self.state.lock().await;
+                let (tokens, refilled) = &mut *lock;
+
+                let now = Instant::now();
+                *tokens = (*tokens + now.duration_since(*refilled).as_secs_f64() * self.per_sec)
+                    .min(self.capacity);
+                *refilled = now;
+
+                if *tokens >= 1.0 {
+                    if take {
+                        *tokens -= 1.0;
+                    }
+                    return;
+                }
+
+                Duration::from_secs_f64((1.0 - *tokens) / self.per_sec)
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Language metadata is fetched in unless a library asks for another one.
+const DEFAULT_LANGUAGE: &str = "en-US";
+
+lazy_static::lazy_static! {
+    static ref SEARCH_CACHE: CacheStore = Arc::new(RwLock::new(HashMap::new()));
+    static ref DETAILS_CACHE: DetailsStore = Arc::new(RwLock::new(HashMap::new()));
+    static ref RATE_LIMITER: RateLimiter = RateLimiter::new(TMDB_RATE_LIMIT, TMDB_RATE_WINDOW);
+}
+
+/// Function waits until TMDB would take another request from us, without using up the request.
+pub async fn wait_for_rate_limit() {
+    RATE_LIMITER.ready().await;
+}
+
+/// Function returns the number of cached search results and roughly how many bytes they take up.
+pub async fn search_cache_usage() -> (usize, u64) {
+    let lock = (*SEARCH_CACHE).read().await;
+
+    let details = (*DETAILS_CACHE).read().await;
+
+    let bytes = lock
+        .iter()
+        .map(|((title, _, _, _), results)| {
+            title.len() + results.iter().map(Media::approximate_size).sum::<usize>()
+        })
+        .chain(details.values().map(Media::approximate_size))
+        .sum::<usize>();
+
+    (lock.len() + details.len(), bytes as u64)
+}
+
+/// Function drops every cached search result.
+pub async fn clear_search_cache() {
+    (*SEARCH_CACHE).write().await.clear();
+    (*DETAILS_CACHE).write().await.clear();
 }
 
 #[derive(Clone)]
@@ -39,6 +210,8 @@ pub struct Tmdb {
     client: Client,
     base: String,
     media_type: MediaType,
+    bypass_cache: bool,
+    language: Option<String>,
 }
 
 impl Tmdb {
@@ -50,9 +223,80 @@ impl Tmdb {
             client: client.build().unwrap(),
             base: "https://api.themoviedb.org/3".into(),
             media_type,
+            bypass_cache: false,
+            language: None,
+        }
+    }
+
+    /// Method makes titles, overviews and artwork come back in `language`, ie `de-DE`, instead of
+    /// english. Fields TMDB has no translation for can be filled in with their english version
+    /// with [`fill_untranslated`](Self::fill_untranslated). Languages that arent a
+    /// [language tag](is_language) are ignored.
+    pub fn language(mut self, language: Option<String>) -> Self {
+        self.language = language.filter(|x| is_language(x));
+        self
+    }
+
+    /// Method sends `req` to TMDB once our rate limiter lets us.
+    async fn send(&self, req: RequestBuilder) -> Result<Response, TmdbError> {
+        RATE_LIMITER.acquire().await;
+        req.send().await.map_err(TmdbError::from_reqwest)
+    }
+
+    /// Method returns the language we ask TMDB for.
+    fn lang(&self) -> String {
+        self.language
+            .clone()
+            .unwrap_or_else(|| DEFAULT_LANGUAGE.to_string())
+    }
+
+    /// Method returns the same agent asking for english metadata, used to fill in the fields that
+    /// werent translated.
+    fn english(&self) -> Option<Self> {
+        self.language.as_ref()?;
+
+        Some(Self {
+            language: None,
+            ..self.clone()
+        })
+    }
+
+    /// Method returns the english version of the media `id` if its `title` or `overview` werent
+    /// translated.
+    async fn english_of(&self, id: u64, title: &str, overview: &Option<String>) -> Option<Media> {
+        let untranslated = title.is_empty() || is_untranslated(overview);
+
+        match self.english() {
+            Some(mut x) if untranslated => x.search_by_id(id as i32).await.ok(),
+            _ => None,
         }
     }
 
+    /// Method fills in the title and overview of `media` with their english version if TMDB has
+    /// no translation of them. Every result filled in costs another request, so search results
+    /// are only filled in once we picked the one we match with.
+    pub async fn fill_untranslated(&self, media: &mut super::ApiMedia) {
+        if let Some(x) = self
+            .english_of(media.id, &media.title, &media.overview)
+            .await
+        {
+            if media.title.is_empty() {
+                media.title = x.title;
+            }
+
+            if is_untranslated(&media.overview) {
+                media.overview = x.overview;
+            }
+        }
+    }
+
+    /// Method makes every request go out to TMDB even if we have a cached response for it, ie when
+    /// the user asks for metadata to be refreshed. Fresh responses still end up in the cache.
+    pub fn bypass_cache(mut self) -> Self {
+        self.bypass_cache = true;
+        self
+    }
+
     pub async fn search(
         &mut self,
         title: String,
@@ -66,19 +310,28 @@ impl Tmdb {
             .ok_or(TmdbError::NoResults)
     }
 
+    #[async_recursion]
     pub async fn search_by_id(&mut self, id: i32) -> Result<Media, TmdbError> {
+        if !self.bypass_cache {
+            let lock = (*DETAILS_CACHE).read().await;
+
+            if let Some(x) = lock.get(&(id, self.media_type, self.lang())) {
+                return Ok(x.clone());
+            }
+        }
+
         let mut args: Vec<(String, String)> = Vec::new();
         args.push(("api_key".into(), self.api_key.clone()));
-        args.push(("language".into(), "en-US".into()));
+        args.push(("language".into(), self.lang()));
+        args.push((
+            "include_image_language".into(),
+            image_languages(&self.lang()),
+        ));
 
         let url = format!("{}/{}/{}", self.base, self.media_type.to_string(), id);
-        let req = self
-            .client
-            .get(url)
-            .query(&args)
-            .send()
-            .await
-            .map_err(|_| TmdbError::ReqwestError)?;
+        let req = self.send(self.client.get(url).query(&args)).await?;
+
+        check_status(&req)?;
 
         #[derive(Deserialize, Clone, Debug)]
         struct WMedia {
@@ -92,6 +345,8 @@ impl Tmdb {
             pub poster_path: Option<String>,
             pub backdrop_path: Option<String>,
             pub genres: Vec<GenrePair>,
+            pub tagline: Option<String>,
+            pub runtime: Option<u32>,
         }
 
         #[derive(Deserialize, Clone, Debug)]
@@ -105,7 +360,7 @@ impl Tmdb {
             .await
             .map_err(|_| TmdbError::DeserializationError)?;
 
-        Ok(Media {
+        let mut media = Media {
             id: result.id,
             title: result.title,
             release_date: result.release_date,
@@ -120,7 +375,73 @@ impl Tmdb {
                 .into_iter()
                 .map(|x| x.name)
                 .collect::<Vec<String>>(),
-        })
+            tagline: result.tagline.filter(|x| !x.is_empty()),
+            runtime: result.runtime.filter(|x| *x > 0),
+        };
+
+        if let Some(x) = self
+            .english_of(media.id, &media.title, &media.overview)
+            .await
+        {
+            if media.title.is_empty() {
+                media.title = x.title;
+            }
+
+            if is_untranslated(&media.overview) {
+                media.overview = x.overview;
+            }
+        }
+
+        {
+            let mut lock = (*DETAILS_CACHE).write().await;
+            lock.insert((id, self.media_type, self.lang()), media.clone());
+        }
+
+        Ok(media)
+    }
+
+    /// Method looks up the media whose id in the database `source` is `external_id`, ie the IMDB
+    /// id `tt1375666`, and returns its details.
+    pub async fn find_by_external_id(
+        &mut self,
+        external_id: &str,
+        source: ExternalIdSource,
+    ) -> Result<Media, TmdbError> {
+        let mut args: Vec<(String, String)> = Vec::new();
+        args.push(("api_key".into(), self.api_key.clone()));
+        args.push(("external_source".into(), source.as_str().into()));
+
+        let url = format!("{}/find/{}", self.base, external_id);
+        let req = self.send(self.client.get(url).query(&args)).await?;
+
+        check_status(&req)?;
+
+        #[derive(Deserialize, Clone, Debug)]
+        struct FindResult {
+            #[serde(default)]
+            movie_results: Vec<FindEntry>,
+            #[serde(default)]
+            tv_results: Vec<FindEntry>,
+        }
+
+        #[derive(Deserialize, Clone, Debug)]
+        struct FindEntry {
+            pub id: u64,
+        }
+
+        let result = req
+            .json::<FindResult>()
+            .await
+            .map_err(|_| TmdbError::DeserializationError)?;
+
+        let results = match self.media_type {
+            MediaType::Tv => result.tv_results,
+            _ => result.movie_results,
+        };
+
+        let id = results.first().ok_or(TmdbError::NoResults)?.id;
+
+        self.search_by_id(id as i32).await
     }
 
     #[async_recursion]
@@ -130,16 +451,9 @@ impl Tmdb {
         year: Option<i32>,
         max_tries: Option<usize>,
     ) -> Result<Vec<Media>, TmdbError> {
-        type CacheKey = (String, Option<i32>, MediaType);
-        type CacheStore = Arc<RwLock<HashMap<CacheKey, Vec<Media>>>>;
-
-        lazy_static::lazy_static! {
-            static ref __CACHE: CacheStore = Arc::new(RwLock::new(HashMap::new()));
-        }
-
-        {
-            let lock = (*__CACHE).read().await;
-            let key = (title.clone(), year, self.media_type);
+        if !self.bypass_cache {
+            let lock = (*SEARCH_CACHE).read().await;
+            let key = (title.clone(), year, self.media_type, self.lang());
 
             if let Some(x) = lock.get(&key) {
                 return Ok(x.to_vec());
@@ -154,7 +468,7 @@ impl Tmdb {
 
         let mut args: Vec<(String, String)> = Vec::new();
         args.push(("api_key".into(), self.api_key.clone()));
-        args.push(("language".into(), "en-US".into()));
+        args.push(("language".into(), self.lang()));
         args.push(("query".into(), title.clone()));
         args.push(("page".into(), "1".into()));
         args.push(("include_adult".into(), "false".into()));
@@ -165,17 +479,16 @@ impl Tmdb {
 
         let url = format!("{}/search/{}", self.base, self.media_type.to_string(),);
 
-        let req = self
-            .client
-            .get(url)
-            .query(&args)
-            .send()
-            .await
-            .map_err(|_| TmdbError::ReqwestError)?;
+        let req = self.send(self.client.get(url).query(&args)).await?;
+
+        if let Err(e) = check_status(&req) {
+            if matches!(e, TmdbError::RateLimited(_)) {
+                let wait = e.retry_after().unwrap_or(Duration::from_millis(1000));
+                tokio::time::sleep(wait).await;
+                return self.search_by_name(title, year, Some(max_tries - 1)).await;
+            }
 
-        if matches!(req.status(), StatusCode::TOO_MANY_REQUESTS) {
-            tokio::time::sleep(Duration::from_millis(1000)).await;
-            return self.search_by_name(title, year, Some(max_tries - 1)).await;
+            return Err(e);
         }
 
         let mut result: Vec<Media> = req
@@ -188,6 +501,7 @@ impl Tmdb {
             .collect();
 
         for media in result.iter_mut() {
+            // NOTE: Genres are shared by every library, thus we keep their names in english.
             let ids = media.genre_ids.clone().unwrap_or_default();
             media.genres = stream::iter(ids)
                 .filter_map(|x| {
@@ -197,6 +511,8 @@ impl Tmdb {
                         client: client.build().unwrap(),
                         base: self.base.clone(),
                         media_type: self.media_type.clone(),
+                        bypass_cache: self.bypass_cache,
+                        language: None,
                     };
 
                     async move { this.get_genre_detail(x).await.ok().map(|x| x.name.clone()) }
@@ -206,8 +522,8 @@ impl Tmdb {
         }
 
         {
-            let mut lock = (*__CACHE).write().await;
-            let key = (title.clone(), year, self.media_type);
+            let mut lock = (*SEARCH_CACHE).write().await;
+            let key = (title.clone(), year, self.media_type, self.lang());
             lock.insert(key, result.clone());
         }
 
@@ -217,14 +533,17 @@ impl Tmdb {
     pub async fn get_seasons_for(&mut self, id: u64) -> Result<Vec<Season>, TmdbError> {
         let mut args: Vec<(String, String)> = Vec::new();
         args.push(("api_key".into(), self.api_key.clone()));
+        args.push(("language".into(), self.lang()));
 
         let req = self
-            .client
-            .get(format!("{}/tv/{}", self.base, id))
-            .query(&args)
-            .send()
-            .await
-            .map_err(|_| TmdbError::ReqwestError)?;
+            .send(
+                self.client
+                    .get(format!("{}/tv/{}", self.base, id))
+                    .query(&args),
+            )
+            .await?;
+
+        check_status(&req)?;
 
         #[derive(Deserialize)]
         struct Wrapper {
@@ -238,6 +557,55 @@ impl Tmdb {
             .ok_or(TmdbError::NoResults)
     }
 
+    /// Method returns the range of years a tv show aired in. Shows that are still in production
+    /// are assumed to be airing up until the current year.
+    pub async fn get_air_years(
+        &mut self,
+        id: u64,
+    ) -> Result<Option<RangeInclusive<i64>>, TmdbError> {
+        let mut args: Vec<(String, String)> = Vec::new();
+        args.push(("api_key".into(), self.api_key.clone()));
+
+        let req = self
+            .send(
+                self.client
+                    .get(format!("{}/tv/{}", self.base, id))
+                    .query(&args),
+            )
+            .await?;
+
+        check_status(&req)?;
+
+        #[derive(Deserialize)]
+        struct Wrapper {
+            first_air_date: Option<String>,
+            last_air_date: Option<String>,
+            #[serde(default)]
+            in_production: bool,
+        }
+
+        let result = req
+            .json::<Wrapper>()
+            .await
+            .map_err(|_| TmdbError::DeserializationError)?;
+
+        let year = |x: Option<String>| x.as_deref().and_then(super::filename::year_from_date);
+
+        let first = match year(result.first_air_date) {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+
+        let last = if result.in_production {
+            Utc::now().year() as i64
+        } else {
+            year(result.last_air_date).unwrap_or(first)
+        };
+
+        Ok(Some(first..=last.max(first)))
+    }
+
+    #[async_recursion]
     pub async fn get_episodes_for(
         &mut self,
         id: u64,
@@ -245,25 +613,54 @@ impl Tmdb {
     ) -> Result<Vec<Episode>, TmdbError> {
         let mut args: Vec<(String, String)> = Vec::new();
         args.push(("api_key".into(), self.api_key.clone()));
+        args.push(("language".into(), self.lang()));
 
         let req = self
-            .client
-            .get(format!("{}/tv/{}/season/{}", self.base, id, season))
-            .query(&args)
-            .send()
-            .await
-            .map_err(|_| TmdbError::ReqwestError)?;
+            .send(
+                self.client
+                    .get(format!("{}/tv/{}/season/{}", self.base, id, season))
+                    .query(&args),
+            )
+            .await?;
+
+        check_status(&req)?;
 
         #[derive(Deserialize)]
         struct Wrapper {
             episodes: Option<Vec<Episode>>,
         }
 
-        req.json::<Wrapper>()
+        let mut episodes = req
+            .json::<Wrapper>()
             .await
             .map_err(|_| TmdbError::DeserializationError)?
             .episodes
-            .ok_or(TmdbError::NoResults)
+            .ok_or(TmdbError::NoResults)?;
+
+        let missing = episodes.iter().any(|x| is_untranslated(&x.overview));
+
+        if let Some(mut english) = self.english().filter(|_| missing) {
+            let english = english
+                .get_episodes_for(id, season)
+                .await
+                .unwrap_or_default();
+
+            for episode in episodes.iter_mut() {
+                if !is_untranslated(&episode.overview) {
+                    continue;
+                }
+
+                if let Some(x) = english.iter().find(|x| x.id == episode.id) {
+                    episode.overview = x.overview.clone();
+
+                    if episode.name.is_none() {
+                        episode.name = x.name.clone();
+                    }
+                }
+            }
+        }
+
+        Ok(episodes)
     }
 
     pub async fn get_genre_detail(&mut self, genre_id: u64) -> Result<Genre, TmdbError> {
@@ -284,13 +681,9 @@ impl Tmdb {
         args.push(("api_key".into(), self.api_key.clone()));
 
         let url = format!("{}/genre/{}/list", self.base.clone(), self.media_type);
-        let req = self
-            .client
-            .get(url)
-            .query(&args)
-            .send()
-            .await
-            .map_err(|_| TmdbError::ReqwestError)?;
+        let req = self.send(self.client.get(url).query(&args)).await?;
+
+        check_status(&req)?;
 
         #[derive(Deserialize)]
         struct Wrapper {
@@ -364,17 +757,83 @@ pub struct Media {
     pub genre_ids: Option<Vec<u64>>,
     #[serde(skip_deserializing)]
     pub genres: Vec<String>,
+    /// Only returned when we fetch the details of a movie.
+    #[serde(skip_deserializing)]
+    pub tagline: Option<String>,
+    /// Only returned when we fetch the details of a movie.
+    #[serde(skip_deserializing)]
+    pub runtime: Option<u32>,
+}
+
+impl Media {
+    fn approximate_size(&self) -> usize {
+        let strings = [
+            &self.release_date,
+            &self.overview,
+            &self.poster_path,
+            &self.backdrop_path,
+            &self.tagline,
+        ];
+
+        std::mem::size_of::<Self>()
+            + self.title.len()
+            + strings
+                .iter()
+                .filter_map(|x| x.as_ref())
+                .map(String::len)
+                .sum::<usize>()
+            + self.genres.iter().map(String::len).sum::<usize>()
+    }
+}
+
+/// Function returns whether TMDB left `field` blank, which it does for text it has no translation
+/// of.
+fn is_untranslated(field: &Option<String>) -> bool {
+    field.as_deref().map_or(true, |x| x.trim().is_empty())
+}
+
+/// Function returns whether `language` is a language tag TMDB understands, which is a lowercase
+/// language code optionally followed by a uppercase region code, ie `de` or `de-DE`.
+pub fn is_language(language: &str) -> bool {
+    let code = |x: &str, upper: bool| {
+        x.len() == 2
+            && x.chars()
+                .all(|x| x.is_ascii_alphabetic() && x.is_ascii_uppercase() == upper)
+    };
+
+    match language.split_once('-') {
+        Some((lang, region)) => code(lang, false) && code(region, true),
+        None => code(language, false),
+    }
+}
+
+/// Function returns the languages we accept artwork in when fetching metadata in `language`, ie
+/// `de-DE` yields `de,en,null`. Artwork without any text comes last.
+fn image_languages(language: &str) -> String {
+    let primary = language.split('-').next().unwrap_or(language);
+
+    match primary {
+        "en" => "en,null".to_string(),
+        x => format!("{},en,null", x),
+    }
+}
+
+/// Function returns the url of the TMDB image `path` at `size`, ie `w500` or `original`.
+fn image_url(size: &str, path: &str) -> String {
+    format!(
+        "https://image.tmdb.org/t/p/{}/{}",
+        size,
+        path.trim_start_matches('/')
+    )
 }
 
 impl From<Media> for super::ApiMedia {
     fn from(this: Media) -> Self {
-        let backdrop_path = this.backdrop_path.clone().map(|bp| {
-            if bp.starts_with('/') {
-                format!("https://image.tmdb.org/t/p/original/{}", bp)
-            } else {
-                format!("https://image.tmdb.org/t/p/original{}", bp)
-            }
-        });
+        let settings = get_global_settings();
+        let backdrop_path = this
+            .backdrop_path
+            .as_deref()
+            .map(|x| image_url(&settings.backdrop_size, x));
 
         Self {
             id: this.id,
@@ -383,14 +842,17 @@ impl From<Media> for super::ApiMedia {
             overview: this.overview,
             poster_path: this
                 .poster_path
-                .clone()
-                .map(|s| format!("https://image.tmdb.org/t/p/w600_and_h900_bestv2{}", s)),
+                .as_deref()
+                .map(|x| image_url(&settings.poster_size, x)),
             poster_file: this.poster_path,
             backdrop_path,
             backdrop_file: this.backdrop_path,
             genres: this.genres,
             rating: this.vote_average.map(|x| x as i32),
             seasons: Vec::new(),
+            tagline: this.tagline,
+            runtime: this.runtime,
+            agent: TvAgent::Tmdb,
         }
     }
 }
@@ -419,8 +881,8 @@ impl From<Season> for super::ApiSeason {
             name: this.name,
             poster_path: this
                 .poster_path
-                .clone()
-                .map(|s| format!("https://image.tmdb.org/t/p/w600_and_h900_bestv2{}", s)),
+                .as_deref()
+                .map(|x| image_url(&get_global_settings().poster_size, x)),
             poster_file: this.poster_path.clone(),
             season_number: this.season_number.unwrap_or(1),
             episodes: Vec::new(),
@@ -446,9 +908,10 @@ impl From<Episode> for super::ApiEpisode {
             episode: other.episode_number,
             still: other
                 .still_path
-                .clone()
-                .map(|s| format!("https://image.tmdb.org/t/p/w600_and_h900_bestv2{}", s)),
+                .as_deref()
+                .map(|x| image_url(&get_global_settings().poster_size, x)),
             still_file: other.still_path,
+            owned: false,
         }
     }
 }
@@ -459,6 +922,21 @@ mod tests {
 
     const API_KEY: &str = "38c372f5bc572c8aadde7a802638534e";
 
+    #[test]
+    fn test_is_language() {
+        assert!(is_language("de"));
+        assert!(is_language("de-DE"));
+        assert!(is_language("pt-BR"));
+
+        assert!(!is_language(""));
+        assert!(!is_language("DE"));
+        assert!(!is_language("de-de"));
+        assert!(!is_language("deu"));
+        assert!(!is_language("de-DE-x"));
+        assert!(!is_language("de_DE"));
+        assert!(!is_language("../de"));
+    }
+
     // #[test]
     // fn test_search_by_name() {
     //     let mut tmdb = Tmdb::new(API_KEY.to_string(), MediaType::Movie);
@@ -3,9 +3,14 @@ use database::genre::InsertableGenre;
 use database::genre::InsertableGenreMedia;
 use database::DbConnection;
 
+use database::episode::Episode;
 use database::episode::InsertableEpisode;
+use database::library::Library;
 use database::library::MediaType;
+use database::library::TvAgent;
+use database::match_cache::CachedMatch;
 use database::media::InsertableMedia;
+use database::media::Media;
 use database::mediafile::MediaFile;
 use database::mediafile::UpdateMediaFile;
 use database::movie::InsertableMovie;
@@ -16,6 +21,8 @@ use chrono::prelude::Utc;
 use chrono::Datelike;
 use chrono::NaiveDate;
 
+use std::sync::Arc;
+
 use slog::debug;
 use slog::error;
 use slog::warn;
@@ -26,6 +33,10 @@ use events::PushEventType;
 
 use crate::core::EventTx;
 use crate::fetcher::insert_into_queue;
+use crate::get_global_settings;
+use crate::scanners::artwork;
+use crate::scanners::artwork::ArtworkKind;
+use crate::scanners::tmdb::Tmdb;
 
 pub struct TvShowMatcher<'a> {
     pub conn: &'a DbConnection,
@@ -45,9 +56,24 @@ impl<'a> TvShowMatcher<'a> {
             .unwrap_or(None)
             .map(|s| s.year() as i64);
 
-        let poster_path = result.poster_path.clone();
+        // NOTE: Artwork kept next to the media wins over remote artwork unless the user told us
+        // otherwise, in which case it is only used when the remote has none.
+        let prefer_local = get_global_settings().prefer_local_artwork;
+        let dirs = artwork::artwork_dirs(&orphan.target_file, true);
+        let local_poster = artwork::find_sidecar(&dirs, ArtworkKind::Poster)
+            .filter(|_| prefer_local || result.poster_path.is_none());
+        let local_backdrop = artwork::find_sidecar(&dirs, ArtworkKind::Backdrop)
+            .filter(|_| prefer_local || result.backdrop_path.is_none());
+
+        let poster_path = result
+            .poster_path
+            .clone()
+            .filter(|_| local_poster.is_none());
 
-        let backdrop_path = result.backdrop_path.clone();
+        let backdrop_path = result
+            .backdrop_path
+            .clone()
+            .filter(|_| local_backdrop.is_none());
 
         if let Some(poster_path) = poster_path.as_ref() {
             let _ = insert_into_queue(self.log, poster_path.clone(), 3).await;
@@ -117,6 +143,16 @@ impl<'a> TvShowMatcher<'a> {
             None => None,
         };
 
+        let poster = match local_poster {
+            Some(image) => artwork::insert_sidecar(self.conn, self.log, &image).await,
+            None => poster,
+        };
+
+        let backdrop = match local_backdrop {
+            Some(image) => artwork::insert_sidecar(self.conn, self.log, &image).await,
+            None => backdrop,
+        };
+
         let media = InsertableMedia {
             name,
             year,
@@ -127,6 +163,8 @@ impl<'a> TvShowMatcher<'a> {
             poster,
             backdrop,
             media_type: MediaType::Tv,
+            external_id: Some(result.id as i64).filter(|_| result.agent == TvAgent::Tmdb),
+            tvdb_id: Some(result.id as i64).filter(|_| result.agent == TvAgent::Tvdb),
         };
 
         if let Err(e) = self.insert(orphan, media, result).await {
@@ -145,6 +183,7 @@ impl<'a> TvShowMatcher<'a> {
         media: InsertableMedia,
         result: super::ApiMedia,
     ) -> Result<(), super::base::ScannerError> {
+        let _writing = super::base::MATCH_WRITES.lock().await;
         let media_id = media.insert(&self.conn).await?;
         let _ = TVShow::insert(&self.conn, media_id).await;
 
@@ -158,14 +197,17 @@ impl<'a> TvShowMatcher<'a> {
             }
         }
 
-        let season = {
-            let orphan_season = orphan.season.unwrap_or(0) as u64;
+        // NOTE: Some shows are numbered differently on disk than on TMDB, ie because specials are
+        // counted as the first season, which the user can correct with a season offset.
+        let season_offset = TVShow::get_season_offset(self.conn, media_id)
+            .await
+            .unwrap_or(0);
+        let season_number = orphan.season.unwrap_or(0) + season_offset;
 
-            result
-                .seasons
-                .iter()
-                .find(|s| s.season_number == orphan_season)
-        };
+        let season = result
+            .seasons
+            .iter()
+            .find(|s| s.season_number as i64 == season_number);
 
         let poster_file = season.and_then(|x| x.poster_path.clone());
 
@@ -203,7 +245,7 @@ impl<'a> TvShowMatcher<'a> {
         };
 
         let insertable_season = InsertableSeason {
-            season_number: orphan.season.unwrap_or(0),
+            season_number,
             added: Utc::now().to_string(),
             poster: season_poster,
         };
@@ -216,12 +258,92 @@ impl<'a> TvShowMatcher<'a> {
             }
         };
 
+        debug!(
+            self.log,
+            "Inserting new episode";
+            "seasonid" => seasonid,
+            "episode" => orphan.episode.unwrap_or(0),
+            "target_file" => &orphan.target_file,
+        );
+
+        // NOTE: Clients want to know about new episodes of shows they already follow, but the
+        // first file of a show is already announced as a new card.
+        let episode_number = orphan.episode.unwrap_or(0);
+        let is_new_episode = Episode::get_numbers_of_tv(self.conn, media_id)
+            .await
+            .map(|x| !x.is_empty() && !x.contains(&(season_number, episode_number)))
+            .unwrap_or(false);
+
+        let episode_id = self
+            .insert_episode(orphan, season, result.agent, seasonid, episode_number)
+            .await?;
+
+        // NOTE: The score of a match is only recorded once the match went through.
+        let updated_mediafile = UpdateMediaFile {
+            media_id: Some(episode_id),
+            stale: Some(false),
+            match_score: orphan.match_score,
+            ..Default::default()
+        };
+
+        updated_mediafile.update(&self.conn, orphan.id).await?;
+
+        // NOTE: Files holding several episodes are matched with their first episode and linked to
+        // the episodes that follow. Links from an earlier match of the file are dropped first.
+        MediaFile::unlink_episodes(&self.conn, orphan.id).await?;
+
+        for number in (episode_number + 1)..=orphan.episode_end.unwrap_or(episode_number) {
+            match self
+                .insert_episode(orphan, season, result.agent, seasonid, number)
+                .await
+            {
+                Ok(x) => {
+                    MediaFile::link_episode(&self.conn, orphan.id, x).await?;
+                }
+                Err(e) => warn!(
+                    self.log,
+                    "Failed to insert episode of a multi-episode file";
+                    "episode" => number,
+                    "target_file" => &orphan.target_file,
+                    "reason" => e.to_string(),
+                ),
+            }
+        }
+
+        if is_new_episode {
+            let event = Message {
+                id: episode_id,
+                event_type: PushEventType::EventNewEpisode {
+                    lib_id: orphan.library_id,
+                    show_id: media_id,
+                    season: season_number,
+                    episode: episode_number,
+                },
+            };
+
+            let _ = self.event_tx.send(serde_json::to_string(&event).unwrap());
+        }
+
+        Ok(())
+    }
+
+    /// Method inserts the episode `episode_number` of the season `seasonid` along with its still,
+    /// and returns its id. The metadata of the episode is looked up in `season`, which `agent`
+    /// found.
+    async fn insert_episode(
+        &self,
+        orphan: &MediaFile,
+        season: Option<&super::ApiSeason>,
+        agent: TvAgent,
+        seasonid: i64,
+        episode_number: i64,
+    ) -> Result<i64, super::base::ScannerError> {
         let search_ep = {
-            let orphan_episode = orphan.episode.unwrap_or(0) as u64;
+            let episode_number = episode_number as u64;
             season.and_then(|x| {
                 x.episodes
                     .iter()
-                    .find(|&s| s.episode == Some(orphan_episode))
+                    .find(|&s| s.episode == Some(episode_number))
             })
         };
 
@@ -261,23 +383,15 @@ impl<'a> TvShowMatcher<'a> {
             None => None,
         };
 
-        debug!(
-            self.log,
-            "Inserting new episode";
-            "seasonid" => seasonid,
-            "episode" => orphan.episode.unwrap_or(0),
-            "target_file" => &orphan.target_file,
-        );
-
         let episode = InsertableEpisode {
-            episode: orphan.episode.unwrap_or(0),
+            episode: episode_number,
             seasonid,
             media: InsertableMedia {
                 library_id: orphan.library_id,
                 name: search_ep
                     .as_ref()
                     .and_then(|x| x.name.clone())
-                    .unwrap_or_else(|| orphan.episode.unwrap_or(0).to_string()),
+                    .unwrap_or_else(|| episode_number.to_string()),
                 added: Utc::now().to_string(),
                 media_type: MediaType::Episode,
                 description: search_ep
@@ -285,6 +399,12 @@ impl<'a> TvShowMatcher<'a> {
                     .map(|x| x.overview.clone())
                     .unwrap_or_default(),
                 backdrop,
+                external_id: search_ep
+                    .filter(|_| agent == TvAgent::Tmdb)
+                    .map(|x| x.id as i64),
+                tvdb_id: search_ep
+                    .filter(|_| agent == TvAgent::Tvdb)
+                    .map(|x| x.id as i64),
                 ..Default::default()
             },
         };
@@ -301,16 +421,7 @@ impl<'a> TvShowMatcher<'a> {
             );
         }
 
-        let episode_id = episode.insert(&self.conn).await?;
-
-        let updated_mediafile = UpdateMediaFile {
-            media_id: Some(episode_id),
-            ..Default::default()
-        };
-
-        updated_mediafile.update(&self.conn, orphan.id).await?;
-
-        Ok(())
+        episode.insert(&self.conn).await.map_err(Into::into)
     }
 
     async fn push_event(&self, id: i64, lib_id: i64) {
@@ -335,3 +446,124 @@ impl<'a> TvShowMatcher<'a> {
         let _ = self.event_tx.send(serde_json::to_string(&event).unwrap());
     }
 }
+
+/// Function returns the full season and episode structure of a matched tv show, including the
+/// episodes we dont have any files for. Every episode is flagged as owned if we have a file for
+/// it. When we may not query TMDB the seasons are taken from the match cache, or from the episodes
+/// we have if they arent cached.
+///
+/// # Arguments
+/// * `conn` - db connection
+/// * `media_id` - id of the tv show
+pub async fn get_show_structure(
+    conn: &DbConnection,
+    media_id: i64,
+) -> Result<super::ApiMedia, super::base::ScannerError> {
+    let mut tmdb = Tmdb::new("38c372f5bc572c8aadde7a802638534e".into(), MediaType::Tv);
+    let mut result = show_metadata(&mut tmdb, conn, media_id).await?;
+
+    let owned = Episode::get_numbers_of_tv(conn, media_id).await?;
+
+    if !super::base::online_matching() {
+        let cached = CachedMatch::get(conn, &CachedMatch::seasons_key(result.id))
+            .await
+            .ok()
+            .and_then(|x| serde_json::from_str::<Vec<super::ApiSeason>>(&x.media).ok());
+
+        result.seasons = match cached {
+            Some(x) => x,
+            None => super::export::export_seasons(conn, media_id).await?,
+        };
+    } else {
+        let mut seasons: Vec<super::ApiSeason> = tmdb
+            .get_seasons_for(result.id)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(Into::into)
+            .collect();
+
+        for season in seasons.iter_mut() {
+            season.episodes = tmdb
+                .get_episodes_for(result.id, season.season_number)
+                .await
+                .unwrap_or_default()
+                .into_iter()
+                .map(Into::into)
+                .collect();
+        }
+
+        result.seasons = seasons;
+    }
+
+    for season in result.seasons.iter_mut() {
+        for episode in season.episodes.iter_mut() {
+            episode.owned = episode.episode.map_or(false, |x| {
+                owned.contains(&(season.season_number as i64, x as i64))
+            });
+        }
+    }
+
+    Ok(result)
+}
+
+/// Function matches all files of a matched tv show again, ie after its season offset has been
+/// changed. The episodes and seasons that are left without files once every file was matched are
+/// dropped afterwards, files that fail to match keep their episode.
+///
+/// # Arguments
+/// * `conn` - db connection
+/// * `media_id` - id of the tv show
+pub async fn rematch_show(
+    conn: &DbConnection,
+    media_id: i64,
+) -> Result<(), super::base::ScannerError> {
+    let mut tmdb = Tmdb::new("38c372f5bc572c8aadde7a802638534e".into(), MediaType::Tv);
+    let result = show_metadata(&mut tmdb, conn, media_id).await?;
+    let library_id = Media::get(conn, media_id).await?.library_id;
+    let settings = Arc::new(Library::get_one(conn, library_id).await?.settings);
+
+    let mut files = Vec::new();
+
+    for episode in Episode::get_all_of_tv(conn, media_id).await? {
+        files.append(&mut MediaFile::get_of_media(conn, episode.id).await?);
+    }
+
+    // NOTE: Files holding several episodes are returned for every one of them.
+    files.sort_by_key(|x| x.id);
+    files.dedup_by_key(|x| x.id);
+
+    let matcher = super::get_matcher_unchecked();
+
+    for file in files {
+        matcher
+            .match_tv_to_result(file, result.clone(), settings.clone())
+            .await?;
+    }
+
+    Episode::delete_unused_of_tv(conn, media_id).await?;
+
+    Ok(())
+}
+
+/// Function looks up the metadata of a matched tv show. When we may not query TMDB the metadata
+/// we already have is returned instead.
+async fn show_metadata(
+    tmdb: &mut Tmdb,
+    conn: &DbConnection,
+    media_id: i64,
+) -> Result<super::ApiMedia, super::base::ScannerError> {
+    let media = Media::get(conn, media_id).await?;
+
+    if !super::base::online_matching() {
+        return super::export::export_metadata(conn, &media).await;
+    }
+
+    // NOTE: Shows matched before we kept track of external ids have to be looked up by name.
+    let result = match Media::get_external_id(conn, media_id).await? {
+        Some(id) => tmdb.search_by_id(id as i32).await.map(Into::into),
+        None => tmdb.search(media.name, media.year.map(|x| x as i32)).await,
+    };
+
+    result.map_err(|_| super::base::ScannerError::UnknownError)
+}
@@ -0,0 +1,278 @@
+//! Matching of tv shows against [TheTVDB](https://thetvdb.com), which tends to have far better
+//! episode data than TMDB for anime and long-running shows. Libraries opt into it with their
+//! [`TvAgent`](database::library::TvAgent) setting, TheTVDB requires a api key of its own which has
+//! to be configured as `tvdb_api_key`.
+//!
+//! Episodes are numbered in aired order, which is how most releases are named.
+//!
+//! NOTE: Only matching goes through TheTVDB, the full show structure and rematching of a show are
+//! still looked up on TMDB.
+use super::agent::MetadataAgent;
+use super::tmdb::check_status;
+use super::tmdb::TmdbError;
+use super::ApiEpisode;
+use super::ApiMedia;
+use super::ApiSeason;
+
+use database::library::TvAgent;
+
+use async_trait::async_trait;
+use reqwest::Client;
+use reqwest::ClientBuilder;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::RwLock;
+
+use std::collections::BTreeMap;
+
+static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
+
+lazy_static::lazy_static! {
+    /// Token we got from logging into TheTVDB. Tokens are valid for a month, so we only log in
+    /// again when TheTVDB rejects the one we have.
+    static ref TVDB_TOKEN: RwLock<Option<String>> = RwLock::new(None);
+}
+
+#[derive(Clone)]
+pub struct TvdbAgent {
+    api_key: String,
+    client: Client,
+    base: String,
+}
+
+impl TvdbAgent {
+    pub fn new(api_key: String) -> Self {
+        let client = ClientBuilder::new().user_agent(APP_USER_AGENT);
+
+        Self {
+            api_key,
+            client: client.build().unwrap(),
+            base: "https://api4.thetvdb.com/v4".into(),
+        }
+    }
+
+    /// Method returns the token we authenticate with, logging in if we dont have one yet or
+    /// `refresh` is set.
+    async fn token(&self, refresh: bool) -> Result<String, TmdbError> {
+        if !refresh {
+            if let Some(token) = TVDB_TOKEN.read().await.clone() {
+                return Ok(token);
+            }
+        }
+
+        #[derive(Serialize)]
+        struct Login<'a> {
+            apikey: &'a str,
+        }
+
+        #[derive(Deserialize)]
+        struct LoginData {
+            token: String,
+        }
+
+        let req = self
+            .client
+            .post(format!("{}/login", self.base))
+            .json(&Login {
+                apikey: &self.api_key,
+            })
+            .send()
+            .await
+            .map_err(TmdbError::from_reqwest)?;
+
+        check_status(&req)?;
+
+        let token = req
+            .json::<Response<LoginData>>()
+            .await
+            .map_err(|_| TmdbError::DeserializationError)?
+            .data
+            .token;
+
+        *TVDB_TOKEN.write().await = Some(token.clone());
+
+        Ok(token)
+    }
+
+    /// Method sends a authenticated `GET` request to `path` and deserializes the `data` field of
+    /// the response. If our token was rejected we log in again once.
+    async fn get<T>(&self, path: &str, args: &[(&str, String)]) -> Result<T, TmdbError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let mut refresh = false;
+
+        loop {
+            let token = self.token(refresh).await?;
+            let req = self
+                .client
+                .get(format!("{}/{}", self.base, path))
+                .bearer_auth(token)
+                .query(args)
+                .send()
+                .await
+                .map_err(TmdbError::from_reqwest)?;
+
+            if req.status() == StatusCode::UNAUTHORIZED && !refresh {
+                refresh = true;
+                continue;
+            }
+
+            if req.status() == StatusCode::NOT_FOUND {
+                return Err(TmdbError::NoResults);
+            }
+
+            check_status(&req)?;
+
+            return Ok(req
+                .json::<Response<T>>()
+                .await
+                .map_err(|_| TmdbError::DeserializationError)?
+                .data);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Response<T> {
+    data: T,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct SearchResult {
+    tvdb_id: String,
+    name: String,
+    first_air_time: Option<String>,
+    overview: Option<String>,
+    image_url: Option<String>,
+    #[serde(default)]
+    genres: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct EpisodePage {
+    episodes: Vec<Episode>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Episode {
+    id: u64,
+    name: Option<String>,
+    overview: Option<String>,
+    image: Option<String>,
+    number: Option<u64>,
+    season_number: Option<u64>,
+}
+
+/// Function returns the name of the file artwork at `url` is cached as, which is how the fetcher
+/// names the files it downloads.
+fn file_of(url: &str) -> Option<String> {
+    url.rsplit('/')
+        .next()
+        .filter(|x| !x.is_empty())
+        .map(ToOwned::to_owned)
+}
+
+impl From<SearchResult> for ApiMedia {
+    fn from(this: SearchResult) -> Self {
+        Self {
+            id: this.tvdb_id.parse().unwrap_or_default(),
+            title: this.name,
+            release_date: this.first_air_time,
+            overview: this.overview,
+            poster_file: this.image_url.as_deref().and_then(file_of),
+            poster_path: this.image_url,
+            backdrop_path: None,
+            backdrop_file: None,
+            genres: this.genres,
+            rating: None,
+            seasons: Vec::new(),
+            tagline: None,
+            runtime: None,
+            agent: TvAgent::Tvdb,
+        }
+    }
+}
+
+impl From<Episode> for ApiEpisode {
+    fn from(this: Episode) -> Self {
+        Self {
+            id: this.id,
+            name: this.name,
+            overview: this.overview,
+            episode: this.number,
+            still_file: this.image.as_deref().and_then(file_of),
+            still: this.image,
+            owned: false,
+        }
+    }
+}
+
+/// Number of episodes TheTVDB returns per page.
+const EPISODES_PER_PAGE: usize = 500;
+
+/// Maximum number of pages of episodes we fetch for a single show.
+const MAX_EPISODE_PAGES: usize = 10;
+
+#[async_trait]
+impl MetadataAgent for TvdbAgent {
+    async fn search(&mut self, title: String, year: Option<i32>) -> Result<ApiMedia, TmdbError> {
+        let mut args = vec![("query", title), ("type", "series".to_string())];
+
+        if let Some(year) = year {
+            args.push(("year", year.to_string()));
+        }
+
+        self.get::<Vec<SearchResult>>("search", &args)
+            .await?
+            .into_iter()
+            .find(|x| x.tvdb_id.parse::<u64>().is_ok())
+            .map(Into::into)
+            .ok_or(TmdbError::NoResults)
+    }
+
+    async fn seasons(&mut self, id: u64) -> Result<Vec<ApiSeason>, TmdbError> {
+        let mut seasons: BTreeMap<u64, Vec<ApiEpisode>> = BTreeMap::new();
+
+        // NOTE: The `default` season type is the aired order of the show.
+        for page in 0..MAX_EPISODE_PAGES {
+            let episodes = self
+                .get::<EpisodePage>(
+                    &format!("series/{}/episodes/default", id),
+                    &[("page", page.to_string())],
+                )
+                .await?
+                .episodes;
+
+            let last_page = episodes.len() < EPISODES_PER_PAGE;
+
+            for episode in episodes {
+                let season = episode.season_number.unwrap_or(1);
+                seasons.entry(season).or_default().push(episode.into());
+            }
+
+            if last_page {
+                break;
+            }
+        }
+
+        Ok(seasons
+            .into_iter()
+            .map(|(season_number, episodes)| ApiSeason {
+                // NOTE: Episodes dont tell us the id of their season, so we derive one that is
+                // unique within the show.
+                id: id * 1000 + season_number,
+                name: Some(match season_number {
+                    0 => "Specials".to_string(),
+                    x => format!("Season {}", x),
+                }),
+                poster_path: None,
+                poster_file: None,
+                season_number,
+                episodes,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,94 @@
+//! Coordinates shutting down while scans are running. Once a shutdown has been requested scans
+//! stop picking up new files, while the files that are already being mounted and matched are
+//! given some time to be committed, so that we dont exit in the middle of an insert.
+use slog::info;
+use slog::warn;
+use slog::Logger;
+
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How often we check whether all in-flight files have been committed.
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+/// Guard held for as long as a file is being mounted and matched. Shutdowns wait until every
+/// guard has been dropped or the drain timeout runs out.
+pub struct InFlight(());
+
+impl InFlight {
+    /// Method marks a file as in flight until the returned guard is dropped.
+    pub fn begin() -> Self {
+        IN_FLIGHT.fetch_add(1, Ordering::SeqCst);
+        Self(())
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Function returns whether a shutdown has been requested, in which case no new work should be
+/// started.
+pub fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::SeqCst)
+}
+
+/// Function resolves once we have been asked to shut down, either with ctrl-c or with `SIGTERM`.
+pub async fn signal() {
+    cfg_if::cfg_if! {
+        if #[cfg(unix)] {
+            use tokio::signal::unix::signal;
+            use tokio::signal::unix::SignalKind;
+
+            match signal(SignalKind::terminate()) {
+                Ok(mut sigterm) => {
+                    tokio::select! {
+                        _ = tokio::signal::ctrl_c() => {},
+                        _ = sigterm.recv() => {},
+                    }
+                }
+                Err(_) => {
+                    let _ = tokio::signal::ctrl_c().await;
+                }
+            }
+        } else {
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
+/// Function stops scans from picking up new files and waits up to `timeout` for the files that
+/// are in flight to be committed. Returns whether every in-flight file made it.
+pub async fn drain(log: &Logger, timeout: Duration) -> bool {
+    SHUTTING_DOWN.store(true, Ordering::SeqCst);
+
+    let in_flight = IN_FLIGHT.load(Ordering::SeqCst);
+    if in_flight > 0 {
+        info!(log, "Waiting for in-flight files to be committed"; "files" => in_flight);
+    }
+
+    let started = Instant::now();
+
+    while IN_FLIGHT.load(Ordering::SeqCst) > 0 {
+        if started.elapsed() >= timeout {
+            warn!(
+                log,
+                "Shutting down with files still in flight";
+                "files" => IN_FLIGHT.load(Ordering::SeqCst),
+            );
+            return false;
+        }
+
+        tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+    }
+
+    true
+}
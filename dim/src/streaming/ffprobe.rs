@@ -1,7 +1,15 @@
 use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::collections::VecDeque;
+use std::io::Read;
+use std::process::Stdio;
+use std::time::Duration;
+use std::time::Instant;
 use std::{path::Path, process::Command, str};
 
+/// Time a single probe may take before we give up on the file.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
 #[derive(Default, Debug, Clone, PartialEq)]
 pub struct FFPWrapper {
     ffpstream: Option<FFPStream>,
@@ -39,6 +47,11 @@ pub struct Stream {
     pub duration: Option<String>,
     pub color_range: Option<String>,
     pub color_space: Option<String>,
+    pub color_transfer: Option<String>,
+    pub color_primaries: Option<String>,
+    pub avg_frame_rate: Option<String>,
+    pub r_frame_rate: Option<String>,
+    pub bits_per_raw_sample: Option<String>,
     pub disposition: Option<Disposition>,
 }
 
@@ -95,6 +108,22 @@ pub struct Tags {
     statistics_tags_eng: Option<String>,
     filename: Option<String>,
     mimetype: Option<String>,
+    /// Any other tags of the stream, ie the vorbis comments of a ogg file.
+    #[serde(flatten)]
+    pub other: HashMap<String, String>,
+}
+
+impl Tags {
+    /// Method returns the tag `key`, ignoring case as the case of tags differs between formats.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        if key.eq_ignore_ascii_case("title") {
+            if let Some(x) = self.title.as_deref() {
+                return Some(x);
+            }
+        }
+
+        find_tag(&self.other, key)
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -108,48 +137,124 @@ struct Format {
     pub duration: String,
     pub size: String,
     pub bit_rate: String,
+    /// Tags of the file as a whole, ie the ID3 tags of a mp3.
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
 }
 
 pub struct FFProbeCtx {
     ffprobe_bin: String,
+    timeout: Duration,
 }
 
 impl FFProbeCtx {
     pub fn new(ffprobe_bin: &'static str) -> Self {
         Self {
             ffprobe_bin: ffprobe_bin.to_owned(),
+            timeout: DEFAULT_TIMEOUT,
         }
     }
 
+    /// Method sets the time a probe may take before ffprobe is killed.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Method runs ffprobe on `file`. If ffprobe doesnt finish within the timeout it is killed
+    /// and a error of kind [`TimedOut`](std::io::ErrorKind::TimedOut) is returned. Files ffprobe
+    /// fails on are reported as a error of kind [`InvalidData`](std::io::ErrorKind::InvalidData)
+    /// holding whatever ffprobe printed to stderr.
     pub fn get_meta(&self, file: &Path) -> Result<FFPWrapper, std::io::Error> {
-        let probe = Command::new(self.ffprobe_bin.clone())
+        let mut child = Command::new(self.ffprobe_bin.clone())
             .arg(file.to_str().unwrap())
             .arg("-v")
-            .arg("quiet")
+            .arg("error")
             .arg("-print_format")
             .arg("json")
             .arg("-show_streams")
             .arg("-show_format")
-            .output()?;
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // NOTE: The output has to be drained while we wait, otherwise ffprobe blocks once a pipe
+        // is full.
+        let stdout = drain(child.stdout.take().expect("stdout is piped"));
+        let stderr = drain(child.stderr.take().expect("stderr is piped"));
+
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
 
-        let json = String::from_utf8_lossy(probe.stdout.as_slice());
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
 
-        let de: FFPWrapper = serde_json::from_str(&json).map_or_else(
-            |_| FFPWrapper {
-                ffpstream: None,
-                corrupt: Some(true),
-            },
-            |x| FFPWrapper {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("ffprobe took longer than {}s", self.timeout.as_secs()),
+                ));
+            }
+
+            std::thread::sleep(Duration::from_millis(50));
+        };
+
+        let output = join_drain(stdout)?;
+        let errors = join_drain(stderr)?;
+
+        match serde_json::from_slice::<FFPStream>(&output) {
+            Ok(x) => Ok(FFPWrapper {
                 ffpstream: Some(x),
                 corrupt: None,
-            },
-        );
-
-        Ok(de)
+            }),
+            Err(_) if !status.success() => {
+                let errors = String::from_utf8_lossy(&errors).trim().to_owned();
+
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    if errors.is_empty() {
+                        format!("ffprobe exited with {}", status)
+                    } else {
+                        errors
+                    },
+                ))
+            }
+            Err(_) => Ok(FFPWrapper::corrupt()),
+        }
     }
 }
 
+/// Function reads `pipe` to the end on a thread of its own.
+fn drain<R: Read + Send + 'static>(
+    mut pipe: R,
+) -> std::thread::JoinHandle<Result<Vec<u8>, std::io::Error>> {
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        pipe.read_to_end(&mut buf).map(|_| buf)
+    })
+}
+
+/// Function returns what the thread spawned by [`drain`] read.
+fn join_drain(
+    handle: std::thread::JoinHandle<Result<Vec<u8>, std::io::Error>>,
+) -> Result<Vec<u8>, std::io::Error> {
+    handle
+        .join()
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "reader panicked"))?
+}
+
 impl FFPWrapper {
+    /// Method returns the probe results of a file ffprobe couldnt make sense of.
+    pub fn corrupt() -> Self {
+        Self {
+            ffpstream: None,
+            corrupt: Some(true),
+        }
+    }
+
     pub fn get_container(&self) -> Option<String> {
         if let Some(ctx) = self.ffpstream.clone() {
             Some(ctx.format.format_name)
@@ -158,23 +263,146 @@ impl FFPWrapper {
         }
     }
 
+    /// Method checks whether the container detected by ffprobe agrees with the file extension
+    /// `ext`. Returns `None` if ffprobe couldnt detect the container at all.
+    pub fn container_matches_ext(&self, ext: &str) -> Option<bool> {
+        let container = self.get_container()?;
+        let ext = ext.to_ascii_lowercase();
+
+        // NOTE: ffprobe reports a list of format names that share a demuxer, ie `matroska,webm`.
+        let formats: &[&str] = match ext.as_str() {
+            "mkv" | "mk3d" => &["matroska"],
+            "webm" => &["webm"],
+            "mp4" | "m4v" | "mov" => &["mp4", "mov"],
+            "m2ts" | "ts" => &["mpegts"],
+            "vob" | "mpg" | "mpeg" => &["mpeg"],
+            "opus" => &["ogg"],
+            other => return Some(container.split(',').any(|x| x == other)),
+        };
+
+        Some(container.split(',').any(|x| formats.contains(&x)))
+    }
+
     pub fn get_container_bitrate(&self) -> Option<u64> {
         self.ffpstream.as_ref()?.format.bit_rate.parse::<u64>().ok()
     }
 
     pub fn get_video_codec(&self) -> Option<String> {
-        Some(self.find_by_type("video").first()?.codec_name.clone())
+        Some(self.get_primary_video()?.codec_name.clone())
     }
 
     pub fn get_height(&self) -> Option<i64> {
-        self.find_by_type("video").first()?.height
+        self.get_primary_video()?.height
     }
 
     pub fn get_width(&self) -> Option<i64> {
-        self.find_by_type("video").first()?.width
+        self.get_primary_video()?.width
+    }
+
+    /// Method returns the average frame rate of the primary video stream. ffprobe reports frame
+    /// rates as fractions, ie `24000/1001`, and `0/0` when it doesnt know the frame rate.
+    pub fn get_frame_rate(&self) -> Option<f64> {
+        let video = self.get_primary_video()?;
+
+        [&video.avg_frame_rate, &video.r_frame_rate]
+            .iter()
+            .filter_map(|x| x.as_deref())
+            .find_map(parse_fraction)
+    }
+
+    /// Method returns the bit depth of the primary video stream. Not every decoder reports
+    /// `bits_per_raw_sample`, in which case we read it off the pixel format, ie `yuv420p10le`.
+    pub fn get_bit_depth(&self) -> Option<i64> {
+        let video = self.get_primary_video()?;
+
+        if let Some(x) = video
+            .bits_per_raw_sample
+            .as_deref()
+            .and_then(|x| x.parse::<i64>().ok())
+        {
+            return Some(x);
+        }
+
+        // NOTE: Pixel formats without a depth suffix, ie `yuv420p`, are 8 bit.
+        let pix_fmt = video.pix_fmt.as_deref()?;
+        match pix_fmt
+            .trim_end_matches("le")
+            .trim_end_matches("be")
+            .rsplit_once('p')?
+        {
+            (_, "") => Some(8),
+            (_, depth) => depth.parse::<i64>().ok(),
+        }
+    }
+
+    /// Method returns the HDR format of the primary video stream based on its color transfer,
+    /// `hdr10` for PQ (`smpte2084`) and `hlg` for `arib-std-b67`. Some encoders leave out the
+    /// color transfer, in which case 10 bit streams with `bt2020` primaries are taken for HDR10.
+    pub fn get_hdr(&self) -> Option<String> {
+        let video = self.get_primary_video()?;
+
+        match video.color_transfer.as_deref() {
+            Some("smpte2084") => Some("hdr10".into()),
+            Some("arib-std-b67") => Some("hlg".into()),
+            Some(_) => None,
+            None => match (video.color_primaries.as_deref(), self.get_bit_depth()) {
+                (Some("bt2020"), Some(x)) if x >= 10 => Some("hdr10".into()),
+                _ => None,
+            },
+        }
+    }
+
+    /// Method returns every audio stream of the file in the order they appear in.
+    pub fn get_audio_tracks(&self) -> Vec<AudioTrack> {
+        self.find_by_type("audio")
+            .into_iter()
+            .map(|x| AudioTrack {
+                index: x.index,
+                codec: x.codec_name.clone(),
+                channels: x.channels,
+                language: x.get_language(),
+                title: x.get_title(),
+                default: x.disposition.as_ref().map_or(false, |d| d.default == 1),
+            })
+            .collect()
+    }
+
+    /// Method returns every subtitle stream of the file in the order they appear in.
+    pub fn get_subtitle_tracks(&self) -> Vec<SubtitleTrack> {
+        self.find_by_type("subtitle")
+            .into_iter()
+            .map(|x| SubtitleTrack {
+                index: x.index,
+                format: x.codec_name.clone(),
+                language: x.get_language(),
+                title: x.get_title(),
+                default: x.disposition.as_ref().map_or(false, |d| d.default == 1),
+                forced: x.disposition.as_ref().map_or(false, |d| d.forced == 1),
+            })
+            .collect()
+    }
+
+    /// Method returns the main video stream of a file. Some files carry more than one video
+    /// stream, like embedded cover art or the enhancement layer of a Dolby Vision release, so we
+    /// skip over attached pictures and then pick the stream with the largest dimensions, falling
+    /// back to the default disposition when they are equally large.
+    pub fn get_primary_video(&self) -> Option<&Stream> {
+        self.find_by_type("video")
+            .into_iter()
+            .filter(|x| x.disposition.as_ref().map_or(true, |d| d.attached_pic == 0))
+            .max_by_key(|x| {
+                let area = x.width.unwrap_or(0) * x.height.unwrap_or(0);
+                let is_default = x.disposition.as_ref().map_or(0, |d| d.default);
+
+                (area, is_default, -x.index)
+            })
     }
 
     pub fn get_primary(&self, codec_type: &str) -> Option<&Stream> {
+        if codec_type == "video" {
+            return self.get_primary_video();
+        }
+
         let mut streams: VecDeque<_> = self.find_by_type(codec_type).into();
 
         if streams.is_empty() {
@@ -221,6 +449,32 @@ impl FFPWrapper {
             .ok()
     }
 
+    /// Method returns why a file that ffprobe could read cant be played as a video, if it cant.
+    /// Files without a video stream, not counting cover art, are `audio_only`, while files that
+    /// last less than a second are `zero_duration`.
+    pub fn get_problem(&self) -> Option<&'static str> {
+        if self.get_primary_video().is_none() {
+            return Some("audio_only");
+        }
+
+        match self.get_duration() {
+            Some(x) if x < 1 => Some("zero_duration"),
+            _ => None,
+        }
+    }
+
+    /// Method returns the tag `key` of the file, ie `album`, ignoring case. Most containers tag
+    /// the file as a whole, while ogg and opus files tag their audio stream, thus we fall back to
+    /// the tags of the primary audio stream.
+    pub fn get_tag(&self, key: &str) -> Option<&str> {
+        let ffpstream = self.ffpstream.as_ref()?;
+
+        find_tag(&ffpstream.format.tags, key)
+            .or_else(|| self.get_primary("audio")?.tags.as_ref()?.get(key))
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+    }
+
     pub fn is_corrupt(&self) -> Option<bool> {
         Some(self.corrupt.unwrap_or(false))
     }
@@ -229,6 +483,20 @@ impl FFPWrapper {
         Some(!self.find_by_type(codec_type).is_empty())
     }
 
+    /// Method returns the first stream of type `codec_type` tagged with one of `languages`, ie
+    /// `["jpn", "eng"]`. Earlier languages are preferred over later ones and languages are
+    /// compared ignoring case.
+    pub fn find_by_language(&self, codec_type: &str, languages: &[String]) -> Option<&Stream> {
+        let streams = self.find_by_type(codec_type);
+
+        languages.iter().find_map(|language| {
+            streams.iter().copied().find(|x| {
+                x.get_language()
+                    .map_or(false, |x| x.eq_ignore_ascii_case(language))
+            })
+        })
+    }
+
     pub fn find_by_type(&self, codec_type: &str) -> Vec<&Stream> {
         if let Some(x) = self.ffpstream.as_ref() {
             x.streams
@@ -241,6 +509,29 @@ impl FFPWrapper {
     }
 }
 
+/// Struct describes a single audio stream of a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AudioTrack {
+    pub index: i64,
+    pub codec: String,
+    pub channels: Option<i64>,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub default: bool,
+}
+
+/// Struct describes a single subtitle stream of a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SubtitleTrack {
+    pub index: i64,
+    /// Format of the subtitles, ie `subrip` or `hdmv_pgs_subtitle`.
+    pub format: String,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub default: bool,
+    pub forced: bool,
+}
+
 #[derive(Debug, Deserialize, Clone, PartialEq, Eq, Serialize)]
 pub struct Disposition {
     pub default: i64,
@@ -252,4 +543,162 @@ pub struct Disposition {
     pub forced: i64,
     pub hearing_impaired: i64,
     pub visual_impaired: i64,
+    #[serde(default)]
+    pub attached_pic: i64,
+}
+
+/// Function returns the value of the tag `key` in `tags`, ignoring case.
+fn find_tag<'a>(tags: &'a HashMap<String, String>, key: &str) -> Option<&'a str> {
+    tags.iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .map(|(_, v)| v.as_str())
+}
+
+/// Function parses a fraction as reported by ffprobe, ie `30000/1001`.
+fn parse_fraction(x: &str) -> Option<f64> {
+    let (num, den) = x.split_once('/')?;
+    let (num, den) = (num.parse::<f64>().ok()?, den.parse::<f64>().ok()?);
+
+    (num > 0.0 && den > 0.0).then(|| num / den)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probed(streams: Vec<Stream>, duration: &str) -> FFPWrapper {
+        FFPWrapper {
+            ffpstream: Some(FFPStream {
+                streams,
+                format: Format {
+                    duration: duration.into(),
+                    ..Default::default()
+                },
+            }),
+            corrupt: None,
+        }
+    }
+
+    fn video(pix_fmt: &str) -> Stream {
+        Stream {
+            codec_name: "hevc".into(),
+            codec_type: "video".into(),
+            width: Some(3840),
+            height: Some(2160),
+            pix_fmt: Some(pix_fmt.into()),
+            ..Default::default()
+        }
+    }
+
+    fn audio() -> Stream {
+        Stream {
+            index: 1,
+            codec_name: "flac".into(),
+            codec_type: "audio".into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn problem_of_unplayable_files() {
+        assert_eq!(
+            probed(vec![audio()], "240.0").get_problem(),
+            Some("audio_only")
+        );
+
+        // NOTE: Cover art embedded in a song is reported as a video stream.
+        let cover = Stream {
+            disposition: Some(Disposition {
+                default: 0,
+                dub: 0,
+                original: 0,
+                comment: 0,
+                lyrics: 0,
+                karaoke: 0,
+                forced: 0,
+                hearing_impaired: 0,
+                visual_impaired: 0,
+                attached_pic: 1,
+            }),
+            ..video("yuvj420p")
+        };
+        assert_eq!(
+            probed(vec![cover, audio()], "240.0").get_problem(),
+            Some("audio_only")
+        );
+
+        assert_eq!(
+            probed(vec![video("yuv420p"), audio()], "0.4").get_problem(),
+            Some("zero_duration")
+        );
+        assert_eq!(
+            probed(vec![video("yuv420p"), audio()], "5400.0").get_problem(),
+            None
+        );
+    }
+
+    #[test]
+    fn frame_rate_of_fractions() {
+        let ntsc = Stream {
+            avg_frame_rate: Some("24000/1001".into()),
+            ..video("yuv420p")
+        };
+        let rate = probed(vec![ntsc], "60.0").get_frame_rate().unwrap();
+        assert!((rate - 23.976).abs() < 0.001);
+
+        // NOTE: ffprobe reports `0/0` when it doesnt know the average frame rate.
+        let unknown = Stream {
+            avg_frame_rate: Some("0/0".into()),
+            r_frame_rate: Some("25/1".into()),
+            ..video("yuv420p")
+        };
+        assert_eq!(probed(vec![unknown], "60.0").get_frame_rate(), Some(25.0));
+
+        assert_eq!(probed(vec![audio()], "60.0").get_frame_rate(), None);
+    }
+
+    #[test]
+    fn bit_depth_of_pixel_formats() {
+        let raw = Stream {
+            bits_per_raw_sample: Some("12".into()),
+            ..video("yuv420p10le")
+        };
+        assert_eq!(probed(vec![raw], "60.0").get_bit_depth(), Some(12));
+
+        assert_eq!(
+            probed(vec![video("yuv420p10le")], "60.0").get_bit_depth(),
+            Some(10)
+        );
+        assert_eq!(
+            probed(vec![video("yuv420p12be")], "60.0").get_bit_depth(),
+            Some(12)
+        );
+        assert_eq!(
+            probed(vec![video("yuv420p")], "60.0").get_bit_depth(),
+            Some(8)
+        );
+    }
+
+    #[test]
+    fn hdr_of_color_transfers() {
+        let with_transfer = |transfer: &str| Stream {
+            color_transfer: Some(transfer.into()),
+            ..video("yuv420p10le")
+        };
+
+        let hdr = |stream: Stream| probed(vec![stream], "60.0").get_hdr();
+
+        assert_eq!(hdr(with_transfer("smpte2084")).as_deref(), Some("hdr10"));
+        assert_eq!(hdr(with_transfer("arib-std-b67")).as_deref(), Some("hlg"));
+        assert_eq!(hdr(with_transfer("bt709")), None);
+
+        // NOTE: Without a color transfer we go by the primaries and the bit depth.
+        let bt2020 = |pix_fmt: &str| Stream {
+            color_primaries: Some("bt2020".into()),
+            ..video(pix_fmt)
+        };
+
+        assert_eq!(hdr(bt2020("yuv420p10le")).as_deref(), Some("hdr10"));
+        assert_eq!(hdr(bt2020("yuv420p")), None);
+    }
 }
@@ -1,19 +1,49 @@
+//! Events dispatched by dim to its clients over a websocket.
+//!
+//! # Versioning
+//! Every message carries a `version` field which holds the schema version of its event type as
+//! returned by [`PushEventType::version`]. To keep older clients working the following rules
+//! apply when changing an event:
+//! * Adding new fields is backwards compatible and does not require a version bump as clients
+//! must ignore fields they dont know about.
+//! * Removing, renaming or changing the meaning of an existing field requires the version of that
+//! event type to be bumped.
+//! * New event types start out at version `1`.
 use serde::Serialize;
+use serde::Serializer;
 use std::collections::HashMap;
 
 /// Struct encompasses a message we are trying to relay to a client from somehwere within dim. It
-/// holds an id and a event_type field.
-#[derive(Serialize)]
+/// holds an id and a event_type field. When serialized the message also carries the schema version
+/// of its event type.
 pub struct Message {
     /// Field id, can hold anything and the client usually discriminates its meaning based on the
     /// event_type. For example within dim, sometimes it can be the library_id or media_id or
     /// sometimes its just -1 meaning ignore
     pub id: i64,
     /// Field holds the event type that gets relayed to the clients.
-    #[serde(flatten)]
     pub event_type: PushEventType,
 }
 
+impl Serialize for Message {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Wire<'a> {
+            id: i64,
+            version: u32,
+            #[serde(flatten)]
+            event_type: &'a PushEventType,
+        }
+
+        Wire {
+            id: self.id,
+            version: self.event_type.version(),
+            event_type: &self.event_type,
+        }
+        .serialize(serializer)
+    }
+}
+
 impl ToString for Message {
     fn to_string(&self) -> String {
         serde_json::to_string(&self).unwrap()
@@ -38,10 +68,60 @@ pub enum PushEventType {
     EventStreamStats(HashMap<String, String>),
     /// A library is being scanned.
     EventStartedScanning,
-    /// A library has finished scanning.
-    EventStoppedScanning,
+    /// Progress of a library scan, sent whenever another batch of files has been mounted. `id` is
+    /// the id of the library.
+    EventScanProgress {
+        files_total: usize,
+        files_done: usize,
+        current_file: Option<String>,
+    },
+    /// A library has finished scanning. `state` tells the client how the scan went.
+    EventStoppedScanning { state: ScanState },
     /// Tell client auth is ok
     EventAuthOk,
     /// Tell client their token is wrong or missing
     EventAuthErr,
+    /// A new episode of a tv show we already had episodes of has been added, `id` is the id of
+    /// the episode.
+    EventNewEpisode {
+        lib_id: i64,
+        show_id: i64,
+        season: i64,
+        episode: i64,
+    },
+}
+
+impl PushEventType {
+    /// Method returns the schema version of the payload of this event type. Check the versioning
+    /// rules in the crate docs before bumping any of these.
+    pub fn version(&self) -> u32 {
+        match self {
+            Self::EventNewCard { .. } => 1,
+            Self::EventRemoveCard => 1,
+            Self::EventNewLibrary => 1,
+            Self::EventRemoveLibrary => 1,
+            Self::EventStreamIsReady => 1,
+            Self::EventStreamStats(_) => 1,
+            Self::EventStartedScanning => 1,
+            Self::EventScanProgress { .. } => 1,
+            Self::EventStoppedScanning { .. } => 1,
+            Self::EventAuthOk => 1,
+            Self::EventAuthErr => 1,
+            Self::EventNewEpisode { .. } => 1,
+        }
+    }
+}
+
+/// Enum holds the state a library scan ended up in, relayed to clients with
+/// [`PushEventType::EventStoppedScanning`].
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScanState {
+    /// Every file was mounted and matched.
+    CompletedClean,
+    /// The scan went through, but some files failed to mount or match.
+    CompletedWithErrors,
+    /// The scan didnt go through, ie because the database couldnt be reached.
+    Failed,
+    /// The library was removed while it was being scanned.
+    Cancelled,
 }